@@ -14,7 +14,7 @@ use std::io::{Write};
 %start_symbol {program}
 %derive_token {Debug,Clone,PartialEq}
 %wildcard ANY.
-%extra_argument { Result<Ast, i32> }
+%extra_argument { ast::ParseState }
 
 %type AND { SrcLoc }
 %type BLOCKARROW { SrcLoc }
@@ -92,6 +92,7 @@ use std::io::{Write};
 %type localid { Ast }
 %type lri { Ast }
 %type lri_base { (Vec<Lstr>, SrcLoc) }
+%type import_dots { (Vec<Lstr>, SrcLoc) }
 %type match_expr { Ast }
 %type defstruple { Ast }
 %type defstruple_block { LinkedList<Kxpr> }
@@ -133,27 +134,27 @@ use std::io::{Write};
 }
 
 %syntax_error {
-    match yymajor {
-        TOKEN_EOI => {
-	        panic!("Unexpected end of file. Maybe add newline?");
-        }
-        _ => {
-	        println!("syntax error at token {:?}\n", yymajor);
-	        panic!("Syntax error. wtf? @ <line,column>?");
-        }
+    // keep the first error, the later ones are usually caused by it
+    if self.extra.error.is_none() {
+        let msg = match yymajor {
+            TOKEN_EOI => "unexpected end of file, maybe add a newline?",
+            _ => "syntax error",
+        };
+        self.extra.error = Some((self.extra.loc, msg.to_string()));
     }
 }
 
 %parse_failure {
-	println!("parse failure");
-	panic!("Parse failed.");
+    if self.extra.error.is_none() {
+        self.extra.error = Some((self.extra.loc, "parse failed".to_string()));
+    }
 }
 
 program(A) ::= stmts(B). {
     // ignore A, it doesn't really go anywhere for program
     A = Ast::ConstVoid;
     // we're done, so put B in extra
-    self.extra = Ok(Ast::Block(B)); // , SrcLoc::default()));
+    self.extra.ast = Some(Ast::Block(B));
 }
 
 stmts(A) ::= . {
@@ -176,6 +177,13 @@ stmt(A) ::= defenum(B). { A = B; }
 stmt(A) ::= IMPORT(C) localid(B). {
     A = Ast::Import(Box::new(B), C);
 }
+/* nested modules can be imported w/ either :: or . separators */
+stmt(A) ::= IMPORT(C) lri_base(B). {
+    A = Ast::Import(Box::new(Ast::Lri(B.0, None, B.1)), C);
+}
+stmt(A) ::= IMPORT(C) import_dots(B). {
+    A = Ast::Import(Box::new(Ast::Lri(B.0, None, B.1)), C);
+}
 stmt(A) ::= let_stmt(B). { A = B; }
 stmt(A) ::= failed_stmt(B). { A = B; }
 stmt(A) ::= func_stmt(B). { A = B; }
@@ -600,6 +608,14 @@ lri_base(A) ::= lri_base(B) DBLCOLON ID(C). {
     tmp.0.push(Lstr::from(C.data));
     A = tmp;
 }
+import_dots(A) ::= ID(B) DOT ID(C). {
+    A = (vec![Lstr::from(B.data), Lstr::from(C.data)], B.loc);
+}
+import_dots(A) ::= import_dots(B) DOT ID(C). {
+    let mut tmp = B;
+    tmp.0.push(Lstr::from(C.data));
+    A = tmp;
+}
 
 
 /** 3 list types
@@ -3,8 +3,9 @@ use leema::log;
 use leema::lstr::Lstr;
 use leema::msg::{AppMsg, IoMsg, MsgItem, WorkerMsg};
 use leema::program;
-use leema::val::Val;
-use leema::worker::Worker;
+use leema::struple::Struple;
+use leema::val::{MsgVal, Val};
+use leema::worker::{self, Worker};
 
 use std::cmp::min;
 use std::collections::{HashMap, LinkedList};
@@ -14,6 +15,7 @@ use std::thread;
 use std::time::Duration;
 
 use futures::sync::oneshot as futures_oneshot;
+use num_cpus;
 
 
 pub struct Application
@@ -24,11 +26,14 @@ pub struct Application
     io_recv: Option<Receiver<IoMsg>>,
     io_send: Sender<IoMsg>,
     worker: HashMap<i64, Sender<WorkerMsg>>,
-    calls: LinkedList<(Option<futures_oneshot::Sender<Val>>, Lstr, Lstr)>,
-    args: Val,
+    calls: LinkedList<(Option<futures_oneshot::Sender<Val>>, Lstr, Lstr, Val)>,
     result: Option<Val>,
+    repl_result: Option<Val>,
     done: bool,
+    worker_count: usize,
+    reduction_budget: u32,
     last_worker_id: i64,
+    last_victim_id: i64,
 }
 
 impl Application
@@ -45,18 +50,16 @@ impl Application
             io_send: iotx,
             worker: HashMap::new(),
             calls: LinkedList::new(),
-            args: Val::Nil,
             result: None,
+            repl_result: None,
             done: false,
+            worker_count: default_worker_count(),
+            reduction_budget: worker::DEFAULT_REDUCTION_BUDGET,
             last_worker_id: 0,
+            last_victim_id: 0,
         }
     }
 
-    pub fn set_args(&mut self, args: Val)
-    {
-        self.args = args;
-    }
-
     pub fn caller(&self) -> AppCaller
     {
         AppCaller {
@@ -66,14 +69,45 @@ impl Application
 
     pub fn push_call(&mut self, module: Lstr, func: Lstr)
     {
-        self.calls.push_back((None, module, func));
+        self.push_call_with_args(module, func, Val::Tuple(Struple(vec![])));
+    }
+
+    /**
+     * Call a function w/ a tuple of args once the app is running
+     */
+    pub fn push_call_with_args(&mut self, module: Lstr, func: Lstr, args: Val)
+    {
+        self.calls.push_back((None, module, func, args));
+    }
+
+    pub fn prog_mut(&mut self) -> &mut program::Lib
+    {
+        &mut self.prog
+    }
+
+    /**
+     * Set the number of worker threads to start, before calling run
+     */
+    pub fn set_worker_count(&mut self, n: usize)
+    {
+        self.worker_count = n.max(1);
+    }
+
+    /**
+     * Set the number of ops a fiber runs before it yields
+     * to the other fibers on its worker
+     */
+    pub fn set_reduction_budget(&mut self, budget: u32)
+    {
+        self.reduction_budget = budget;
     }
 
     pub fn run(&mut self)
     {
         self.start_io();
-        self.start_worker();
-        self.start_worker();
+        for _ in 0..self.worker_count {
+            self.start_worker();
+        }
     }
 
     fn start_io(&mut self) -> thread::JoinHandle<()>
@@ -94,9 +128,12 @@ impl Application
         let app_send = self.app_send.clone();
         let io_send = self.io_send.clone();
         let (worker_send, worker_recv) = channel();
+        let budget = self.reduction_budget;
         vout!("start worker {}\n", worker_id);
         let handle = thread::spawn(move || {
-            let w = Worker::init(worker_id, app_send, io_send, worker_recv);
+            let mut w =
+                Worker::init(worker_id, app_send, io_send, worker_recv);
+            w.set_reduction_budget(budget);
             Worker::run(w);
         });
         self.worker.insert(worker_id, worker_send.clone());
@@ -130,11 +167,40 @@ impl Application
         self.result.take()
     }
 
+    /**
+     * Run a function on the workers and wait for its result
+     * without ending the application like a main call would
+     */
+    pub fn repl_call(&mut self, module: Lstr, func: Lstr) -> Val
+    {
+        vout!("application repl call {}.{}()\n", module, func);
+        {
+            let w = self.worker.values().next().unwrap();
+            let msg = WorkerMsg::ReplSpawn(
+                MsgItem::new(&module),
+                MsgItem::new(&func),
+            );
+            w.send(msg).expect("fail sending repl call to worker");
+        }
+        let mut did_nothing = 0;
+        while self.repl_result.is_none() {
+            if self.iterate() {
+                did_nothing = 0;
+            } else {
+                did_nothing = min(did_nothing + 1, 100_000);
+                if did_nothing > 1000 {
+                    thread::sleep(Duration::from_micros(did_nothing));
+                }
+            }
+        }
+        self.repl_result.take().unwrap()
+    }
+
     pub fn iterate(&mut self) -> bool
     {
         let mut did_something = false;
-        while let Some((dst, module, call)) = self.calls.pop_front() {
-            vout!("application call {}.{}()\n", module, call);
+        while let Some((dst, module, call, args)) = self.calls.pop_front() {
+            vout!("application call {}.{}{}\n", module, call, args);
             let w = self.worker.values().next().unwrap();
             let msg = match dst {
                 Some(idst) => {
@@ -142,10 +208,15 @@ impl Application
                         idst,
                         MsgItem::new(&module),
                         MsgItem::new(&call),
+                        MsgVal::new(&args),
                     )
                 }
                 None => {
-                    WorkerMsg::Spawn(MsgItem::new(&module), MsgItem::new(&call))
+                    WorkerMsg::Spawn(
+                        MsgItem::new(&module),
+                        MsgItem::new(&call),
+                        MsgVal::new(&args),
+                    )
                 }
             };
             w.send(msg).expect("fail sending spawn call to worker");
@@ -153,12 +224,48 @@ impl Application
         }
 
         while let Result::Ok(msg) = self.app_recv.try_recv() {
+            // idle workers asking for work don't keep the app awake
+            let idle = match msg {
+                AppMsg::Steal(_) | AppMsg::Stolen(_, None) => true,
+                _ => false,
+            };
             self.process_msg(msg);
-            did_something = true;
+            if !idle {
+                did_something = true;
+            }
         }
         did_something
     }
 
+    /**
+     * Pick the next worker to steal from, round robin and
+     * skipping the worker that's asking
+     */
+    fn next_victim(&mut self, thief_id: i64) -> Option<i64>
+    {
+        if self.worker.len() < 2 {
+            return None;
+        }
+        loop {
+            self.last_victim_id = self.last_victim_id % self.last_worker_id + 1;
+            if self.last_victim_id != thief_id
+                && self.worker.contains_key(&self.last_victim_id)
+            {
+                return Some(self.last_victim_id);
+            }
+        }
+    }
+
+    fn send_to_worker(&self, worker_id: i64, msg: WorkerMsg)
+    {
+        // a worker that already finished can't take any more work
+        if let Some(w) = self.worker.get(&worker_id) {
+            if w.send(msg).is_err() {
+                vout!("worker {} is gone\n", worker_id);
+            }
+        }
+    }
+
     pub fn process_msg(&mut self, msg: AppMsg)
     {
         vout!("Received a message! {:?}\n", msg);
@@ -166,37 +273,72 @@ impl Application
             AppMsg::RequestCode(worker_id, frame, mmodule, mfunc) => {
                 let module = mmodule.take();
                 let func = mfunc.take();
-                let code = self.prog.load_code(&module, &func);
+                let code = match self.prog.load_code(&module, &func) {
+                    Ok(code) => code.clone(),
+                    Err(errs) => {
+                        self.prog.print_errors(&errs);
+                        self.result = Some(Val::Int(1));
+                        self.done = true;
+                        return;
+                    }
+                };
+                let file = Lstr::from(self.prog.mod_file_name(&module));
                 let worker = self.worker.get(&worker_id).unwrap();
                 worker
                     .send(WorkerMsg::FoundCode(
                         frame,
                         MsgItem::new(&module),
                         MsgItem::new(&func),
-                        code.clone(),
+                        MsgItem::new(&file),
+                        code,
                     )).expect("fail to send found code to worker");
             }
             AppMsg::MainResult(mv) => {
                 self.result = Some(mv.take());
                 self.done = true;
             }
+            AppMsg::ReplResult(mv) => {
+                self.repl_result = Some(mv.take());
+            }
             AppMsg::Spawn(_, _) => {
                 panic!("whoa a spawn msg sent to Application");
             }
-            AppMsg::Spawn2(result_dst, func) => {
+            AppMsg::Spawn2(result_dst, func, args) => {
                 self.calls.push_back((
                     Some(result_dst),
                     func.mod_ref().unwrap().clone(),
                     func.localid,
+                    args.take(),
                 ));
             }
-            AppMsg::ResultSpawn(result_dst, modname, funcname) => {
+            AppMsg::ResultSpawn(result_dst, modname, funcname, args) => {
                 self.calls.push_back((
                     Some(result_dst),
                     modname.take(),
                     funcname.take(),
+                    args.take(),
                 ));
             }
+            AppMsg::Steal(thief_id) => {
+                match self.next_victim(thief_id) {
+                    Some(victim_id) => {
+                        self.send_to_worker(
+                            victim_id,
+                            WorkerMsg::Steal(thief_id),
+                        );
+                    }
+                    None => {
+                        self.send_to_worker(thief_id, WorkerMsg::Stolen(None));
+                    }
+                }
+            }
+            AppMsg::Stolen(thief_id, opt_fiber) => {
+                self.send_to_worker(thief_id, WorkerMsg::Stolen(opt_fiber));
+            }
+            AppMsg::WakeReceiver(worker_id, fiber_id) => {
+                let msg = WorkerMsg::WakeReceiver(fiber_id);
+                self.send_to_worker(worker_id, msg);
+            }
         }
     }
 
@@ -214,7 +356,8 @@ impl Application
             Val::Failure(ref tag, ref msg, ref trace, status) => {
                 println!("Failure: {}", tag);
                 println!("Message: {}", msg);
-                println!("Stack Trace:\n{}", trace);
+                println!("Stack Trace (most recent call first):");
+                print!("{}", trace);
                 status
             }
             res => {
@@ -226,6 +369,14 @@ impl Application
     }
 }
 
+/**
+ * One worker per cpu unless told otherwise
+ */
+pub fn default_worker_count() -> usize
+{
+    num_cpus::get().max(1)
+}
+
 /*
 #[derive(Debug)]
 pub struct CallHandle
@@ -254,10 +405,14 @@ pub struct AppCaller
 
 impl AppCaller
 {
+    /**
+     * Call a function w/ a tuple of args and get its result
+     */
     pub fn push_call(
         &self,
         modname: &Lstr,
         fname: &Lstr,
+        args: Val,
     ) -> futures_oneshot::Receiver<Val>
     {
         let (result_send, result_recv) = futures_oneshot::channel();
@@ -266,6 +421,7 @@ impl AppCaller
                 result_send,
                 MsgItem::new(modname),
                 MsgItem::new(fname),
+                MsgVal::new(&args),
             )).unwrap();
         result_recv
     }
@@ -308,7 +464,7 @@ mod tests
         };
         writeln!(stderr(), "test_main_func_finishes {:?}", p).unwrap();
         let input = "func main() -> 3 --".to_string();
-        let mut inter = Interloader::new(Lstr::Sref("test.lma"));
+        let mut inter = Interloader::new(Lstr::Sref("test.lma")).unwrap();
         inter.set_mod_txt(Lstr::Sref("test"), input);
         let prog = program::Lib::new(inter);
 
@@ -321,4 +477,52 @@ mod tests
         assert_eq!(Some(Val::Int(3)), result);
     }
 
+    #[test]
+    fn test_forked_call_result()
+    {
+        let input = "
+            func double(x: Int): Int -> x * 2 --
+
+            func main() ->
+                fork a := double(3)
+                fork b := double(4)
+                a + b
+            --
+            "
+        .to_string();
+        let mut inter = Interloader::new(Lstr::Sref("test.lma")).unwrap();
+        inter.set_mod_txt(Lstr::Sref("test"), input);
+        let prog = program::Lib::new(inter);
+
+        let mut app = Application::new(prog);
+        app.push_call(Lstr::Sref("test"), Lstr::Sref("main"));
+        app.run();
+
+        let result = app.wait_for_result();
+        assert_eq!(Some(Val::Int(14)), result);
+    }
+
+    #[test]
+    fn test_forked_call_failure_propagates()
+    {
+        let input = "
+            func boom(x: Int): Int -> fail(#boom, \"kaboom\") --
+
+            func main(): Int ->
+                fork a := boom(3)
+                a + 1
+            --
+            "
+        .to_string();
+        let mut inter = Interloader::new(Lstr::Sref("test.lma")).unwrap();
+        inter.set_mod_txt(Lstr::Sref("test"), input);
+        let prog = program::Lib::new(inter);
+
+        let mut app = Application::new(prog);
+        app.push_call(Lstr::Sref("test"), Lstr::Sref("main"));
+        app.run();
+
+        let result = app.wait_for_result().unwrap();
+        assert!(result.is_failure());
+    }
 }
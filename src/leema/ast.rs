@@ -1,3 +1,4 @@
+use leema::error::{CompileError, CompileResult, ErrorKind};
 use leema::lri::Lri;
 use leema::lstr::Lstr;
 use leema::parse::{Parser, Token};
@@ -174,6 +175,20 @@ impl Kxpr
         }
     }
 
+    pub fn try_map_x<F, E>(&self, op: F) -> Result<Kxpr, E>
+    where
+        F: FnOnce(&Ast) -> Result<Ast, E>,
+    {
+        let new_x = match self.x {
+            Some(ref bx) => Some(Box::new(op(&**bx)?)),
+            None => None,
+        };
+        Ok(Kxpr {
+            k: self.k.clone(),
+            x: new_x,
+        })
+    }
+
     pub fn map_1<F, T>(&self, op: F) -> (Option<Lstr>, T)
     where
         F: FnOnce(&Ast) -> T,
@@ -479,15 +494,55 @@ impl<'a> From<&'a Ast> for Type
 }
 
 
-pub fn parse(toks: Vec<Token>) -> Ast
+/**
+ * The parser's extra state. The location of each token is set before
+ * it's parsed so a syntax error knows where it happened.
+ */
+#[derive(Debug)]
+pub struct ParseState
+{
+    pub loc: SrcLoc,
+    pub ast: Option<Ast>,
+    pub error: Option<(SrcLoc, String)>,
+}
+
+impl ParseState
+{
+    pub fn new() -> ParseState
+    {
+        ParseState {
+            loc: SrcLoc::new(1, 1),
+            ast: None,
+            error: None,
+        }
+    }
+}
+
+/**
+ * Parse the located tokens for a module, failing w/ the location
+ * of the first syntax error
+ */
+pub fn parse(module: &Lstr, toks: Vec<(Token, SrcLoc)>) -> CompileResult<Ast>
 {
-    let e = Err(0);
-    let mut p = Parser::new(e);
-    for t in toks {
+    let mut p = Parser::new(ParseState::new());
+    for (t, loc) in toks {
+        p.extra_mut().loc = loc;
         p.parse(t);
     }
     p.parse(Token::EOI);
-    p.into_extra().unwrap()
+    let state = p.into_extra();
+    match (state.error, state.ast) {
+        (None, Some(ast)) => Ok(ast),
+        (Some((loc, msg)), _) => {
+            Err(CompileError::new(module, loc, ErrorKind::Syntax, msg))
+        }
+        (None, None) => Err(CompileError::new(
+            module,
+            state.loc,
+            ErrorKind::Syntax,
+            "incomplete program".to_string(),
+        )),
+    }
 }
 
 
@@ -495,7 +550,8 @@ pub fn parse(toks: Vec<Token>) -> Ast
 mod tests
 {
     use leema::ast::{self, Ast, Kxpr, Type};
-    use leema::lex::lex;
+    use leema::error::{CompileResult, ErrorKind};
+    use leema::lex::lex_located;
     use leema::lri::Lri;
     use leema::lstr::Lstr;
     use leema::val::SrcLoc;
@@ -503,6 +559,12 @@ mod tests
     use std::collections::LinkedList;
 
 
+    fn parse_str(input: &str) -> CompileResult<Ast>
+    {
+        let m = Lstr::Sref("test");
+        lex_located(&m, input).and_then(|toks| ast::parse(&m, toks))
+    }
+
     fn test_lri(item: &'static str, line: i16, col: i8) -> Ast
     {
         Ast::Lri(
@@ -582,8 +644,7 @@ mod tests
     fn test_ast_parse_plus()
     {
         let input = "5 + 3\n";
-        let lexed = lex(input);
-        let root = ast::parse(lexed);
+        let root = parse_str(input).unwrap();
 
         let expected = Ast::Block(vec![Ast::Call(
             Box::new(test_mod_lri("prefab", "int_add", 1, 2)),
@@ -599,7 +660,7 @@ mod tests
     fn test_ast_parse_strlit()
     {
         let input = "\"taco\"\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let expected = Ast::Block(vec![Ast::ConstStr(Lstr::from("taco"))]);
         assert_eq!(expected, root);
@@ -609,7 +670,7 @@ mod tests
     fn test_ast_parse_string_id()
     {
         let input = "\"$var\"\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let expected = Ast::Block(vec![Ast::StrExpr(
             vec![Ast::Localid(Lstr::from("var"), SrcLoc::new(1, 3))],
@@ -622,7 +683,7 @@ mod tests
     fn test_ast_parse_string_list()
     {
         let input = "\"hello $name\n\"\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let part1 = Ast::ConstStr(Lstr::from("hello "));
         let part2 = Ast::Localid(Lstr::from("name"), SrcLoc::new(1, 9));
@@ -638,7 +699,7 @@ mod tests
     fn test_ast_parse_plus_twice()
     {
         let input = "5 + 3 + 2\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let inner = Ast::Call(
             Box::new(test_mod_lri("prefab", "int_add", 1, 2)),
@@ -663,7 +724,7 @@ mod tests
     fn test_ast_parse_call_one_param()
     {
         let input = "inc(~4)\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let neg4 = Ast::Call(
             Box::new(test_mod_lri("prefab", "int_negate", 1, 5)),
@@ -682,7 +743,7 @@ mod tests
     fn test_ast_parse_function_call()
     {
         let input = "foo(7, 2)\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let xargs =
             vec![Kxpr::new_x(Ast::ConstInt(7)), Kxpr::new_x(Ast::ConstInt(2))]
@@ -700,7 +761,7 @@ mod tests
     fn test_ast_parse_tuple()
     {
         let input = "(3, \"taco\", true)\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let xtup = Ast::Tuple(
             vec![
@@ -718,7 +779,7 @@ mod tests
     fn test_ast_parse_list_empty()
     {
         let input = "[]\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let xlist = Ast::List(vec![].into_iter().collect());
         let expected = Ast::Block(vec![xlist]);
@@ -729,7 +790,7 @@ mod tests
     fn test_ast_parse_const_list()
     {
         let input = "[1, 2, x]\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let xlist = Ast::List(
             vec![
@@ -747,7 +808,7 @@ mod tests
     fn test_ast_parse_list_cons()
     {
         let input = "1;2;x\n";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let inner = Ast::Cons(
             Box::new(Ast::ConstInt(2)),
@@ -767,7 +828,7 @@ mod tests
         foo(x+1, 40)
     --
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
         if let Ast::Block(items) = root {
             assert_eq!(1, items.len());
         } else {
@@ -779,7 +840,7 @@ mod tests
     fn test_parse_empty_tuple()
     {
         let input = "()";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let xtuple = Ast::Tuple(LinkedList::new());
         let expected = Ast::Block(vec![xtuple]);
@@ -790,7 +851,7 @@ mod tests
     fn test_parse_one_tuple()
     {
         let input = "(5)";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let tuple_items =
             vec![Kxpr::new_x(Ast::ConstInt(5))].into_iter().collect();
@@ -799,6 +860,36 @@ mod tests
         assert_eq!(expected, root);
     }
 
+    #[test]
+    fn test_parse_syntax_error_location()
+    {
+        let input = "
+    func main() ->
+        5 +
+    --
+    ";
+        let err = parse_str(input).unwrap_err();
+        assert_eq!(ErrorKind::Syntax, err.kind);
+        assert_eq!("test", err.module.str());
+        assert_eq!("syntax error", err.msg.str());
+        assert_eq!(4, err.loc.lineno);
+    }
+
+    #[test]
+    fn test_parse_unexpected_end()
+    {
+        let input = "
+    func main() ->
+        5
+    ";
+        let err = parse_str(input).unwrap_err();
+        assert_eq!(ErrorKind::Syntax, err.kind);
+        assert_eq!(
+            "unexpected end of file, maybe add a newline?",
+            err.msg.str()
+        );
+    }
+
     #[test]
     fn test_parse_match_empty_list()
     {
@@ -808,7 +899,7 @@ mod tests
     |(_) -> false
     --
     ";
-        ast::parse(lex(input));
+        parse_str(input).unwrap();
 
         // didn't crash
         assert_eq!(2, 2);
@@ -822,7 +913,7 @@ mod tests
         foo(40, x+1)
     --
     ";
-        ast::parse(lex(input));
+        parse_str(input).unwrap();
 
         // didn't crash
         assert_eq!(3, 3);
@@ -840,7 +931,7 @@ mod tests
         doubles(5, 10)
     --
     ";
-        ast::parse(lex(input));
+        parse_str(input).unwrap();
     }
 
     #[test]
@@ -853,7 +944,7 @@ mod tests
         z
     --
     ";
-        ast::parse(lex(input));
+        parse_str(input).unwrap();
 
         // didn't crash
         assert_eq!(1, 1);
@@ -863,7 +954,7 @@ mod tests
     fn test_ast_parse_if_no_else()
     {
         let input = "if x -> y --";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let blocka = ast::IfCase::new(
             Ast::Localid(Lstr::from("x"), SrcLoc::new(1, 3)),
@@ -892,7 +983,7 @@ mod tests
         --
     --
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         if let Ast::Block(lines) = root {
             let f = lines.first().unwrap();
@@ -919,7 +1010,7 @@ mod tests
     fn test_parse_call_function_call_result()
     {
         let input = "(foo(5))(6)";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let foo_call = Ast::Tuple(
             vec![Kxpr::new_x(Ast::Call(
@@ -953,7 +1044,7 @@ mod tests
             .weight: $A
         --
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         if let Ast::Block(lines) = root {
             let first = lines.first().unwrap();
@@ -979,7 +1070,7 @@ mod tests
         let input = "
     struct Taco(Int, Str)
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let def = Ast::DefData(
             ast::DataType::Struple,
@@ -998,7 +1089,7 @@ mod tests
         let input = "
     struct Taco(number: Int, style: Str)
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let def = Ast::DefData(
             ast::DataType::Struple,
@@ -1019,7 +1110,7 @@ mod tests
         let input = "
     struct Taco(Int, style: Str)
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let def = Ast::DefData(
             ast::DataType::Struple,
@@ -1043,7 +1134,7 @@ mod tests
     .style: Str
     --
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let def = Ast::DefData(
             ast::DataType::Struple,
@@ -1067,7 +1158,7 @@ mod tests
     |(_) -> false
     --
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let match_line = if let &Ast::Block(ref items) = &root {
             items.first().unwrap()
@@ -1089,7 +1180,7 @@ mod tests
     fn test_parse_constructor_call()
     {
         let input = "Taco(1, 2)";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let call = Ast::Call(
             Box::new(test_localid("Taco", 1, 1)),
@@ -1106,7 +1197,7 @@ mod tests
     fn test_parse_strlit_field_access()
     {
         let input = "\"hello ${dog.name}\"";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         let strx = Ast::StrExpr(
             vec![
@@ -1129,7 +1220,7 @@ mod tests
     let x := 4 + 8
     ~x
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         if let Ast::Block(lines) = root {
             assert_eq!(2, lines.len());
@@ -1145,7 +1236,7 @@ mod tests
     let x := 4 + y
     (x, z)
     ";
-        let root = ast::parse(lex(input));
+        let root = parse_str(input).unwrap();
 
         if let Ast::Block(items) = root {
             assert_eq!(2, items.len());
@@ -1162,7 +1253,7 @@ mod tests
         [#tacos, #burritos]
     --
     ";
-        ast::parse(lex(input));
+        parse_str(input).unwrap();
 
         assert!(true); // didn't panic!
     }
@@ -1176,7 +1267,7 @@ mod tests
     |Baz(B)
     --
     ";
-        ast::parse(lex(input));
+        parse_str(input).unwrap();
 
         assert!(true); // didn't panic!
     }
@@ -1189,7 +1280,7 @@ mod tests
         option::None
     --
     ";
-        ast::parse(lex(input));
+        parse_str(input).unwrap();
 
         assert!(true); // didn't panic!
     }
@@ -1202,7 +1293,7 @@ mod tests
         f(i)
     --
     ";
-        ast::parse(lex(input));
+        parse_str(input).unwrap();
 
         assert!(true); // didn't panic!
     }
@@ -0,0 +1,1011 @@
+use leema::code::{Op, OpVec};
+use leema::log;
+use leema::lri::Lri;
+use leema::lstr::Lstr;
+use leema::reg::{Ireg, Reg};
+use leema::struple::Struple;
+use leema::val::{Type, Val};
+
+use std::io::Write;
+use std::sync::Arc;
+
+
+pub const CACHE_EXT: &'static str = "lmc";
+
+// bump the last byte when the format changes
+const MAGIC: &'static [u8] = b"LMC\x02";
+const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/**
+ * Hash of a module's source text, used to tell if a cache is stale
+ *
+ * This is 64 bit FNV-1a so the hash written to the cache stays the
+ * same across builds and rust versions, unlike the std hasher.
+ */
+pub fn src_hash(txt: &str) -> u64
+{
+    txt.bytes().fold(FNV_OFFSET, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct CachedImport
+{
+    pub name: Lstr,
+    pub path: Vec<Lstr>,
+    pub src_hash: u64,
+}
+
+/**
+ * The compiled form of a module as stored in its cache file
+ *
+ * funcs holds the ops for each leema function and None for functions
+ * implemented in rust, which are looked up again when loaded.
+ * imports lists every module this one imports, directly or through
+ * another import. The cache is only fresh if the module and each
+ * of those imports still hash to the same values.
+ */
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct CachedModule
+{
+    pub src_hash: u64,
+    pub imports: Vec<CachedImport>,
+    pub types: Vec<(Lstr, Type)>,
+    pub funcs: Vec<(Lstr, Option<OpVec>)>,
+}
+
+impl CachedModule
+{
+    pub fn get_func(&self, funcname: &str) -> Option<&Option<OpVec>>
+    {
+        self.funcs
+            .iter()
+            .find(|&&(ref name, _)| name == funcname)
+            .map(|&(_, ref ops)| ops)
+    }
+
+    /**
+     * Functions with values that can't be written to the cache
+     * are left out and get compiled from source when loaded
+     */
+    pub fn encode(&self) -> Vec<u8>
+    {
+        let mut w = Writer::new();
+        w.buf.extend_from_slice(MAGIC);
+        w.str(VERSION);
+        w.u64(self.src_hash);
+
+        w.len(self.imports.len());
+        for imp in self.imports.iter() {
+            w.str(&imp.name);
+            w.len(imp.path.len());
+            for seg in imp.path.iter() {
+                w.str(seg);
+            }
+            w.u64(imp.src_hash);
+        }
+
+        w.len(self.types.len());
+        for &(ref name, ref typ) in self.types.iter() {
+            w.str(name);
+            w.typ(typ);
+        }
+
+        let mut funcs = Writer::new();
+        let mut func_count = 0;
+        for &(ref name, ref ops) in self.funcs.iter() {
+            let mut fw = Writer::new();
+            fw.str(name);
+            let encoded = match ops {
+                &Some(ref opv) => {
+                    fw.u8(1);
+                    fw.ops(opv)
+                }
+                &None => {
+                    fw.u8(0);
+                    Ok(())
+                }
+            };
+            match encoded {
+                Ok(()) => {
+                    funcs.buf.extend(fw.buf);
+                    func_count += 1;
+                }
+                Err(e) => {
+                    vout!("not caching function {}: {}\n", name, e);
+                }
+            }
+        }
+        w.len(func_count);
+        w.buf.extend(funcs.buf);
+        w.buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<CachedModule, String>
+    {
+        let mut r = Reader::new(bytes);
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err("not a leema cache file".to_string());
+        }
+        let version = r.str()?;
+        if version != VERSION {
+            return Err(format!("cache is from leema version {}", version));
+        }
+        let src_hash = r.u64()?;
+
+        let import_count = r.len()?;
+        let mut imports = Vec::with_capacity(import_count);
+        for _ in 0..import_count {
+            let name = r.lstr()?;
+            let seg_count = r.len()?;
+            let mut path = Vec::with_capacity(seg_count);
+            for _ in 0..seg_count {
+                path.push(r.lstr()?);
+            }
+            let src_hash = r.u64()?;
+            imports.push(CachedImport {
+                name,
+                path,
+                src_hash,
+            });
+        }
+
+        let type_count = r.len()?;
+        let mut types = Vec::with_capacity(type_count);
+        for _ in 0..type_count {
+            let name = r.lstr()?;
+            types.push((name, r.typ()?));
+        }
+
+        let func_count = r.len()?;
+        let mut funcs = Vec::with_capacity(func_count);
+        for _ in 0..func_count {
+            let name = r.lstr()?;
+            let ops = match r.u8()? {
+                0 => None,
+                _ => Some(r.ops()?),
+            };
+            funcs.push((name, ops));
+        }
+
+        if r.pos != bytes.len() {
+            return Err("unexpected data at end of cache".to_string());
+        }
+        Ok(CachedModule {
+            src_hash,
+            imports,
+            types,
+            funcs,
+        })
+    }
+}
+
+
+struct Writer
+{
+    buf: Vec<u8>,
+}
+
+impl Writer
+{
+    fn new() -> Writer
+    {
+        Writer { buf: vec![] }
+    }
+
+    fn u8(&mut self, b: u8)
+    {
+        self.buf.push(b);
+    }
+
+    fn i8(&mut self, i: i8)
+    {
+        self.buf.push(i as u8);
+    }
+
+    fn i16(&mut self, i: i16)
+    {
+        let u = i as u16;
+        self.buf.push(u as u8);
+        self.buf.push((u >> 8) as u8);
+    }
+
+    fn i64(&mut self, i: i64)
+    {
+        self.u64(i as u64);
+    }
+
+    fn u64(&mut self, i: u64)
+    {
+        for shift in 0..8 {
+            self.buf.push((i >> (shift * 8)) as u8);
+        }
+    }
+
+    fn len(&mut self, n: usize)
+    {
+        self.u64(n as u64);
+    }
+
+    fn bytes(&mut self, b: &[u8])
+    {
+        self.len(b.len());
+        self.buf.extend_from_slice(b);
+    }
+
+    fn str(&mut self, s: &str)
+    {
+        self.bytes(s.as_bytes());
+    }
+
+    fn opt_str(&mut self, s: &Option<Lstr>)
+    {
+        match s {
+            &Some(ref txt) => {
+                self.u8(1);
+                self.str(txt);
+            }
+            &None => self.u8(0),
+        }
+    }
+
+    fn ireg(&mut self, r: &Ireg)
+    {
+        match r {
+            &Ireg::Reg(i) => {
+                self.u8(0);
+                self.i8(i);
+            }
+            &Ireg::Sub(i, ref sub) => {
+                self.u8(1);
+                self.i8(i);
+                self.ireg(sub);
+            }
+        }
+    }
+
+    fn reg(&mut self, r: &Reg)
+    {
+        match r {
+            &Reg::Param(ref ir) => {
+                self.u8(0);
+                self.ireg(ir);
+            }
+            &Reg::Local(ref ir) => {
+                self.u8(1);
+                self.ireg(ir);
+            }
+            &Reg::Lib => self.u8(2),
+            &Reg::Void => self.u8(3),
+            &Reg::Undecided => self.u8(4),
+        }
+    }
+
+    fn lri(&mut self, i: &Lri)
+    {
+        self.opt_str(&i.modules);
+        self.str(&i.localid);
+        match i.params {
+            Some(ref params) => {
+                self.u8(1);
+                self.types(params);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn types(&mut self, types: &Vec<Type>)
+    {
+        self.len(types.len());
+        for t in types.iter() {
+            self.typ(t);
+        }
+    }
+
+    fn type_struple(&mut self, s: &Struple<Type>)
+    {
+        self.len(s.0.len());
+        for &(ref name, ref t) in s.0.iter() {
+            self.opt_str(name);
+            self.typ(t);
+        }
+    }
+
+    fn typ(&mut self, t: &Type)
+    {
+        match t {
+            &Type::Int => self.u8(0),
+            &Type::Str => self.u8(1),
+            &Type::Bool => self.u8(2),
+            &Type::Hashtag => self.u8(3),
+            &Type::Tuple(ref items) => {
+                self.u8(4);
+                self.type_struple(items);
+            }
+            &Type::Failure => self.u8(5),
+            &Type::Func(ref args, ref result) => {
+                self.u8(6);
+                self.types(args);
+                self.typ(result);
+            }
+            &Type::StrictList(ref inner) => {
+                self.u8(7);
+                self.typ(inner);
+            }
+            &Type::Map => self.u8(8),
+            &Type::UserDef(ref i) => {
+                self.u8(9);
+                self.lri(i);
+            }
+            &Type::Lib(ref name) => {
+                self.u8(10);
+                self.str(name);
+            }
+            &Type::Resource(ref name) => {
+                self.u8(11);
+                self.str(name);
+            }
+            &Type::RustBlock => self.u8(12),
+            &Type::Param(p) => {
+                self.u8(13);
+                self.i8(p);
+            }
+            &Type::Void => self.u8(14),
+            &Type::Kind => self.u8(15),
+            &Type::Any => self.u8(16),
+            &Type::Unknown => self.u8(17),
+            &Type::Var(ref name) => {
+                self.u8(18);
+                self.str(name);
+            }
+            &Type::AnonVar => self.u8(19),
+            &Type::Buffer => self.u8(20),
+        }
+    }
+
+    fn val_struple(&mut self, s: &Struple<Val>) -> Result<(), String>
+    {
+        self.len(s.0.len());
+        for &(ref name, ref v) in s.0.iter() {
+            self.opt_str(name);
+            self.val(v)?;
+        }
+        Ok(())
+    }
+
+    fn val(&mut self, v: &Val) -> Result<(), String>
+    {
+        match v {
+            &Val::Int(i) => {
+                self.u8(0);
+                self.i64(i);
+            }
+            &Val::Str(ref s) => {
+                self.u8(1);
+                self.str(s);
+            }
+            &Val::Bool(b) => {
+                self.u8(2);
+                self.u8(b as u8);
+            }
+            &Val::Hashtag(ref s) => {
+                self.u8(3);
+                self.str(s);
+            }
+            &Val::Buffer(ref b) => {
+                self.u8(4);
+                self.bytes(b);
+            }
+            &Val::Cons(ref head, ref tail) => {
+                self.u8(5);
+                self.val(head)?;
+                self.val(tail)?;
+            }
+            &Val::Nil => self.u8(6),
+            &Val::Tuple(ref items) => {
+                self.u8(7);
+                self.val_struple(items)?;
+            }
+            &Val::Struct(ref i, ref flds) => {
+                self.u8(8);
+                self.lri(i);
+                self.val_struple(flds)?;
+            }
+            &Val::EnumStruct(ref i, ref var, ref flds) => {
+                self.u8(9);
+                self.lri(i);
+                self.str(var);
+                self.val_struple(flds)?;
+            }
+            &Val::EnumToken(ref i, ref var) => {
+                self.u8(10);
+                self.lri(i);
+                self.str(var);
+            }
+            &Val::Token(ref i) => {
+                self.u8(11);
+                self.lri(i);
+            }
+            &Val::Id(ref id) => {
+                self.u8(12);
+                self.str(id);
+            }
+            &Val::Lri(ref i) => {
+                self.u8(13);
+                self.lri(i);
+            }
+            &Val::Type(ref t) => {
+                self.u8(14);
+                self.typ(t);
+            }
+            &Val::Kind(k) => {
+                self.u8(15);
+                self.u8(k);
+            }
+            &Val::FuncRef(ref i, ref t) => {
+                self.u8(16);
+                self.lri(i);
+                self.typ(t);
+            }
+            &Val::RustBlock => self.u8(17),
+            &Val::Void => self.u8(18),
+            &Val::Wildcard => self.u8(19),
+            &Val::PatternVar(ref r) => {
+                self.u8(20);
+                self.reg(r);
+            }
+            _ => {
+                return Err(format!("cannot cache value: {:?}", v));
+            }
+        }
+        Ok(())
+    }
+
+    fn ops(&mut self, ops: &OpVec) -> Result<(), String>
+    {
+        self.len(ops.len());
+        for &(ref op, line) in ops.iter() {
+            self.op(op)?;
+            self.i16(line);
+        }
+        Ok(())
+    }
+
+    fn op(&mut self, op: &Op) -> Result<(), String>
+    {
+        match op {
+            &Op::ApplyFunc(ref dst, ref f, ref args, col) => {
+                self.u8(0);
+                self.reg(dst);
+                self.reg(f);
+                self.reg(args);
+                self.i8(col);
+            }
+            &Op::Return => self.u8(1),
+            &Op::SetResult(ref src) => {
+                self.u8(2);
+                self.reg(src);
+            }
+            &Op::PropagateFailure(ref src, line) => {
+                self.u8(3);
+                self.reg(src);
+                self.i16(line);
+            }
+            &Op::ConstVal(ref dst, ref v) => {
+                self.u8(4);
+                self.reg(dst);
+                self.val(v)?;
+            }
+            &Op::Construple(ref dst, ref typ, ref flds) => {
+                self.u8(5);
+                self.reg(dst);
+                self.typ(typ);
+                self.type_struple(flds);
+            }
+            &Op::Copy(ref dst, ref src) => {
+                self.u8(6);
+                self.reg(dst);
+                self.reg(src);
+            }
+            &Op::Jump(j) => {
+                self.u8(7);
+                self.i16(j);
+            }
+            &Op::JumpIfNot(j, ref tst) => {
+                self.u8(8);
+                self.i16(j);
+                self.reg(tst);
+            }
+            &Op::IfFailure(ref src, j) => {
+                self.u8(9);
+                self.reg(src);
+                self.i16(j);
+            }
+            &Op::MatchPattern(ref dst, ref patt, ref input) => {
+                self.u8(10);
+                self.reg(dst);
+                self.val(patt)?;
+                self.reg(input);
+            }
+            &Op::ListCons(ref dst, ref head, ref tail) => {
+                self.u8(11);
+                self.reg(dst);
+                self.reg(head);
+                self.reg(tail);
+            }
+            &Op::ListCreate(ref dst) => {
+                self.u8(12);
+                self.reg(dst);
+            }
+            &Op::MapCreate(ref dst) => {
+                self.u8(13);
+                self.reg(dst);
+            }
+            &Op::StrCat(ref dst, ref src) => {
+                self.u8(14);
+                self.reg(dst);
+                self.reg(src);
+            }
+            &Op::TupleCreate(ref dst, sz) => {
+                self.u8(15);
+                self.reg(dst);
+                self.i8(sz);
+            }
+            &Op::Fork(ref dst, ref f, ref args, col) => {
+                self.u8(16);
+                self.reg(dst);
+                self.reg(f);
+                self.reg(args);
+                self.i8(col);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+struct Reader<'a>
+{
+    buf: &'a [u8],
+    pos: usize,
+}
+
+type ReadResult<T> = Result<T, String>;
+
+impl<'a> Reader<'a>
+{
+    fn new(buf: &'a [u8]) -> Reader<'a>
+    {
+        Reader { buf, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> ReadResult<&'a [u8]>
+    {
+        if self.buf.len() - self.pos < n {
+            return Err("unexpected end of cache".to_string());
+        }
+        let result = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> ReadResult<u8>
+    {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i8(&mut self) -> ReadResult<i8>
+    {
+        Ok(self.u8()? as i8)
+    }
+
+    fn i16(&mut self) -> ReadResult<i16>
+    {
+        let bytes = self.take(2)?;
+        Ok((bytes[0] as u16 | (bytes[1] as u16) << 8) as i16)
+    }
+
+    fn i64(&mut self) -> ReadResult<i64>
+    {
+        Ok(self.u64()? as i64)
+    }
+
+    fn u64(&mut self) -> ReadResult<u64>
+    {
+        let bytes = self.take(8)?;
+        let mut result = 0;
+        for (shift, b) in bytes.iter().enumerate() {
+            result |= (*b as u64) << (shift * 8);
+        }
+        Ok(result)
+    }
+
+    fn len(&mut self) -> ReadResult<usize>
+    {
+        let n = self.u64()? as usize;
+        if n > self.buf.len() - self.pos {
+            // every item takes at least a byte, so this can't be right
+            return Err(format!("invalid length in cache: {}", n));
+        }
+        Ok(n)
+    }
+
+    fn bytes(&mut self) -> ReadResult<&'a [u8]>
+    {
+        let n = self.len()?;
+        self.take(n)
+    }
+
+    fn str(&mut self) -> ReadResult<String>
+    {
+        let b = self.bytes()?;
+        String::from_utf8(b.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn lstr(&mut self) -> ReadResult<Lstr>
+    {
+        Ok(Lstr::from(self.str()?))
+    }
+
+    fn opt_lstr(&mut self) -> ReadResult<Option<Lstr>>
+    {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.lstr()?)),
+        }
+    }
+
+    fn ireg(&mut self) -> ReadResult<Ireg>
+    {
+        match self.u8()? {
+            0 => Ok(Ireg::Reg(self.i8()?)),
+            1 => {
+                let i = self.i8()?;
+                Ok(Ireg::Sub(i, Box::new(self.ireg()?)))
+            }
+            tag => Err(format!("invalid ireg tag: {}", tag)),
+        }
+    }
+
+    fn reg(&mut self) -> ReadResult<Reg>
+    {
+        match self.u8()? {
+            0 => Ok(Reg::Param(self.ireg()?)),
+            1 => Ok(Reg::Local(self.ireg()?)),
+            2 => Ok(Reg::Lib),
+            3 => Ok(Reg::Void),
+            4 => Ok(Reg::Undecided),
+            tag => Err(format!("invalid reg tag: {}", tag)),
+        }
+    }
+
+    fn lri(&mut self) -> ReadResult<Lri>
+    {
+        let modules = self.opt_lstr()?;
+        let localid = self.lstr()?;
+        let params = match self.u8()? {
+            0 => None,
+            _ => Some(self.types()?),
+        };
+        Ok(Lri::full(modules, localid, params))
+    }
+
+    fn types(&mut self) -> ReadResult<Vec<Type>>
+    {
+        let n = self.len()?;
+        let mut result = Vec::with_capacity(n);
+        for _ in 0..n {
+            result.push(self.typ()?);
+        }
+        Ok(result)
+    }
+
+    fn type_struple(&mut self) -> ReadResult<Struple<Type>>
+    {
+        let n = self.len()?;
+        let mut items = Vec::with_capacity(n);
+        for _ in 0..n {
+            let name = self.opt_lstr()?;
+            items.push((name, self.typ()?));
+        }
+        Ok(Struple(items))
+    }
+
+    fn typ(&mut self) -> ReadResult<Type>
+    {
+        let t = match self.u8()? {
+            0 => Type::Int,
+            1 => Type::Str,
+            2 => Type::Bool,
+            3 => Type::Hashtag,
+            4 => Type::Tuple(self.type_struple()?),
+            5 => Type::Failure,
+            6 => {
+                let args = self.types()?;
+                Type::Func(args, Box::new(self.typ()?))
+            }
+            7 => Type::StrictList(Box::new(self.typ()?)),
+            8 => Type::Map,
+            9 => Type::UserDef(self.lri()?),
+            10 => Type::Lib(self.str()?),
+            11 => Type::Resource(self.lstr()?),
+            12 => Type::RustBlock,
+            13 => Type::Param(self.i8()?),
+            14 => Type::Void,
+            15 => Type::Kind,
+            16 => Type::Any,
+            17 => Type::Unknown,
+            18 => Type::Var(self.lstr()?),
+            19 => Type::AnonVar,
+            20 => Type::Buffer,
+            tag => {
+                return Err(format!("invalid type tag: {}", tag));
+            }
+        };
+        Ok(t)
+    }
+
+    fn val_struple(&mut self) -> ReadResult<Struple<Val>>
+    {
+        let n = self.len()?;
+        let mut items = Vec::with_capacity(n);
+        for _ in 0..n {
+            let name = self.opt_lstr()?;
+            items.push((name, self.val()?));
+        }
+        Ok(Struple(items))
+    }
+
+    fn val(&mut self) -> ReadResult<Val>
+    {
+        let v = match self.u8()? {
+            0 => Val::Int(self.i64()?),
+            1 => Val::Str(self.lstr()?),
+            2 => Val::Bool(self.u8()? != 0),
+            3 => Val::Hashtag(self.lstr()?),
+            4 => Val::Buffer(self.bytes()?.to_vec()),
+            5 => {
+                let head = self.val()?;
+                Val::Cons(Box::new(head), Arc::new(self.val()?))
+            }
+            6 => Val::Nil,
+            7 => Val::Tuple(self.val_struple()?),
+            8 => {
+                let i = self.lri()?;
+                Val::Struct(i, self.val_struple()?)
+            }
+            9 => {
+                let i = self.lri()?;
+                let var = self.lstr()?;
+                Val::EnumStruct(i, var, self.val_struple()?)
+            }
+            10 => {
+                let i = self.lri()?;
+                Val::EnumToken(i, self.lstr()?)
+            }
+            11 => Val::Token(self.lri()?),
+            12 => Val::Id(self.lstr()?),
+            13 => Val::Lri(self.lri()?),
+            14 => Val::Type(self.typ()?),
+            15 => Val::Kind(self.u8()?),
+            16 => {
+                let i = self.lri()?;
+                Val::FuncRef(i, self.typ()?)
+            }
+            17 => Val::RustBlock,
+            18 => Val::Void,
+            19 => Val::Wildcard,
+            20 => Val::PatternVar(self.reg()?),
+            tag => {
+                return Err(format!("invalid val tag: {}", tag));
+            }
+        };
+        Ok(v)
+    }
+
+    fn ops(&mut self) -> ReadResult<OpVec>
+    {
+        let n = self.len()?;
+        let mut result = Vec::with_capacity(n);
+        for _ in 0..n {
+            let op = self.op()?;
+            result.push((op, self.i16()?));
+        }
+        Ok(result)
+    }
+
+    fn op(&mut self) -> ReadResult<Op>
+    {
+        let op = match self.u8()? {
+            0 => {
+                let dst = self.reg()?;
+                let f = self.reg()?;
+                let args = self.reg()?;
+                Op::ApplyFunc(dst, f, args, self.i8()?)
+            }
+            1 => Op::Return,
+            2 => Op::SetResult(self.reg()?),
+            3 => {
+                let src = self.reg()?;
+                Op::PropagateFailure(src, self.i16()?)
+            }
+            4 => {
+                let dst = self.reg()?;
+                Op::ConstVal(dst, self.val()?)
+            }
+            5 => {
+                let dst = self.reg()?;
+                let typ = self.typ()?;
+                Op::Construple(dst, typ, self.type_struple()?)
+            }
+            6 => {
+                let dst = self.reg()?;
+                Op::Copy(dst, self.reg()?)
+            }
+            7 => Op::Jump(self.i16()?),
+            8 => {
+                let j = self.i16()?;
+                Op::JumpIfNot(j, self.reg()?)
+            }
+            9 => {
+                let src = self.reg()?;
+                Op::IfFailure(src, self.i16()?)
+            }
+            10 => {
+                let dst = self.reg()?;
+                let patt = self.val()?;
+                Op::MatchPattern(dst, patt, self.reg()?)
+            }
+            11 => {
+                let dst = self.reg()?;
+                let head = self.reg()?;
+                Op::ListCons(dst, head, self.reg()?)
+            }
+            12 => Op::ListCreate(self.reg()?),
+            13 => Op::MapCreate(self.reg()?),
+            14 => {
+                let dst = self.reg()?;
+                Op::StrCat(dst, self.reg()?)
+            }
+            15 => {
+                let dst = self.reg()?;
+                Op::TupleCreate(dst, self.i8()?)
+            }
+            16 => {
+                let dst = self.reg()?;
+                let f = self.reg()?;
+                let args = self.reg()?;
+                Op::Fork(dst, f, args, self.i8()?)
+            }
+            tag => {
+                return Err(format!("invalid op tag: {}", tag));
+            }
+        };
+        Ok(op)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::cache::{self, CachedImport, CachedModule};
+    use leema::code::Op;
+    use leema::lri::Lri;
+    use leema::lstr::Lstr;
+    use leema::reg::{Ireg, Reg};
+    use leema::struple::Struple;
+    use leema::val::{Type, Val};
+
+    use std::sync::Arc;
+
+
+    fn sample_module() -> CachedModule
+    {
+        let point = Lri::with_modules(Lstr::Sref("tacos"), Lstr::Sref("Point"));
+        let ops = vec![
+            (
+                Op::ConstVal(
+                    Reg::local(0),
+                    Val::Cons(Box::new(Val::Int(-3)), Arc::new(Val::Nil)),
+                ),
+                4,
+            ),
+            (
+                Op::MatchPattern(
+                    Reg::local(1),
+                    Val::Tuple(Struple::new_indexed(vec![
+                        Val::PatternVar(Reg::Local(Ireg::Sub(
+                            2,
+                            Box::new(Ireg::Reg(1)),
+                        ))),
+                        Val::Wildcard,
+                    ])),
+                    Reg::param(0),
+                ),
+                5,
+            ),
+            (Op::JumpIfNot(-2, Reg::local(1)), 5),
+            (
+                Op::Construple(
+                    Reg::local(2),
+                    Type::UserDef(point.clone()),
+                    Struple(vec![
+                        (Some(Lstr::Sref("x")), Type::Int),
+                        (Some(Lstr::Sref("y")), Type::Int),
+                    ]),
+                ),
+                6,
+            ),
+            (Op::ConstVal(Reg::local(3), Val::Str(Lstr::Sref("hi"))), 7),
+            (Op::SetResult(Reg::local(3)), 7),
+            (Op::Return, 8),
+        ];
+        CachedModule {
+            src_hash: cache::src_hash("func main() -> 5 --"),
+            imports: vec![CachedImport {
+                name: Lstr::Sref("http"),
+                path: vec![Lstr::Sref("net"), Lstr::Sref("http")],
+                src_hash: 7,
+            }],
+            types: vec![(
+                Lstr::Sref("main"),
+                Type::Func(
+                    vec![Type::StrictList(Box::new(Type::Str))],
+                    Box::new(Type::Tuple(Struple::new_indexed(vec![
+                        Type::Var(Lstr::Sref("A")),
+                        Type::UserDef(point),
+                    ]))),
+                ),
+            )],
+            funcs: vec![
+                (Lstr::Sref("main"), Some(ops)),
+                (Lstr::Sref("cout"), None),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip()
+    {
+        let m = sample_module();
+        let bytes = m.encode();
+        let decoded = CachedModule::decode(&bytes).unwrap();
+        assert_eq!(m, decoded);
+        assert_eq!(Some(&None), decoded.get_func("cout"));
+    }
+
+    #[test]
+    fn test_cache_skips_uncacheable_functions()
+    {
+        let mut m = sample_module();
+        m.funcs.push((
+            Lstr::Sref("fail"),
+            Some(vec![(Op::ConstVal(Reg::local(0), Val::ResourceRef(3)), 1)]),
+        ));
+        let decoded = CachedModule::decode(&m.encode()).unwrap();
+        assert!(decoded.get_func("main").is_some());
+        assert!(decoded.get_func("fail").is_none());
+    }
+
+    #[test]
+    fn test_cache_rejects_bad_data()
+    {
+        let bytes = sample_module().encode();
+        assert!(CachedModule::decode(b"tacos").is_err());
+        assert!(CachedModule::decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_src_hash_is_fnv1a()
+    {
+        assert_eq!(0xcbf29ce484222325, cache::src_hash(""));
+        assert_eq!(0xaf63dc4c8601ec8c, cache::src_hash("a"));
+    }
+}
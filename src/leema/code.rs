@@ -39,14 +39,16 @@ impl fmt::Display for ModSym
 #[derive(PartialEq)]
 pub enum Op
 {
-    ApplyFunc(Reg, Reg, Reg),
+    // ApplyFunc(dst, func, args, column)
+    ApplyFunc(Reg, Reg, Reg, i8),
     Return,
     SetResult(Reg),
     PropagateFailure(Reg, i16),
     ConstVal(Reg, Val),
     Construple(Reg, Type, Struple<Type>),
     Copy(Reg, Reg),
-    // Fork(Reg, Reg, Reg),
+    // Fork(dst, func, args, column)
+    Fork(Reg, Reg, Reg, i8),
     Jump(i16),
     JumpIfNot(i16, Reg),
     IfFailure(Reg, i16),
@@ -67,8 +69,8 @@ impl Clone for Op
     fn clone(&self) -> Op
     {
         match self {
-            &Op::ApplyFunc(ref dst, ref f, ref args) => {
-                Op::ApplyFunc(dst.clone(), f.clone(), args.clone())
+            &Op::ApplyFunc(ref dst, ref f, ref args, col) => {
+                Op::ApplyFunc(dst.clone(), f.clone(), args.clone(), col)
             }
             &Op::Return => Op::Return,
             &Op::SetResult(ref src) => Op::SetResult(src.clone()),
@@ -86,6 +88,9 @@ impl Clone for Op
                 )
             }
             &Op::Copy(ref dst, ref src) => Op::Copy(dst.clone(), src.clone()),
+            &Op::Fork(ref dst, ref f, ref args, col) => {
+                Op::Fork(dst.clone(), f.clone(), args.clone(), col)
+            }
             &Op::Jump(j) => Op::Jump(j),
             &Op::JumpIfNot(j, ref tst) => Op::JumpIfNot(j, tst.clone()),
             &Op::IfFailure(ref src, j) => Op::IfFailure(src.clone(), j),
@@ -259,7 +264,10 @@ pub fn make_sub_ops(rt: &mut RegTable, input: &Ixpr) -> Oxpr
             rt.def_args(argnames);
             make_sub_ops(rt, &body)
         }
-        Source::Call(ref f, ref args) => make_call_ops(rt, f, args),
+        Source::Call(ref f, ref args, col) => {
+            make_call_ops(rt, f, args, col)
+        }
+        Source::Fork(ref var, ref call) => make_fork_ops(rt, var, call),
         Source::Cons(ref h, ref t) => {
             let dst = rt.dst().clone();
             rt.push_dst();
@@ -357,7 +365,12 @@ pub fn make_sub_ops(rt: &mut RegTable, input: &Ixpr) -> Oxpr
     }
 }
 
-pub fn make_call_ops(rt: &mut RegTable, f: &Ixpr, args: &Ixpr) -> Oxpr
+pub fn make_call_ops(
+    rt: &mut RegTable,
+    f: &Ixpr,
+    args: &Ixpr,
+    col: i8,
+) -> Oxpr
 {
     let dst = rt.dst().clone();
     vout!("make_call_ops: {:?} = {:?}\n", dst, f);
@@ -369,7 +382,7 @@ pub fn make_call_ops(rt: &mut RegTable, f: &Ixpr, args: &Ixpr) -> Oxpr
     let mut argops = make_sub_ops(rt, args);
     fops.ops.append(&mut argops.ops);
     fops.ops.push((
-        Op::ApplyFunc(dst.clone(), fops.dst.clone(), argops.dst),
+        Op::ApplyFunc(dst.clone(), fops.dst.clone(), argops.dst, col),
         f.line,
     ));
 
@@ -616,16 +629,35 @@ pub fn make_if_else_ops(
     }
 }
 
-/*
-pub fn make_fork_ops(rt: &mut RegTable, dst: &Reg, f: &Ixpr, args: &Ixpr) -> Oxpr
+/**
+ * Fork a call into its own fiber and put the future of its result
+ * straight into the variable's register
+ */
+pub fn make_fork_ops(rt: &mut RegTable, var: &Lstr, call: &Ixpr) -> Oxpr
 {
-    println!("make_fork_ops({:?}, {:?}, {:?})", dst, f, args);
-    let mut ops = make_sub_ops(rt, f);
-    ops.append(&mut make_sub_ops(rt, args));
-    ops.push(Op::Fork(dst.clone(), Reg::Undecided, Reg::Undecided));
-    ops
+    let (f, args, col) = match call.src {
+        Source::Call(ref f, ref args, col) => (f, args, col),
+        _ => {
+            panic!("cannot fork a non-call: {:?}", call);
+        }
+    };
+    let dst = rt.id(var);
+    vout!("make_fork_ops: {:?} = {:?}\n", dst, f);
+
+    rt.push_dst();
+    let mut fops = make_sub_ops(rt, f);
+
+    rt.push_dst();
+    let mut argops = make_sub_ops(rt, args);
+    fops.ops.append(&mut argops.ops);
+    let fork = Op::Fork(dst, fops.dst.clone(), argops.dst, col);
+    fops.ops.push((fork, call.line));
+
+    rt.pop_dst();
+    rt.pop_dst();
+    fops.dst = rt.dst().clone();
+    fops
 }
-*/
 
 pub fn make_list_ops(rt: &mut RegTable, items: &Vec<Ixpr>, line: i16) -> Oxpr
 {
@@ -708,10 +740,10 @@ mod tests
             ",
         );
 
-        let mut loader = Interloader::new(Lstr::Sref("tacos.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("tacos"), input);
         let mut prog = program::Lib::new(loader);
-        prog.load_code(&Lstr::Sref("tacos"), &Lstr::Sref("main"));
+        prog.load_code(&Lstr::Sref("tacos"), &Lstr::Sref("main")).unwrap();
     }
 
     #[test]
@@ -731,10 +763,10 @@ mod tests
             --
             ".to_string();
 
-        let mut loader = Interloader::new(Lstr::Sref("tacos.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("tacos"), input);
         let mut prog = program::Lib::new(loader);
-        prog.load_code(&Lstr::Sref("tacos"), &Lstr::Sref("main"));
+        prog.load_code(&Lstr::Sref("tacos"), &Lstr::Sref("main")).unwrap();
     }
 
 }
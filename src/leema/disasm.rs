@@ -0,0 +1,260 @@
+use leema::code::{Op, OpVec};
+use leema::reg::{Ireg, Reg};
+use leema::val::Val;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+
+/**
+ * Disassemble an OpVec into one op per line
+ *
+ *      0  const        l0, 5               ; line 3
+ *      1  jump_if_not  l0, L0              ; line 4
+ * L0:
+ *      2  ...
+ *
+ * Relative jumps are resolved to labels and each op is annotated
+ * w/ its source line. If the module source is given, the text of
+ * each line is shown above the first op that came from it.
+ */
+pub fn disassemble(ops: &OpVec, src: Option<&str>) -> String
+{
+    let labels = find_labels(ops);
+    let src_lines: Vec<&str> =
+        src.map(|s| s.lines().collect()).unwrap_or_default();
+    let mut result = String::new();
+    let mut prev_line = None;
+    for (offset, &(ref op, line)) in ops.iter().enumerate() {
+        if let Some(label) = labels.get(&(offset as i32)) {
+            writeln!(result, "L{}:", label).unwrap();
+        }
+        if prev_line != Some(line) {
+            prev_line = Some(line);
+            let txt = src_lines.get((line as usize).wrapping_sub(1));
+            if let Some(txt) = txt {
+                writeln!(result, "      ; {}", txt.trim()).unwrap();
+            }
+        }
+        let op_txt = op_str(op, offset as i32, &labels);
+        writeln!(result, "{:6}  {:<40} ; line {}", offset, op_txt, line)
+            .unwrap();
+    }
+    // a jump to the end of the ops
+    if let Some(label) = labels.get(&(ops.len() as i32)) {
+        writeln!(result, "L{}:", label).unwrap();
+    }
+    result
+}
+
+/**
+ * Short name for a register, p0 for params and l3.1 for locals
+ */
+pub fn reg_str(r: &Reg) -> String
+{
+    match r {
+        &Reg::Param(ref ir) => format!("p{}", ireg_str(ir)),
+        &Reg::Local(ref ir) => format!("l{}", ireg_str(ir)),
+        &Reg::Lib => "lib".to_string(),
+        &Reg::Void => "void".to_string(),
+        &Reg::Undecided => "undecided".to_string(),
+    }
+}
+
+fn ireg_str(ir: &Ireg) -> String
+{
+    match ir {
+        &Ireg::Reg(r) => format!("{}", r),
+        &Ireg::Sub(r, ref sub) => format!("{}.{}", r, ireg_str(sub)),
+    }
+}
+
+fn jump_target(op: &Op, offset: i32) -> Option<i32>
+{
+    match op {
+        &Op::Jump(j) => Some(offset + j as i32),
+        &Op::JumpIfNot(j, _) => Some(offset + j as i32),
+        &Op::IfFailure(_, j) => Some(offset + j as i32),
+        _ => None,
+    }
+}
+
+/**
+ * Number each jump target in the order they appear in the ops
+ */
+fn find_labels(ops: &OpVec) -> BTreeMap<i32, usize>
+{
+    let mut labels = BTreeMap::new();
+    for (offset, &(ref op, _)) in ops.iter().enumerate() {
+        if let Some(target) = jump_target(op, offset as i32) {
+            labels.insert(target, 0);
+        }
+    }
+    for (i, label) in labels.values_mut().enumerate() {
+        *label = i;
+    }
+    labels
+}
+
+fn label_str(target: i32, labels: &BTreeMap<i32, usize>) -> String
+{
+    match labels.get(&target) {
+        Some(label) => format!("L{}", label),
+        None => format!("@{}", target),
+    }
+}
+
+fn val_str(v: &Val) -> String
+{
+    match v {
+        &Val::Str(ref s) => format!("{:?}", s.str()),
+        &Val::PatternVar(ref r) => format!("${}", reg_str(r)),
+        _ => format!("{}", v),
+    }
+}
+
+fn op_str(op: &Op, offset: i32, labels: &BTreeMap<i32, usize>) -> String
+{
+    let target = jump_target(op, offset).map(|t| label_str(t, labels));
+    match op {
+        &Op::ApplyFunc(ref dst, ref f, ref args, _) => {
+            format!(
+                "{:<12} {}, {}, {}",
+                "call",
+                reg_str(dst),
+                reg_str(f),
+                reg_str(args)
+            )
+        }
+        &Op::Fork(ref dst, ref f, ref args, _) => {
+            format!(
+                "{:<12} {}, {}, {}",
+                "fork",
+                reg_str(dst),
+                reg_str(f),
+                reg_str(args)
+            )
+        }
+        &Op::Return => "return".to_string(),
+        &Op::SetResult(ref src) => {
+            format!("{:<12} {}", "set_result", reg_str(src))
+        }
+        &Op::PropagateFailure(ref src, _) => {
+            // not a jump, it returns from the function if src failed
+            format!("{:<12} {}, return", "propagate", reg_str(src))
+        }
+        &Op::ConstVal(ref dst, ref v) => {
+            format!("{:<12} {}, {}", "const", reg_str(dst), val_str(v))
+        }
+        &Op::Construple(ref dst, ref typ, ref flds) => {
+            format!("{:<12} {}, {}{}", "construple", reg_str(dst), typ, flds)
+        }
+        &Op::Copy(ref dst, ref src) => {
+            format!("{:<12} {}, {}", "copy", reg_str(dst), reg_str(src))
+        }
+        &Op::Jump(_) => format!("{:<12} {}", "jump", target.unwrap()),
+        &Op::JumpIfNot(_, ref tst) => {
+            format!(
+                "{:<12} {}, {}",
+                "jump_if_not",
+                reg_str(tst),
+                target.unwrap()
+            )
+        }
+        &Op::IfFailure(ref src, _) => {
+            // falls through if src is a failure, jumps otherwise
+            format!(
+                "{:<12} {}, else {}",
+                "if_failure",
+                reg_str(src),
+                target.unwrap()
+            )
+        }
+        &Op::MatchPattern(ref dst, ref patt, ref input) => {
+            format!(
+                "{:<12} {}, {}, {}",
+                "match",
+                reg_str(dst),
+                val_str(patt),
+                reg_str(input)
+            )
+        }
+        &Op::ListCons(ref dst, ref head, ref tail) => {
+            format!(
+                "{:<12} {}, {}, {}",
+                "list_cons",
+                reg_str(dst),
+                reg_str(head),
+                reg_str(tail)
+            )
+        }
+        &Op::ListCreate(ref dst) => {
+            format!("{:<12} {}", "list_create", reg_str(dst))
+        }
+        &Op::MapCreate(ref dst) => {
+            format!("{:<12} {}", "map_create", reg_str(dst))
+        }
+        &Op::StrCat(ref dst, ref src) => {
+            format!("{:<12} {}, {}", "strcat", reg_str(dst), reg_str(src))
+        }
+        &Op::TupleCreate(ref dst, sz) => {
+            format!("{:<12} {}, {}", "tuple", reg_str(dst), sz)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::code::Op;
+    use leema::disasm;
+    use leema::lstr::Lstr;
+    use leema::reg::{Ireg, Reg};
+    use leema::val::Val;
+
+
+    #[test]
+    fn test_reg_str()
+    {
+        assert_eq!("p0", disasm::reg_str(&Reg::param(0)));
+        let sub = Reg::Local(Ireg::Sub(3, Box::new(Ireg::Reg(1))));
+        assert_eq!("l3.1", disasm::reg_str(&sub));
+        assert_eq!("void", disasm::reg_str(&Reg::Void));
+    }
+
+    #[test]
+    fn test_disassemble_labels_and_lines()
+    {
+        let ops = vec![
+            (Op::ConstVal(Reg::local(0), Val::Bool(true)), 2),
+            (Op::JumpIfNot(3, Reg::local(0)), 2),
+            (Op::ConstVal(Reg::local(1), Val::Str(Lstr::Sref("yes"))), 3),
+            (Op::Jump(2), 3),
+            (Op::ConstVal(Reg::local(1), Val::Int(4)), 4),
+            (Op::SetResult(Reg::local(1)), 5),
+            (Op::Return, 5),
+        ];
+        let src = "func main() ->\nif true\n|\"yes\"\n|4\n--\n";
+        let out = disasm::disassemble(&ops, Some(src));
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            vec![
+                "      ; if true",
+                "     0  const        l0, true                    ; line 2",
+                "     1  jump_if_not  l0, L0                      ; line 2",
+                "      ; |\"yes\"",
+                "     2  const        l1, \"yes\"                   ; line 3",
+                "     3  jump         L1                          ; line 3",
+                "L0:",
+                "      ; |4",
+                "     4  const        l1, 4                       ; line 4",
+                "L1:",
+                "      ; --",
+                "     5  set_result   l1                          ; line 5",
+                "     6  return                                   ; line 5",
+            ],
+            lines
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use leema::lstr::Lstr;
+use leema::val::{SrcLoc, TypeErr};
+
+use std::fmt;
+
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum ErrorKind
+{
+    FileNotFound,
+    InvalidFile,
+    ModuleNotFound,
+    AmbiguousModule,
+    Syntax,
+    Macro,
+    Pattern,
+    Undefined,
+    Reassign,
+    Type,
+    Unsupported,
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct CompileError
+{
+    pub module: Lstr,
+    pub loc: SrcLoc,
+    pub kind: ErrorKind,
+    pub msg: Lstr,
+    pub notes: Vec<Lstr>,
+}
+
+impl CompileError
+{
+    pub fn new(
+        module: &Lstr,
+        loc: SrcLoc,
+        kind: ErrorKind,
+        msg: String,
+    ) -> CompileError
+    {
+        CompileError {
+            module: module.clone(),
+            loc,
+            kind,
+            msg: Lstr::from(msg),
+            notes: vec![],
+        }
+    }
+
+    /**
+     * Convert a type error, using the fallback location if the
+     * error didn't get one of its own
+     */
+    pub fn type_err(module: &Lstr, fallback: SrcLoc, e: &TypeErr)
+        -> CompileError
+    {
+        let loc = e.loc().unwrap_or(fallback);
+        let mut notes = vec![];
+        let msg = match e.root() {
+            &TypeErr::Mismatch(ref expected, ref found) => {
+                notes.push(Lstr::from(format!("expected: {}", expected)));
+                notes.push(Lstr::from(format!("   found: {}", found)));
+                "type mismatch".to_string()
+            }
+            &TypeErr::Error(ref msg) => format!("type error: {}", msg),
+            &TypeErr::Unknowable => "type cannot be known".to_string(),
+            other => format!("type error: {}", other),
+        };
+        notes.extend(e.context());
+        let mut err = CompileError::new(module, loc, ErrorKind::Type, msg);
+        err.notes = notes;
+        err
+    }
+
+    /**
+     * Format the error w/ the file it came from, file:line:col: message
+     */
+    pub fn in_file(&self, file: &str) -> String
+    {
+        format!(
+            "{}:{}:{}: {}",
+            file, self.loc.lineno, self.loc.column, self.msg
+        )
+    }
+
+    /**
+     * Render the error w/ the line of source it came from,
+     * a caret under the column and any notes
+     *
+     * when the column isn't known, the caret goes under
+     * the start of the code on that line
+     */
+    pub fn render(&self, file: &str, src: Option<&str>) -> String
+    {
+        let mut out = self.in_file(file);
+        let line = if self.loc.lineno > 0 {
+            src.and_then(|txt| txt.lines().nth(self.loc.lineno as usize - 1))
+        } else {
+            None
+        };
+        if let Some(line) = line {
+            let lineno = self.loc.lineno.to_string();
+            let gutter = " ".repeat(lineno.len());
+            let caret_col = if self.loc.column > 0 {
+                self.loc.column as usize - 1
+            } else {
+                line.len() - line.trim_start().len()
+            };
+            // keep tabs so the caret lines up w/ the source
+            let indent: String = line
+                .chars()
+                .take(caret_col)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            out.push_str(&format!("\n{} |", gutter));
+            out.push_str(&format!("\n{} | {}", lineno, line));
+            out.push_str(&format!("\n{} | {}^", gutter, indent));
+        }
+        for n in self.notes.iter() {
+            out.push_str(&format!("\n  = note: {}", n));
+        }
+        out
+    }
+}
+
+impl fmt::Display for CompileError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}", self.in_file(self.module.str()))
+    }
+}
+
+pub type CompileResult<T> = Result<T, CompileError>;
+
+/**
+ * Result for a whole module or more, where errors are collected
+ * rather than stopping at the first one
+ */
+pub type ModuleResult<T> = Result<T, Vec<CompileError>>;
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::error::{CompileError, ErrorKind};
+    use leema::lstr::Lstr;
+    use leema::val::{SrcLoc, Type, TypeErr};
+
+
+    #[test]
+    fn test_compile_error_in_file()
+    {
+        let e = CompileError::new(
+            &Lstr::Sref("tacos"),
+            SrcLoc::new(4, 9),
+            ErrorKind::Undefined,
+            "undefined variable: x".to_string(),
+        );
+        assert_eq!(
+            "T/tacos.lma:4:9: undefined variable: x",
+            e.in_file("T/tacos.lma")
+        );
+    }
+
+    #[test]
+    fn test_render_type_mismatch()
+    {
+        let src = "func main() ->\n    foo(5)\n--\n";
+        let terr = TypeErr::Mismatch(Type::Str, Type::Int)
+            .at(SrcLoc::new(2, 0))
+            .add_context(Lstr::Sref("function args for foo"));
+        let e = CompileError::type_err(
+            &Lstr::Sref("tacos"),
+            SrcLoc::default(),
+            &terr,
+        );
+        let expected = "T/tacos.lma:2:0: type mismatch
+  |
+2 |     foo(5)
+  |     ^
+  = note: expected: Str
+  = note:    found: Int
+  = note: function args for foo";
+        assert_eq!(expected, e.render("T/tacos.lma", Some(src)));
+    }
+}
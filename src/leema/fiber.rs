@@ -4,13 +4,17 @@ use leema::list;
 use leema::lmap::Lmap;
 use leema::log;
 use leema::lstr::Lstr;
+use leema::mailbox::Pid;
 use leema::reg::Reg;
 use leema::struple::Struple;
-use leema::val::{Env, Type, Val};
+use leema::val::{Env, FutureVal, SrcLoc, Type, Val};
 
+use std::fmt;
 use std::io::Write;
 use std::mem;
 use std::rc::Rc;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
 
 
 #[derive(Debug)]
@@ -18,15 +22,85 @@ pub struct Fiber
 {
     pub fiber_id: i64,
     pub head: Frame,
+    // ops run since this fiber last yielded
+    pub reductions: u32,
+    // cleared if a supervisor kills this fiber
+    alive: Option<Arc<AtomicBool>>,
+    // created the first time something asks for it
+    pid: Option<Pid>,
 }
 
 impl Fiber
 {
     pub fn spawn(id: i64, root: Frame) -> Fiber
     {
+        let alive = match root.parent {
+            Parent::Supervised(ref child, _) => Some(child.alive_flag()),
+            _ => None,
+        };
+        let pid = match root.parent {
+            Parent::Process(ref pid) => Some(pid.clone()),
+            _ => None,
+        };
         Fiber {
             fiber_id: id,
             head: root,
+            reductions: 0,
+            alive,
+            pid,
+        }
+    }
+
+    /**
+     * Get the pid for this fiber's mailbox
+     */
+    pub fn pid(&mut self) -> Pid
+    {
+        if self.pid.is_none() {
+            self.pid = Some(Pid::new());
+        }
+        self.pid.clone().unwrap()
+    }
+
+    pub fn is_killed(&self) -> bool
+    {
+        self.alive
+            .as_ref()
+            .map(|a| !a.load(atomic::Ordering::Acquire))
+            .unwrap_or(false)
+    }
+
+    /**
+     * Pack this fiber up to move it to another worker
+     *
+     * head_code is the code for the head frame if it has already
+     * been loaded, None for a fiber that hasn't started yet.
+     */
+    pub fn pack(self, head_code: Option<&Code>) -> MsgFiber
+    {
+        let mut frames = vec![];
+        let mut callers = vec![];
+        let mut frame = self.head;
+        loop {
+            match frame.take_parent() {
+                Parent::Caller(code, pf, dst) => {
+                    callers.push(((*code).clone(), dst));
+                    frames.push(frame.pack());
+                    frame = *pf;
+                }
+                root => {
+                    frame.set_parent(root);
+                    frames.push(frame.pack());
+                    break;
+                }
+            }
+        }
+        MsgFiber {
+            head_code: head_code.map(|c| c.clone()),
+            frames,
+            callers,
+            alive: self.alive,
+            pid: self.pid,
         }
     }
 
@@ -44,7 +118,7 @@ impl Fiber
         &mut self,
         code: Rc<Code>,
         dst: Reg,
-        line: i16,
+        loc: SrcLoc,
         module: Lstr,
         func: Lstr,
         args: Val,
@@ -54,7 +128,8 @@ impl Fiber
             parent: Parent::Null,
             module: module.clone(),
             function: func.clone(),
-            trace: self.head.push_frame_trace(line),
+            file: Lstr::empty(),
+            trace: self.head.push_frame_trace(loc),
             e: Env::with_args(args),
             pc: 0,
         };
@@ -63,10 +138,20 @@ impl Fiber
         self.head.set_parent(parent);
     }
 
-    pub fn execute_leema_frame(&mut self, ops: &OpVec) -> Event
+    /**
+     * run ops until something happens or the fiber uses up its
+     * reduction budget. one op is one reduction and the count
+     * carries across calls until the fiber yields.
+     */
+    pub fn execute_leema_frame(&mut self, ops: &OpVec, budget: u32) -> Event
     {
         let mut e = Event::Uneventful;
         while let Event::Uneventful = e {
+            if self.reductions >= budget {
+                self.reductions = 0;
+                return Event::Yield;
+            }
+            self.reductions += 1;
             e = self.execute_leema_op(ops);
         }
         e
@@ -77,6 +162,9 @@ impl Fiber
         let op = ops.get(self.head.pc as usize).unwrap();
         let line = op.1;
         vout!("exec: {:?}\n", op);
+        if let Some(ev) = self.resolve_futures(&op.0, line) {
+            return ev;
+        }
         match &op.0 {
             &Op::ConstVal(ref dst, ref v) => self.execute_const_val(dst, v),
             &Op::Construple(ref dst, ref typ, ref flds) => {
@@ -98,8 +186,13 @@ impl Fiber
                 self.execute_create_tuple(dst, *sz)
             }
             &Op::StrCat(ref dst, ref src) => self.execute_strcat(dst, src),
-            &Op::ApplyFunc(ref dst, ref func, ref args) => {
-                self.execute_call(dst, func, args, line)
+            &Op::ApplyFunc(ref dst, ref func, ref args, col) => {
+                let loc = SrcLoc::new(line, col);
+                self.execute_call(dst, func, args, loc)
+            }
+            &Op::Fork(ref dst, ref func, ref args, col) => {
+                let loc = SrcLoc::new(line, col);
+                self.execute_fork(dst, func, args, loc)
             }
             &Op::Return => Event::Complete(true),
             &Op::SetResult(ref dst) => {
@@ -124,17 +217,7 @@ impl Fiber
         let result = {
             let dst = self.head.e.get_reg(dstreg);
             let src = self.head.e.get_reg(srcreg);
-            match (dst, src) {
-                (&Val::Future(_), _) => {
-                    // oops, not ready to do this yet, let's bail and wait
-                    return Event::FutureWait(dstreg.clone());
-                }
-                (_, &Val::Future(_)) => {
-                    // oops, not ready to do this yet, let's bail and wait
-                    return Event::FutureWait(srcreg.clone());
-                }
-                _ => Val::Str(Lstr::from(format!("{}{}", dst, src))),
-            }
+            Val::Str(Lstr::from(format!("{}{}", dst, src)))
         };
         self.head.e.set_reg(dstreg, result);
         self.head.pc += 1;
@@ -192,49 +275,25 @@ impl Fiber
         dst: &Reg,
         freg: &Reg,
         argreg: &Reg,
-        line: i16,
+        loc: SrcLoc,
     ) -> Event
     {
-        let (modname, funcname) = {
-            let ref fname_val = self.head.e.get_reg(freg);
-            match *fname_val {
-                &Val::Str(ref name_str) => {
-                    // pass in args
-                    println!("found a string for function call: {}", name_str);
-                    (Lstr::Sref(""), name_str.clone())
-                }
-                &Val::Tuple(ref modfunc) if modfunc.0.len() == 2 => {
-                    println!("found tuple for func call: {:?}", modfunc);
-                    let modnm = &modfunc.0.get(0).unwrap().1;
-                    let funcnm = &modfunc.0.get(1).unwrap().1;
-                    match (modnm, funcnm) {
-                        (&Val::Str(ref m), &Val::Str(ref f)) => {
-                            (m.clone(), f.clone())
-                        }
-                        _ => {
-                            panic!("That's not a function! {:?}", fname_val);
-                        }
-                    }
-                }
-                &Val::FuncRef(ref callri, _) => {
-                    (callri.mod_ref().unwrap().clone(), callri.localid.clone())
-                }
-                _ => {
-                    panic!("That's not a function! {:?}", fname_val);
-                }
-            }
-        };
+        let (modname, funcname) = self.call_func_name(freg);
         vout!("execute_call({}::{})\n", modname, funcname);
 
-        let opt_failure = Fiber::call_arg_failure(self.head.e.get_reg(argreg))
-            .map(|argv| argv.clone());
+        let opt_failure = Fiber::call_arg_failure(
+            self.head.e.get_reg(freg),
+            self.head.e.get_reg(argreg),
+        ).map(|argv| argv.clone());
         match opt_failure {
             Some(mut failur) => {
                 if let &mut Val::Failure(_, _, ref mut trace, _) = &mut failur {
                     *trace = FrameTrace::propagate_down(
                         trace,
+                        &self.head.module,
                         &self.head.function,
-                        0,
+                        &self.head.file,
+                        loc,
                     );
                 }
                 self.head.parent.set_result(failur);
@@ -242,7 +301,81 @@ impl Fiber
             }
             None => {
                 let args_copy = self.head.e.get_reg(argreg).clone();
-                Event::Call(dst.clone(), line, modname, funcname, args_copy)
+                Event::Call(dst.clone(), loc, modname, funcname, args_copy)
+            }
+        }
+    }
+
+    /**
+     * start a new fiber for the call and set dst to a future
+     * for its result. the current fiber keeps going until it
+     * reads the future.
+     */
+    pub fn execute_fork(
+        &mut self,
+        dst: &Reg,
+        freg: &Reg,
+        argreg: &Reg,
+        loc: SrcLoc,
+    ) -> Event
+    {
+        let (modname, funcname) = self.call_func_name(freg);
+        vout!("execute_fork({}::{})\n", modname, funcname);
+
+        let fv = FutureVal::new();
+        let args = self.head.e.get_reg(argreg).clone();
+        let opt_failure =
+            Fiber::call_arg_failure(self.head.e.get_reg(freg), &args)
+                .map(|f| f.clone());
+        if let Some(failur) = opt_failure {
+            // the call would fail right away, so skip the fiber
+            fv.set(&failur);
+            self.head.e.set_reg(dst, Val::future(fv));
+            self.head.pc += 1;
+            return Event::Uneventful;
+        }
+
+        let forked = Frame {
+            parent: Parent::Fork(fv.clone(), Val::Void),
+            module: modname,
+            function: funcname,
+            file: Lstr::empty(),
+            trace: self.head.push_frame_trace(loc),
+            e: Env::with_args(args),
+            pc: 0,
+        };
+        self.head.e.set_reg(dst, Val::future(fv));
+        self.head.pc += 1;
+        Event::Fork(forked)
+    }
+
+    fn call_func_name(&self, freg: &Reg) -> (Lstr, Lstr)
+    {
+        let ref fname_val = self.head.e.get_reg(freg);
+        match *fname_val {
+            &Val::Str(ref name_str) => {
+                // pass in args
+                println!("found a string for function call: {}", name_str);
+                (Lstr::Sref(""), name_str.clone())
+            }
+            &Val::Tuple(ref modfunc) if modfunc.0.len() == 2 => {
+                println!("found tuple for func call: {:?}", modfunc);
+                let modnm = &modfunc.0.get(0).unwrap().1;
+                let funcnm = &modfunc.0.get(1).unwrap().1;
+                match (modnm, funcnm) {
+                    (&Val::Str(ref m), &Val::Str(ref f)) => {
+                        (m.clone(), f.clone())
+                    }
+                    _ => {
+                        panic!("That's not a function! {:?}", fname_val);
+                    }
+                }
+            }
+            &Val::FuncRef(ref callri, _) => {
+                (callri.mod_ref().unwrap().clone(), callri.localid.clone())
+            }
+            _ => {
+                panic!("That's not a function! {:?}", fname_val);
             }
         }
     }
@@ -381,12 +514,98 @@ impl Fiber
         Event::Uneventful
     }
 
+    /**
+     * replace futures in the registers an op reads w/ their results
+     *
+     * returns an event if the op can't run yet, either b/c a future
+     * isn't ready or b/c the forked call failed. failures go to
+     * the failure ops as values so they can still be handled.
+     */
+    fn resolve_futures(&mut self, op: &Op, line: i16) -> Option<Event>
+    {
+        let (regs, propagate): (Vec<Reg>, bool) = match op {
+            &Op::ApplyFunc(_, ref f, ref args, _)
+            | &Op::Fork(_, ref f, ref args, _) => {
+                let mut regs = vec![f.clone()];
+                regs.extend(self.arg_regs(args));
+                (regs, true)
+            }
+            &Op::SetResult(ref src) => (vec![src.clone()], true),
+            &Op::JumpIfNot(_, ref tst) => (vec![tst.clone()], true),
+            &Op::MatchPattern(_, _, ref input) => (vec![input.clone()], true),
+            &Op::ListCons(_, ref head, ref tail) => {
+                (vec![head.clone(), tail.clone()], true)
+            }
+            &Op::StrCat(ref dst, ref src) => {
+                (vec![dst.clone(), src.clone()], true)
+            }
+            &Op::IfFailure(ref src, _) => (vec![src.clone()], false),
+            &Op::PropagateFailure(ref src, _) => (vec![src.clone()], false),
+            _ => (vec![], false),
+        };
+        for r in regs.iter() {
+            let opt_ev = self.resolve_future(r, line, propagate);
+            if opt_ev.is_some() {
+                return opt_ev;
+            }
+        }
+        None
+    }
+
+    fn resolve_future(
+        &mut self,
+        reg: &Reg,
+        line: i16,
+        propagate: bool,
+    ) -> Option<Event>
+    {
+        let fv = match reg {
+            &Reg::Param(_) | &Reg::Local(_) => {
+                match self.head.e.get_reg(reg) {
+                    &Val::Future(ref fv) => fv.clone(),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        match fv.get() {
+            None => Some(Event::FutureWait(reg.clone())),
+            Some(result) => {
+                let failed = result.is_failure();
+                self.head.e.set_reg(reg, result);
+                if failed && propagate {
+                    Some(self.propagate_failure(reg, line))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn arg_regs(&self, args: &Reg) -> Vec<Reg>
+    {
+        if *args == Reg::Void {
+            return vec![];
+        }
+        match self.head.e.get_reg(args) {
+            &Val::Tuple(ref items) => {
+                (0..items.0.len()).map(|i| args.sub(i as i8)).collect()
+            }
+            _ => vec![],
+        }
+    }
+
     pub fn propagate_failure(&mut self, src: &Reg, line: i16) -> Event
     {
         let srcval = self.head.e.get_reg(src);
         if let &Val::Failure(ref tag, ref msg, ref trace, status) = srcval {
-            let new_trace =
-                FrameTrace::propagate_down(trace, &self.head.function, line);
+            let new_trace = FrameTrace::propagate_down(
+                trace,
+                &self.head.module,
+                &self.head.function,
+                &self.head.file,
+                SrcLoc::new(line, 0),
+            );
             let new_fail =
                 Val::Failure(tag.clone(), msg.clone(), new_trace, status);
             self.head.parent.set_result(new_fail);
@@ -396,11 +615,24 @@ impl Fiber
         }
     }
 
-    fn call_arg_failure(args: &Val) -> Option<&Val>
+    /**
+     * find a failed arg that should fail the call before it starts
+     *
+     * params declared as Failure take failures as regular values
+     */
+    fn call_arg_failure<'a>(func: &Val, args: &'a Val) -> Option<&'a Val>
     {
+        let param_types = match func {
+            &Val::FuncRef(_, Type::Func(ref params, _)) => Some(params),
+            _ => None,
+        };
         if let &Val::Tuple(ref items) = args {
-            for i in items.0.iter() {
-                if i.1.is_failure() {
+            for (idx, i) in items.0.iter().enumerate() {
+                let takes_failure = param_types
+                    .and_then(|p| p.get(idx))
+                    .map(|t| *t == Type::Failure)
+                    .unwrap_or(false);
+                if i.1.is_failure() && !takes_failure {
                     return Some(&i.1);
                 }
             }
@@ -411,15 +643,106 @@ impl Fiber
     }
 }
 
+/**
+ * A fiber on its way from one worker to another
+ *
+ * The Rc code refs in the call stack can't cross threads, so each
+ * caller's code travels by value next to its frame. The receiving
+ * worker swaps in its own copy of the code if it already has one.
+ */
+pub struct MsgFiber
+{
+    head_code: Option<Code>,
+    // frames from the head down to the root
+    frames: Vec<Frame>,
+    // code and result reg in the caller for all but the root frame
+    callers: Vec<(Code, Reg)>,
+    alive: Option<Arc<AtomicBool>>,
+    pid: Option<Pid>,
+}
+
+// like MsgVal, pack copies everything in the frames that could be
+// shared w/ the old worker, except the Arcs that are Send + Sync
+unsafe impl Send for MsgFiber {}
+
+impl MsgFiber
+{
+    pub fn module_name(&self) -> &Lstr
+    {
+        &self.frames[0].module
+    }
+
+    pub fn function_name(&self) -> &Lstr
+    {
+        &self.frames[0].function
+    }
+
+    /**
+     * Rebuild the fiber w/ a new id on its new worker
+     *
+     * share_code takes the module and function names and the code
+     * that came w/ the fiber and returns the Rc for it to use.
+     * Returns the head code if it had been loaded.
+     */
+    pub fn unpack<F>(
+        self,
+        fiber_id: i64,
+        mut share_code: F,
+    ) -> (Fiber, Option<Rc<Code>>)
+    where
+        F: FnMut(&Lstr, &Lstr, Code) -> Rc<Code>,
+    {
+        let MsgFiber {
+            head_code,
+            mut frames,
+            mut callers,
+            alive,
+            pid,
+        } = self;
+        let mut frame = frames.pop().unwrap();
+        while let Some(mut callee) = frames.pop() {
+            let (code, dst) = callers.pop().unwrap();
+            let rc_code = share_code(&frame.module, &frame.function, code);
+            callee.set_parent(Parent::Caller(rc_code, Box::new(frame), dst));
+            frame = callee;
+        }
+        let rc_head = head_code
+            .map(|code| share_code(&frame.module, &frame.function, code));
+        let mut fib = Fiber::spawn(fiber_id, frame);
+        fib.alive = alive;
+        fib.pid = pid;
+        (fib, rc_head)
+    }
+}
+
+impl fmt::Debug for MsgFiber
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(
+            f,
+            "MsgFiber({}::{}, depth {})",
+            self.module_name(),
+            self.function_name(),
+            self.frames.len()
+        )
+    }
+}
+
 
 #[cfg(test)]
 mod tests
 {
+    use leema::code::{Code, Op};
     use leema::fiber::Fiber;
     use leema::frame::{Event, Frame, Parent};
     use leema::lstr::Lstr;
     use leema::reg::Reg;
-    use leema::val::Val;
+    use leema::struple::Struple;
+    use leema::val::{SrcLoc, Val};
+
+    use std::rc::Rc;
+    use std::sync::Arc;
 
 
     #[test]
@@ -438,4 +761,65 @@ mod tests
         assert_eq!(Event::Uneventful, event);
     }
 
+    #[test]
+    fn test_pack_unpack_call_stack()
+    {
+        let main_parent = Parent::new_main();
+        let frame =
+            Frame::new_root(main_parent, Lstr::Sref("foo"), Lstr::Sref("main"));
+        let mut fib = Fiber::spawn(1, frame);
+        let main_code = Rc::new(Code::Leema(vec![(Op::Return, 1)]));
+        fib.push_call(
+            main_code,
+            Reg::local(2),
+            SrcLoc::new(3, 5),
+            Lstr::Sref("foo"),
+            Lstr::Sref("bar"),
+            Val::Tuple(Struple(vec![])),
+        );
+        let bar_code = Code::Leema(vec![(Op::Return, 4)]);
+
+        let packed = fib.pack(Some(&bar_code));
+        assert_eq!("bar", packed.function_name().str());
+
+        let mut shared = vec![];
+        let (unpacked, head_code) = packed.unpack(7, |m, f, code| {
+            shared.push(format!("{}::{}", m, f));
+            Rc::new(code)
+        });
+        assert_eq!(7, unpacked.fiber_id);
+        assert_eq!("bar", unpacked.function_name().str());
+        assert!(head_code.is_some());
+        assert_eq!(vec!["foo::main", "foo::bar"], shared);
+        match unpacked.head.parent {
+            Parent::Caller(_, ref pf, ref dst) => {
+                assert_eq!("main", pf.function.str());
+                assert_eq!(Reg::local(2), *dst);
+                assert!(match pf.parent {
+                    Parent::Main(_) => true,
+                    _ => false,
+                });
+            }
+            _ => panic!("expected a caller parent"),
+        }
+    }
+
+    #[test]
+    fn test_pack_copies_frame_values()
+    {
+        let mut frame =
+            Frame::new_root(Parent::Null, Lstr::Sref("foo"), Lstr::Sref("a"));
+        frame.e.set_reg(&Reg::local(1), Val::Buffer(vec![3, 4]));
+        let trace = frame.trace.clone();
+        let fib = Fiber::spawn(1, frame);
+
+        let (unpacked, _) = fib.pack(None).unpack(2, |_, _, c| Rc::new(c));
+        assert_eq!(
+            Val::Buffer(vec![3, 4]),
+            *unpacked.head.e.get_reg(&Reg::local(1))
+        );
+        // the trace was copied, not shared w/ the old worker
+        assert!(!Arc::ptr_eq(&trace, &unpacked.head.trace));
+    }
+
 }
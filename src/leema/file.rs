@@ -1,35 +1,320 @@
 use leema::code::Code;
+use leema::list;
 use leema::log;
 use leema::lstr::Lstr;
-use leema::rsrc;
-use leema::val::Val;
+use leema::rsrc::{self, Rsrc};
+use leema::struple::Struple;
+use leema::val::{self, Type, Val};
 
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::time::UNIX_EPOCH;
 
 
-pub fn file_open(_ctx: rsrc::IopCtx) -> rsrc::Event
+/**
+ * An open file, buffered so lines can be read one at a time
+ */
+#[derive(Debug)]
+pub struct LeemaFile
 {
-    vout!("file_open()\n");
-    rsrc::Event::Result(Val::Void, None)
+    f: BufReader<File>,
 }
 
-pub fn file_read_file(mut ctx: rsrc::IopCtx) -> rsrc::Event
+impl Rsrc for LeemaFile
 {
-    vout!("file_read_file()\n");
+    fn get_type(&self) -> Type
+    {
+        Type::Resource(Lstr::Sref("File"))
+    }
+}
+
+/**
+ * Convert an io error to a failure w/ a #file_* tag
+ */
+fn file_failure(action: &str, path: &str, e: io::Error) -> Val
+{
+    let (tag, status) = match e.kind() {
+        io::ErrorKind::NotFound => ("file_not_found", val::FAILURE_NOENTRY),
+        io::ErrorKind::PermissionDenied => {
+            ("file_permission_denied", val::FAILURE_UNAUTHORIZED)
+        }
+        io::ErrorKind::AlreadyExists => ("file_exists", val::FAILURE_BADINPUT),
+        io::ErrorKind::InvalidInput => {
+            ("file_invalid_input", val::FAILURE_BADINPUT)
+        }
+        io::ErrorKind::InvalidData => {
+            ("file_invalid_data", val::FAILURE_BADINPUT)
+        }
+        _ => ("file_error", val::FAILURE_INTERNAL),
+    };
+    rsrc::iop_failure(tag, format!("{} {}: {}", action, path, e), status)
+}
+
+fn file_result(rsrc: LeemaFile, result: Val) -> rsrc::Event
+{
+    rsrc::Event::Result(result, Some(Box::new(rsrc)))
+}
+
+fn void_result(action: &str, path: &str, r: io::Result<()>) -> rsrc::Event
+{
+    let result = match r {
+        Ok(()) => Val::Void,
+        Err(e) => file_failure(action, path, e),
+    };
+    rsrc::Event::Result(result, None)
+}
+
+fn str_result(action: &str, bytes: Vec<u8>) -> Val
+{
+    match String::from_utf8(bytes) {
+        Ok(s) => Val::Str(Lstr::from(s)),
+        Err(e) => {
+            let err = io::Error::new(io::ErrorKind::InvalidData, e);
+            file_failure(action, "file", err)
+        }
+    }
+}
+
+fn eof_failure() -> Val
+{
+    rsrc::iop_failure(
+        "file_eof",
+        "end of file".to_string(),
+        val::FAILURE_NOENTRY,
+    )
+}
+
+/**
+ * file::open(path: Str, mode: #): File
+ *
+ * mode is one of #read, #write, #append or #read_write.
+ * #write truncates the file and #append writes to the end,
+ * both create the file if it doesn't exist.
+ */
+pub fn file_open(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_open()\n");
     let pathval = ctx.take_param(0).unwrap();
-    let path = Path::new(pathval.str());
-    let mut f = File::open(path).unwrap();
-    let mut s = String::new();
-    f.read_to_string(&mut s).expect("read_to_string failure");
-    rsrc::Event::Result(Val::Str(Lstr::from(s)), None)
+    let modeval = ctx.take_param(1).unwrap();
+    let mut opts = OpenOptions::new();
+    match modeval {
+        Val::Hashtag(ref m) if m.str() == "read" => opts.read(true),
+        Val::Hashtag(ref m) if m.str() == "write" => {
+            opts.write(true).create(true).truncate(true)
+        }
+        Val::Hashtag(ref m) if m.str() == "append" => {
+            opts.append(true).create(true)
+        }
+        Val::Hashtag(ref m) if m.str() == "read_write" => {
+            opts.read(true).write(true).create(true)
+        }
+        _ => {
+            let failure = rsrc::iop_failure(
+                "file_invalid_mode",
+                format!("unknown file mode: {}", modeval),
+                val::FAILURE_BADINPUT,
+            );
+            return rsrc::Event::Result(failure, None);
+        }
+    };
+    match opts.open(Path::new(pathval.str())) {
+        Ok(f) => {
+            let lf = LeemaFile {
+                f: BufReader::new(f),
+            };
+            rsrc::Event::NewRsrc(Box::new(lf), None)
+        }
+        Err(e) => {
+            let failure = file_failure("open", pathval.str(), e);
+            rsrc::Event::Result(failure, None)
+        }
+    }
+}
+
+/**
+ * file::read_line(f: File): Str
+ *
+ * read the next line w/o its newline, fails w/ #file_eof
+ * at the end of the file
+ */
+pub fn file_read_line(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_read_line()\n");
+    let mut lf: LeemaFile = ctx.take_rsrc();
+    let mut line = vec![];
+    let result = match lf.f.read_until(b'\n', &mut line) {
+        Ok(0) => eof_failure(),
+        Ok(_) => {
+            if line.last() == Some(&b'\n') {
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+            }
+            str_result("read line from", line)
+        }
+        Err(e) => file_failure("read line from", "file", e),
+    };
+    file_result(lf, result)
+}
+
+/**
+ * file::read(f: File, max_bytes: Int): Str
+ *
+ * read the next chunk of at most max_bytes, fails w/ #file_eof
+ * at the end of the file
+ */
+pub fn file_read(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_read()\n");
+    let mut lf: LeemaFile = ctx.take_rsrc();
+    let max_bytes = ctx.take_param(1).unwrap().to_int().max(0) as usize;
+    let result = match read_chunk(&mut lf, max_bytes) {
+        Ok(chunk) => str_result("read from", chunk),
+        Err(failure) => failure,
+    };
+    file_result(lf, result)
+}
+
+/**
+ * file::read_buffer(f: File, max_bytes: Int): Buffer
+ *
+ * like read but the bytes aren't decoded
+ */
+pub fn file_read_buffer(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_read_buffer()\n");
+    let mut lf: LeemaFile = ctx.take_rsrc();
+    let max_bytes = ctx.take_param(1).unwrap().to_int().max(0) as usize;
+    let result = match read_chunk(&mut lf, max_bytes) {
+        Ok(chunk) => Val::Buffer(chunk),
+        Err(failure) => failure,
+    };
+    file_result(lf, result)
+}
+
+fn read_chunk(lf: &mut LeemaFile, max_bytes: usize) -> Result<Vec<u8>, Val>
+{
+    let mut chunk = vec![0; max_bytes];
+    match lf.f.read(&mut chunk) {
+        Ok(0) if max_bytes > 0 => Err(eof_failure()),
+        Ok(n) => {
+            chunk.truncate(n);
+            Ok(chunk)
+        }
+        Err(e) => Err(file_failure("read from", "file", e)),
+    }
 }
 
-pub fn file_write(_ctx: rsrc::IopCtx) -> rsrc::Event
+/**
+ * file::read_all(f: File): Str
+ *
+ * read everything from the current position to the end
+ */
+pub fn file_read_all(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_read_all()\n");
+    let mut lf: LeemaFile = ctx.take_rsrc();
+    let mut bytes = vec![];
+    let result = match lf.f.read_to_end(&mut bytes) {
+        Ok(_) => str_result("read from", bytes),
+        Err(e) => file_failure("read from", "file", e),
+    };
+    file_result(lf, result)
+}
+
+/**
+ * file::write(f: File, data: Str): Int
+ * file::write_buffer(f: File, data: Buffer): Int
+ *
+ * returns the number of bytes written
+ */
+pub fn file_write(mut ctx: rsrc::IopCtx) -> rsrc::Event
 {
     vout!("file_write()\n");
-    rsrc::Event::Result(Val::Void, None)
+    let mut lf: LeemaFile = ctx.take_rsrc();
+    let data = ctx.take_param(1).unwrap();
+    let bytes = data.as_bytes();
+    // seek to where reading left off before writing past the buffer
+    let written = lf
+        .f
+        .seek(SeekFrom::Current(0))
+        .and_then(|_| lf.f.get_mut().write_all(bytes));
+    let result = match written {
+        Ok(()) => Val::Int(bytes.len() as i64),
+        Err(e) => file_failure("write to", "file", e),
+    };
+    file_result(lf, result)
+}
+
+/**
+ * file::seek(f: File, offset: Int, from: #): Int
+ *
+ * from is one of #start, #current or #end,
+ * returns the new position from the start of the file
+ */
+pub fn file_seek(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_seek()\n");
+    let mut lf: LeemaFile = ctx.take_rsrc();
+    let offset = ctx.take_param(1).unwrap().to_int();
+    let fromval = ctx.take_param(2).unwrap();
+    let pos = match fromval {
+        Val::Hashtag(ref w) if w.str() == "start" && offset >= 0 => {
+            Some(SeekFrom::Start(offset as u64))
+        }
+        Val::Hashtag(ref w) if w.str() == "current" => {
+            Some(SeekFrom::Current(offset))
+        }
+        Val::Hashtag(ref w) if w.str() == "end" => Some(SeekFrom::End(offset)),
+        _ => None,
+    };
+    let result = match pos {
+        Some(p) => {
+            // seeking the BufReader also drops its buffer
+            match lf.f.seek(p) {
+                Ok(newpos) => Val::Int(newpos as i64),
+                Err(e) => file_failure("seek in", "file", e),
+            }
+        }
+        None => {
+            rsrc::iop_failure(
+                "file_invalid_seek",
+                format!("cannot seek {} from {}", offset, fromval),
+                val::FAILURE_BADINPUT,
+            )
+        }
+    };
+    file_result(lf, result)
+}
+
+/**
+ * file::close(f: File): Void
+ *
+ * flush and close the file, later operations on it will fail
+ */
+pub fn file_close(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_close()\n");
+    let mut lf: LeemaFile = ctx.take_rsrc();
+    let result = match lf.f.get_mut().sync_all() {
+        Ok(()) => Val::Void,
+        Err(e) => file_failure("close", "file", e),
+    };
+    rsrc::Event::DropRsrc(result)
+}
+
+pub fn file_read_file(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_read_file()\n");
+    let pathval = ctx.take_param(0).unwrap();
+    let path = pathval.str();
+    let result = match fs::read(Path::new(path)) {
+        Ok(bytes) => str_result("read", bytes),
+        Err(e) => file_failure("read", path, e),
+    };
+    rsrc::Event::Result(result, None)
 }
 
 pub fn file_write_file(mut ctx: rsrc::IopCtx) -> rsrc::Event
@@ -37,22 +322,135 @@ pub fn file_write_file(mut ctx: rsrc::IopCtx) -> rsrc::Event
     vout!("file_write_file()\n");
     let pathval = ctx.take_param(0).unwrap();
     let output = ctx.take_param(1).unwrap();
+    let path = pathval.str();
+    let r = fs::write(Path::new(path), output.str().as_bytes());
+    void_result("write", path, r)
+}
+
+pub fn file_exists(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_exists()\n");
+    let pathval = ctx.take_param(0).unwrap();
+    let exists = Path::new(pathval.str()).exists();
+    rsrc::Event::Result(Val::Bool(exists), None)
+}
+
+/**
+ * file::stat(path: Str): (Int, Bool, Int)
+ *
+ * the size in bytes, whether it's a directory and when it was
+ * last modified in seconds since the epoch
+ */
+pub fn file_stat(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_stat()\n");
+    let pathval = ctx.take_param(0).unwrap();
+    let path = pathval.str();
+    let result = match fs::metadata(Path::new(path)) {
+        Ok(meta) => {
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            Val::Tuple(Struple::new_indexed(vec![
+                Val::Int(meta.len() as i64),
+                Val::Bool(meta.is_dir()),
+                Val::Int(modified),
+            ]))
+        }
+        Err(e) => file_failure("stat", path, e),
+    };
+    rsrc::Event::Result(result, None)
+}
+
+/**
+ * file::list_dir(path: Str): [Str]
+ *
+ * the names of the entries in the directory, sorted
+ */
+pub fn file_list_dir(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_list_dir()\n");
+    let pathval = ctx.take_param(0).unwrap();
+    let path = pathval.str();
+    let entries = fs::read_dir(Path::new(path)).and_then(|dir| {
+        dir.map(|entry| {
+            entry.map(|e| e.file_name().to_string_lossy().into_owned())
+        }).collect::<io::Result<Vec<String>>>()
+    });
+    let result = match entries {
+        Ok(mut names) => {
+            names.sort();
+            let items: Vec<Val> =
+                names.into_iter().map(|n| Val::Str(Lstr::from(n))).collect();
+            list::from_vec(&items)
+        }
+        Err(e) => file_failure("list", path, e),
+    };
+    rsrc::Event::Result(result, None)
+}
+
+/**
+ * file::mkdir(path: Str): Void
+ *
+ * create the directory and any missing parents
+ */
+pub fn file_mkdir(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_mkdir()\n");
+    let pathval = ctx.take_param(0).unwrap();
+    let path = pathval.str();
+    void_result("mkdir", path, fs::create_dir_all(Path::new(path)))
+}
+
+/**
+ * file::remove(path: Str): Void
+ *
+ * remove a file or an empty directory
+ */
+pub fn file_remove(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_remove()\n");
+    let pathval = ctx.take_param(0).unwrap();
     let path = Path::new(pathval.str());
-    let mut f = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(path)
-        .unwrap();
-    f.write_all(output.str().as_bytes())
-        .expect("write_all failure");
-    rsrc::Event::Result(Val::Void, None)
+    let r = if path.is_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    };
+    void_result("remove", pathval.str(), r)
+}
+
+pub fn file_rename(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("file_rename()\n");
+    let fromval = ctx.take_param(0).unwrap();
+    let toval = ctx.take_param(1).unwrap();
+    let r = fs::rename(Path::new(fromval.str()), Path::new(toval.str()));
+    void_result("rename", fromval.str(), r)
 }
 
 pub fn load_rust_func(func_name: &str) -> Option<Code>
 {
     match func_name {
+        "close" => Some(Code::Iop(file_close, Some(0))),
+        "exists" => Some(Code::Iop(file_exists, None)),
+        "list_dir" => Some(Code::Iop(file_list_dir, None)),
+        "mkdir" => Some(Code::Iop(file_mkdir, None)),
+        "open" => Some(Code::Iop(file_open, None)),
+        "read" => Some(Code::Iop(file_read, Some(0))),
+        "read_all" => Some(Code::Iop(file_read_all, Some(0))),
+        "read_buffer" => Some(Code::Iop(file_read_buffer, Some(0))),
         "read_file" => Some(Code::Iop(file_read_file, None)),
+        "read_line" => Some(Code::Iop(file_read_line, Some(0))),
+        "remove" => Some(Code::Iop(file_remove, None)),
+        "rename" => Some(Code::Iop(file_rename, None)),
+        "seek" => Some(Code::Iop(file_seek, Some(0))),
+        "stat" => Some(Code::Iop(file_stat, None)),
+        "write" => Some(Code::Iop(file_write, Some(0))),
+        "write_buffer" => Some(Code::Iop(file_write, Some(0))),
         "write_file" => Some(Code::Iop(file_write_file, None)),
         _ => None,
     }
@@ -1,13 +1,19 @@
 use leema::code::Code;
+use leema::list;
 use leema::lstr::Lstr;
+use leema::mailbox::{Mailbox, Pid};
 use leema::reg::{Ireg, Reg};
 use leema::rsrc;
-use leema::val::{Env, Val};
+use leema::sendclone::SendClone;
+use leema::struple::Struple;
+use leema::supervisor::ChildRef;
+use leema::val::{Env, FutureVal, SrcLoc, Val};
 
 use std::fmt::{self, Debug};
 use std::mem;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Instant;
 
 use futures::sync::oneshot::Sender as FutureSender;
 
@@ -16,7 +22,9 @@ pub enum Parent
 {
     Null,
     Caller(Rc<Code>, Box<Frame>, Reg),
-    // Fork(Arc<AtomicBool>, mpsc::Sender<Msg>),
+    Fork(FutureVal, Val),
+    Supervised(ChildRef, Val),
+    Process(Pid),
     Future(FutureSender<Val>, Val),
     Repl(Val),
     Main(Val),
@@ -40,7 +48,12 @@ impl Parent
             &mut Parent::Caller(_, ref mut pf, ref dst) => {
                 pf.e.set_reg(dst, r);
             }
-            // &mut Parent::Fork(_, _) => {}
+            &mut Parent::Fork(_, ref mut res) => {
+                *res = r;
+            }
+            &mut Parent::Supervised(_, ref mut res) => {
+                *res = r;
+            }
             &mut Parent::Main(ref mut res) => {
                 *res = r;
             }
@@ -48,6 +61,7 @@ impl Parent
                 *res = r;
             }
             &mut Parent::Null => {}
+            &mut Parent::Process(_) => {}
             &mut Parent::Future(_, ref mut dst) => {
                 *dst = r;
             }
@@ -64,11 +78,15 @@ impl Debug for Parent
             &Parent::Caller(ref code, ref pf, ref dst) => {
                 write!(f, "Parent::Caller({:?}, {}, {:?})", dst, code, pf)
             }
-            /*
-            &Parent::Fork(ref ready, _) => {
-                write!(f, "Parent::Fork({:?})", ready)
+            &Parent::Fork(ref fv, ref res) => {
+                write!(f, "Parent::Fork({:?}, {:?})", fv, res)
+            }
+            &Parent::Supervised(ref child, ref res) => {
+                write!(f, "Parent::Supervised({:?}, {:?})", child, res)
+            }
+            &Parent::Process(ref pid) => {
+                write!(f, "Parent::Process({:?})", pid)
             }
-            */
             &Parent::Repl(ref res) => write!(f, "Parent::Repl({:?})", res),
             &Parent::Main(ref res) => write!(f, "Parent::Main({:?})", res),
             &Parent::Future(_, ref res) => {
@@ -81,9 +99,14 @@ impl Debug for Parent
 pub enum Event
 {
     Uneventful,
-    Call(Reg, i16, Lstr, Lstr, Val),
-    Fork,
+    Call(Reg, SrcLoc, Lstr, Lstr, Val),
+    Fork(Frame),
     FutureWait(Reg),
+    // Receive(mailbox, pattern, deadline)
+    Receive(Arc<Mailbox>, Val, Option<Instant>),
+    // WakeReceiver(worker_id, fiber_id)
+    WakeReceiver(i64, i64),
+    Yield,
     IOWait,
     Iop((i64, i64), rsrc::IopAction, Vec<Val>),
     // IoFuture(Box<future::Future<Item=(), Error=()>>),
@@ -111,15 +134,24 @@ impl fmt::Debug for Event
     {
         match self {
             &Event::Uneventful => write!(f, "Uneventful"),
-            &Event::Call(ref r, line, ref cmod, ref cfunc, ref cargs) => {
+            &Event::Call(ref r, loc, ref cmod, ref cfunc, ref cargs) => {
                 write!(
                     f,
-                    "Event::Call({:?}@{}, {}::{}, {:?})",
-                    r, line, cmod, cfunc, cargs
+                    "Event::Call({:?}@{}:{}, {}::{}, {:?})",
+                    r, loc.lineno, loc.column, cmod, cfunc, cargs
                 )
             }
-            &Event::Fork => write!(f, "Event::Fork"),
+            &Event::Fork(ref fr) => {
+                write!(f, "Event::Fork({}::{})", fr.module, fr.function)
+            }
             &Event::FutureWait(ref r) => write!(f, "Event::FutureWait({})", r),
+            &Event::Receive(_, ref patt, ref deadline) => {
+                write!(f, "Event::Receive({:?}, {:?})", patt, deadline)
+            }
+            &Event::WakeReceiver(wid, fid) => {
+                write!(f, "Event::WakeReceiver({}, {})", wid, fid)
+            }
+            &Event::Yield => write!(f, "Event::Yield"),
             &Event::IOWait => write!(f, "Event::IOWait"),
             &Event::Iop(wrid, _, ref iopargs) => {
                 write!(f, "Event::Iop({:?}, f, {:?})", wrid, iopargs)
@@ -141,10 +173,10 @@ impl PartialEq for Event
                 &Event::Call(ref r1, line1, ref m1, ref f1, ref a1),
                 &Event::Call(ref r2, line2, ref m2, ref f2, ref a2),
             ) => r1 == r2 && line1 == line2 && m1 == m2 && f1 == f2 && a1 == a2,
-            (&Event::Fork, &Event::Fork) => true,
             (&Event::FutureWait(ref r1), &Event::FutureWait(ref r2)) => {
                 r1 == r2
             }
+            (&Event::Yield, &Event::Yield) => true,
             (&Event::IOWait, &Event::IOWait) => true,
             (&Event::Success, &Event::Success) => true,
             (&Event::Failure, &Event::Failure) => true,
@@ -153,7 +185,10 @@ impl PartialEq for Event
     }
 }
 
+#[derive(Clone)]
+#[derive(Copy)]
 #[derive(Debug)]
+#[derive(PartialEq)]
 pub enum FrameTraceDirection
 {
     CallUp,
@@ -161,13 +196,22 @@ pub enum FrameTraceDirection
     ReturnDown,
 }
 
+/**
+ * A node in the trace of calls that led to a failure
+ *
+ * Each node is a location in some function. Calls push CallUp nodes
+ * for the call site in the caller, a failure marks the top node as
+ * FailHere and propagating the failure pushes ReturnDown nodes on
+ * top of that.
+ */
 #[derive(Debug)]
 pub struct FrameTrace
 {
-    // TODO: Implement this in leema later
     direction: FrameTraceDirection,
+    module: Lstr,
     function: Lstr,
-    line: i16,
+    file: Lstr,
+    loc: SrcLoc,
     parent: Option<Arc<FrameTrace>>,
 }
 
@@ -177,36 +221,46 @@ impl FrameTrace
     {
         Arc::new(FrameTrace {
             direction: FrameTraceDirection::CallUp,
+            module: Lstr::Sref(""),
             function: Lstr::Sref("__init__"),
-            line: 0,
+            file: Lstr::empty(),
+            loc: SrcLoc::default(),
             parent: None,
         })
     }
 
     pub fn push_call(
         parent: &Arc<FrameTrace>,
+        module: &Lstr,
         func: &Lstr,
-        line: i16,
+        file: &Lstr,
+        loc: SrcLoc,
     ) -> Arc<FrameTrace>
     {
         Arc::new(FrameTrace {
             direction: FrameTraceDirection::CallUp,
+            module: module.clone(),
             function: func.clone(),
-            line,
+            file: file.clone(),
+            loc,
             parent: Some(parent.clone()),
         })
     }
 
     pub fn propagate_down(
         trace: &Arc<FrameTrace>,
+        module: &Lstr,
         func: &Lstr,
-        line: i16,
+        file: &Lstr,
+        loc: SrcLoc,
     ) -> Arc<FrameTrace>
     {
         Arc::new(FrameTrace {
             direction: FrameTraceDirection::ReturnDown,
+            module: module.clone(),
             function: func.clone(),
-            line,
+            file: file.clone(),
+            loc,
             parent: Some(trace.clone()),
         })
     }
@@ -215,31 +269,159 @@ impl FrameTrace
     {
         Arc::new(FrameTrace {
             direction: FrameTraceDirection::FailHere,
+            module: self.module.clone(),
             function: self.function.clone(),
-            line: self.line,
+            file: self.file.clone(),
+            loc: self.loc,
             parent: self.parent.clone(),
         })
     }
+
+    pub fn module(&self) -> &Lstr
+    {
+        &self.module
+    }
+
+    pub fn function(&self) -> &Lstr
+    {
+        &self.function
+    }
+
+    pub fn loc(&self) -> SrcLoc
+    {
+        self.loc
+    }
+
+    /**
+     * Source file for this frame's module, the path the loader read
+     * it from. Frames w/o a recorded path fall back to the module's
+     * relative path, net::http is in net/http.lma.
+     */
+    pub fn file(&self) -> String
+    {
+        let file = String::from(&self.file);
+        if !file.is_empty() {
+            return file;
+        }
+        let path: Vec<&str> = self
+            .module
+            .str()
+            .split("::")
+            .flat_map(|s| s.split('.'))
+            .collect();
+        format!("{}.lma", path.join("/"))
+    }
+
+    fn same_place(&self, other: &FrameTrace) -> bool
+    {
+        self.module == other.module
+            && self.function == other.function
+            && self.loc == other.loc
+    }
+
+    /**
+     * The frames of this trace, most recent first
+     *
+     * Frames returning a failure back down through a function that's
+     * already in the call stack are left out. A run of the same frame,
+     * like from recursion, is collapsed into one frame and a count of
+     * how many more times it repeated.
+     */
+    pub fn frames(&self) -> Vec<(&FrameTrace, usize)>
+    {
+        let mut nodes = vec![];
+        let mut next = Some(self);
+        while let Some(node) = next {
+            // skip the root, it's not a real function
+            if node.parent.is_some() {
+                nodes.push(node);
+            }
+            next = node.parent.as_ref().map(|p| &**p);
+        }
+
+        let fail_idx = nodes
+            .iter()
+            .position(|n| n.direction == FrameTraceDirection::FailHere)
+            .unwrap_or(0);
+        let (returns, calls) = nodes.split_at(fail_idx);
+        let in_calls = |r: &FrameTrace| {
+            calls.iter().any(|c| {
+                c.module == r.module && c.function == r.function
+            })
+        };
+
+        let mut frames: Vec<(&FrameTrace, usize)> = vec![];
+        let kept_returns = returns.iter().filter(|r| !in_calls(**r));
+        for &node in kept_returns.chain(calls.iter()) {
+            if let Some(prev) = frames.last_mut() {
+                if prev.0.same_place(node) {
+                    prev.1 += 1;
+                    continue;
+                }
+            }
+            frames.push((node, 0));
+        }
+        frames
+    }
+
+    /**
+     * The trace as a list of (module, function, file, line, column)
+     * tuples for leema code to inspect, most recent first
+     */
+    pub fn to_val(&self) -> Val
+    {
+        let frames: Vec<Val> = self
+            .frames()
+            .iter()
+            .map(|&(node, _)| {
+                Val::Tuple(Struple::new_indexed(vec![
+                    Val::Str(node.module.clone()),
+                    Val::Str(node.function.clone()),
+                    Val::Str(Lstr::from(node.file())),
+                    Val::Int(node.loc.lineno as i64),
+                    Val::Int(node.loc.column as i64),
+                ]))
+            }).collect();
+        list::from_vec(&frames)
+    }
+}
+
+impl SendClone for FrameTrace
+{
+    type Item = Arc<FrameTrace>;
+
+    fn clone_for_send(&self) -> Arc<FrameTrace>
+    {
+        Arc::new(FrameTrace {
+            direction: self.direction,
+            module: self.module.clone_for_send(),
+            function: self.function.clone_for_send(),
+            file: self.file.clone_for_send(),
+            loc: self.loc,
+            parent: self.parent.as_ref().map(|p| p.clone_for_send()),
+        })
+    }
 }
 
 impl fmt::Display for FrameTrace
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
     {
-        let dir = match self.direction {
-            FrameTraceDirection::CallUp => " >",
-            FrameTraceDirection::FailHere => "<>",
-            FrameTraceDirection::ReturnDown => "< ",
-        };
-
-        write!(f, "{} {}", dir, self.function).ok();
-        if self.line != 0 {
-            write!(f, ":{}", self.line).ok();
-        }
-        match self.parent {
-            None => writeln!(f),
-            Some(ref p) => write!(f, "\n{}", p),
+        for (node, repeats) in self.frames() {
+            write!(f, "  at {}::{} ", node.module, node.function)?;
+            write!(f, "({}", node.file())?;
+            if node.loc.lineno != 0 {
+                write!(f, ":{}", node.loc.lineno)?;
+                if node.loc.column != 0 {
+                    write!(f, ":{}", node.loc.column)?;
+                }
+            }
+            writeln!(f, ")")?;
+            if repeats > 0 {
+                writeln!(f, "  ... repeated {} more times", repeats)?;
+            }
         }
+        Ok(())
     }
 }
 
@@ -249,6 +431,7 @@ pub struct Frame
     pub parent: Parent,
     pub module: Lstr,
     pub function: Lstr,
+    pub file: Lstr,
     pub trace: Arc<FrameTrace>,
     pub e: Env,
     pub pc: i32,
@@ -258,31 +441,57 @@ impl Frame
 {
     pub fn new_root(parent: Parent, module: Lstr, function: Lstr) -> Frame
     {
-        let env = Env::new();
+        Frame::new_root_with_args(parent, module, function, Val::Void)
+    }
+
+    /**
+     * A root frame for calling a function w/ a tuple of args
+     */
+    pub fn new_root_with_args(
+        parent: Parent,
+        module: Lstr,
+        function: Lstr,
+        args: Val,
+    ) -> Frame
+    {
         Frame {
             parent,
             trace: FrameTrace::new_root(),
             module,
             function,
-            e: env,
+            file: Lstr::empty(),
+            e: Env::with_args(args),
             pc: 0,
         }
     }
 
-    /*
-    pub fn new_fork(f: &Frame, ready: &Arc<AtomicBool>, tx: mpsc::Sender<Msg>)
-        -> Frame
+    /**
+     * Copy the frame's values so none of them are still shared
+     * w/ the worker it's leaving. Futures and children in the
+     * parent stay shared, they're how the result gets back.
+     */
+    pub fn pack(self) -> Frame
     {
-        Frame{
-            parent: Parent::Fork(ready.clone(), tx),
-            name: f.name.clone(),
-            trace: f.trace.clone(),
-            e: f.e.clone(),
-            id: f.id,
-            pc: 0,
+        let parent = match self.parent {
+            Parent::Fork(fv, v) => Parent::Fork(fv, v.deep_clone()),
+            Parent::Supervised(child, v) => {
+                Parent::Supervised(child, v.deep_clone())
+            }
+            Parent::Future(dst, v) => Parent::Future(dst, v.deep_clone()),
+            Parent::Repl(v) => Parent::Repl(v.deep_clone()),
+            Parent::Main(v) => Parent::Main(v.deep_clone()),
+            other => other,
+        };
+        Frame {
+            parent,
+            module: self.module.clone_for_send(),
+            function: self.function.clone_for_send(),
+            file: self.file.clone_for_send(),
+            trace: self.trace.clone_for_send(),
+            e: self.e.clone_for_send(),
+            pc: self.pc,
         }
     }
-    */
 
     pub fn set_parent(&mut self, p: Parent)
     {
@@ -294,9 +503,15 @@ impl Frame
         mem::replace(&mut self.parent, Parent::Null)
     }
 
-    pub fn push_frame_trace(&self, line: i16) -> Arc<FrameTrace>
+    pub fn push_frame_trace(&self, loc: SrcLoc) -> Arc<FrameTrace>
     {
-        FrameTrace::push_call(&self.trace, &self.function, line)
+        FrameTrace::push_call(
+            &self.trace,
+            &self.module,
+            &self.function,
+            &self.file,
+            loc,
+        )
     }
 
     pub fn take_env(&mut self) -> Env
@@ -332,29 +547,6 @@ impl Debug for Frame
 }
 */
 
-/*
- * fork the frame and frame state
- * add it to the fresh queue
- * jump current frame state past the fork block
-fn execute_fork(curf: &mut Frame,
-    dst: &Reg, freg: &Reg, argreg: &Reg
-) -> Event {
-    println!("execute_fork");
-
-    // args are empty for a fork
-    // create new frame
-    let e = Env::new();
-    // set current state to called
-    let (tx, rx) = mpsc::channel::<Val>();
-    let ready = Arc::new(AtomicBool::new(false));
-    let newf = Frame::new_fork(&curf, &ready, tx);
-    curf.e.set_reg(dst, Val::future(ready, rx));
-
-    curf.pc = curf.pc + 1;
-    Event::Fork
-}
- */
-
 
 /*
 process_set
@@ -363,3 +555,77 @@ process
 |      |       \- call code
  \--- fork
 */
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::frame::FrameTrace;
+    use leema::lstr::Lstr;
+    use leema::val::SrcLoc;
+
+
+    #[test]
+    fn test_trace_most_recent_first_without_returns()
+    {
+        let m = Lstr::Sref("foo::bar");
+        let main = Lstr::Sref("main");
+        let sub = Lstr::Sref("sub");
+        let file = Lstr::empty();
+        let root = FrameTrace::new_root();
+        let t =
+            FrameTrace::push_call(&root, &m, &main, &file, SrcLoc::new(4, 9));
+        let t = FrameTrace::push_call(&t, &m, &sub, &file, SrcLoc::new(2, 5));
+        let t = t.fail_here();
+        let t =
+            FrameTrace::propagate_down(&t, &m, &main, &file, SrcLoc::new(4, 0));
+
+        let txt = format!("{}", t);
+        let lines: Vec<&str> = txt.lines().collect();
+        assert_eq!(
+            vec![
+                "  at foo::bar::sub (foo/bar.lma:2:5)",
+                "  at foo::bar::main (foo/bar.lma:4:9)",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_trace_collapses_recursion()
+    {
+        let m = Lstr::Sref("fact");
+        let f = Lstr::Sref("fact");
+        let file = Lstr::empty();
+        let mut t = FrameTrace::new_root();
+        for _ in 0..4 {
+            t = FrameTrace::push_call(&t, &m, &f, &file, SrcLoc::new(3, 7));
+        }
+        let t = t.fail_here();
+
+        let frames = t.frames();
+        assert_eq!(1, frames.len());
+        assert_eq!(3, frames[0].1);
+        assert_eq!(
+            "  at fact::fact (fact.lma:3:7)\n  ... repeated 3 more times\n",
+            format!("{}", t)
+        );
+    }
+
+    #[test]
+    fn test_trace_uses_loaded_file()
+    {
+        let m = Lstr::Sref("net::http");
+        let f = Lstr::Sref("get");
+        let file = Lstr::Sref("lib/net/http.lma");
+        let root = FrameTrace::new_root();
+        let t = FrameTrace::push_call(&root, &m, &f, &file, SrcLoc::new(8, 3));
+        let t = t.fail_here();
+
+        assert_eq!("lib/net/http.lma", t.file());
+        assert_eq!(
+            "  at net::http::get (lib/net/http.lma:8:3)\n",
+            format!("{}", t)
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use leema::application::AppCaller;
+use leema::lib_hyper;
 use leema::lstr::Lstr;
 
 use std::thread;
 
-use futures::future;
 use futures::sync::oneshot::Canceled;
 use futures::Future;
 use hyper::service::service_fn;
@@ -45,20 +45,9 @@ pub fn handle_request(
 ) -> BoxFut
 {
     println!("handle_request({},\n\t{:?})", module, req);
-    let response_future: BoxFut = Box::new(
-        caller
-            .push_call(&module, &Lstr::Sref("http_main"))
-            .and_then(|v| {
-                let msg = format!("{}", v);
-                println!("response msg: {}", msg);
-                // future::ok(Response::new(Body::from(msg)))
-                future::ok(Response::new(Body::from(msg)))
-            }).map_err(|e| {
-                println!("request error: {:?}", e);
-                e
-            }),
-    );
-    response_future
+    lib_hyper::call_handler(req, move |args| {
+        caller.push_call(&module, &Lstr::Sref("http_main"), args)
+    })
 }
 
 struct LeemaService
@@ -212,6 +212,18 @@ impl<'b> Inferator<'b>
                     &Type::StrictList(Box::new(tvar_inner.clone())),
                 ).map(|_| ())
             }
+            (&Val::Tuple(ref flds1), &Type::Var(ref tvar_name)) => {
+                let item_types: Vec<(Option<Lstr>, Type)> = (0..flds1.0.len())
+                    .map(|i| {
+                        let item_name = format!("{}_{}", tvar_name, i);
+                        (None, Type::Var(Lstr::from(item_name)))
+                    }).collect();
+                for (fp, ft) in flds1.0.iter().zip(item_types.iter()) {
+                    self.match_pattern(typeset, &fp.1, &ft.1, lineno)?;
+                }
+                self.merge_types(valtype, &Type::Tuple(Struple(item_types)))
+                    .map(|_| ())
+            }
             (&Val::Tuple(ref flds1), &Type::Tuple(ref item_types)) => {
                 if flds1.0.len() != item_types.0.len() {
                     panic!(
@@ -471,24 +483,32 @@ impl<'b> Inferator<'b>
         let defargslen = defargst.len();
         let argslen = argst.len();
         if argslen > defargslen {
-            panic!("too many args passed to {:?}: {:?}", ftype, argst);
+            return Err(TypeErr::Error(Lstr::from(format!(
+                "too many args passed to {}: expected {}, found {}",
+                ftype, defargslen, argslen
+            ))));
         }
         if argslen < defargslen {
-            panic!("it's so much fun to curry, but not supported yet");
+            return Err(TypeErr::Error(Lstr::from(format!(
+                "too few args passed to {}: expected {}, found {}",
+                ftype, defargslen, argslen
+            ))));
         }
 
+        let funcname = self.funcname;
         let mashed_args = defargst
             .iter()
             .zip(argst.iter())
             .map(|(defargt, argt)| {
-                Inferator::mash(&mut self.inferences, defargt, argt)
-                    .map_err(|e| {
+                Inferator::mash(&mut self.inferences, defargt, argt).map_err(
+                    |e| {
                         e.add_context(Lstr::from(format!(
                             "expected function args in {}: {:?} found {:?}",
-                            self.funcname, defargst, argst,
+                            funcname, defargst, argst,
                         )))
-                    }).unwrap()
-            }).collect();
+                    },
+                )
+            }).collect::<Result<Vec<Type>, TypeErr>>()?;
         Ok(Type::f(mashed_args, self.inferred_type(defresult)))
     }
 }
@@ -612,6 +632,24 @@ mod tests
         assert_eq!(exp, t.inferred_type(&tvar));
     }
 
+    #[test]
+    fn test_match_pattern_tuple_against_var()
+    {
+        let mut t = Inferator::new("burritos");
+        let tvar = Type::Var(Lstr::Sref("Taco"));
+        let patt = Val::Tuple(Struple::new_tuple2(
+            Val::Hashtag(Lstr::Sref("ping")),
+            Val::Id(Lstr::Sref("n")),
+        ));
+        let ts = TypeSet::new();
+        t.match_pattern(&ts, &patt, &tvar, 14).unwrap();
+        let ntype = t.vartype("n").unwrap();
+        t.merge_types(&ntype, &Type::Int).unwrap();
+
+        let exp = Type::Tuple(Struple::new_tuple2(Type::Hashtag, Type::Int));
+        assert_eq!(exp, t.inferred_type(&tvar));
+    }
+
     #[test]
     #[should_panic]
     fn test_match_pattern_tuple_size_mismatch()
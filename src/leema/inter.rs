@@ -1,4 +1,5 @@
 use leema::ast::{self, Ast, IfCase, Kxpr};
+use leema::error::{CompileError, CompileResult, ErrorKind, ModuleResult};
 use leema::ixpr::{Ixpr, MatchFailure, Source};
 use leema::list;
 use leema::log;
@@ -94,19 +95,27 @@ impl Intermod
     pub fn compile(
         proto: &Protomod,
         imports: &HashMap<Lstr, Rc<Protomod>>,
-    ) -> Intermod
+    ) -> ModuleResult<Intermod>
     {
         let mod_lstr = &proto.key.name;
         let mut inter = Intermod::new(mod_lstr.clone());
+        let mut errs = vec![];
         for fname in proto.funcseq.iter() {
-            let opt_defunc = proto.funcsrc.get(fname);
-            if opt_defunc.is_none() {
-                panic!(
-                    "No function source found for {}::{}",
-                    proto.key.name, fname
-                );
-            }
-            let defunc = opt_defunc.unwrap();
+            let defunc = match proto.funcsrc.get(fname) {
+                Some(defunc) => defunc,
+                None => {
+                    errs.push(CompileError::new(
+                        mod_lstr,
+                        SrcLoc::default(),
+                        ErrorKind::Undefined,
+                        format!(
+                            "no function source found for {}::{}",
+                            mod_lstr, fname
+                        ),
+                    ));
+                    continue;
+                }
+            };
             let (args, body, loc) = split_func_args_body(defunc);
             let ftype = proto.valtypes.get(fname).unwrap();
             let ifunc = compile_function(
@@ -118,9 +127,20 @@ impl Intermod
                 body,
                 loc,
             );
-            inter.interfunc.insert(fname.clone(), ifunc);
+            match ifunc {
+                Ok(f) => {
+                    inter.interfunc.insert(fname.clone(), f);
+                }
+                Err(e) => {
+                    errs.push(e);
+                }
+            }
+        }
+        if errs.is_empty() {
+            Ok(inter)
+        } else {
+            Err(errs)
         }
-        inter
     }
 }
 
@@ -223,6 +243,7 @@ impl Blockstack
     }
 
     pub fn assign_var(&mut self, id: &Lstr, vt: LocalType)
+        -> Result<(), String>
     {
         if self.var_in_scope(id) {
             let var_data = self.locals.get_mut(id).unwrap();
@@ -231,10 +252,16 @@ impl Blockstack
                     var_data.num_reassignments += 1;
                 }
                 (LocalType::Param, LocalType::Let) => {
-                    panic!("cannot reassign a function parameter: {}", id);
+                    return Err(format!(
+                        "cannot reassign a function parameter: {}",
+                        id
+                    ));
                 }
                 (LocalType::Match, LocalType::Let) => {
-                    panic!("cannot reassign a pattern variable: {}", id);
+                    return Err(format!(
+                        "cannot reassign a pattern variable: {}",
+                        id
+                    ));
                 }
                 _ => {
                     // matching on an existing variable, that's cool
@@ -245,6 +272,7 @@ impl Blockstack
             self.locals.insert(id.clone(), new_var);
             self.current_block_mut().vars.insert(id.clone());
         }
+        Ok(())
     }
 
     pub fn access_var(&mut self, id: &Lstr, lineno: i16)
@@ -309,7 +337,8 @@ impl<'a> Interscope<'a>
             vout!("bind func param as: #{} {:?}\n", i, a);
             let at = Type::from(a.x_ref().unwrap());
             let opt_k = a.k_ref().map(|k| {
-                blocks.assign_var(k, LocalType::Param);
+                // params are assigned first so this can't be a reassignment
+                blocks.assign_var(k, LocalType::Param).unwrap();
                 k.clone()
             });
             if opt_k.is_some() {}
@@ -326,6 +355,12 @@ impl<'a> Interscope<'a>
         }
     }
 
+    pub fn error(&self, kind: ErrorKind, loc: &SrcLoc, msg: String)
+        -> CompileError
+    {
+        CompileError::new(&self.proto.key.name, *loc, kind, msg)
+    }
+
     pub fn import_vartype(&self, modnm: &str, valnm: &str) -> Option<&Type>
     {
         match self.imports.get(modnm) {
@@ -345,23 +380,28 @@ impl<'a> Interscope<'a>
         NewBlockscope::new(self)
     }
 
-    pub fn type_module(&self, typ: &Type) -> &Protomod
+    pub fn type_module(
+        &self,
+        typ: &Type,
+        loc: &SrcLoc,
+    ) -> CompileResult<&Protomod>
     {
-        match typ {
-            &Type::UserDef(ref i) => {
-                i.mod_ref()
-                    .and_then(|mods| {
-                        if *mods == self.proto.key.name {
-                            return None;
-                        }
-                        let imp = self.imports.get(mods.str());
-                        if imp.is_none() {
-                            panic!("module for type cannot be found: {}", typ);
-                        }
-                        Some(&**imp.unwrap())
-                    }).unwrap_or(self.proto)
+        let mods = match typ {
+            &Type::UserDef(ref i) => i.mod_ref(),
+            _ => None,
+        };
+        match mods {
+            Some(mods) if *mods != self.proto.key.name => {
+                match self.imports.get(mods.str()) {
+                    Some(imp) => Ok(&**imp),
+                    None => Err(self.error(
+                        ErrorKind::ModuleNotFound,
+                        loc,
+                        format!("module for type cannot be found: {}", typ),
+                    )),
+                }
             }
-            _ => self.proto,
+            _ => Ok(self.proto),
         }
     }
 
@@ -396,27 +436,32 @@ impl<'a, 'b> NewBlockscope<'a, 'b>
         NewBlockscope { scope }
     }
 
-    pub fn collect_failures<'c>(&mut self, stmt: &'c Ast) -> Option<&'c Ast>
+    pub fn collect_failures<'c>(
+        &mut self,
+        stmt: &'c Ast,
+    ) -> CompileResult<Option<&'c Ast>>
     {
         match stmt {
             &Ast::IfExpr(
                 ast::IfType::MatchFailure,
                 ref input,
                 ref cases,
-                ref _iloc,
+                ref iloc,
             ) => {
                 if let Ast::Localid(ref name, ref _loc2) = **input {
                     let b = self.scope.blocks.current_block_mut();
                     b.add_failure(name, (**cases).clone());
                 } else {
-                    panic!(
-                        "match failure input must be a local variable: {:?}",
-                        input
-                    );
+                    return Err(self.scope.error(
+                        ErrorKind::Syntax,
+                        iloc,
+                        "match failure input must be a local variable"
+                            .to_string(),
+                    ));
                 }
-                None
+                Ok(None)
             }
-            _ => Some(stmt),
+            _ => Ok(Some(stmt)),
         }
     }
 }
@@ -438,21 +483,23 @@ pub fn compile_function<'a>(
     args: &LinkedList<Kxpr>,
     body: &Ast,
     loc: &SrcLoc,
-) -> Ixpr
+) -> CompileResult<Ixpr>
 {
     vout!("compile {}({:?}): {:?}\n", fname, args, ftype);
     let (argt, result_type) = Type::split_func_ref(ftype);
     if *body == Ast::RustBlock {
-        return Ixpr {
+        return Ok(Ixpr {
             src: Source::RustBlock(argt.clone(), result_type.clone()),
             line: loc.lineno,
-        };
+            column: loc.column,
+        });
     }
     let mut scope = Interscope::new(proto, imports, fname, args);
-    let ibody = compile_expr(&mut scope, body, loc);
+    let ibody = compile_expr(&mut scope, body, loc)?;
     let ibody2 = Ixpr {
         src: ibody.src,
         line: loc.lineno,
+        column: loc.column,
     };
     vout!("compile function {}({:?}): {}\n", fname, argt, result_type);
     let rc_args = args
@@ -468,26 +515,35 @@ pub fn compile_function<'a>(
         result_type.clone(),
         Box::new(ibody2),
     );
-    Ixpr {
+    Ok(Ixpr {
         src,
         line: loc.lineno,
-    }
+        column: loc.column,
+    })
 }
 
-pub fn compile_expr(scope: &mut Interscope, x: &Ast, loc: &SrcLoc) -> Ixpr
+pub fn compile_expr(
+    scope: &mut Interscope,
+    x: &Ast,
+    loc: &SrcLoc,
+) -> CompileResult<Ixpr>
 {
-    match x {
-        &Ast::Block(ref lines) => compile_block(scope, lines, loc),
-        &Ast::Localid(ref id, ref loc) => compile_local_id(scope, id, loc),
-        &Ast::Lri(ref names, None, ref loc) => compile_lri(scope, names, loc),
-        &Ast::Lri(_, ref _types, _) => {
-            panic!("cannot handle typed lri");
+    let mut ix = match x {
+        &Ast::Block(ref lines) => compile_block(scope, lines, loc)?,
+        &Ast::Localid(ref id, ref loc) => compile_local_id(scope, id, loc)?,
+        &Ast::Lri(ref names, None, ref loc) => compile_lri(scope, names, loc)?,
+        &Ast::Lri(_, ref _types, ref iloc) => {
+            return Err(scope.error(
+                ErrorKind::Unsupported,
+                iloc,
+                "cannot handle typed lri".to_string(),
+            ));
         }
         &Ast::DotAccess(ref outer, ref inner) => {
-            compile_dot_access(scope, outer, inner, loc)
+            compile_dot_access(scope, outer, inner, loc)?
         }
         &Ast::Call(ref callx, ref args, ref iloc) => {
-            compile_call(scope, callx, args, iloc)
+            compile_call(scope, callx, args, iloc)?
         }
         &Ast::ConstBool(b) => Ixpr::const_val(Val::Bool(b), loc.lineno),
         &Ast::ConstInt(i) => Ixpr::const_val(Val::Int(i), loc.lineno),
@@ -498,108 +554,144 @@ pub fn compile_expr(scope: &mut Interscope, x: &Ast, loc: &SrcLoc) -> Ixpr
             Ixpr::const_val(Val::Hashtag(s.clone()), loc.lineno)
         }
         &Ast::ConstVoid => Ixpr::const_val(Val::Void, loc.lineno),
+        // a wildcard value is a pattern to match, like for receive
+        &Ast::Wildcard => Ixpr::const_val(Val::Wildcard, loc.lineno),
         &Ast::Cons(ref head, ref tail) => {
-            let chead = compile_expr(scope, head, loc);
-            let ctail = compile_expr(scope, tail, loc);
+            let chead = compile_expr(scope, head, loc)?;
+            let ctail = compile_expr(scope, tail, loc)?;
             Ixpr::cons(chead, ctail, loc.lineno)
         }
-        &Ast::IfExpr(_, _, _, _) => compile_ifx(scope, x),
+        &Ast::IfExpr(_, _, _, _) => compile_ifx(scope, x)?,
         &Ast::StrExpr(ref items, ref iloc) => {
-            let strvec =
-                items.iter().map(|i| compile_expr(scope, i, iloc)).collect();
+            let strvec = items
+                .iter()
+                .map(|i| compile_expr(scope, i, iloc))
+                .collect::<CompileResult<_>>()?;
             Ixpr::new_str_mash(strvec, iloc.lineno)
         }
         &Ast::List(ref items) => {
-            let c_items =
-                items.iter().map(|i| compile_expr(scope, i, loc)).collect();
+            let c_items = items
+                .iter()
+                .map(|i| compile_expr(scope, i, loc))
+                .collect::<CompileResult<_>>()?;
             Ixpr::new_list(c_items, loc.lineno)
         }
         &Ast::Tuple(ref items) => {
             let c_items = items
                 .iter()
                 .map(|i| {
-                    let ix = compile_expr(scope, i.x_ref().unwrap(), loc);
-                    (i.k_clone(), ix)
-                }).collect();
+                    let ix = compile_expr(scope, i.x_ref().unwrap(), loc)?;
+                    Ok((i.k_clone(), ix))
+                }).collect::<CompileResult<_>>()?;
             Ixpr::new_tuple(Struple(c_items), loc.lineno)
         }
         &Ast::Map(ref items) => {
             let c_items = items
                 .iter()
                 .map(|i| {
-                    let ix = compile_expr(scope, i.x_ref().unwrap(), loc);
-                    (i.k_clone(), ix)
-                }).collect();
+                    let ix = compile_expr(scope, i.x_ref().unwrap(), loc)?;
+                    Ok((i.k_clone(), ix))
+                }).collect::<CompileResult<_>>()?;
             Ixpr::new_map(Struple(c_items), loc.lineno)
         }
         &Ast::ConstructData(ast::DataType::Struple, ref ast_typ) => {
             let type_lri = Lri::from(&**ast_typ);
             let opt_full_type = scope.proto.func_result_type(&type_lri.localid);
             if opt_full_type.is_none() {
-                panic!(
-                    "cannot find full type for: {:?} in {:?}",
-                    type_lri.localid, scope.proto.valtypes
-                );
+                return Err(scope.error(
+                    ErrorKind::Undefined,
+                    loc,
+                    format!("cannot find full type for: {}", type_lri.localid),
+                ));
             }
             let full_type = opt_full_type.unwrap();
             let fields = scope.proto.get_struple_fields(&type_lri.localid);
             Ixpr::construple(full_type.clone(), fields, loc.lineno)
         }
         &Ast::Let(ltype, ref lhs, ref rhs, ref iloc) => {
-            compile_let_stmt(scope, ltype, lhs, rhs, iloc)
+            compile_let_stmt(scope, ltype, lhs, rhs, iloc)?
         }
         _ => {
-            panic!("Cannot compile expr: {:?}", x);
+            return Err(scope.error(
+                ErrorKind::Unsupported,
+                loc,
+                format!("cannot compile expression: {:?}", x),
+            ));
         }
+    };
+    if ix.column == 0 {
+        ix.column = loc.column;
     }
+    Ok(ix)
 }
 
 pub fn compile_lri(
     scope: &mut Interscope,
     names: &Vec<Lstr>,
     loc: &SrcLoc,
-) -> Ixpr
+) -> CompileResult<Ixpr>
 {
     if names.len() != 2 {
-        panic!("too many modules: {:?}", names);
+        return Err(scope.error(
+            ErrorKind::Unsupported,
+            loc,
+            format!("too many modules: {:?}", names),
+        ));
     }
     let modname = names.first().unwrap();
     if !scope.imports_module(modname) {
-        panic!("module not found: {:?}", names);
+        return Err(scope.error(
+            ErrorKind::ModuleNotFound,
+            loc,
+            format!("module not imported: {}", modname),
+        ));
     }
     let id = names.last().unwrap();
     let opt_vartype = scope.import_vartype(modname, id);
     if opt_vartype.is_none() {
-        panic!("failure for import_vartype({}, {})", modname, id);
+        return Err(scope.error(
+            ErrorKind::Undefined,
+            loc,
+            format!("undefined value: {}::{}", modname, id),
+        ));
     }
     let vartype = opt_vartype.unwrap();
     let lri = Lri::with_modules(modname.clone(), id.clone());
 
     let fref = Val::FuncRef(lri, vartype.clone());
-    Ixpr::const_val(fref, loc.lineno)
+    Ok(Ixpr::const_val(fref, loc.lineno))
 }
 
-pub fn compile_local_id(scope: &mut Interscope, id: &Lstr, loc: &SrcLoc)
-    -> Ixpr
+pub fn compile_local_id(
+    scope: &mut Interscope,
+    id: &Lstr,
+    loc: &SrcLoc,
+) -> CompileResult<Ixpr>
 {
     vout!("compile_local_id({})\n", id);
     match scope.scope_level(id) {
         Some(ScopeLevel::Local) => {
             scope.blocks.access_var(id, loc.lineno);
-            Ixpr {
+            Ok(Ixpr {
                 src: Source::Id(id.clone(), loc.lineno),
                 line: loc.lineno,
-            }
+                column: loc.column,
+            })
         }
         Some(ScopeLevel::Module(val)) => {
-            Ixpr {
+            Ok(Ixpr {
                 src: Source::ConstVal(val),
                 line: loc.lineno,
-            }
+                column: loc.column,
+            })
         }
         None => {
             vout!("undefined variable {} in {:?}\n", id, scope.proto.constants);
-            panic!("undefined variable: {}", id);
+            Err(scope.error(
+                ErrorKind::Undefined,
+                loc,
+                format!("undefined variable: {}", id),
+            ))
         }
     }
 }
@@ -609,40 +701,92 @@ pub fn compile_call(
     callx: &Ast,
     args: &LinkedList<Kxpr>,
     loc: &SrcLoc,
-) -> Ixpr
+) -> CompileResult<Ixpr>
 {
-    let icall = compile_expr(scope, callx, loc);
+    let icall = compile_expr(scope, callx, loc)?;
     let iargs: Vec<(Option<Lstr>, Ixpr)> = args
         .iter()
-        .map(|i| i.map_1(|x| compile_expr(scope, x, loc)))
-        .collect();
+        .map(|i| {
+            let (k, ix) = i.map_1(|x| compile_expr(scope, x, loc));
+            Ok((k, ix?))
+        }).collect::<CompileResult<_>>()?;
     let argsix = Ixpr::new_tuple(Struple(iargs), loc.lineno);
-    Ixpr {
-        src: Source::Call(Box::new(icall), Box::new(argsix)),
+    let callsrc = Source::Call(Box::new(icall), Box::new(argsix), loc.column);
+    Ok(Ixpr {
+        src: callsrc,
         line: loc.lineno,
-    }
+        column: loc.column,
+    })
 }
 
 pub fn compile_let_stmt(
     scope: &mut Interscope,
-    _lettype: ast::LetType,
+    lettype: ast::LetType,
     lhs: &Ast,
     rhs: &Ast,
     loc: &SrcLoc,
-) -> Ixpr
+) -> CompileResult<Ixpr>
 {
     vout!("compile let {:?} := {:?}\n", lhs, rhs);
-    let irhs = compile_expr(scope, rhs, loc);
+    if lettype == ast::LetType::Forked {
+        return compile_fork_stmt(scope, lhs, rhs, loc);
+    }
+    let irhs = compile_expr(scope, rhs, loc)?;
     let mut new_vars = Vec::new();
-    let cpatt = compile_pattern(scope, &mut new_vars, lhs);
+    let cpatt = compile_pattern(scope, &mut new_vars, lhs, loc)?;
     vout!("new vars in let: {:?} = {:?} = {:?}\n", new_vars, lhs, irhs);
     let failures: Vec<MatchFailure> = new_vars
         .iter()
         .map(|v| {
-            scope.blocks.assign_var(v, LocalType::Let);
+            scope
+                .blocks
+                .assign_var(v, LocalType::Let)
+                .map_err(|msg| scope.error(ErrorKind::Reassign, loc, msg))?;
             compile_failed_var(scope, v, loc)
-        }).collect();
-    Ixpr::new(Source::Let(cpatt, Box::new(irhs), failures), loc.lineno)
+        }).collect::<CompileResult<_>>()?;
+    Ok(Ixpr::new(
+        Source::Let(cpatt, Box::new(irhs), failures),
+        loc.lineno,
+    ))
+}
+
+/**
+ * A forked let runs a function call in its own fiber and binds the
+ * variable to a future of the result. Failures aren't checked until
+ * the variable is used, so there are no failure cases to compile.
+ */
+pub fn compile_fork_stmt(
+    scope: &mut Interscope,
+    lhs: &Ast,
+    rhs: &Ast,
+    loc: &SrcLoc,
+) -> CompileResult<Ixpr>
+{
+    let var = match lhs {
+        &Ast::Localid(ref name, _) => name.clone(),
+        _ => {
+            return Err(scope.error(
+                ErrorKind::Unsupported,
+                loc,
+                format!("fork must assign a single variable: {:?}", lhs),
+            ));
+        }
+    };
+    if let &Ast::Call(_, _, _) = rhs {
+        // that's what we want
+    } else {
+        return Err(scope.error(
+            ErrorKind::Unsupported,
+            loc,
+            format!("fork must be a function call: {:?}", rhs),
+        ));
+    }
+    let icall = compile_expr(scope, rhs, loc)?;
+    scope
+        .blocks
+        .assign_var(&var, LocalType::Let)
+        .map_err(|msg| scope.error(ErrorKind::Reassign, loc, msg))?;
+    Ok(Ixpr::new(Source::Fork(var, Box::new(icall)), loc.lineno))
 }
 
 pub fn compile_dot_access(
@@ -650,13 +794,13 @@ pub fn compile_dot_access(
     base_val: &Ast,
     field: &Lstr,
     loc: &SrcLoc,
-) -> Ixpr
+) -> CompileResult<Ixpr>
 {
-    let ix_base = compile_expr(scope, base_val, loc);
-    Ixpr::new_field_access(ix_base, field.clone())
+    let ix_base = compile_expr(scope, base_val, loc)?;
+    Ok(Ixpr::new_field_access(ix_base, field.clone()))
 }
 
-pub fn compile_ifx(scope: &mut Interscope, ifx: &Ast) -> Ixpr
+pub fn compile_ifx(scope: &mut Interscope, ifx: &Ast) -> CompileResult<Ixpr>
 {
     match ifx {
         &Ast::IfExpr(ast::IfType::If, ref const_void, ref case, ref iloc) => {
@@ -666,76 +810,114 @@ pub fn compile_ifx(scope: &mut Interscope, ifx: &Ast) -> Ixpr
             compile_if_case(scope, case)
         }
         &Ast::IfExpr(ast::IfType::Match, ref x, ref ifcase, ref iloc) => {
-            let ix = compile_expr(scope, x, iloc);
-            let ixcase = compile_match_case(scope, ifcase);
-            Ixpr::new_match_expr(ix, ixcase)
+            let ix = compile_expr(scope, x, iloc)?;
+            let ixcase = compile_match_case(scope, ifcase)?;
+            Ok(Ixpr::new_match_expr(ix, ixcase))
         }
         &Ast::IfExpr(ast::IfType::MatchFailure, _, _, _) => {
             panic!("what's a MatchFailed doing here?");
         }
+        &Ast::IfExpr(ast::IfType::TypeCast, _, _, ref iloc) => {
+            Err(scope.error(
+                ErrorKind::Unsupported,
+                iloc,
+                "typecast not ready yet".to_string(),
+            ))
+        }
         _ => {
             panic!("not an expected if expression: {:?}", ifx);
         }
     }
 }
 
-pub fn compile_if_case(scope: &mut Interscope, case: &ast::IfCase) -> Ixpr
+pub fn compile_if_case(
+    scope: &mut Interscope,
+    case: &ast::IfCase,
+) -> CompileResult<Ixpr>
 {
     let ix = if case.cond == Ast::Wildcard {
         if case.else_case.is_some() {
-            panic!("cannot have else case for else case: {:?}", case);
+            return Err(scope.error(
+                ErrorKind::Syntax,
+                &case.loc,
+                "cannot have a case after an else case".to_string(),
+            ));
         }
         Ixpr::const_val(Val::Bool(true), case.loc.lineno)
     } else {
-        compile_expr(scope, &case.cond, &case.loc)
+        compile_expr(scope, &case.cond, &case.loc)?
+    };
+    let ibody = compile_expr(scope, &case.body, &case.loc)?;
+    let inext = match case.else_case {
+        Some(ref else_case) => Some(compile_if_case(scope, else_case)?),
+        None => None,
     };
-    let ibody = compile_expr(scope, &case.body, &case.loc);
-    let inext = case
-        .else_case
-        .as_ref()
-        .map(|else_case| compile_if_case(scope, else_case));
-    Ixpr::new_if(ix, ibody, inext)
+    Ok(Ixpr::new_if(ix, ibody, inext))
 }
 
-pub fn compile_match_case(scope: &mut Interscope, case: &ast::IfCase) -> Ixpr
+pub fn compile_match_case(
+    scope: &mut Interscope,
+    case: &ast::IfCase,
+) -> CompileResult<Ixpr>
 {
     let (patt, iblk) = {
         let new_block = scope.push_blockscope();
         let mut new_vars = Vec::new();
-        let cpatt = compile_pattern(new_block.scope, &mut new_vars, &case.cond);
+        let cpatt = compile_pattern(
+            new_block.scope,
+            &mut new_vars,
+            &case.cond,
+            &case.loc,
+        )?;
         for nv in new_vars.iter() {
-            new_block.scope.blocks.assign_var(nv, LocalType::Match);
+            new_block
+                .scope
+                .blocks
+                .assign_var(nv, LocalType::Match)
+                .map_err(|msg| {
+                    new_block.scope.error(ErrorKind::Reassign, &case.loc, msg)
+                })?;
         }
-        (cpatt, compile_expr(new_block.scope, &case.body, &case.loc))
+        (cpatt, compile_expr(new_block.scope, &case.body, &case.loc)?)
+    };
+    let inext = match case.else_case {
+        Some(ref else_case) => compile_match_case(scope, &else_case)?,
+        None => Ixpr::noop(),
     };
-    let inext = case.else_case.as_ref().map_or(Ixpr::noop(), |else_case| {
-        compile_match_case(scope, &else_case)
-    });
-    Ixpr::new_match_case(patt, iblk, inext)
+    Ok(Ixpr::new_match_case(patt, iblk, inext))
 }
 
 pub fn compile_pattern(
     scope: &mut Interscope,
     new_vars: &mut Vec<Lstr>,
     patt: &Ast,
-) -> Val
+    loc: &SrcLoc,
+) -> CompileResult<Val>
 {
-    match patt {
+    let cpatt = match patt {
         &Ast::Localid(ref name, _) => {
             new_vars.push(name.clone());
             Val::Id(name.clone())
         }
         &Ast::Cons(ref head, ref tail) => {
-            let chead = compile_pattern(scope, new_vars, head);
+            let chead = compile_pattern(scope, new_vars, head, loc)?;
             let ctail = match &**tail {
                 &Ast::Localid(_, _) => {
-                    compile_pattern(scope, new_vars, &**tail)
+                    compile_pattern(scope, new_vars, &**tail, loc)?
                 }
                 &Ast::Wildcard => Val::Wildcard,
-                &Ast::Cons(_, _) => compile_pattern(scope, new_vars, &**tail),
-                &Ast::List(_) => compile_pattern(scope, new_vars, &**tail),
+                &Ast::Cons(_, _) => {
+                    compile_pattern(scope, new_vars, &**tail, loc)?
+                }
+                &Ast::List(_) => {
+                    compile_pattern(scope, new_vars, &**tail, loc)?
+                }
                 _ => {
-                    panic!("invalid pattern tail: {:?}", tail);
+                    return Err(scope.error(
+                        ErrorKind::Pattern,
+                        loc,
+                        format!("invalid pattern tail: {:?}", tail),
+                    ));
                 }
             };
             Val::Cons(Box::new(chead), Arc::new(ctail))
@@ -743,15 +925,16 @@ pub fn compile_pattern(
         &Ast::List(ref items) => {
             let c_items: Vec<Val> = items
                 .iter()
-                .map(|i| compile_pattern(scope, new_vars, i))
-                .collect();
+                .map(|i| compile_pattern(scope, new_vars, i, loc))
+                .collect::<CompileResult<_>>()?;
             list::from_vec(&c_items)
         }
         &Ast::Tuple(ref items) => {
             let citems = items
                 .iter()
-                .map(|i| compile_pattern(scope, new_vars, i.x_ref().unwrap()))
-                .collect();
+                .map(|i| {
+                    compile_pattern(scope, new_vars, i.x_ref().unwrap(), loc)
+                }).collect::<CompileResult<_>>()?;
             Val::Tuple(Struple::new_indexed(citems))
         }
         &Ast::ConstInt(i) => Val::Int(i),
@@ -760,12 +943,17 @@ pub fn compile_pattern(
         &Ast::ConstHashtag(ref h) => Val::Hashtag(h.clone()),
         &Ast::Wildcard => Val::Wildcard,
         &Ast::Call(ref callx, ref args, ref iloc) => {
-            compile_pattern_call(scope, new_vars, callx, args, iloc)
+            compile_pattern_call(scope, new_vars, callx, args, iloc)?
         }
         _ => {
-            panic!("invalid pattern: {:?}", patt);
+            return Err(scope.error(
+                ErrorKind::Pattern,
+                loc,
+                format!("invalid pattern: {:?}", patt),
+            ));
         }
-    }
+    };
+    Ok(cpatt)
 }
 
 pub fn compile_pattern_call(
@@ -773,23 +961,21 @@ pub fn compile_pattern_call(
     new_vars: &mut Vec<Lstr>,
     callx: &Ast,
     args: &LinkedList<Kxpr>,
-    _loc: &SrcLoc,
-) -> Val
+    loc: &SrcLoc,
+) -> CompileResult<Val>
 {
     let args_vec: Vec<(Option<Lstr>, Val)> = args
         .iter()
         .map(|a| {
-            (
-                a.k_clone(),
-                compile_pattern(scope, new_vars, a.x_ref().unwrap()),
-            )
-        }).collect();
+            let x = a.x_ref().unwrap();
+            Ok((a.k_clone(), compile_pattern(scope, new_vars, x, loc)?))
+        }).collect::<CompileResult<_>>()?;
 
     let mut struct_lri = Lri::from(callx);
     if !struct_lri.has_modules() {
         struct_lri = struct_lri.add_modules(scope.proto.key.name.clone());
     }
-    Val::Struct(struct_lri, Struple(args_vec))
+    Ok(Val::Struct(struct_lri, Struple(args_vec)))
 }
 
 pub fn push_block(_scope: &mut Interscope, stmts: &Vec<Ast>) -> Vec<Ast>
@@ -818,33 +1004,35 @@ pub fn compile_block(
     scope: &mut Interscope,
     blk: &Vec<Ast>,
     loc: &SrcLoc,
-) -> Ixpr
+) -> CompileResult<Ixpr>
 {
     let mut new_block = scope.push_blockscope();
-    let non_failures: Vec<&Ast> = blk
-        .iter()
-        .filter_map(|stmt| new_block.collect_failures(stmt))
-        .collect();
+    let mut non_failures: Vec<&Ast> = Vec::with_capacity(blk.len());
+    for stmt in blk.iter() {
+        if let Some(s) = new_block.collect_failures(stmt)? {
+            non_failures.push(s);
+        }
+    }
     let ixs: Vec<Ixpr> = non_failures
         .iter()
         .map(|stmt| compile_block_stmt(&mut new_block.scope, stmt, loc))
-        .collect();
-    Ixpr::new_block(ixs, loc.lineno)
+        .collect::<CompileResult<_>>()?;
+    Ok(Ixpr::new_block(ixs, loc.lineno))
 }
 
 pub fn compile_block_stmt(
     scope: &mut Interscope,
     stmt: &Ast,
     loc: &SrcLoc,
-) -> Ixpr
+) -> CompileResult<Ixpr>
 {
     match stmt {
         &Ast::IfExpr(ast::IfType::MatchFailure, ref input, _, _) => {
             panic!("MatchFailed should be removed from blocks: {:?}", input);
         }
         &Ast::Return(ref result, ref iloc) => {
-            let cresult = compile_expr(scope, result, iloc);
-            Ixpr::new(Source::Return(Box::new(cresult)), iloc.lineno)
+            let cresult = compile_expr(scope, result, iloc)?;
+            Ok(Ixpr::new(Source::Return(Box::new(cresult)), iloc.lineno))
         }
         _ => compile_expr(scope, stmt, loc),
     }
@@ -854,21 +1042,21 @@ pub fn compile_failed_var(
     scope: &mut Interscope,
     v: &Lstr,
     loc: &SrcLoc,
-) -> MatchFailure
+) -> CompileResult<MatchFailure>
 {
     let var_failure = scope.blocks.get_failure(v).map(|f| f.clone());
     let mf = match var_failure {
         Some(fail_case) => {
             vout!("compile failure handling for {}\n", v);
-            Some(compile_match_case(scope, &fail_case))
+            Some(compile_match_case(scope, &fail_case)?)
         }
         None => None,
     };
-    MatchFailure {
+    Ok(MatchFailure {
         var: v.clone(),
         case: mf,
         line: loc.lineno,
-    }
+    })
 }
 
 pub fn split_func_args_body(defunc: &Ast)
@@ -909,6 +1097,7 @@ impl fmt::Debug for Intermod
 mod tests
 {
     use leema::ast::Ast;
+    use leema::error::ErrorKind;
     use leema::inter::{self, Interscope, ScopeLevel};
     use leema::loader::Interloader;
     use leema::lstr::Lstr;
@@ -930,7 +1119,8 @@ mod tests
         let mut scope = Interscope::new(&proto, &imps, "foo", &args);
         scope
             .blocks
-            .assign_var(&Lstr::Sref("hello"), inter::LocalType::Param);
+            .assign_var(&Lstr::Sref("hello"), inter::LocalType::Param)
+            .unwrap();
 
         let scope_lvl = scope.scope_level(&Lstr::from("hello")).unwrap();
         assert_eq!(ScopeLevel::Local, scope_lvl);
@@ -946,7 +1136,8 @@ mod tests
         let mut scope = Interscope::new(&proto, &imps, "foo", &args);
         scope
             .blocks
-            .assign_var(&Lstr::Sref("hello"), inter::LocalType::Let);
+            .assign_var(&Lstr::Sref("hello"), inter::LocalType::Let)
+            .unwrap();
 
         assert!(scope.blocks.var_in_scope(&Lstr::from("hello")));
         let hello_lvl = scope.scope_level(&Lstr::from("hello")).unwrap();
@@ -957,7 +1148,8 @@ mod tests
             new_block
                 .scope
                 .blocks
-                .assign_var(&Lstr::Sref("world"), inter::LocalType::Let);
+                .assign_var(&Lstr::Sref("world"), inter::LocalType::Let)
+                .unwrap();
 
             assert!(new_block.scope.blocks.var_in_scope(&Lstr::from("world")));
             let world_lvl =
@@ -984,9 +1176,11 @@ mod tests
         let mut scope = Interscope::new(&proto, &imps, "foo", &args);
 
         let mut new_vars = Vec::default();
-        let patt = Ast::Localid(Lstr::from("x"), SrcLoc::default());
+        let patt_loc = SrcLoc::default();
+        let patt = Ast::Localid(Lstr::from("x"), patt_loc);
 
-        inter::compile_pattern(&mut scope, &mut new_vars, &patt);
+        inter::compile_pattern(&mut scope, &mut new_vars, &patt, &patt_loc)
+            .unwrap();
 
         assert_eq!(1, new_vars.len());
         assert_eq!("x", &**(new_vars.first().unwrap()));
@@ -1004,10 +1198,10 @@ mod tests
             --
             ".to_string();
 
-        let mut loader = Interloader::new(Lstr::Sref("fact.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("fact.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("fact"), input);
         let mut prog = program::Lib::new(loader);
-        prog.read_inter(&Lstr::Sref("fact"));
+        prog.read_inter(&Lstr::Sref("fact")).unwrap();
         // assert that it didn't panic
         assert!(true);
     }
@@ -1027,10 +1221,10 @@ mod tests
             --
             ".to_string();
 
-        let mut loader = Interloader::new(Lstr::Sref("tacos.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("tacos"), input);
         let mut prog = program::Lib::new(loader);
-        prog.read_inter(&Lstr::Sref("tacos"));
+        prog.read_inter(&Lstr::Sref("tacos")).unwrap();
         assert!(true); // didn't panic earlier
     }
 
@@ -1048,10 +1242,10 @@ mod tests
         );
 
         let greeting_str = Lstr::Sref("greeting");
-        let mut loader = Interloader::new(Lstr::Sref("greeting.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("greeting.lma")).unwrap();
         loader.set_mod_txt(greeting_str.clone(), input);
         let mut prog = program::Lib::new(loader);
-        prog.read_inter(&greeting_str);
+        prog.read_inter(&greeting_str).unwrap();
         assert!(true); // didn't panic earlier
     }
 
@@ -1075,10 +1269,10 @@ mod tests
             --
             ".to_string();
 
-        let mut loader = Interloader::new(Lstr::Sref("animals.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("animals.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("animals"), input);
         let mut prog = program::Lib::new(loader);
-        prog.read_inter(&Lstr::Sref("animals"));
+        prog.read_inter(&Lstr::Sref("animals")).unwrap();
         assert!(true); // didn't panic earlier
     }
 
@@ -1096,10 +1290,34 @@ mod tests
             --
             ".to_string();
 
-        let mut loader = Interloader::new(Lstr::Sref("foo.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("foo.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("foo"), input);
         let mut prog = program::Lib::new(loader);
-        prog.read_inter(&Lstr::Sref("foo"));
+        prog.read_inter(&Lstr::Sref("foo")).unwrap();
         assert!(true); // assert that it didn't panic
     }
+
+    #[test]
+    fn test_compile_undefined_vars_collects_errors()
+    {
+        let input = "
+            func foo(): Int ->
+                a + 1
+            --
+
+            func bar(): Int ->
+                b - 1
+            --
+            ".to_string();
+
+        let mut loader = Interloader::new(Lstr::Sref("foo.lma")).unwrap();
+        loader.set_mod_txt(Lstr::Sref("foo"), input);
+        let mut prog = program::Lib::new(loader);
+        let errs = prog.read_inter(&Lstr::Sref("foo")).unwrap_err();
+        assert_eq!(2, errs.len());
+        for e in errs.iter() {
+            assert_eq!(ErrorKind::Undefined, e.kind);
+            assert_eq!("foo", e.module.str());
+        }
+    }
 }
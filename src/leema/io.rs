@@ -2,7 +2,7 @@ use leema::log;
 use leema::lri::Lri;
 use leema::msg::{AppMsg, IoMsg, WorkerMsg};
 use leema::rsrc::{self, Event, IopCtx, Rsrc};
-use leema::val::{MsgVal, Val};
+use leema::val::{self, MsgVal, Val};
 
 use std;
 use std::cell::RefCell;
@@ -109,11 +109,22 @@ pub struct RunQueue
 
 impl RunQueue
 {
-    pub fn spawn(&self, func: Lri) -> futures_oneshot::Receiver<Val>
+    pub fn new(app_send: Sender<AppMsg>) -> RunQueue
+    {
+        RunQueue { app_send }
+    }
+
+    /**
+     * Call a leema function in a new fiber and get its result
+     *
+     * args is the tuple of arguments to pass to func
+     */
+    pub fn spawn(&self, func: Lri, args: Val)
+        -> futures_oneshot::Receiver<Val>
     {
         let (result_send, result_recv) = futures_oneshot::channel();
         self.app_send
-            .send(AppMsg::Spawn2(result_send, func))
+            .send(AppMsg::Spawn2(result_send, func, MsgVal::new(&args)))
             .unwrap();
         result_recv
     }
@@ -231,13 +242,12 @@ impl Io
                 self.next.push_back(iop);
             }
             Some(rsrc_id) => {
-                let opt_rsrc_op = {
-                    let opt_rsrcq = self.resource.get_mut(&rsrc_id);
-                    if opt_rsrcq.is_none() {
-                        panic!("missing queue for rsrc: {}", rsrc_id);
+                let opt_rsrc_op = match self.resource.get_mut(&rsrc_id) {
+                    Some(rsrcq) => rsrcq.push_iop(iop),
+                    None => {
+                        self.fail_closed_iop(iop);
+                        None
                     }
-                    let mut rsrcq = opt_rsrcq.unwrap();
-                    rsrcq.push_iop(iop)
                 };
                 if let Some(rsrc_op) = opt_rsrc_op {
                     self.next.push_back(rsrc_op);
@@ -281,6 +291,11 @@ impl Io
                 self.return_rsrc(rsrc_id, rsrc);
                 self.send_result(worker_id, fiber_id, result);
             }
+            Event::DropRsrc(result) => {
+                vout!("handle Event::DropRsrc\n");
+                self.drop_rsrc(rsrc_id);
+                self.send_result(worker_id, fiber_id, result);
+            }
             Event::Future(libfut) => {
                 vout!("handle Event::Future\n");
                 let rcio: Rc<RefCell<Io>> = self.io.clone().unwrap();
@@ -344,9 +359,7 @@ impl Io
         let h = self.handle.clone();
         let tx = self.worker_tx.clone();
         */
-        let run_queue = RunQueue {
-            app_send: self.app_tx.clone(),
-        };
+        let run_queue = RunQueue::new(self.app_tx.clone());
         IopCtx::new(
             rcio,
             src_worker_id,
@@ -374,6 +387,30 @@ impl Io
             .expect("failed sending iop result to worker");
     }
 
+    /**
+     * Remove a closed resource and fail any iops still waiting for it
+     */
+    pub fn drop_rsrc(&mut self, rsrc_id: Option<i64>)
+    {
+        vout!("drop_rsrc({:?})\n", rsrc_id);
+        let rsrcq = rsrc_id.and_then(|id| self.resource.remove(&id));
+        if let Some(mut q) = rsrcq {
+            while let Some(iop) = q.queue.pop_front() {
+                self.fail_closed_iop(iop);
+            }
+        }
+    }
+
+    fn fail_closed_iop(&mut self, iop: Iop)
+    {
+        let result = rsrc::iop_failure(
+            "rsrc_closed",
+            format!("resource is closed: {:?}", iop.rsrc_id),
+            val::FAILURE_BADINPUT,
+        );
+        self.send_result(iop.src_worker_id, iop.src_fiber_id, result);
+    }
+
     pub fn return_rsrc(&mut self, rsrc_id: Option<i64>, rsrc: Option<Box<Rsrc>>)
     {
         vout!("return_rsrc({:?})\n", rsrc_id);
@@ -462,6 +499,8 @@ pub mod tests
     use leema::val::{MsgVal, Type, Val};
 
     use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
 
     #[derive(Debug)]
     struct MockRsrc {}
@@ -520,6 +559,51 @@ pub mod tests
         })
     }
 
+    /**
+     * Run an io loop on its own thread so iops that return futures
+     * have time to finish. Send IoMsg::Done to stop it.
+     */
+    pub fn spawn_io_thread() -> (
+        mpsc::Sender<msg::IoMsg>,
+        mpsc::Receiver<msg::WorkerMsg>,
+        thread::JoinHandle<()>,
+    )
+    {
+        let (msg_tx, msg_rx) = mpsc::channel::<msg::IoMsg>();
+        let (worker_tx, worker_rx) = mpsc::channel::<msg::WorkerMsg>();
+        msg_tx.send(msg::IoMsg::NewWorker(11, worker_tx)).unwrap();
+        let handle = thread::spawn(move || {
+            let (app_tx, _) = mpsc::channel::<msg::AppMsg>();
+            IoLoop::run(Io::new(app_tx, msg_rx));
+        });
+        (msg_tx, worker_rx, handle)
+    }
+
+    /**
+     * Send an iop to an io thread and wait for its result
+     */
+    pub fn iop_result(
+        msg_tx: &mpsc::Sender<msg::IoMsg>,
+        worker_rx: &mpsc::Receiver<msg::WorkerMsg>,
+        action: rsrc::IopAction,
+        rsrc_id: Option<i64>,
+        params: Vec<(Option<Lstr>, Val)>,
+    ) -> Val
+    {
+        msg_tx
+            .send(msg::IoMsg::Iop {
+                worker_id: 11,
+                fiber_id: 21,
+                action,
+                rsrc_id,
+                params: MsgVal::new(&Val::Tuple(Struple(params))),
+            }).unwrap();
+        match worker_rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            msg::WorkerMsg::IopResult(_, result) => result.take(),
+            other => panic!("expected an iop result: {:?}", other),
+        }
+    }
+
     #[test]
     fn test_io_constructor()
     {
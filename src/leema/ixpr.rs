@@ -1,6 +1,6 @@
 use leema::lstr::Lstr;
 use leema::struple::Struple;
-use leema::val::{Type, Val};
+use leema::val::{SrcLoc, Type, Val};
 
 
 #[derive(Clone)]
@@ -19,12 +19,15 @@ pub struct MatchFailure
 pub enum Source
 {
     Block(Vec<Ixpr>),
-    Call(Box<Ixpr>, Box<Ixpr>),
+    // Call(func, args, column)
+    Call(Box<Ixpr>, Box<Ixpr>, i8),
     Cons(Box<Ixpr>, Box<Ixpr>),
     Construple(Type, Struple<Type>),
     ConstVal(Val),
     EnumConstructor(Type, i16, Box<Ixpr>),
     FieldAccess(Box<Ixpr>, Lstr, Option<i8>),
+    // Fork(var, call)
+    Fork(Lstr, Box<Ixpr>),
     Func(Vec<Lstr>, Vec<Type>, Type, Box<Ixpr>),
     Let(Val, Box<Ixpr>, Vec<MatchFailure>),
     MatchExpr(Box<Ixpr>, Box<Ixpr>),
@@ -55,6 +58,7 @@ impl Source
         match src {
             &Source::ConstVal(ref v) => v.get_type(),
             &Source::Let(_, _, _) => Type::Void,
+            &Source::Fork(_, _) => Type::Void,
             _ => Type::Unknown,
         }
     }
@@ -67,13 +71,23 @@ pub struct Ixpr
 {
     pub src: Source,
     pub line: i16,
+    pub column: i8,
 }
 
 impl Ixpr
 {
     pub fn new(src: Source, line: i16) -> Ixpr
     {
-        Ixpr { src, line }
+        Ixpr {
+            src,
+            line,
+            column: 0,
+        }
+    }
+
+    pub fn loc(&self) -> SrcLoc
+    {
+        SrcLoc::new(self.line, self.column)
     }
 
     pub fn new_block(code: Vec<Ixpr>, line: i16) -> Ixpr
@@ -81,6 +95,7 @@ impl Ixpr
         Ixpr {
             src: Source::Block(code),
             line,
+            column: 0,
         }
     }
 
@@ -89,6 +104,7 @@ impl Ixpr
         Ixpr {
             src: Source::ConstVal(Val::Void),
             line: 0,
+            column: 0,
         }
     }
 
@@ -97,6 +113,7 @@ impl Ixpr
         Ixpr {
             src: Source::ConstVal(src),
             line: lineno,
+            column: 0,
         }
     }
 
@@ -105,21 +122,25 @@ impl Ixpr
         Ixpr {
             src: Source::List(items),
             line,
+            column: 0,
         }
     }
 
     pub fn new_tuple(items: Struple<Ixpr>, mut lineno: i16) -> Ixpr
     {
+        let mut column = 0;
         let mut set_lineno = false;
         for i in items.0.iter() {
             if !set_lineno {
                 lineno = i.1.line;
+                column = i.1.column;
                 set_lineno = true;
             }
         }
         Ixpr {
             src: Source::Tuple(items),
             line: lineno,
+            column,
         }
     }
 
@@ -128,6 +149,7 @@ impl Ixpr
         Ixpr {
             src: Source::Map(items),
             line: lineno,
+            column: 0,
         }
     }
 
@@ -136,6 +158,7 @@ impl Ixpr
         Ixpr {
             src: Source::Cons(Box::new(head), Box::new(tail)),
             line,
+            column: 0,
         }
     }
 
@@ -144,30 +167,36 @@ impl Ixpr
         Ixpr {
             src: Source::Construple(t, flds.clone()),
             line: lineno,
+            column: 0,
         }
     }
 
     pub fn new_match_expr(input: Ixpr, cases: Ixpr) -> Ixpr
     {
         let lineno = input.line;
+        let column = input.column;
         Ixpr {
             src: Source::MatchExpr(Box::new(input), Box::new(cases)),
             line: lineno,
+            column,
         }
     }
 
     pub fn new_match_case(pattern: Val, code: Ixpr, next: Ixpr) -> Ixpr
     {
         let lineno = code.line;
+        let column = code.column;
         Ixpr {
             src: Source::MatchCase(pattern, Box::new(code), Box::new(next)),
             line: lineno,
+            column,
         }
     }
 
     pub fn new_if(test: Ixpr, truth: Ixpr, lies: Option<Ixpr>) -> Ixpr
     {
         let lineno = test.line;
+        let column = test.column;
         Ixpr {
             src: Source::IfExpr(
                 Box::new(test),
@@ -175,15 +204,18 @@ impl Ixpr
                 lies.map(|l| Box::new(l)),
             ),
             line: lineno,
+            column,
         }
     }
 
     pub fn new_field_access(base: Ixpr, sub: Lstr) -> Ixpr
     {
         let lineno = base.line;
+        let column = base.column;
         Ixpr {
             src: Source::FieldAccess(Box::new(base), sub, None),
             line: lineno,
+            column,
         }
     }
 
@@ -192,6 +224,7 @@ impl Ixpr
         Ixpr {
             src: Source::StrMash(items),
             line: lineno,
+            column: 0,
         }
     }
 }
@@ -202,7 +235,7 @@ mod tests
 {
     use leema::ixpr::{Ixpr, Source};
     use leema::lstr::Lstr;
-    use leema::val::Val;
+    use leema::val::{SrcLoc, Val};
 
 
     #[test]
@@ -213,6 +246,7 @@ mod tests
         let expected = Ixpr {
             src: Source::ConstVal(hello),
             line: 7,
+            column: 0,
         };
         assert_eq!(expected, actual);
     }
@@ -233,4 +267,12 @@ mod tests
         }
     }
 
+    #[test]
+    fn test_match_expr_keeps_input_loc()
+    {
+        let mut input = Ixpr::const_val(Val::Int(4), 3);
+        input.column = 12;
+        let mx = Ixpr::new_match_expr(input, Ixpr::noop());
+        assert_eq!(SrcLoc::new(3, 12), mx.loc());
+    }
 }
@@ -1,56 +1,578 @@
 use leema::ast::TokenData;
-use leema::parse::{self, Token};
+use leema::error::{CompileError, CompileResult, ErrorKind};
+use leema::lstr::Lstr;
+use leema::parse::Token;
 use leema::val::SrcLoc;
-use std::ptr;
 
-#[repr(C)]
+
+#[derive(Clone)]
+#[derive(Copy)]
 #[derive(Debug)]
-struct LibTokenBuffer
+#[derive(PartialEq)]
+enum LexState
 {
-    tok: i32,
-    value: *const u8,
-    len: usize,
+    Initial,
+    CallParen,
+    Str,
+    StrId,
+    StrExpr,
+    BlockComment,
+}
 
+/**
+ * Hand written scanner that follows the rules from lexparse/leema.l
+ *
+ * Columns are tracked the same way the flex scanner tracked them,
+ * only lexed tokens move the column forward. Whitespace and comments
+ * don't, and only newlines outside of strings and comments bump the line.
+ * Lexing stops at the first character that can't start a token.
+ */
+struct Lexer<'a>
+{
+    input: &'a [u8],
+    pos: usize,
+    state: LexState,
     lineno: i32,
-    token_column: i32,
-    next_column: i32, // ignored here
-    block_comment_depth: i32,
+    next_column: i32,
+    comment_depth: i32,
+    error: Option<(SrcLoc, String)>,
 }
 
-impl LibTokenBuffer
+impl<'a> Lexer<'a>
 {
-    fn val(&self) -> String
+    fn new(input: &'a str) -> Lexer<'a>
     {
-        let mut valvec = Vec::with_capacity(self.len + 1);
-        unsafe {
-            valvec.set_len(self.len);
-            ptr::copy(self.value, valvec.as_mut_ptr(), self.len + 1);
+        let bytes = input.as_bytes();
+        // the flex scanner stopped at the first null
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        Lexer {
+            input: &bytes[..end],
+            pos: 0,
+            state: LexState::Initial,
+            lineno: 1,
+            next_column: 1,
+            comment_depth: 0,
+            error: None,
+        }
+    }
+
+    /**
+     * Get the next token and the location where it starts
+     */
+    fn next_token(&mut self) -> (Token, SrcLoc)
+    {
+        loop {
+            let start = SrcLoc::new(self.lineno as i16, self.next_column as i8);
+            if self.pos >= self.input.len() {
+                return (Token::EOI, start);
+            }
+            let tok = match self.state {
+                LexState::Initial | LexState::CallParen => self.lex_code(),
+                LexState::Str => self.lex_str(),
+                LexState::StrId => self.lex_str_id(),
+                LexState::StrExpr => self.lex_str_expr(),
+                LexState::BlockComment => self.lex_block_comment(),
+            };
+            if let Some(t) = tok {
+                return (t, start);
+            }
+        }
+    }
+
+    fn lex_code(&mut self) -> Option<Token>
+    {
+        let c = self.peek(0);
+        let next = self.peek(1);
+        let tok = match c {
+            b'\n' => {
+                self.newline(1);
+                return None;
+            }
+            b'\r' => {
+                let len = if next == b'\n' { 2 } else { 1 };
+                self.newline(len);
+                return None;
+            }
+            b' ' | b'\t' => {
+                let len = self.count_while(0, |b| b == b' ' || b == b'\t');
+                self.skip(len);
+                self.state = LexState::Initial;
+                return None;
+            }
+            b'"' => {
+                let tl = self.loc(1);
+                self.state = LexState::Str;
+                return Some(Token::StrOpen(tl));
+            }
+            b'0'..=b'9' => {
+                let len = self.count_while(0, |b| b >= b'0' && b <= b'9');
+                let txt = self.text(len);
+                self.loc(len);
+                Token::INT(i64::from_str_radix(&txt, 10).unwrap())
+            }
+            b'#' => return self.lex_hash(),
+            b'$' => {
+                if next == b'?' {
+                    self.loc(2);
+                    Token::DollarQuestion
+                } else if is_id_start(next) {
+                    let len = 1 + id_len(&self.input[self.pos + 1..]);
+                    let name = self.text(len)[1..].to_string();
+                    let tl = self.loc(len);
+                    Token::TYPE_VAR(TokenData::new(name, tl))
+                } else {
+                    self.loc(1);
+                    Token::DOLLAR
+                }
+            }
+            b'`' => {
+                self.loc(1);
+                Token::BACKTICK
+            }
+            b'-' => {
+                if self.input[self.pos..].starts_with(b"-RUST-") {
+                    self.loc(6);
+                    Token::RUSTBLOCK
+                } else if next == b'>' {
+                    Token::BLOCKARROW(self.loc(2))
+                } else if next == b'-' {
+                    Token::DOUBLEDASH(self.loc(2))
+                } else {
+                    Token::MINUS(self.loc(1))
+                }
+            }
+            b'=' => {
+                if next == b'>' {
+                    Token::TYPEARROW(self.loc(2))
+                } else if next == b'=' {
+                    Token::EQ(self.loc(2))
+                } else {
+                    Token::EQ1(self.loc(1))
+                }
+            }
+            b':' => {
+                if next == b'=' {
+                    self.loc(2);
+                    Token::ASSIGN
+                } else if next == b':' {
+                    Token::DBLCOLON(self.loc(2))
+                } else {
+                    Token::COLON(self.loc(1))
+                }
+            }
+            b'<' => {
+                if next == b'=' {
+                    Token::LTEQ(self.loc(2))
+                } else {
+                    Token::LT(self.loc(1))
+                }
+            }
+            b'>' => {
+                if next == b'=' {
+                    Token::GTEQ(self.loc(2))
+                } else {
+                    Token::GT(self.loc(1))
+                }
+            }
+            b'!' if next == b'=' => Token::NEQ(self.loc(2)),
+            b'(' => {
+                if self.state == LexState::CallParen {
+                    Token::PARENCALL(self.loc(1))
+                } else {
+                    Token::LPAREN(self.loc(1))
+                }
+            }
+            b')' => {
+                let tl = self.loc(1);
+                self.state = LexState::CallParen;
+                return Some(Token::RPAREN(tl));
+            }
+            b'[' => {
+                if self.state == LexState::CallParen {
+                    Token::SquareCall(self.loc(1))
+                } else {
+                    Token::SquareL(self.loc(1))
+                }
+            }
+            b']' => Token::SquareR(self.loc(1)),
+            b'{' => Token::CurlyL(self.loc(1)),
+            b'}' => Token::CurlyR(self.loc(1)),
+            b',' => Token::COMMA(self.loc(1)),
+            b';' => Token::SEMICOLON(self.loc(1)),
+            b'~' => Token::NEGATE(self.loc(1)),
+            b'+' => Token::PLUS(self.loc(1)),
+            b'*' => Token::TIMES(self.loc(1)),
+            b'/' => Token::SLASH(self.loc(1)),
+            b'|' => Token::PIPE(self.loc(1)),
+            b'.' => {
+                self.loc(1);
+                Token::DOT
+            }
+            b'\\' if next == b'n' => Token::ConcatNewline(self.loc(2)),
+            _ if is_id_start(c) => return Some(self.lex_word()),
+            _ => {
+                let bad = String::from_utf8_lossy(&self.input[self.pos..])
+                    .chars()
+                    .next()
+                    .unwrap_or(c as char);
+                let loc =
+                    SrcLoc::new(self.lineno as i16, self.next_column as i8);
+                let msg = format!("unrecognized character: '{}'", bad);
+                self.error = Some((loc, msg));
+                // nothing after this can be lexed reliably
+                self.pos = self.input.len();
+                return None;
+            }
+        };
+        self.state = LexState::Initial;
+        Some(tok)
+    }
+
+    fn lex_word(&mut self) -> Token
+    {
+        let len = id_len(&self.input[self.pos..]);
+        let word = self.text(len);
+        if word == "void" {
+            // void is the one keyword that leaves the state alone
+            self.loc(len);
+            return Token::VOID;
+        }
+        let tl = self.loc(len);
+        let tok = match word.as_str() {
+            "_" => Token::UNDERSCORE,
+            "else" => Token::ELSE(tl),
+            "enum" => Token::ENUM(tl),
+            "failed" => Token::FAILED(tl),
+            "func" => Token::Func(tl),
+            "if" => Token::IF(tl),
+            "import" => Token::IMPORT(tl),
+            "true" => Token::True,
+            "false" => Token::False,
+            "fork" => Token::Fork(tl),
+            "let" => Token::Let(tl),
+            "macro" => Token::MACRO(tl),
+            "match" => Token::MATCH(tl),
+            "mod" => Token::MOD(tl),
+            "struct" => Token::STRUCT(tl),
+            "return" => Token::RETURN(tl),
+            "and" => Token::AND(tl),
+            "or" => Token::OR(tl),
+            "xor" => Token::XOR(tl),
+            "not" => Token::NOT(tl),
+            "Failure" => Token::TYPE_FAILURE,
+            "Int" => Token::TYPE_INT,
+            "Str" => Token::TYPE_STR,
+            "Bool" => Token::TYPE_BOOL,
+            "Void" => Token::TYPE_VOID,
+            _ => {
+                self.state = LexState::CallParen;
+                return Token::ID(TokenData::new(word, tl));
+            }
+        };
+        self.state = LexState::Initial;
+        tok
+    }
+
+    fn lex_hash(&mut self) -> Option<Token>
+    {
+        match self.peek(1) {
+            b'#' => {
+                // line comment, leaves the state and column alone
+                let len = self.count_while(0, |b| b != b'\n');
+                self.skip(len);
+                None
+            }
+            b':' => {
+                self.skip(2);
+                self.comment_depth += 1;
+                self.state = LexState::BlockComment;
+                None
+            }
+            next if is_id_char(next) => {
+                let mut len = self.count_while(1, is_id_char);
+                let last = self.peek(len);
+                if last == b'?' || last == b'!' {
+                    len += 1;
+                }
+                let tag = self.text(len)[1..].to_string();
+                let tl = self.loc(len);
+                Some(Token::HASHTAG(TokenData::new(tag, tl)))
+            }
+            _ => {
+                self.loc(1);
+                self.state = LexState::Initial;
+                Some(Token::TYPE_HASHTAG)
+            }
+        }
+    }
+
+    fn lex_str(&mut self) -> Option<Token>
+    {
+        let next = self.peek(1);
+        match self.peek(0) {
+            b'"' => {
+                self.loc(1);
+                self.state = LexState::Initial;
+                Some(Token::StrClose)
+            }
+            b'$' if next == b'{' => {
+                self.skip(2);
+                self.state = LexState::StrExpr;
+                None
+            }
+            b'$' => {
+                self.loc(1);
+                self.state = LexState::StrId;
+                None
+            }
+            b'\\' => {
+                let escaped = match next {
+                    b'"' => "\"",
+                    b'\\' => "\\",
+                    b'$' => "$",
+                    b'n' => "\n",
+                    _ => {
+                        self.skip(1);
+                        return None;
+                    }
+                };
+                self.loc(2);
+                Some(Token::StrLit(escaped.to_string()))
+            }
+            _ => {
+                let len = self.count_while(0, |b| {
+                    b != b'$' && b != b'\\' && b != b'"'
+                });
+                let lit = self.text(len);
+                self.loc(len);
+                Some(Token::StrLit(lit))
+            }
+        }
+    }
+
+    fn lex_str_id(&mut self) -> Option<Token>
+    {
+        if !is_id_start(self.peek(0)) {
+            self.skip(1);
+            return None;
+        }
+        let len = id_len(&self.input[self.pos..]);
+        let name = self.text(len);
+        let tl = self.loc(len);
+        self.state = LexState::Str;
+        Some(Token::ID(TokenData::new(name, tl)))
+    }
+
+    fn lex_str_expr(&mut self) -> Option<Token>
+    {
+        match self.peek(0) {
+            b'.' => {
+                self.loc(1);
+                Some(Token::DOT)
+            }
+            b'}' => {
+                self.skip(1);
+                self.state = LexState::Str;
+                None
+            }
+            c if is_id_start(c) => {
+                let len = id_len(&self.input[self.pos..]);
+                let name = self.text(len);
+                let tl = self.loc(len);
+                Some(Token::ID(TokenData::new(name, tl)))
+            }
+            _ => {
+                self.skip(1);
+                None
+            }
         }
-        String::from_utf8(valvec).unwrap()
     }
 
-    fn ival(&self) -> i64
+    fn lex_block_comment(&mut self) -> Option<Token>
+    {
+        let next = self.peek(1);
+        match self.peek(0) {
+            b'#' if next == b':' => {
+                self.skip(2);
+                self.comment_depth += 1;
+            }
+            b'#' if next == b';' => {
+                self.skip(2);
+                self.comment_depth -= 1;
+                if self.comment_depth == 0 {
+                    self.state = LexState::Initial;
+                }
+            }
+            b'\\' if next == b'#' => {
+                self.skip(2);
+            }
+            b'#' | b'\\' => {
+                self.skip(1);
+            }
+            _ => {
+                let len = self.count_while(0, |b| b != b'#' && b != b'\\');
+                self.skip(len);
+            }
+        }
+        None
+    }
+
+    fn peek(&self, offset: usize) -> u8
+    {
+        self.input.get(self.pos + offset).cloned().unwrap_or(0)
+    }
+
+    fn count_while<F>(&self, offset: usize, f: F) -> usize
+    where
+        F: Fn(u8) -> bool,
+    {
+        let start = self.pos + offset;
+        let len = self.input[start..].iter().take_while(|b| f(**b)).count();
+        offset + len
+    }
+
+    fn text(&self, len: usize) -> String
+    {
+        let bytes = &self.input[self.pos..self.pos + len];
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    /**
+     * Consume a token of the given length and return its location
+     */
+    fn loc(&mut self, len: usize) -> SrcLoc
+    {
+        let column = self.next_column;
+        self.pos += len;
+        self.next_column += len as i32;
+        SrcLoc::new(self.lineno as i16, column as i8)
+    }
+
+    fn skip(&mut self, len: usize)
+    {
+        self.pos += len;
+    }
+
+    fn newline(&mut self, len: usize)
     {
-        let strval = self.val();
-        let iresult = i64::from_str_radix(&strval, 10);
-        iresult.unwrap()
+        self.pos += len;
+        self.lineno += 1;
+        self.next_column = 1;
+        self.state = LexState::Initial;
     }
 }
 
-enum LexState {}
+fn is_id_start(c: u8) -> bool
+{
+    (c >= b'a' && c <= b'z') || (c >= b'A' && c <= b'Z') || c == b'_'
+}
 
-#[link(name = "leemalex")]
-#[link(name = "stdc++")]
-extern "C" {
-    fn lib_lexscan(input: *const u8) -> *mut LexState;
-    fn lib_lexone(scanner: *mut LexState) -> *const LibTokenBuffer;
-    fn lib_lexclose(scanner: *mut LexState);
+fn is_id_char(c: u8) -> bool
+{
+    is_id_start(c) || (c >= b'0' && c <= b'9')
 }
 
+fn id_len(input: &[u8]) -> usize
+{
+    let len = input.iter().take_while(|c| is_id_char(**c)).count();
+    match input.get(len) {
+        Some(&b'?') | Some(&b'\'') => len + 1,
+        _ => len,
+    }
+}
 
-impl Token
+
+pub fn lex(module: &Lstr, str_input: &str) -> CompileResult<Vec<Token>>
+{
+    let toks = lex_located(module, str_input)?;
+    Ok(toks.into_iter().map(|(t, _)| t).collect())
+}
+
+/**
+ * Lex the input w/ the location of each token, for the parser
+ * to say where a syntax error is
+ *
+ * Fails w/ a syntax error at the first unrecognized character.
+ */
+pub fn lex_located(
+    module: &Lstr,
+    str_input: &str,
+) -> CompileResult<Vec<(Token, SrcLoc)>>
 {
-    fn from_lib(tok: *const LibTokenBuffer) -> Token
+    let mut lexer = Lexer::new(str_input);
+    let mut toks = vec![];
+    loop {
+        let (tok, loc) = lexer.next_token();
+        if tok == Token::EOI {
+            break;
+        }
+        toks.push((tok, loc));
+    }
+    match lexer.error {
+        Some((loc, msg)) => {
+            Err(CompileError::new(module, loc, ErrorKind::Syntax, msg))
+        }
+        None => Ok(toks),
+    }
+}
+
+
+/**
+ * The old flex scanner, kept around to check the rust lexer against it
+ */
+#[cfg(test)]
+mod flex
+{
+    use leema::ast::TokenData;
+    use leema::parse::{self, Token};
+    use leema::val::SrcLoc;
+    use std::ptr;
+
+    #[repr(C)]
+    #[derive(Debug)]
+    struct LibTokenBuffer
+    {
+        tok: i32,
+        value: *const u8,
+        len: usize,
+
+        lineno: i32,
+        token_column: i32,
+        next_column: i32, // ignored here
+        block_comment_depth: i32,
+    }
+
+    impl LibTokenBuffer
+    {
+        fn val(&self) -> String
+        {
+            let mut valvec = Vec::with_capacity(self.len + 1);
+            unsafe {
+                valvec.set_len(self.len);
+                ptr::copy(self.value, valvec.as_mut_ptr(), self.len + 1);
+            }
+            String::from_utf8(valvec).unwrap()
+        }
+
+        fn ival(&self) -> i64
+        {
+            let strval = self.val();
+            let iresult = i64::from_str_radix(&strval, 10);
+            iresult.unwrap()
+        }
+    }
+
+    enum LexState {}
+
+    #[link(name = "leemalex")]
+    #[link(name = "stdc++")]
+    extern "C" {
+        fn lib_lexscan(input: *const u8) -> *mut LexState;
+        fn lib_lexone(scanner: *mut LexState) -> *const LibTokenBuffer;
+        fn lib_lexclose(scanner: *mut LexState);
+    }
+
+    fn token_from_lib(tok: *const LibTokenBuffer) -> Token
     {
         unsafe {
             let tl =
@@ -94,15 +616,6 @@ impl Token
                 parse::TOKEN_ASSIGN => Token::ASSIGN,
                 parse::TOKEN_Fork => Token::Fork(tl),
                 parse::TOKEN_Let => Token::Let(tl),
-                /*
-                parse::TOKEN_CurlyL => {
-                    Token::CurlyL
-                }
-                parse::TOKEN_CurlyR => {
-                    Token::CurlyR
-                }
-                */
-                /* string tokens */
                 parse::TOKEN_StrOpen => Token::StrOpen(tl),
                 parse::TOKEN_StrLit => Token::StrLit((*tok).val()),
                 parse::TOKEN_StrClose => Token::StrClose,
@@ -142,42 +655,42 @@ impl Token
                 parse::TOKEN_EOI => Token::EOI,
                 parse::TOKEN_ANY => Token::ANY,
                 parse::TOKEN_DollarQuestion => Token::DollarQuestion,
+                parse::TOKEN_DOLLAR => Token::DOLLAR,
+                parse::TOKEN_BACKTICK => Token::BACKTICK,
+                parse::TOKEN_VOID => Token::VOID,
                 _ => {
                     panic!("Unrecognized token: {:?}", (*tok));
                 }
             }
         }
     }
-}
-
-
-pub fn lex(str_input: &str) -> Vec<Token>
-{
-    // need to append \0 so C library knows where the string stops
-    let input = format!("{}\0", str_input);
-    let lexer;
-    unsafe {
-        lexer = lib_lexscan(input.as_ptr());
-    }
 
-    let mut toks = vec![];
-    let mut i = 0;
-    loop {
-        let tok;
+    pub fn lex(str_input: &str) -> Vec<Token>
+    {
+        // need to append \0 so C library knows where the string stops
+        let input = format!("{}\0", str_input);
+        let lexer;
         unsafe {
-            let libtok = lib_lexone(lexer);
-            tok = Token::from_lib(libtok);
+            lexer = lib_lexscan(input.as_ptr());
         }
-        if tok == Token::EOI {
-            break;
+
+        let mut toks = vec![];
+        loop {
+            let tok;
+            unsafe {
+                let libtok = lib_lexone(lexer);
+                tok = token_from_lib(libtok);
+            }
+            if tok == Token::EOI {
+                break;
+            }
+            toks.push(tok);
         }
-        toks.push(tok);
-        i = i + 1;
-    }
-    unsafe {
-        lib_lexclose(lexer);
+        unsafe {
+            lib_lexclose(lexer);
+        }
+        toks
     }
-    toks
 }
 
 
@@ -185,13 +698,25 @@ pub fn lex(str_input: &str) -> Vec<Token>
 mod tests
 {
     use leema::ast::TokenData;
+    use leema::error::ErrorKind;
+    use leema::lstr::Lstr;
     use leema::parse::Token;
     use leema::val::SrcLoc;
 
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::path::PathBuf;
+
+
+    fn lex(input: &str) -> Vec<Token>
+    {
+        super::lex(&Lstr::Sref("test"), input).unwrap()
+    }
+
     #[test]
     fn test_lex_int()
     {
-        let actual = super::lex("5");
+        let actual = lex("5");
         assert_eq!(1, actual.len());
         assert_eq!(Token::INT(5), actual[0]);
     }
@@ -199,7 +724,7 @@ mod tests
     #[test]
     fn test_lex_minus_int()
     {
-        let actual = super::lex("-7");
+        let actual = lex("-7");
         assert_eq!(2, actual.len());
         assert_eq!(Token::MINUS(SrcLoc::new(1, 1)), actual[0]);
         assert_eq!(Token::INT(7), actual[1]);
@@ -208,7 +733,7 @@ mod tests
     #[test]
     fn test_lex_string_id()
     {
-        let actual = super::lex("\"hello $who\n\"");
+        let actual = lex("\"hello $who\n\"");
         assert_eq!(5, actual.len());
 
         assert_eq!(Token::StrOpen(SrcLoc::new(1, 1)), actual[0]);
@@ -221,10 +746,62 @@ mod tests
         assert_eq!(Token::StrClose, actual[4]);
     }
 
+    #[test]
+    fn test_lex_string_expr()
+    {
+        let actual = lex("\"x is ${p.x}\"");
+        assert_eq!(6, actual.len());
+        assert_eq!(Token::StrLit("x is ".to_string()), actual[1]);
+        assert_eq!(
+            Token::ID(TokenData::new("p".to_string(), SrcLoc::new(1, 7))),
+            actual[2]
+        );
+        assert_eq!(Token::DOT, actual[3]);
+        assert_eq!(
+            Token::ID(TokenData::new("x".to_string(), SrcLoc::new(1, 9))),
+            actual[4]
+        );
+        assert_eq!(Token::StrClose, actual[5]);
+    }
+
+    #[test]
+    fn test_lex_nested_block_comment()
+    {
+        let actual = lex("1 #: a #: b #; c #; 2");
+        assert_eq!(vec![Token::INT(1), Token::INT(2)], actual);
+    }
+
+    #[test]
+    fn test_lex_call_paren()
+    {
+        let actual = lex("foo(x) (y)");
+        assert_eq!(Token::PARENCALL(SrcLoc::new(1, 4)), actual[1]);
+        assert_eq!(Token::LPAREN(SrcLoc::new(1, 7)), actual[4]);
+    }
+
+    #[test]
+    fn test_lex_located()
+    {
+        let m = Lstr::Sref("test");
+        let actual = super::lex_located(&m, "x +\n  \"y\" 3").unwrap();
+        let locs: Vec<SrcLoc> = actual.iter().map(|t| t.1).collect();
+        assert_eq!(
+            vec![
+                SrcLoc::new(1, 1),
+                SrcLoc::new(1, 2),
+                SrcLoc::new(2, 1),
+                SrcLoc::new(2, 2),
+                SrcLoc::new(2, 3),
+                SrcLoc::new(2, 4),
+            ],
+            locs
+        );
+    }
+
     #[test]
     fn test_lex_enum_variants()
     {
-        let actual = super::lex(
+        let actual = lex(
             "
         enum Animal
         |Dog
@@ -239,4 +816,39 @@ mod tests
         assert_eq!(21, actual.len());
     }
 
+    #[test]
+    fn test_lex_matches_flex()
+    {
+        let mut dirs = vec![PathBuf::from("T")];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if path.extension().map_or(true, |ext| ext != "lma") {
+                    continue;
+                }
+                let mut input = String::new();
+                File::open(&path)
+                    .unwrap()
+                    .read_to_string(&mut input)
+                    .unwrap();
+                let expected = super::flex::lex(&input);
+                let actual = lex(&input);
+                assert_eq!(expected, actual, "tokens differ for {:?}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lex_unrecognized_char_fails()
+    {
+        let m = Lstr::Sref("test");
+        let err = super::lex_located(&m, "x +\n  @y").unwrap_err();
+        assert_eq!(ErrorKind::Syntax, err.kind);
+        assert_eq!(SrcLoc::new(2, 1), err.loc);
+        assert_eq!("unrecognized character: '@'", err.msg.str());
+    }
 }
@@ -0,0 +1,504 @@
+use leema::code::Code;
+use leema::fiber::Fiber;
+use leema::frame::Event;
+use leema::list;
+use leema::lstr::Lstr;
+use leema::val::{self, Val};
+
+
+const BASE64_CHARS: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn buffer_param(f: &Fiber, p: i8) -> &Vec<u8>
+{
+    match f.head.get_param(p) {
+        &Val::Buffer(ref b) => b,
+        other => {
+            panic!("not a buffer: {:?}", other);
+        }
+    }
+}
+
+fn bad_input(f: &Fiber, tag: &'static str, msg: String) -> Val
+{
+    Val::failure(
+        Val::Hashtag(Lstr::Sref(tag)),
+        Val::Str(Lstr::from(msg)),
+        f.head.trace.fail_here(),
+        val::FAILURE_BADINPUT,
+    )
+}
+
+/**
+ * Check that [start, start + len) fits in a buffer of size buflen
+ */
+fn in_bounds(start: i64, len: i64, buflen: usize) -> bool
+{
+    start >= 0 && len >= 0 && start + len <= buflen as i64
+}
+
+/**
+ * Read an int size and endianness, 1, 2, 4 or 8 and #big or #little
+ */
+fn int_format(size: &Val, endian: &Val) -> Option<(usize, bool)>
+{
+    let size = match size {
+        &Val::Int(s) if s == 1 || s == 2 || s == 4 || s == 8 => s as usize,
+        _ => return None,
+    };
+    match endian {
+        &Val::Hashtag(ref e) if e.str() == "big" => Some((size, true)),
+        &Val::Hashtag(ref e) if e.str() == "little" => Some((size, false)),
+        _ => None,
+    }
+}
+
+/**
+ * buffer::new(size: Int): Buffer
+ *
+ * a buffer of size zero bytes
+ */
+pub fn new(f: &mut Fiber) -> Event
+{
+    let result = {
+        let size = f.head.get_param(0).to_int();
+        if size < 0 {
+            bad_input(f, "invalid_size", format!("negative size: {}", size))
+        } else {
+            Val::Buffer(vec![0; size as usize])
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+/**
+ * buffer::from_list(bytes: [Int]): Buffer
+ *
+ * fails w/ #invalid_byte if any int isn't from 0 to 255
+ */
+pub fn from_list(f: &mut Fiber) -> Event
+{
+    let result = {
+        let src = f.head.get_param(0);
+        let bad = list::iter(src).find(|b| {
+            let i = b.to_int();
+            i < 0 || i > 255
+        });
+        match bad {
+            Some(b) => {
+                bad_input(f, "invalid_byte", format!("not a byte: {}", b))
+            }
+            None => {
+                Val::Buffer(list::iter(src).map(|b| b.to_int() as u8).collect())
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+pub fn to_list(f: &mut Fiber) -> Event
+{
+    let result = {
+        let bytes: Vec<Val> =
+            buffer_param(f, 0).iter().map(|b| Val::Int(*b as i64)).collect();
+        list::from_vec(&bytes)
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+pub fn len(f: &mut Fiber) -> Event
+{
+    let len = buffer_param(f, 0).len();
+    f.head.parent.set_result(Val::Int(len as i64));
+    Event::success()
+}
+
+/**
+ * buffer::get(b: Buffer, i: Int): Int
+ *
+ * the byte at index i, fails w/ #index_out_of_bounds
+ */
+pub fn get(f: &mut Fiber) -> Event
+{
+    let result = {
+        let buf = buffer_param(f, 0);
+        let i = f.head.get_param(1).to_int();
+        if in_bounds(i, 1, buf.len()) {
+            Val::Int(buf[i as usize] as i64)
+        } else {
+            bad_input(
+                f,
+                "index_out_of_bounds",
+                format!("no byte {} in buffer of len {}", i, buf.len()),
+            )
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+/**
+ * buffer::slice(b: Buffer, start: Int, end: Int): Buffer
+ *
+ * end is exclusive, fails w/ #index_out_of_bounds
+ */
+pub fn slice(f: &mut Fiber) -> Event
+{
+    let result = {
+        let buf = buffer_param(f, 0);
+        let start = f.head.get_param(1).to_int();
+        let end = f.head.get_param(2).to_int();
+        if in_bounds(start, end - start, buf.len()) {
+            Val::Buffer(buf[start as usize..end as usize].to_vec())
+        } else {
+            bad_input(
+                f,
+                "index_out_of_bounds",
+                format!(
+                    "cannot slice {}..{} from buffer of len {}",
+                    start,
+                    end,
+                    buf.len()
+                ),
+            )
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+pub fn concat(f: &mut Fiber) -> Event
+{
+    let result = {
+        let mut a = buffer_param(f, 0).clone();
+        a.extend_from_slice(buffer_param(f, 1));
+        a
+    };
+    f.head.parent.set_result(Val::Buffer(result));
+    Event::success()
+}
+
+/**
+ * buffer::pack_int(i: Int, size: Int, endian: #): Buffer
+ *
+ * size is 1, 2, 4 or 8 bytes and endian is #big or #little.
+ * fails w/ #int_out_of_range if i doesn't fit in size bytes
+ */
+pub fn pack_int(f: &mut Fiber) -> Event
+{
+    let result = {
+        let i = f.head.get_param(0).to_int();
+        let format = int_format(f.head.get_param(1), f.head.get_param(2));
+        match format {
+            Some((size, big_endian)) => {
+                let bits = size as u32 * 8;
+                // allow anything that fits as signed or unsigned
+                let fits = bits == 64
+                    || (i >= -(1 << (bits - 1)) && i < (1 << bits));
+                if fits {
+                    let mut bytes: Vec<u8> =
+                        (0..size).map(|b| (i >> (b * 8)) as u8).collect();
+                    if big_endian {
+                        bytes.reverse();
+                    }
+                    Val::Buffer(bytes)
+                } else {
+                    bad_input(
+                        f,
+                        "int_out_of_range",
+                        format!("{} does not fit in {} bytes", i, size),
+                    )
+                }
+            }
+            None => {
+                bad_input(
+                    f,
+                    "invalid_int_format",
+                    format!(
+                        "int size must be 1, 2, 4 or 8 and endian #big or \
+                         #little: {}, {}",
+                        f.head.get_param(1),
+                        f.head.get_param(2)
+                    ),
+                )
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+fn unpack(f: &mut Fiber, signed: bool) -> Event
+{
+    let result = {
+        let buf = buffer_param(f, 0);
+        let offset = f.head.get_param(1).to_int();
+        let format = int_format(f.head.get_param(2), f.head.get_param(3));
+        match format {
+            Some((size, big_endian)) => {
+                if in_bounds(offset, size as i64, buf.len()) {
+                    let start = offset as usize;
+                    let mut bytes = buf[start..start + size].to_vec();
+                    if !big_endian {
+                        bytes.reverse();
+                    }
+                    let mut i = bytes.iter().fold(0u64, |acc, b| {
+                        (acc << 8) | *b as u64
+                    }) as i64;
+                    let bits = size as u32 * 8;
+                    if signed && bits < 64 && i >= 1 << (bits - 1) {
+                        i -= 1 << bits;
+                    }
+                    Val::Int(i)
+                } else {
+                    bad_input(
+                        f,
+                        "index_out_of_bounds",
+                        format!(
+                            "cannot read {} bytes at {} from buffer of len {}",
+                            size,
+                            offset,
+                            buf.len()
+                        ),
+                    )
+                }
+            }
+            None => {
+                bad_input(
+                    f,
+                    "invalid_int_format",
+                    format!(
+                        "int size must be 1, 2, 4 or 8 and endian #big or \
+                         #little: {}, {}",
+                        f.head.get_param(2),
+                        f.head.get_param(3)
+                    ),
+                )
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+/**
+ * buffer::unpack_int(b: Buffer, offset: Int, size: Int, endian: #): Int
+ *
+ * read a signed int of size bytes starting at offset
+ */
+pub fn unpack_int(f: &mut Fiber) -> Event
+{
+    unpack(f, true)
+}
+
+/**
+ * buffer::unpack_uint(b: Buffer, offset: Int, size: Int, endian: #): Int
+ */
+pub fn unpack_uint(f: &mut Fiber) -> Event
+{
+    unpack(f, false)
+}
+
+/**
+ * buffer::from_str(s: Str): Buffer
+ *
+ * the utf8 bytes of s
+ */
+pub fn from_str(f: &mut Fiber) -> Event
+{
+    let result = f.head.get_param(0).str().as_bytes().to_vec();
+    f.head.parent.set_result(Val::Buffer(result));
+    Event::success()
+}
+
+/**
+ * buffer::to_str(b: Buffer): Str
+ *
+ * fails w/ #invalid_utf8 if b isn't valid utf8
+ */
+pub fn to_str(f: &mut Fiber) -> Event
+{
+    let result = {
+        let buf = buffer_param(f, 0);
+        match String::from_utf8(buf.clone()) {
+            Ok(s) => Val::Str(Lstr::from(s)),
+            Err(e) => bad_input(f, "invalid_utf8", format!("{}", e)),
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+pub fn to_hex(f: &mut Fiber) -> Event
+{
+    let result: String = buffer_param(f, 0)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    f.head.parent.set_result(Val::Str(Lstr::from(result)));
+    Event::success()
+}
+
+/**
+ * buffer::from_hex(s: Str): Buffer
+ *
+ * fails w/ #invalid_hex if s isn't pairs of hex digits
+ */
+pub fn from_hex(f: &mut Fiber) -> Event
+{
+    let result = {
+        let src = f.head.get_param(0).str();
+        match decode_hex(src) {
+            Some(bytes) => Val::Buffer(bytes),
+            None => bad_input(f, "invalid_hex", format!("not hex: {:?}", src)),
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+pub fn to_base64(f: &mut Fiber) -> Event
+{
+    let result = encode_base64(buffer_param(f, 0));
+    f.head.parent.set_result(Val::Str(Lstr::from(result)));
+    Event::success()
+}
+
+/**
+ * buffer::from_base64(s: Str): Buffer
+ *
+ * fails w/ #invalid_base64 if s isn't padded, standard base64
+ */
+pub fn from_base64(f: &mut Fiber) -> Event
+{
+    let result = {
+        let src = f.head.get_param(0).str();
+        match decode_base64(src) {
+            Some(bytes) => Val::Buffer(bytes),
+            None => {
+                bad_input(f, "invalid_base64", format!("not base64: {:?}", src))
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+fn decode_hex(src: &str) -> Option<Vec<u8>>
+{
+    let digits = src.as_bytes();
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let hi = (pair[0] as char).to_digit(16)?;
+            let lo = (pair[1] as char).to_digit(16)?;
+            Some((hi * 16 + lo) as u8)
+        }).collect()
+}
+
+fn encode_base64(bytes: &[u8]) -> String
+{
+    let mut result = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let c = BASE64_CHARS[(n >> (18 - 6 * i)) & 0x3f];
+                result.push(c as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+fn decode_base64(src: &str) -> Option<Vec<u8>>
+{
+    let chars = src.as_bytes();
+    if chars.len() % 4 != 0 {
+        return None;
+    }
+    let mut result = Vec::with_capacity(chars.len() / 4 * 3);
+    let nchunks = chars.len() / 4;
+    for (ci, chunk) in chars.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        // padding is only allowed at the end
+        if padding > 2 || (padding > 0 && ci + 1 < nchunks) {
+            return None;
+        }
+        let mut n = 0;
+        for c in chunk[..4 - padding].iter() {
+            let v = BASE64_CHARS.iter().position(|b| b == c)?;
+            n = n << 6 | v;
+        }
+        n <<= 6 * padding;
+        let decoded = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        result.extend_from_slice(&decoded[..3 - padding]);
+    }
+    Some(result)
+}
+
+
+pub fn load_rust_func(func_name: &str) -> Option<Code>
+{
+    match func_name {
+        "concat" => Some(Code::Rust(concat)),
+        "from_base64" => Some(Code::Rust(from_base64)),
+        "from_hex" => Some(Code::Rust(from_hex)),
+        "from_list" => Some(Code::Rust(from_list)),
+        "from_str" => Some(Code::Rust(from_str)),
+        "get" => Some(Code::Rust(get)),
+        "len" => Some(Code::Rust(len)),
+        "new" => Some(Code::Rust(new)),
+        "pack_int" => Some(Code::Rust(pack_int)),
+        "slice" => Some(Code::Rust(slice)),
+        "to_base64" => Some(Code::Rust(to_base64)),
+        "to_hex" => Some(Code::Rust(to_hex)),
+        "to_list" => Some(Code::Rust(to_list)),
+        "to_str" => Some(Code::Rust(to_str)),
+        "unpack_int" => Some(Code::Rust(unpack_int)),
+        "unpack_uint" => Some(Code::Rust(unpack_uint)),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::lib_buffer::{decode_base64, decode_hex, encode_base64};
+
+
+    #[test]
+    fn test_base64_round_trip()
+    {
+        assert_eq!("", encode_base64(b""));
+        assert_eq!("Zg==", encode_base64(b"f"));
+        assert_eq!("Zm8=", encode_base64(b"fo"));
+        assert_eq!("Zm9vYmFy", encode_base64(b"foobar"));
+        assert_eq!(Some(b"fooba".to_vec()), decode_base64("Zm9vYmE="));
+        assert_eq!(None, decode_base64("Zm9vYmE"));
+        assert_eq!(None, decode_base64("Zg==Zm8="));
+        assert_eq!(None, decode_base64("Zm9*"));
+    }
+
+    #[test]
+    fn test_decode_hex()
+    {
+        assert_eq!(Some(vec![0x00, 0xff, 0x1a]), decode_hex("00ff1A"));
+        assert_eq!(None, decode_hex("0ff"));
+        assert_eq!(None, decode_hex("zz"));
+    }
+}
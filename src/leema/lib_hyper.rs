@@ -1,8 +1,10 @@
 use leema::code::Code;
 use leema::log;
+use leema::lmap::Lmap;
 use leema::lri::Lri;
 use leema::lstr::Lstr;
 use leema::rsrc::{self, Rsrc, RunQueue};
+use leema::struple::Struple;
 use leema::val::{Type, Val};
 
 use std::fmt;
@@ -11,7 +13,7 @@ use std::net::{IpAddr, SocketAddr};
 use std::thread;
 
 use futures::sync::oneshot as futures_oneshot;
-use futures::{future, Future};
+use futures::{future, Future, Stream};
 use hyper::service::service_fn;
 use hyper::{Body, Request, Response, Server, StatusCode};
 
@@ -120,22 +122,146 @@ pub fn handle_request(
 ) -> BoxFut
 {
     vout!("handle_request({},\n\t{:?})", func, req);
-    Box::new(
-        caller
-            .spawn(func)
-            .and_then(|v| {
-                let msg = format!("{}", v);
-                vout!("response msg: {}", msg);
-                future::ok(Response::new(Body::from(msg)))
-            }).or_else(|e| {
-                println!("request error: {:?}", e);
-                let resp: Response<Body> = Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(Body::from("server error\n"))
-                    .unwrap();
-                future::ok(resp)
-            }),
-    )
+    let runq = caller.clone();
+    call_handler(req, move |args| runq.spawn(func, args))
+}
+
+/**
+ * Call a leema handler w/ a request and convert its result to a response
+ *
+ * The body is collected first, then spawn is called w/ a (http::Request,)
+ * tuple and should start the handler and return its result.
+ */
+pub fn call_handler<F>(req: Request<Body>, spawn: F) -> BoxFut
+where
+    F: FnOnce(Val) -> futures_oneshot::Receiver<Val> + Send + 'static,
+{
+    let (parts, body) = req.into_parts();
+    Box::new(body.concat2().then(move |body_result| -> BoxFut {
+        let body_bytes = match body_result {
+            Ok(chunk) => chunk.to_vec(),
+            Err(e) => {
+                println!("request body error: {:?}", e);
+                let resp =
+                    error_response(StatusCode::BAD_REQUEST, "bad request\n");
+                return Box::new(future::ok(resp));
+            }
+        };
+        let head = Request::from_parts(parts, ());
+        let reqval = request_val(&head, body_bytes);
+        let args = Val::Tuple(Struple::new_indexed(vec![reqval]));
+        Box::new(
+            spawn(args)
+                .and_then(|v| future::ok(response_from_val(v)))
+                .or_else(|e| {
+                    println!("request error: {:?}", e);
+                    future::ok(error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "server error\n",
+                    ))
+                }),
+        )
+    }))
+}
+
+/**
+ * Convert a hyper request to an http::Request
+ *
+ * (method, path, query, headers, body, body_buffer)
+ */
+pub fn request_val(req: &Request<()>, body: Vec<u8>) -> Val
+{
+    let mut headers = Lmap::new();
+    for name in req.headers().keys() {
+        let values: Vec<String> = req
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect();
+        headers = Lmap::insert(
+            &headers,
+            Val::Str(Lstr::from(name.as_str().to_string())),
+            Val::Str(Lstr::from(values.join(", "))),
+        );
+    }
+    let body_str = String::from_utf8_lossy(&body).into_owned();
+    let fields = vec![
+        (
+            Some(Lstr::Sref("method")),
+            Val::Str(Lstr::from(req.method().as_str().to_string())),
+        ),
+        (
+            Some(Lstr::Sref("path")),
+            Val::Str(Lstr::from(req.uri().path().to_string())),
+        ),
+        (
+            Some(Lstr::Sref("query")),
+            Val::Str(Lstr::from(req.uri().query().unwrap_or("").to_string())),
+        ),
+        (Some(Lstr::Sref("headers")), Val::Map(headers)),
+        (Some(Lstr::Sref("body")), Val::Str(Lstr::from(body_str))),
+        (Some(Lstr::Sref("body_buffer")), Val::Buffer(body)),
+    ];
+    let typ = Lri::with_modules(Lstr::Sref("http"), Lstr::Sref("Request"));
+    Val::Struct(typ, Struple(fields))
+}
+
+/**
+ * Convert the handler's result to a hyper response
+ *
+ * An http::Response sets the status, headers and body. Any other
+ * value is formatted as the body of a 200 response.
+ */
+pub fn response_from_val(v: Val) -> Response<Body>
+{
+    let resp = match v {
+        Val::Struct(ref typ, ref flds) if typ.localid.str() == "Response" => {
+            build_response(flds)
+        }
+        Val::Failure(ref tag, ref msg, _, _) => {
+            println!("request failure: {} {}", tag, msg);
+            None
+        }
+        _ => {
+            let msg = format!("{}", v);
+            vout!("response msg: {}", msg);
+            Some(Response::new(Body::from(msg)))
+        }
+    };
+    resp.unwrap_or_else(|| {
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "server error\n")
+    })
+}
+
+fn build_response(flds: &Struple<Val>) -> Option<Response<Body>>
+{
+    let status = match flds.find("status") {
+        Some((_, &Val::Int(s))) => StatusCode::from_u16(s as u16).ok()?,
+        _ => StatusCode::OK,
+    };
+    let mut builder = Response::builder();
+    builder.status(status);
+    if let Some((_, &Val::Map(ref headers))) = flds.find("headers") {
+        for (k, v) in Lmap::iter(headers) {
+            let (name, value) = (format!("{}", k), format!("{}", v));
+            builder.header(name.as_str(), value.as_str());
+        }
+    }
+    let body = match flds.find("body") {
+        Some((_, &Val::Str(ref s))) => Body::from(s.str().to_string()),
+        Some((_, &Val::Buffer(ref b))) => Body::from(b.clone()),
+        _ => Body::empty(),
+    };
+    builder.body(body).ok()
+}
+
+fn error_response(status: StatusCode, msg: &'static str) -> Response<Body>
+{
+    Response::builder()
+        .status(status)
+        .body(Body::from(msg))
+        .unwrap()
 }
 
 pub fn load_rust_func(func_name: &str) -> Option<Code>
@@ -145,3 +271,105 @@ pub fn load_rust_func(func_name: &str) -> Option<Code>
         _ => None,
     }
 }
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::lib_hyper;
+    use leema::lmap::Lmap;
+    use leema::lri::Lri;
+    use leema::lstr::Lstr;
+    use leema::msg::AppMsg;
+    use leema::rsrc::RunQueue;
+    use leema::struple::Struple;
+    use leema::val::Val;
+
+    use std::sync::mpsc;
+
+    use hyper::{Request, StatusCode};
+
+
+    #[test]
+    fn test_request_val()
+    {
+        let req = Request::post("/tacos?n=3")
+            .header("X-Filling", "fish")
+            .body(())
+            .unwrap();
+        let reqval = lib_hyper::request_val(&req, b"hot".to_vec());
+        let flds = match reqval {
+            Val::Struct(_, flds) => flds,
+            _ => panic!("request is not a struct: {:?}", reqval),
+        };
+        assert_eq!(
+            Val::Str(Lstr::Sref("POST")),
+            *flds.find("method").unwrap().1
+        );
+        assert_eq!(
+            Val::Str(Lstr::Sref("/tacos")),
+            *flds.find("path").unwrap().1
+        );
+        assert_eq!(
+            Val::Str(Lstr::Sref("n=3")),
+            *flds.find("query").unwrap().1
+        );
+        assert_eq!(
+            Val::Str(Lstr::Sref("hot")),
+            *flds.find("body").unwrap().1
+        );
+        let headers = match flds.find("headers").unwrap().1 {
+            &Val::Map(ref h) => h.clone(),
+            other => panic!("headers are not a map: {:?}", other),
+        };
+        assert_eq!(
+            Some(&Val::Str(Lstr::Sref("fish"))),
+            Lmap::get(&headers, &Val::Str(Lstr::Sref("x-filling")))
+        );
+    }
+
+    #[test]
+    fn test_spawn_request_val()
+    {
+        let (app_tx, app_rx) = mpsc::channel();
+        let runq = RunQueue::new(app_tx);
+        let req = Request::get("/tacos").body(()).unwrap();
+        // the body buffer has to be cloned to get to the worker
+        let reqval = lib_hyper::request_val(&req, vec![0, 159, 146, 150]);
+        let args = Val::Tuple(Struple::new_indexed(vec![reqval]));
+        let func = Lri::with_modules(Lstr::Sref("app"), Lstr::Sref("main"));
+        let _result = runq.spawn(func.clone(), args.clone());
+
+        match app_rx.recv().unwrap() {
+            AppMsg::Spawn2(_, spawned, msg_args) => {
+                assert_eq!(func, spawned);
+                assert_eq!(args, msg_args.take());
+            }
+            _ => panic!("expected a spawn message"),
+        }
+    }
+
+    #[test]
+    fn test_response_from_val()
+    {
+        let headers = Lmap::insert(
+            &Lmap::new(),
+            Val::Str(Lstr::Sref("content-type")),
+            Val::Str(Lstr::Sref("text/plain")),
+        );
+        let respval = Val::Struct(
+            Lri::with_modules(Lstr::Sref("http"), Lstr::Sref("Response")),
+            Struple(vec![
+                (Some(Lstr::Sref("status")), Val::Int(404)),
+                (Some(Lstr::Sref("headers")), Val::Map(headers)),
+                (Some(Lstr::Sref("body")), Val::Str(Lstr::Sref("no tacos"))),
+            ]),
+        );
+        let resp = lib_hyper::response_from_val(respval);
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+        assert_eq!("text/plain", resp.headers()["content-type"]);
+
+        let plain = lib_hyper::response_from_val(Val::Int(5));
+        assert_eq!(StatusCode::OK, plain.status());
+    }
+}
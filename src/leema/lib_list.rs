@@ -2,6 +2,15 @@ use leema::code::Code;
 use leema::fiber::Fiber;
 use leema::frame;
 use leema::list;
+use leema::lstr::Lstr;
+use leema::struple::Struple;
+use leema::val::{self, Val};
+
+/*
+ * functions that call back into leema (map, filter, fold, etc)
+ * are written in list.lma so each call goes through the fiber
+ * like any other leema call
+ */
 
 
 pub fn sort(f: &mut Fiber) -> frame::Event
@@ -12,10 +21,138 @@ pub fn sort(f: &mut Fiber) -> frame::Event
     frame::Event::success()
 }
 
+pub fn reverse(f: &mut Fiber) -> frame::Event
+{
+    let result = list::reverse(f.head.e.get_param(0));
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+pub fn concat(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let a = f.head.e.get_param(0);
+        let b = f.head.e.get_param(1);
+        list::concat(a, b)
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+pub fn len(f: &mut Fiber) -> frame::Event
+{
+    let result = list::len(f.head.e.get_param(0));
+    f.head.parent.set_result(Val::Int(result as i64));
+    frame::Event::success()
+}
+
+/**
+ * list::zip(a: [$A], b: [$B]): [($A, $B)]
+ *
+ * stops at the end of the shorter list
+ */
+pub fn zip(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let a = f.head.e.get_param(0);
+        let b = f.head.e.get_param(1);
+        let pairs: Vec<Val> = list::iter(a)
+            .zip(list::iter(b))
+            .map(|(x, y)| {
+                Val::Tuple(Struple::new_indexed(vec![x.clone(), y.clone()]))
+            })
+            .collect();
+        list::from_vec(&pairs)
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+/**
+ * list::take(items: [$A], n: Int): [$A]
+ *
+ * the first n items, or all of them if there are fewer than n
+ */
+pub fn take(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let src = f.head.e.get_param(0);
+        let n = f.head.e.get_param(1).to_int().max(0) as usize;
+        let items: Vec<Val> = list::iter(src).take(n).cloned().collect();
+        list::from_vec(&items)
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+/**
+ * list::drop(items: [$A], n: Int): [$A]
+ *
+ * the items after the first n, shares the tail w/ the input
+ */
+pub fn drop(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let mut it = f.head.e.get_param(0);
+        let n = f.head.e.get_param(1).to_int();
+        for _ in 0..n {
+            if *it == Val::Nil {
+                break;
+            }
+            it = &**list::take_ref(it).1;
+        }
+        it.clone()
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+/**
+ * list::nth(items: [$A], n: Int): $A
+ *
+ * fails w/ #index_out_of_bounds if there's no item n
+ */
+pub fn nth(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let src = f.head.e.get_param(0);
+        let n = f.head.e.get_param(1).to_int();
+        let found = if n < 0 {
+            None
+        } else {
+            list::iter(src).nth(n as usize)
+        };
+        match found {
+            Some(item) => item.clone(),
+            None => {
+                Val::failure(
+                    Val::Hashtag(Lstr::Sref("index_out_of_bounds")),
+                    Val::Str(Lstr::from(format!(
+                        "no item {} in list of len {}",
+                        n,
+                        list::len(src)
+                    ))),
+                    f.head.trace.fail_here(),
+                    val::FAILURE_BADINPUT,
+                )
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
 pub fn load_rust_func(func_name: &str) -> Option<Code>
 {
     match func_name {
+        "concat" => Some(Code::Rust(concat)),
+        "drop" => Some(Code::Rust(drop)),
+        "len" => Some(Code::Rust(len)),
+        "nth" => Some(Code::Rust(nth)),
+        "reverse" => Some(Code::Rust(reverse)),
         "sort" => Some(Code::Rust(sort)),
+        "take" => Some(Code::Rust(take)),
+        "zip" => Some(Code::Rust(zip)),
         _ => None,
     }
 }
@@ -0,0 +1,165 @@
+use leema::code::Code;
+use leema::fiber::Fiber;
+use leema::frame::Event;
+use leema::list;
+use leema::lmap::{Lmap, LmapNode};
+use leema::lstr::Lstr;
+use leema::struple::Struple;
+use leema::val::{self, Val};
+
+
+fn map_param(f: &Fiber, p: i8) -> &LmapNode
+{
+    match f.head.get_param(p) {
+        &Val::Map(ref m) => m,
+        other => {
+            panic!("not a map: {:?}", other);
+        }
+    }
+}
+
+pub fn new(f: &mut Fiber) -> Event
+{
+    f.head.parent.set_result(Val::Map(Lmap::new()));
+    Event::success()
+}
+
+/**
+ * map::from_list(items: [($K, $V)]): Map
+ *
+ * later items replace earlier items w/ the same key
+ */
+pub fn from_list(f: &mut Fiber) -> Event
+{
+    let m = list::fold_ref(Lmap::new(), f.head.get_param(0), |m, item| {
+        match item {
+            &Val::Tuple(ref kv) if kv.0.len() == 2 => {
+                Lmap::insert(&m, kv.0[0].1.clone(), kv.0[1].1.clone())
+            }
+            _ => {
+                panic!("map items must be (key, value) tuples: {:?}", item);
+            }
+        }
+    });
+    f.head.parent.set_result(Val::Map(m));
+    Event::success()
+}
+
+/**
+ * map::insert(m: Map, k: $K, v: $V): Map
+ *
+ * replaces the value if k is already in the map
+ */
+pub fn insert(f: &mut Fiber) -> Event
+{
+    let result = {
+        let k = f.head.get_param(1).clone();
+        let v = f.head.get_param(2).clone();
+        Lmap::insert(map_param(f, 0), k, v)
+    };
+    f.head.parent.set_result(Val::Map(result));
+    Event::success()
+}
+
+pub fn remove(f: &mut Fiber) -> Event
+{
+    let result = Lmap::remove(map_param(f, 0), f.head.get_param(1));
+    f.head.parent.set_result(Val::Map(result));
+    Event::success()
+}
+
+/**
+ * map::get(m: Map, k: $K): $V
+ *
+ * fails w/ #key_not_found if k isn't in the map
+ */
+pub fn get(f: &mut Fiber) -> Event
+{
+    let result = {
+        let k = f.head.get_param(1);
+        match Lmap::get(map_param(f, 0), k) {
+            Some(v) => v.clone(),
+            None => {
+                Val::failure(
+                    Val::Hashtag(Lstr::Sref("key_not_found")),
+                    Val::Str(Lstr::from(format!("key not in map: {}", k))),
+                    f.head.trace.fail_here(),
+                    val::FAILURE_NOENTRY,
+                )
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+pub fn get_or(f: &mut Fiber) -> Event
+{
+    let result = Lmap::get(map_param(f, 0), f.head.get_param(1))
+        .unwrap_or(f.head.get_param(2))
+        .clone();
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+pub fn contains(f: &mut Fiber) -> Event
+{
+    let found = Lmap::contains(map_param(f, 0), f.head.get_param(1));
+    f.head.parent.set_result(Val::Bool(found));
+    Event::success()
+}
+
+pub fn size(f: &mut Fiber) -> Event
+{
+    let len = Lmap::len(map_param(f, 0));
+    f.head.parent.set_result(Val::Int(len as i64));
+    Event::success()
+}
+
+/**
+ * map::items(m: Map): [($K, $V)]
+ *
+ * the entries of the map in key order
+ */
+pub fn items(f: &mut Fiber) -> Event
+{
+    let items: Vec<Val> = Lmap::iter(map_param(f, 0))
+        .map(|(k, v)| {
+            Val::Tuple(Struple::new_indexed(vec![k.clone(), v.clone()]))
+        }).collect();
+    f.head.parent.set_result(list::from_vec(&items));
+    Event::success()
+}
+
+pub fn keys(f: &mut Fiber) -> Event
+{
+    let keys = Lmap::keys(map_param(f, 0));
+    f.head.parent.set_result(list::from_vec(&keys));
+    Event::success()
+}
+
+pub fn values(f: &mut Fiber) -> Event
+{
+    let values = Lmap::values(map_param(f, 0));
+    f.head.parent.set_result(list::from_vec(&values));
+    Event::success()
+}
+
+
+pub fn load_rust_func(func_name: &str) -> Option<Code>
+{
+    match func_name {
+        "contains" => Some(Code::Rust(contains)),
+        "from_list" => Some(Code::Rust(from_list)),
+        "get" => Some(Code::Rust(get)),
+        "get_or" => Some(Code::Rust(get_or)),
+        "insert" => Some(Code::Rust(insert)),
+        "items" => Some(Code::Rust(items)),
+        "keys" => Some(Code::Rust(keys)),
+        "new" => Some(Code::Rust(new)),
+        "remove" => Some(Code::Rust(remove)),
+        "size" => Some(Code::Rust(size)),
+        "values" => Some(Code::Rust(values)),
+        _ => None,
+    }
+}
@@ -3,14 +3,57 @@ use leema::fiber::Fiber;
 use leema::frame;
 use leema::list;
 use leema::lstr::Lstr;
-use leema::val::Val;
+use leema::val::{self, Val};
 
 
+fn bad_input(f: &Fiber, tag: &'static str, msg: String) -> Val
+{
+    Val::failure(
+        Val::Hashtag(Lstr::Sref(tag)),
+        Val::Str(Lstr::from(msg)),
+        f.head.trace.fail_here(),
+        val::FAILURE_BADINPUT,
+    )
+}
+
+/**
+ * Set the result to a str made from the str param
+ */
+fn map_str<F>(f: &mut Fiber, op: F) -> frame::Event
+where
+    F: Fn(&str) -> String,
+{
+    let result = op(f.head.e.get_param(0).str());
+    f.head.parent.set_result(Val::Str(Lstr::from(result)));
+    frame::Event::success()
+}
+
+/**
+ * Set the result to a bool from the str param and a second str
+ */
+fn test_str<F>(f: &mut Fiber, op: F) -> frame::Event
+where
+    F: Fn(&str, &str) -> bool,
+{
+    let result = {
+        let src = f.head.e.get_param(0);
+        let other = f.head.e.get_param(1);
+        op(src.str(), other.str())
+    };
+    f.head.parent.set_result(Val::Bool(result));
+    frame::Event::success()
+}
+
+/**
+ * str::len(input: Str): Int
+ *
+ * the number of unicode characters, not bytes
+ */
 pub fn len(f: &mut Fiber) -> frame::Event
 {
     let result = {
         let src = f.head.e.get_param(0);
-        Val::Int(src.str().len() as i64)
+        Val::Int(src.str().chars().count() as i64)
     };
     f.head.parent.set_result(result);
     frame::Event::success()
@@ -24,11 +67,21 @@ pub fn is_empty(f: &mut Fiber) -> frame::Event
     frame::Event::success()
 }
 
+/**
+ * str::join(parts: [Str], sep: Str): Str
+ */
 pub fn join(f: &mut Fiber) -> frame::Event
 {
-    let src = f.head.e.get_param(0);
-    let total_len = list::fold_ref(0, src, |tlen, s| tlen + s.str().len());
-    f.head.parent.set_result(Val::Int(total_len as i64));
+    let result = {
+        let src = f.head.e.get_param(0);
+        let sep = f.head.e.get_param(1).str();
+        let parts = list::fold_ref(vec![], src, |mut acc, s| {
+            acc.push(s.str().to_string());
+            acc
+        });
+        parts.join(sep)
+    };
+    f.head.parent.set_result(Val::Str(Lstr::from(result)));
     frame::Event::success()
 }
 
@@ -45,14 +98,198 @@ pub fn split(f: &mut Fiber) -> frame::Event
     frame::Event::success()
 }
 
+/**
+ * str::slice(input: Str, start: Int, end: Int): Str
+ *
+ * start and end are character indexes, end is exclusive
+ */
+pub fn slice(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let src = f.head.e.get_param(0).str();
+        let start = f.head.e.get_param(1).to_int();
+        let end = f.head.e.get_param(2).to_int();
+        let len = src.chars().count() as i64;
+        if start < 0 || start > end || end > len {
+            bad_input(
+                f,
+                "index_out_of_bounds",
+                format!(
+                    "cannot slice {}..{} from str of len {}",
+                    start, end, len
+                ),
+            )
+        } else {
+            let sub: String = src
+                .chars()
+                .skip(start as usize)
+                .take((end - start) as usize)
+                .collect();
+            Val::Str(Lstr::from(sub))
+        }
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+/**
+ * str::find(input: Str, sub: Str): Int
+ *
+ * the character index of the first sub in input,
+ * fails w/ #substr_not_found if it's not there
+ */
+pub fn find(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let src = f.head.e.get_param(0).str();
+        let sub = f.head.e.get_param(1).str();
+        match src.find(sub) {
+            Some(byte_idx) => Val::Int(src[..byte_idx].chars().count() as i64),
+            None => {
+                Val::failure(
+                    Val::Hashtag(Lstr::Sref("substr_not_found")),
+                    Val::Str(Lstr::from(format!("{:?} not in str", sub))),
+                    f.head.trace.fail_here(),
+                    val::FAILURE_NOENTRY,
+                )
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+pub fn contains(f: &mut Fiber) -> frame::Event
+{
+    test_str(f, |src, sub| src.contains(sub))
+}
+
+pub fn starts_with(f: &mut Fiber) -> frame::Event
+{
+    test_str(f, |src, prefix| src.starts_with(prefix))
+}
+
+pub fn ends_with(f: &mut Fiber) -> frame::Event
+{
+    test_str(f, |src, suffix| src.ends_with(suffix))
+}
+
+/**
+ * str::replace(input: Str, from: Str, to: Str): Str
+ *
+ * replaces every occurrence of from
+ */
+pub fn replace(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let src = f.head.e.get_param(0).str();
+        let from = f.head.e.get_param(1).str();
+        let to = f.head.e.get_param(2).str();
+        src.replace(from, to)
+    };
+    f.head.parent.set_result(Val::Str(Lstr::from(result)));
+    frame::Event::success()
+}
+
+pub fn trim(f: &mut Fiber) -> frame::Event
+{
+    map_str(f, |s| s.trim().to_string())
+}
+
+pub fn trim_start(f: &mut Fiber) -> frame::Event
+{
+    map_str(f, |s| s.trim_left().to_string())
+}
+
+pub fn trim_end(f: &mut Fiber) -> frame::Event
+{
+    map_str(f, |s| s.trim_right().to_string())
+}
+
+pub fn to_upper(f: &mut Fiber) -> frame::Event
+{
+    map_str(f, |s| s.to_uppercase())
+}
+
+pub fn to_lower(f: &mut Fiber) -> frame::Event
+{
+    map_str(f, |s| s.to_lowercase())
+}
+
+/**
+ * str::chars(input: Str): [Str]
+ */
+pub fn chars(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let src = f.head.e.get_param(0);
+        let chars: Vec<Val> = src
+            .str()
+            .chars()
+            .map(|c| Val::Str(Lstr::from(c.to_string())))
+            .collect();
+        list::from_vec(&chars)
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+/**
+ * str::to_int(input: Str): Int
+ *
+ * fails w/ #invalid_int if input isn't a base 10 integer
+ */
+pub fn to_int(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let src = f.head.e.get_param(0).str();
+        match src.trim().parse::<i64>() {
+            Ok(i) => Val::Int(i),
+            Err(e) => {
+                bad_input(
+                    f,
+                    "invalid_int",
+                    format!("cannot convert {:?} to int: {}", src, e),
+                )
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
+pub fn from_int(f: &mut Fiber) -> frame::Event
+{
+    let result = {
+        let i = f.head.e.get_param(0).to_int();
+        Val::Str(Lstr::from(i.to_string()))
+    };
+    f.head.parent.set_result(result);
+    frame::Event::success()
+}
+
 
 pub fn load_rust_func(func_name: &str) -> Option<Code>
 {
     match func_name {
+        "chars" => Some(Code::Rust(chars)),
+        "contains" => Some(Code::Rust(contains)),
+        "ends_with" => Some(Code::Rust(ends_with)),
+        "find" => Some(Code::Rust(find)),
+        "from_int" => Some(Code::Rust(from_int)),
         "is_empty" => Some(Code::Rust(is_empty)),
         "join" => Some(Code::Rust(join)),
         "len" => Some(Code::Rust(len)),
+        "replace" => Some(Code::Rust(replace)),
+        "slice" => Some(Code::Rust(slice)),
         "split" => Some(Code::Rust(split)),
+        "starts_with" => Some(Code::Rust(starts_with)),
+        "to_int" => Some(Code::Rust(to_int)),
+        "to_lower" => Some(Code::Rust(to_lower)),
+        "to_upper" => Some(Code::Rust(to_upper)),
+        "trim" => Some(Code::Rust(trim)),
+        "trim_end" => Some(Code::Rust(trim_end)),
+        "trim_start" => Some(Code::Rust(trim_start)),
         _ => None,
     }
 }
@@ -0,0 +1,143 @@
+use leema::code::Code;
+use leema::fiber::Fiber;
+use leema::frame::Event;
+use leema::lstr::Lstr;
+use leema::struple::Struple;
+use leema::supervisor::{Strategy, Supervisor};
+use leema::val::{self, Val};
+
+use std::time::Duration;
+
+
+fn supervisor_param(f: &Fiber) -> Supervisor
+{
+    let supval = f.head.get_param(0);
+    match supval.libval_as::<Supervisor>() {
+        Some(sup) => sup.clone(),
+        None => {
+            panic!("not a supervisor: {:?}", supval);
+        }
+    }
+}
+
+/**
+ * supervisor::new(strategy: #, max_restarts: Int, period: Int)
+ *
+ * strategy is one of #one_for_one, #one_for_all or #rest_for_one
+ * and period is in seconds
+ */
+pub fn new(f: &mut Fiber) -> Event
+{
+    let result = {
+        let strategy = match f.head.get_param(0) {
+            &Val::Hashtag(ref tag) => Strategy::from_tag(tag.str()),
+            _ => None,
+        };
+        let max_restarts = f.head.get_param(1).to_int();
+        let period = f.head.get_param(2).to_int();
+        match strategy {
+            Some(s) if max_restarts >= 0 && period >= 0 => {
+                let period = Duration::from_secs(period as u64);
+                Val::libval(Supervisor::new(s, max_restarts as usize, period))
+            }
+            Some(_) => {
+                Val::failure(
+                    Val::Hashtag(Lstr::Sref("invalid_restart_intensity")),
+                    Val::Str(Lstr::Sref(
+                        "max restarts and period cannot be negative",
+                    )),
+                    f.head.trace.fail_here(),
+                    val::FAILURE_BADINPUT,
+                )
+            }
+            None => {
+                Val::failure(
+                    Val::Hashtag(Lstr::Sref("invalid_strategy")),
+                    Val::Str(Lstr::from(format!(
+                        "unknown restart strategy: {}",
+                        f.head.get_param(0)
+                    ))),
+                    f.head.trace.fail_here(),
+                    val::FAILURE_BADINPUT,
+                )
+            }
+        }
+    };
+    f.head.parent.set_result(result);
+    Event::success()
+}
+
+/**
+ * supervisor::start_child(sup, func, args): Int
+ *
+ * start func as a new fiber and return the child's id.
+ * args is the tuple of arguments to pass to func each time
+ * it's started. fails w/ #invalid_child if func isn't a function.
+ */
+pub fn start_child(f: &mut Fiber) -> Event
+{
+    let sup = supervisor_param(f);
+    let func = match f.head.get_param(1) {
+        &Val::FuncRef(ref lri, _) => lri.clone(),
+        other => {
+            let failure = Val::failure(
+                Val::Hashtag(Lstr::Sref("invalid_child")),
+                Val::Str(Lstr::from(format!(
+                    "cannot supervise a value that is not a function: {}",
+                    other
+                ))),
+                f.head.trace.fail_here(),
+                val::FAILURE_TYPE,
+            );
+            f.head.parent.set_result(failure);
+            return Event::success();
+        }
+    };
+    let args = match f.head.get_param(2) {
+        &Val::Tuple(_) => f.head.get_param(2).clone(),
+        &Val::Void => Val::Tuple(Struple(vec![])),
+        other => Val::Tuple(Struple::new_indexed(vec![other.clone()])),
+    };
+    let (child_id, frame) = sup.start_child(func, &args);
+    f.head.parent.set_result(Val::Int(child_id));
+    Event::Fork(frame)
+}
+
+/**
+ * supervisor::next_failure(sup): (Int, #, Str, [(Str, Str, Str, Int, Int)])
+ *
+ * wait for a child to fail and get its (child_id, tag, msg, trace)
+ */
+pub fn next_failure(f: &mut Fiber) -> Event
+{
+    let notice = supervisor_param(f).next_notice();
+    f.head.parent.set_result(notice);
+    Event::success()
+}
+
+pub fn running_children(f: &mut Fiber) -> Event
+{
+    let running = supervisor_param(f).running_children();
+    f.head.parent.set_result(Val::Int(running as i64));
+    Event::success()
+}
+
+pub fn stop(f: &mut Fiber) -> Event
+{
+    supervisor_param(f).stop();
+    f.head.parent.set_result(Val::Void);
+    Event::success()
+}
+
+
+pub fn load_rust_func(func_name: &str) -> Option<Code>
+{
+    match func_name {
+        "new" => Some(Code::Rust(new)),
+        "next_failure" => Some(Code::Rust(next_failure)),
+        "running_children" => Some(Code::Rust(running_children)),
+        "start_child" => Some(Code::Rust(start_child)),
+        "stop" => Some(Code::Rust(stop)),
+        _ => None,
+    }
+}
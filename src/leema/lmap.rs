@@ -1,16 +1,26 @@
 use Val;
 
+use std::cmp::Ordering;
 use std::sync::Arc;
 
 pub type LmapNode = Option<Arc<Lmap>>;
 
+/**
+ * A persistent map, kept balanced as an AVL tree
+ *
+ * Updates copy the path from the root to the changed node and share
+ * everything else w/ the old map.
+ */
 #[derive(Clone)]
 #[derive(Debug)]
-#[derive(PartialEq)]
-#[derive(PartialOrd)]
-#[derive(Eq)]
-#[derive(Ord)]
-pub struct Lmap(Option<Arc<Lmap>>, (Val, Val), Option<Arc<Lmap>>);
+pub struct Lmap
+{
+    left: LmapNode,
+    entry: (Val, Val),
+    right: LmapNode,
+    height: u8,
+    size: usize,
+}
 
 impl Lmap
 {
@@ -19,30 +29,59 @@ impl Lmap
         None
     }
 
+    /**
+     * Insert a value, replacing the old value if the key is already set
+     */
     pub fn insert(tree: &LmapNode, k: Val, v: Val) -> LmapNode
     {
-        if tree.is_none() {
-            return Some(Arc::new(Lmap(Lmap::new(), (k, v), Lmap::new())));
+        let n = match tree {
+            &None => return Lmap::node(None, k, v, None),
+            &Some(ref n) => n,
+        };
+        match k.cmp(&n.entry.0) {
+            Ordering::Less => {
+                let newleft = Lmap::insert(&n.left, k, v);
+                let (ref nk, ref nv) = n.entry;
+                Lmap::balance(newleft, nk.clone(), nv.clone(), n.right.clone())
+            }
+            Ordering::Greater => {
+                let newright = Lmap::insert(&n.right, k, v);
+                let (ref nk, ref nv) = n.entry;
+                Lmap::balance(n.left.clone(), nk.clone(), nv.clone(), newright)
+            }
+            Ordering::Equal => {
+                Lmap::node(n.left.clone(), k, v, n.right.clone())
+            }
         }
+    }
 
-        match **tree.as_ref().unwrap() {
-            Lmap(ref left, (ref nkey, ref nval), ref right) => {
-                if k < *nkey {
-                    let newleft = Lmap::insert(left, k, v);
-                    Some(Arc::new(Lmap(
-                        newleft,
-                        (nkey.clone(), nval.clone()),
-                        right.clone(),
-                    )))
-                } else if k > *nkey {
-                    let newright = Lmap::insert(right, k, v);
-                    Some(Arc::new(Lmap(
-                        left.clone(),
-                        (nkey.clone(), nval.clone()),
-                        newright,
-                    )))
-                } else {
-                    tree.clone()
+    /**
+     * Remove a key, returns the same map if the key isn't there
+     */
+    pub fn remove(tree: &LmapNode, k: &Val) -> LmapNode
+    {
+        let n = match tree {
+            &None => return None,
+            &Some(ref n) => n,
+        };
+        let (ref nk, ref nv) = n.entry;
+        match k.cmp(nk) {
+            Ordering::Less => {
+                let newleft = Lmap::remove(&n.left, k);
+                Lmap::balance(newleft, nk.clone(), nv.clone(), n.right.clone())
+            }
+            Ordering::Greater => {
+                let newright = Lmap::remove(&n.right, k);
+                Lmap::balance(n.left.clone(), nk.clone(), nv.clone(), newright)
+            }
+            Ordering::Equal => {
+                match (&n.left, &n.right) {
+                    (&None, _) => n.right.clone(),
+                    (_, &None) => n.left.clone(),
+                    (_, &Some(ref right)) => {
+                        let (mk, mv, rest) = Lmap::remove_min(right);
+                        Lmap::balance(n.left.clone(), mk, mv, rest)
+                    }
                 }
             }
         }
@@ -50,19 +89,252 @@ impl Lmap
 
     pub fn get<'a>(tree: &'a LmapNode, k: &Val) -> Option<&'a Val>
     {
-        if tree.is_none() {
-            return None;
+        let mut next = tree;
+        while let &Some(ref n) = next {
+            match k.cmp(&n.entry.0) {
+                Ordering::Less => next = &n.left,
+                Ordering::Greater => next = &n.right,
+                Ordering::Equal => return Some(&n.entry.1),
+            }
         }
-        match **tree.as_ref().unwrap() {
-            Lmap(ref left, (ref nkey, ref nval), ref right) => {
-                if *k == *nkey {
-                    Some(nval)
-                } else if *k < *nkey {
-                    Lmap::get(left, k)
-                } else {
-                    Lmap::get(right, k)
-                }
+        None
+    }
+
+    pub fn contains(tree: &LmapNode, k: &Val) -> bool
+    {
+        Lmap::get(tree, k).is_some()
+    }
+
+    pub fn len(tree: &LmapNode) -> usize
+    {
+        tree.as_ref().map(|n| n.size).unwrap_or(0)
+    }
+
+    /**
+     * Iterate over the entries in key order
+     */
+    pub fn iter<'a>(tree: &'a LmapNode) -> LmapIter<'a>
+    {
+        let mut it = LmapIter { stack: vec![] };
+        it.push_left(tree);
+        it
+    }
+
+    pub fn keys(tree: &LmapNode) -> Vec<Val>
+    {
+        Lmap::iter(tree).map(|(k, _)| k.clone()).collect()
+    }
+
+    pub fn values(tree: &LmapNode) -> Vec<Val>
+    {
+        Lmap::iter(tree).map(|(_, v)| v.clone()).collect()
+    }
+
+    pub fn cmp(a: &LmapNode, b: &LmapNode) -> Ordering
+    {
+        Lmap::iter(a).cmp(Lmap::iter(b))
+    }
+
+    pub fn deep_clone(tree: &LmapNode) -> LmapNode
+    {
+        tree.as_ref().map(|n| {
+            Arc::new(Lmap {
+                left: Lmap::deep_clone(&n.left),
+                entry: (n.entry.0.deep_clone(), n.entry.1.deep_clone()),
+                right: Lmap::deep_clone(&n.right),
+                height: n.height,
+                size: n.size,
+            })
+        })
+    }
+
+    fn height(tree: &LmapNode) -> u8
+    {
+        tree.as_ref().map(|n| n.height).unwrap_or(0)
+    }
+
+    fn node(left: LmapNode, k: Val, v: Val, right: LmapNode) -> LmapNode
+    {
+        let height = 1 + Lmap::height(&left).max(Lmap::height(&right));
+        let size = 1 + Lmap::len(&left) + Lmap::len(&right);
+        Some(Arc::new(Lmap {
+            left,
+            entry: (k, v),
+            right,
+            height,
+            size,
+        }))
+    }
+
+    /**
+     * Make a node from subtrees whose heights differ by at most 2,
+     * rotating if they differ by 2
+     */
+    fn balance(left: LmapNode, k: Val, v: Val, right: LmapNode) -> LmapNode
+    {
+        let hl = Lmap::height(&left);
+        let hr = Lmap::height(&right);
+        if hl > hr + 1 {
+            let l = left.unwrap();
+            let (ref lk, ref lv) = l.entry;
+            if Lmap::height(&l.left) >= Lmap::height(&l.right) {
+                let newright = Lmap::node(l.right.clone(), k, v, right);
+                Lmap::node(l.left.clone(), lk.clone(), lv.clone(), newright)
+            } else {
+                let lr = l.right.as_ref().unwrap();
+                let (ref lrk, ref lrv) = lr.entry;
+                Lmap::node(
+                    Lmap::node(
+                        l.left.clone(),
+                        lk.clone(),
+                        lv.clone(),
+                        lr.left.clone(),
+                    ),
+                    lrk.clone(),
+                    lrv.clone(),
+                    Lmap::node(lr.right.clone(), k, v, right),
+                )
+            }
+        } else if hr > hl + 1 {
+            let r = right.unwrap();
+            let (ref rk, ref rv) = r.entry;
+            if Lmap::height(&r.right) >= Lmap::height(&r.left) {
+                let newleft = Lmap::node(left, k, v, r.left.clone());
+                Lmap::node(newleft, rk.clone(), rv.clone(), r.right.clone())
+            } else {
+                let rl = r.left.as_ref().unwrap();
+                let (ref rlk, ref rlv) = rl.entry;
+                Lmap::node(
+                    Lmap::node(left, k, v, rl.left.clone()),
+                    rlk.clone(),
+                    rlv.clone(),
+                    Lmap::node(
+                        rl.right.clone(),
+                        rk.clone(),
+                        rv.clone(),
+                        r.right.clone(),
+                    ),
+                )
             }
+        } else {
+            Lmap::node(left, k, v, right)
         }
     }
+
+    fn remove_min(n: &Arc<Lmap>) -> (Val, Val, LmapNode)
+    {
+        let (ref nk, ref nv) = n.entry;
+        match n.left {
+            None => (nk.clone(), nv.clone(), n.right.clone()),
+            Some(ref left) => {
+                let (mk, mv, rest) = Lmap::remove_min(left);
+                let right = n.right.clone();
+                let newn = Lmap::balance(rest, nk.clone(), nv.clone(), right);
+                (mk, mv, newn)
+            }
+        }
+    }
+}
+
+/**
+ * In order iterator over the entries of a map
+ */
+pub struct LmapIter<'a>
+{
+    stack: Vec<&'a Lmap>,
+}
+
+impl<'a> LmapIter<'a>
+{
+    fn push_left(&mut self, mut tree: &'a LmapNode)
+    {
+        while let &Some(ref n) = tree {
+            self.stack.push(n);
+            tree = &n.left;
+        }
+    }
+}
+
+impl<'a> Iterator for LmapIter<'a>
+{
+    type Item = (&'a Val, &'a Val);
+
+    fn next(&mut self) -> Option<(&'a Val, &'a Val)>
+    {
+        let n = self.stack.pop()?;
+        self.push_left(&n.right);
+        Some((&n.entry.0, &n.entry.1))
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::lmap::Lmap;
+    use leema::val::Val;
+
+
+    #[test]
+    fn test_insert_replaces_value()
+    {
+        let m = Lmap::insert(&Lmap::new(), Val::Int(1), Val::Int(10));
+        let m2 = Lmap::insert(&m, Val::Int(1), Val::Int(11));
+        assert_eq!(Some(&Val::Int(10)), Lmap::get(&m, &Val::Int(1)));
+        assert_eq!(Some(&Val::Int(11)), Lmap::get(&m2, &Val::Int(1)));
+        assert_eq!(1, Lmap::len(&m2));
+    }
+
+    #[test]
+    fn test_sorted_inserts_stay_balanced()
+    {
+        let mut m = Lmap::new();
+        for i in 0..1000 {
+            m = Lmap::insert(&m, Val::Int(i), Val::Int(i * 2));
+        }
+        assert_eq!(1000, Lmap::len(&m));
+        // an avl tree w/ 1000 nodes is at most 14 high
+        assert!(Lmap::height(&m) <= 14);
+        let keys = Lmap::keys(&m);
+        assert_eq!(Val::Int(0), keys[0]);
+        assert_eq!(Val::Int(999), keys[999]);
+    }
+
+    #[test]
+    fn test_remove()
+    {
+        let mut m = Lmap::new();
+        for i in 0..20 {
+            m = Lmap::insert(&m, Val::Int(i), Val::Int(i));
+        }
+        let mut removed = m.clone();
+        for i in (0..20).filter(|i| i % 3 == 0) {
+            removed = Lmap::remove(&removed, &Val::Int(i));
+        }
+        assert_eq!(20, Lmap::len(&m));
+        assert_eq!(13, Lmap::len(&removed));
+        assert!(Lmap::contains(&m, &Val::Int(9)));
+        assert!(!Lmap::contains(&removed, &Val::Int(9)));
+        assert!(Lmap::contains(&removed, &Val::Int(10)));
+        assert!(Lmap::height(&removed) <= 5);
+
+        let values: Vec<Val> = Lmap::values(&removed);
+        let mut expected = vec![];
+        for i in (0..20).filter(|i| i % 3 != 0) {
+            expected.push(Val::Int(i));
+        }
+        assert_eq!(expected, values);
+    }
+
+    #[test]
+    fn test_equal_contents_compare_equal()
+    {
+        let a = Lmap::insert(&Lmap::new(), Val::Int(1), Val::Int(1));
+        let a = Lmap::insert(&a, Val::Int(2), Val::Int(2));
+        let a = Lmap::insert(&a, Val::Int(3), Val::Int(3));
+        let b = Lmap::insert(&Lmap::new(), Val::Int(3), Val::Int(3));
+        let b = Lmap::insert(&b, Val::Int(1), Val::Int(1));
+        let b = Lmap::insert(&b, Val::Int(2), Val::Int(2));
+        assert_eq!(Val::Map(a), Val::Map(b));
+    }
 }
@@ -1,44 +1,172 @@
+use leema::cache;
+use leema::error::{CompileError, CompileResult, ErrorKind};
 use leema::lstr::Lstr;
+use leema::manifest::Manifest;
 use leema::module::ModKey;
+use leema::val::SrcLoc;
 
 use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
 
+/**
+ * Modules are searched for in the directory of the main file first,
+ * then each of the lib paths in order.
+ *
+ * A nested module like net::http is found at net/http.lma under one
+ * of those directories and is referenced in code by its last name, http
+ * so a last name can only be imported from one path in a program
+ */
 #[derive(Debug)]
 pub struct Interloader
 {
     pub root_path: PathBuf,
     pub main_mod: Lstr,
+    lib_paths: Vec<PathBuf>,
+    mod_paths: HashMap<Lstr, Vec<Lstr>>,
     modtxt: HashMap<Lstr, String>,
+    ignore_cache: bool,
 }
 
 impl Interloader
 {
-    pub fn new(mainfile: Lstr) -> Interloader
+    pub fn new(mainfile: Lstr) -> CompileResult<Interloader>
     {
+        let invalid = |msg: &str| {
+            Err(CompileError::new(
+                &mainfile,
+                SrcLoc::default(),
+                ErrorKind::InvalidFile,
+                format!("{}: {}", msg, mainfile),
+            ))
+        };
         let path = Path::new(mainfile.str());
         let ext = path.extension();
         if ext.is_none() {
-            panic!("Main file has no extension: {}", mainfile);
+            return invalid("main file has no extension");
         }
         if ext.unwrap() != "lma" {
-            panic!("Main file extension is not lma: {}", mainfile);
+            return invalid("main file extension is not lma");
         }
         let modname = path.file_stem();
         if modname.is_none() {
-            panic!("Is that not a real file? {}", mainfile);
+            return invalid("main file has no module name");
         }
 
         let mod_str =
             Lstr::from(modname.unwrap().to_str().unwrap().to_string());
-        Interloader {
+        Ok(Interloader {
             root_path: path.parent().unwrap().to_path_buf(),
             main_mod: mod_str,
+            lib_paths: vec![],
+            mod_paths: HashMap::new(),
             modtxt: HashMap::new(),
+            ignore_cache: false,
+        })
+    }
+
+    /**
+     * Load a package from its manifest. The entry module is searched
+     * for in the first source directory, the other source directories
+     * and all the dependencies' source directories go in the lib paths.
+     */
+    pub fn from_manifest(manifest: &Manifest) -> CompileResult<Interloader>
+    {
+        let mut src_dirs = manifest.src_dirs();
+        if src_dirs.is_empty() {
+            src_dirs.push(manifest.dir().to_path_buf());
+        }
+        let root_path = src_dirs.remove(0);
+        let mut lib_paths = src_dirs;
+        lib_paths.extend(manifest.dep_dirs()?);
+        Ok(Interloader {
+            root_path,
+            main_mod: manifest.entry.clone(),
+            lib_paths,
+            mod_paths: HashMap::new(),
+            modtxt: HashMap::new(),
+            ignore_cache: false,
+        })
+    }
+
+    pub fn add_lib_path(&mut self, path: PathBuf)
+    {
+        self.lib_paths.push(path);
+    }
+
+    /**
+     * Add lib paths from a list in the platform's PATH format,
+     * like the LEEMA_PATH environment variable
+     */
+    pub fn add_lib_paths(&mut self, paths: &OsStr)
+    {
+        for p in env::split_paths(paths) {
+            if !p.as_os_str().is_empty() {
+                self.lib_paths.push(p);
+            }
+        }
+    }
+
+    /**
+     * Set the nested path for an imported module. Fails if the module's
+     * name was already set to a different path, like net::http and
+     * web::http, because the code can't tell them apart.
+     */
+    pub fn set_mod_path(
+        &mut self,
+        mod_name: Lstr,
+        path: Vec<Lstr>,
+    ) -> CompileResult<()>
+    {
+        if let Some(prev) = self.mod_paths.get(&mod_name) {
+            if *prev == path {
+                return Ok(());
+            }
+            let prev_name: Vec<&str> = prev.iter().map(|s| s.str()).collect();
+            let new_name: Vec<&str> = path.iter().map(|s| s.str()).collect();
+            return Err(CompileError::new(
+                &mod_name,
+                SrcLoc::default(),
+                ErrorKind::AmbiguousModule,
+                format!(
+                    "ambiguous module {}: imported as {} and {}",
+                    mod_name,
+                    prev_name.join("::"),
+                    new_name.join("::")
+                ),
+            ));
         }
+        self.mod_paths.insert(mod_name, path);
+        Ok(())
+    }
+
+    /**
+     * Compile everything from source even if there's a fresh cache
+     */
+    pub fn set_ignore_cache(&mut self, ignore: bool)
+    {
+        self.ignore_cache = ignore;
+    }
+
+    pub fn ignore_cache(&self) -> bool
+    {
+        self.ignore_cache
+    }
+
+    /**
+     * Get the compiled cache file for a module, which is next to
+     * its source file. Modules w/o a file don't get cached.
+     */
+    pub fn cache_file(&self, mod_key: &ModKey) -> Option<PathBuf>
+    {
+        mod_key
+            .file
+            .as_ref()
+            .map(|f| f.with_extension(cache::CACHE_EXT))
     }
 
     pub fn set_mod_txt(&mut self, modname: Lstr, content: String)
@@ -46,41 +174,134 @@ impl Interloader
         self.modtxt.insert(modname, content);
     }
 
+    /**
+     * Find the file for a module in the search path. If it isn't
+     * anywhere, use the first place it would have been and
+     * reading it will fail w/ the full list of places searched.
+     */
     pub fn mod_name_to_key(&self, mod_name: Lstr) -> ModKey
     {
         if self.modtxt.contains_key(&mod_name) {
-            ModKey::name_only(mod_name)
-        } else {
-            let mut path = PathBuf::new();
-            path.push(self.root_path.as_path());
-            path.push(mod_name.str());
-            path.set_extension("lma");
-            ModKey::new(mod_name, path)
+            return ModKey::name_only(mod_name);
+        }
+        let mut candidates = self.mod_candidates(&mod_name);
+        let found = candidates.iter().position(|c| c.is_file()).unwrap_or(0);
+        ModKey::new(mod_name, candidates.swap_remove(found))
+    }
+
+    /**
+     * Get the path for a module relative to a search directory
+     */
+    fn mod_rel_path(&self, mod_name: &Lstr) -> PathBuf
+    {
+        let mut path = PathBuf::new();
+        match self.mod_paths.get(mod_name) {
+            Some(segments) => {
+                for seg in segments.iter() {
+                    path.push(seg.str());
+                }
+            }
+            None => {
+                let segments =
+                    mod_name.str().split("::").flat_map(|s| s.split('.'));
+                for seg in segments {
+                    path.push(seg);
+                }
+            }
+        }
+        path.set_extension("lma");
+        path
+    }
+
+    fn mod_candidates(&self, mod_name: &Lstr) -> Vec<PathBuf>
+    {
+        let rel_path = self.mod_rel_path(mod_name);
+        let mut candidates = Vec::with_capacity(self.lib_paths.len() + 1);
+        candidates.push(self.root_path.join(&rel_path));
+        for lib in self.lib_paths.iter() {
+            candidates.push(lib.join(&rel_path));
+        }
+        candidates
+    }
+
+    fn mod_path_name(&self, mod_name: &Lstr) -> String
+    {
+        match self.mod_paths.get(mod_name) {
+            Some(segments) => {
+                let names: Vec<&str> =
+                    segments.iter().map(|s| s.str()).collect();
+                names.join("::")
+            }
+            None => mod_name.to_string(),
+        }
+    }
+
+    /**
+     * Get the file name to show in error messages for a module
+     */
+    pub fn mod_file_name(&self, mod_name: &Lstr) -> String
+    {
+        match self.mod_name_to_key(mod_name.clone()).file {
+            Some(ref path) => path.to_string_lossy().into_owned(),
+            None => mod_name.to_string(),
         }
     }
 
-    pub fn read_module(&self, mod_key: &ModKey) -> String
+    pub fn read_module(&self, mod_key: &ModKey) -> CompileResult<String>
     {
         if mod_key.file.is_none() {
-            self.modtxt.get(&*mod_key.name).unwrap().clone()
+            Ok(self.modtxt.get(&*mod_key.name).unwrap().clone())
         } else {
-            Interloader::read_file_text(mod_key.file.as_ref().unwrap())
+            let path = mod_key.file.as_ref().unwrap();
+            if !path.exists() {
+                return Err(self.not_found(&mod_key.name));
+            }
+            Interloader::read_file_text(&mod_key.name, path)
         }
     }
 
-    fn read_file_text(path: &Path) -> String
+    fn not_found(&self, mod_name: &Lstr) -> CompileError
     {
+        let searched: Vec<String> = self
+            .mod_candidates(mod_name)
+            .iter()
+            .map(|c| c.display().to_string())
+            .collect();
+        CompileError::new(
+            mod_name,
+            SrcLoc::default(),
+            ErrorKind::ModuleNotFound,
+            format!(
+                "module not found: {}, searched: {}",
+                self.mod_path_name(mod_name),
+                searched.join(", ")
+            ),
+        )
+    }
+
+    fn read_file_text(mod_name: &Lstr, path: &Path) -> CompileResult<String>
+    {
+        let fail = |kind, msg: &str| {
+            Err(CompileError::new(
+                mod_name,
+                SrcLoc::default(),
+                kind,
+                format!("{}: {}", msg, path.display()),
+            ))
+        };
         if !path.exists() {
-            panic!("Module file does not exist: {:?}", path);
+            return fail(ErrorKind::FileNotFound, "module file does not exist");
         }
         if !path.is_file() {
-            panic!("Module is not a file: {:?}", path);
+            return fail(ErrorKind::InvalidFile, "module is not a file");
         }
-        let mut f = File::open(path).ok().unwrap();
         let mut result = String::new();
-        f.read_to_string(&mut result)
-            .expect("failed reading file to text");
-        result
+        let read_result =
+            File::open(path).and_then(|mut f| f.read_to_string(&mut result));
+        if let Err(e) = read_result {
+            return fail(ErrorKind::InvalidFile, &e.to_string());
+        }
+        Ok(result)
     }
 }
 
@@ -88,15 +309,20 @@ impl Interloader
 #[cfg(test)]
 mod tests
 {
+    use leema::error::ErrorKind;
     use leema::loader::Interloader;
     use leema::lstr::Lstr;
+    use leema::manifest::Manifest;
 
-    use std::path::Path;
+    use std::env;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn test_root_path()
     {
-        let i = Interloader::new(Lstr::Sref("hello/world.lma"));
+        let i = Interloader::new(Lstr::Sref("hello/world.lma")).unwrap();
 
         let expected = Path::new("hello");
         assert_eq!(expected, i.root_path);
@@ -105,9 +331,124 @@ mod tests
     #[test]
     fn test_main_mod()
     {
-        let i = Interloader::new(Lstr::Sref("hello/world.lma"));
+        let i = Interloader::new(Lstr::Sref("hello/world.lma")).unwrap();
 
         assert_eq!("world", i.main_mod.str());
     }
 
+    #[test]
+    fn test_cache_file()
+    {
+        let i = Interloader::new(Lstr::Sref("hello/world.lma")).unwrap();
+        let key = i.mod_name_to_key(Lstr::Sref("net::tacos"));
+        let expected = PathBuf::from("hello/net/tacos.lmc");
+        assert_eq!(Some(expected), i.cache_file(&key));
+        assert!(!i.ignore_cache());
+    }
+
+    #[test]
+    fn test_new_bad_extension()
+    {
+        let result = Interloader::new(Lstr::Sref("hello/world.txt"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_nested_mod_path()
+    {
+        let mut i = Interloader::new(Lstr::Sref("hello/world.lma")).unwrap();
+        i.set_mod_path(
+            Lstr::Sref("http"),
+            vec![Lstr::Sref("net"), Lstr::Sref("http")],
+        ).unwrap();
+
+        let key = i.mod_name_to_key(Lstr::Sref("http"));
+        let expected = Path::new("hello/net/http.lma");
+        assert_eq!(Some(expected.to_path_buf()), key.file);
+        assert_eq!("http", key.name.str());
+
+        let dotted = i.mod_name_to_key(Lstr::Sref("net.tcp"));
+        assert_eq!(Some(PathBuf::from("hello/net/tcp.lma")), dotted.file);
+    }
+
+    #[test]
+    fn test_ambiguous_nested_mod_path()
+    {
+        let mut i = Interloader::new(Lstr::Sref("hello/world.lma")).unwrap();
+        let net_http = vec![Lstr::Sref("net"), Lstr::Sref("http")];
+        let web_http = vec![Lstr::Sref("web"), Lstr::Sref("http")];
+        i.set_mod_path(Lstr::Sref("http"), net_http.clone()).unwrap();
+        // setting the same path again is fine
+        i.set_mod_path(Lstr::Sref("http"), net_http).unwrap();
+
+        let err = i.set_mod_path(Lstr::Sref("http"), web_http).unwrap_err();
+        assert_eq!(ErrorKind::AmbiguousModule, err.kind);
+        assert_eq!(
+            "ambiguous module http: imported as net::http and web::http",
+            err.msg.str()
+        );
+        let key = i.mod_name_to_key(Lstr::Sref("http"));
+        assert_eq!(Some(PathBuf::from("hello/net/http.lma")), key.file);
+    }
+
+    #[test]
+    fn test_mod_found_in_lib_path()
+    {
+        let lib_dir = env::temp_dir().join("leema_test_lib_path");
+        fs::create_dir_all(lib_dir.join("net")).unwrap();
+        let mod_file = lib_dir.join("net").join("tacos.lma");
+        fs::write(&mod_file, "func main() -> 5 --\n").unwrap();
+
+        let mut i = Interloader::new(Lstr::Sref("hello/world.lma")).unwrap();
+        i.add_lib_paths(OsStr::new("/does/not/exist"));
+        i.add_lib_path(lib_dir.clone());
+
+        let key = i.mod_name_to_key(Lstr::Sref("net::tacos"));
+        assert_eq!(Some(mod_file), key.file);
+        assert!(i.read_module(&key).is_ok());
+    }
+
+    #[test]
+    fn test_mod_not_found_lists_search_path()
+    {
+        let mut i = Interloader::new(Lstr::Sref("hello/world.lma")).unwrap();
+        i.add_lib_path(PathBuf::from("/usr/lib/leema"));
+
+        let key = i.mod_name_to_key(Lstr::Sref("burritos"));
+        let err = i.read_module(&key).unwrap_err();
+        assert_eq!(ErrorKind::ModuleNotFound, err.kind);
+        assert_eq!(
+            "module not found: burritos, searched: \
+             hello/burritos.lma, /usr/lib/leema/burritos.lma",
+            err.msg.str()
+        );
+    }
+
+    #[test]
+    fn test_from_manifest_resolves_dep_modules()
+    {
+        let root = env::temp_dir().join("leema_test_loader_manifest");
+        let app = root.join("app");
+        let salsa = root.join("salsa");
+        fs::create_dir_all(app.join("src")).unwrap();
+        fs::create_dir_all(&salsa).unwrap();
+        let dep_file = salsa.join("verde.lma");
+        fs::write(&dep_file, "func mild() -> 1 --\n").unwrap();
+        let manifest_file = app.join("leema.toml");
+        fs::write(
+            &manifest_file,
+            "[package]\nname = \"app\"\nentry = \"tacos\"\n\
+             src = [\"src\"]\n[dependencies]\n\
+             salsa = { path = \"../salsa\" }\n",
+        ).unwrap();
+
+        let m = Manifest::read(&manifest_file).unwrap();
+        let i = Interloader::from_manifest(&m).unwrap();
+        assert_eq!("tacos", i.main_mod.str());
+        assert_eq!(app.join("src"), i.root_path);
+
+        let key = i.mod_name_to_key(Lstr::Sref("verde"));
+        assert_eq!(Some(app.join("../salsa").join("verde.lma")), key.file);
+        assert!(i.read_module(&key).is_ok());
+    }
 }
@@ -0,0 +1,205 @@
+use leema::lri::Lri;
+use leema::lstr::Lstr;
+use leema::struple::Struple;
+use leema::val::{LibVal, MsgVal, Type, Val};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+
+static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
+
+struct MailboxState
+{
+    msgs: VecDeque<MsgVal>,
+    // (worker_id, fiber_id) of the fiber blocked in receive
+    waiter: Option<(i64, i64)>,
+}
+
+/**
+ * Messages sent to a fiber, waiting for it to receive them
+ *
+ * Senders on any worker push deep copies of their messages
+ * straight into the mailbox. If the owner is blocked in receive,
+ * the sender gets back the owner's worker and fiber ids so the
+ * owner can be woken up w/ a WorkerMsg.
+ */
+pub struct Mailbox
+{
+    state: Mutex<MailboxState>,
+}
+
+impl Mailbox
+{
+    pub fn new() -> Mailbox
+    {
+        Mailbox {
+            state: Mutex::new(MailboxState {
+                msgs: VecDeque::new(),
+                waiter: None,
+            }),
+        }
+    }
+
+    /**
+     * Add a message and get the receiver to wake up, if it's waiting
+     */
+    pub fn send(&self, msg: &Val) -> Option<(i64, i64)>
+    {
+        let mut state = self.state.lock().unwrap();
+        state.msgs.push_back(MsgVal::new(msg));
+        state.waiter.take()
+    }
+
+    /**
+     * Take the oldest message that matches the pattern
+     */
+    pub fn take_match(&self, patt: &Val) -> Option<Val>
+    {
+        let mut state = self.state.lock().unwrap();
+        Mailbox::take_locked(&mut state, patt)
+    }
+
+    /**
+     * Take a matching message or, if there isn't one, wait for the
+     * next message. Checking and waiting happen under the same lock
+     * so a message sent in between isn't missed.
+     */
+    pub fn wait(
+        &self,
+        worker_id: i64,
+        fiber_id: i64,
+        patt: &Val,
+    ) -> Option<Val>
+    {
+        let mut state = self.state.lock().unwrap();
+        let found = Mailbox::take_locked(&mut state, patt);
+        if found.is_none() {
+            state.waiter = Some((worker_id, fiber_id));
+        }
+        found
+    }
+
+    pub fn stop_waiting(&self)
+    {
+        self.state.lock().unwrap().waiter = None;
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.state.lock().unwrap().msgs.len()
+    }
+
+    fn take_locked(state: &mut MailboxState, patt: &Val) -> Option<Val>
+    {
+        let idx = state
+            .msgs
+            .iter()
+            .position(|m| Val::pattern_match(patt, m).is_some())?;
+        state.msgs.remove(idx).map(|m| m.take())
+    }
+}
+
+/**
+ * A process id, the handle for sending to a fiber's mailbox
+ */
+#[derive(Clone)]
+pub struct Pid
+{
+    pub id: i64,
+    pub mailbox: Arc<Mailbox>,
+}
+
+impl Pid
+{
+    pub fn new() -> Pid
+    {
+        Pid {
+            id: NEXT_PID.fetch_add(1, Ordering::Relaxed) as i64,
+            mailbox: Arc::new(Mailbox::new()),
+        }
+    }
+}
+
+impl LibVal for Pid
+{
+    fn get_type(&self) -> Type
+    {
+        Type::UserDef(Lri::with_modules(
+            Lstr::Sref("prefab"),
+            Lstr::Sref("Pid"),
+        ))
+    }
+}
+
+impl fmt::Debug for Pid
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "Pid({})", self.id)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::lstr::Lstr;
+    use leema::mailbox::Mailbox;
+    use leema::struple::Struple;
+    use leema::val::Val;
+
+
+    fn msg(tag: &'static str, i: i64) -> Val
+    {
+        Val::Tuple(Struple::new_indexed(vec![
+            Val::Hashtag(Lstr::Sref(tag)),
+            Val::Int(i),
+        ]))
+    }
+
+    #[test]
+    fn test_selective_take()
+    {
+        let mb = Mailbox::new();
+        mb.send(&msg("ping", 1));
+        mb.send(&msg("pong", 2));
+        mb.send(&msg("pong", 3));
+
+        let patt = Val::Tuple(Struple::new_indexed(vec![
+            Val::Hashtag(Lstr::Sref("pong")),
+            Val::Wildcard,
+        ]));
+        assert_eq!(Some(msg("pong", 2)), mb.take_match(&patt));
+        assert_eq!(2, mb.len());
+        assert_eq!(Some(msg("pong", 3)), mb.take_match(&patt));
+        assert_eq!(None, mb.take_match(&patt));
+        assert_eq!(1, mb.len());
+    }
+
+    #[test]
+    fn test_wait_registers_waiter()
+    {
+        let mb = Mailbox::new();
+        let any = Val::Wildcard;
+        assert_eq!(None, mb.wait(1, 7, &any));
+        assert_eq!(Some((1, 7)), mb.send(&msg("ping", 1)));
+        // the waiter is only woken once
+        assert_eq!(None, mb.send(&msg("ping", 2)));
+        assert_eq!(Some(msg("ping", 1)), mb.wait(1, 7, &any));
+    }
+
+    #[test]
+    fn test_underscore_hashtag_is_not_a_wildcard()
+    {
+        let mb = Mailbox::new();
+        mb.send(&msg("ping", 1));
+        let underscore = Val::Hashtag(Lstr::Sref("_"));
+        mb.send(&underscore);
+
+        assert_eq!(Some(underscore.clone()), mb.take_match(&underscore));
+        assert_eq!(1, mb.len());
+    }
+}
@@ -0,0 +1,484 @@
+use leema::error::{CompileError, CompileResult, ErrorKind};
+use leema::lstr::Lstr;
+use leema::val::SrcLoc;
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+
+pub const MANIFEST_FILE: &'static str = "leema.toml";
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Dependency
+{
+    pub name: Lstr,
+    pub path: PathBuf,
+}
+
+/**
+ * Package manifest, read from a leema.toml file
+ *
+ * [package]
+ * name = "tacos"
+ * entry = "main"
+ * src = ["src", "lib"]
+ *
+ * [dependencies]
+ * salsa = { path = "../salsa" }
+ *
+ * entry defaults to main and src defaults to the manifest's directory.
+ * Only this small subset of toml is supported: strings, lists of
+ * strings and inline tables of strings.
+ */
+#[derive(Debug)]
+pub struct Manifest
+{
+    pub file: PathBuf,
+    pub name: Lstr,
+    pub entry: Lstr,
+    pub src: Vec<PathBuf>,
+    pub deps: Vec<Dependency>,
+}
+
+#[derive(Debug)]
+enum Value
+{
+    Str(String),
+    List(Vec<String>),
+    Table(Vec<(String, String)>),
+}
+
+impl Manifest
+{
+    /**
+     * Look for a manifest in the directory or any of its parents
+     */
+    pub fn find(start: &Path) -> Option<PathBuf>
+    {
+        let mut dir = Some(start);
+        while let Some(d) = dir {
+            let candidate = d.join(MANIFEST_FILE);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+            dir = d.parent();
+        }
+        None
+    }
+
+    pub fn read(file: &Path) -> CompileResult<Manifest>
+    {
+        let mut txt = String::new();
+        let read_result =
+            File::open(file).and_then(|mut f| f.read_to_string(&mut txt));
+        if let Err(e) = read_result {
+            return Err(manifest_error(file, 0, e.to_string()));
+        }
+        Manifest::parse(file, &txt)
+    }
+
+    pub fn parse(file: &Path, txt: &str) -> CompileResult<Manifest>
+    {
+        let mut name = None;
+        let mut entry = None;
+        let mut src = None;
+        let mut deps = vec![];
+        let mut section = String::new();
+
+        for (i, raw_line) in txt.lines().enumerate() {
+            let lineno = i + 1;
+            let line = strip_comment(raw_line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(manifest_error(
+                        file,
+                        lineno,
+                        format!("invalid section: {}", line),
+                    ));
+                }
+                section = line[1..line.len() - 1].trim().to_string();
+                continue;
+            }
+            let eq = line.find('=').ok_or_else(|| {
+                manifest_error(file, lineno, format!("expected key = value"))
+            })?;
+            let key = line[..eq].trim();
+            let val = parse_value(line[eq + 1..].trim())
+                .map_err(|msg| manifest_error(file, lineno, msg))?;
+
+            match (section.as_str(), key, val) {
+                ("package", "name", Value::Str(s)) => {
+                    name = Some(Lstr::from(s));
+                }
+                ("package", "entry", Value::Str(s)) => {
+                    entry = Some(Lstr::from(s));
+                }
+                ("package", "src", Value::Str(s)) => {
+                    src = Some(vec![PathBuf::from(s)]);
+                }
+                ("package", "src", Value::List(dirs)) => {
+                    src = Some(dirs.into_iter().map(PathBuf::from).collect());
+                }
+                ("dependencies", dep_name, Value::Str(path)) => {
+                    deps.push(Dependency {
+                        name: Lstr::from(dep_name.to_string()),
+                        path: PathBuf::from(path),
+                    });
+                }
+                ("dependencies", dep_name, Value::Table(fields)) => {
+                    let path = fields
+                        .into_iter()
+                        .find(|&(ref k, _)| k == "path")
+                        .map(|(_, v)| v)
+                        .ok_or_else(|| {
+                            manifest_error(
+                                file,
+                                lineno,
+                                format!("dependency has no path: {}", dep_name),
+                            )
+                        })?;
+                    deps.push(Dependency {
+                        name: Lstr::from(dep_name.to_string()),
+                        path: PathBuf::from(path),
+                    });
+                }
+                (_, _, val) => {
+                    return Err(manifest_error(
+                        file,
+                        lineno,
+                        format!(
+                            "unexpected manifest value: [{}] {} = {:?}",
+                            section, key, val
+                        ),
+                    ));
+                }
+            }
+        }
+
+        let name =
+            name.ok_or_else(|| manifest_error(file, 0, missing("name")))?;
+        Ok(Manifest {
+            file: file.to_path_buf(),
+            name,
+            entry: entry.unwrap_or(Lstr::Sref("main")),
+            src: src.unwrap_or_else(|| vec![PathBuf::from(".")]),
+            deps,
+        })
+    }
+
+    pub fn dir(&self) -> &Path
+    {
+        self.file.parent().unwrap_or(Path::new("."))
+    }
+
+    /**
+     * Source directories for this package, relative to
+     * the current directory instead of the manifest
+     */
+    pub fn src_dirs(&self) -> Vec<PathBuf>
+    {
+        self.src.iter().map(|s| self.dir().join(s)).collect()
+    }
+
+    /**
+     * Source directories for all of this package's dependencies,
+     * and their dependencies. A dependency w/o its own manifest
+     * is a single source directory.
+     */
+    pub fn dep_dirs(&self) -> CompileResult<Vec<PathBuf>>
+    {
+        let mut dirs = vec![];
+        let mut visited = HashSet::new();
+        let root = fs::canonicalize(self.dir())
+            .unwrap_or_else(|_| self.dir().to_path_buf());
+        visited.insert(root);
+        self.collect_dep_dirs(&mut dirs, &mut visited)?;
+        Ok(dirs)
+    }
+
+    fn collect_dep_dirs(
+        &self,
+        dirs: &mut Vec<PathBuf>,
+        visited: &mut HashSet<PathBuf>,
+    ) -> CompileResult<()>
+    {
+        for dep in self.deps.iter() {
+            let dep_dir = self.dir().join(&dep.path);
+            if !dep_dir.is_dir() {
+                return Err(manifest_error(
+                    &self.file,
+                    0,
+                    format!(
+                        "dependency {} not found at {}",
+                        dep.name,
+                        dep_dir.display()
+                    ),
+                ));
+            }
+            // compare canonical paths so a cycle through ../ is caught
+            let canonical_dir = fs::canonicalize(&dep_dir).map_err(|e| {
+                manifest_error(
+                    &self.file,
+                    0,
+                    format!(
+                        "cannot resolve dependency {} at {}: {}",
+                        dep.name,
+                        dep_dir.display(),
+                        e
+                    ),
+                )
+            })?;
+            if !visited.insert(canonical_dir) {
+                continue;
+            }
+            let dep_file = dep_dir.join(MANIFEST_FILE);
+            if dep_file.is_file() {
+                let dep_manifest = Manifest::read(&dep_file)?;
+                dirs.extend(dep_manifest.src_dirs());
+                dep_manifest.collect_dep_dirs(dirs, visited)?;
+            } else {
+                dirs.push(dep_dir);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn manifest_error(file: &Path, lineno: usize, msg: String) -> CompileError
+{
+    CompileError::new(
+        &Lstr::from(file.display().to_string()),
+        SrcLoc::new(lineno as i16, 0),
+        ErrorKind::InvalidFile,
+        msg,
+    )
+}
+
+fn missing(field: &str) -> String
+{
+    format!("missing package {} in manifest", field)
+}
+
+fn strip_comment(line: &str) -> &str
+{
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if in_str => {
+                escaped = !escaped;
+                continue;
+            }
+            '"' if !escaped => {
+                in_str = !in_str;
+            }
+            '#' if !in_str => {
+                return &line[..i];
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn parse_value(txt: &str) -> Result<Value, String>
+{
+    if txt.starts_with('"') {
+        parse_str(txt).map(Value::Str)
+    } else if txt.starts_with('[') && txt.ends_with(']') {
+        let items = split_items(&txt[1..txt.len() - 1])
+            .into_iter()
+            .map(parse_str)
+            .collect::<Result<Vec<String>, String>>()?;
+        Ok(Value::List(items))
+    } else if txt.starts_with('{') && txt.ends_with('}') {
+        let fields = split_items(&txt[1..txt.len() - 1])
+            .into_iter()
+            .map(|field| {
+                let eq = field
+                    .find('=')
+                    .ok_or_else(|| format!("expected key = value: {}", field))?;
+                let val = parse_str(field[eq + 1..].trim())?;
+                Ok((field[..eq].trim().to_string(), val))
+            }).collect::<Result<Vec<(String, String)>, String>>()?;
+        Ok(Value::Table(fields))
+    } else {
+        Err(format!("unsupported manifest value: {}", txt))
+    }
+}
+
+/**
+ * Split list or table items on commas that aren't in strings
+ */
+fn split_items(txt: &str) -> Vec<&str>
+{
+    let mut items = vec![];
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in txt.char_indices() {
+        match c {
+            '\\' if in_str => {
+                escaped = !escaped;
+                continue;
+            }
+            '"' if !escaped => {
+                in_str = !in_str;
+            }
+            ',' if !in_str => {
+                items.push(txt[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    items.push(txt[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+fn parse_str(txt: &str) -> Result<String, String>
+{
+    if txt.len() < 2 || !txt.starts_with('"') || !txt.ends_with('"') {
+        return Err(format!("expected a string: {}", txt));
+    }
+    let mut result = String::new();
+    let mut chars = txt[1..txt.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            other => {
+                return Err(format!("invalid escape in string: \\{:?}", other));
+            }
+        }
+    }
+    Ok(result)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::error::ErrorKind;
+    use leema::manifest::{Dependency, Manifest};
+
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+
+    #[test]
+    fn test_parse_manifest()
+    {
+        let input = "
+            # tasty
+            [package]
+            name = \"tacos\"
+            entry = \"app\"
+            src = [\"src\", \"lib\"]
+
+            [dependencies]
+            salsa = { path = \"../salsa\" }
+            guac = \"vendor/guac\" # avocado
+            ";
+        let m = Manifest::parse(Path::new("tacos/leema.toml"), input).unwrap();
+        assert_eq!("tacos", m.name.str());
+        assert_eq!("app", m.entry.str());
+        assert_eq!(
+            vec![PathBuf::from("tacos/src"), PathBuf::from("tacos/lib")],
+            m.src_dirs()
+        );
+        assert_eq!(
+            Dependency {
+                name: "salsa".into(),
+                path: PathBuf::from("../salsa"),
+            },
+            m.deps[0]
+        );
+        assert_eq!(PathBuf::from("vendor/guac"), m.deps[1].path);
+    }
+
+    #[test]
+    fn test_parse_manifest_defaults()
+    {
+        let input = "[package]\nname = \"tacos\"\n";
+        let m = Manifest::parse(Path::new("leema.toml"), input).unwrap();
+        assert_eq!("main", m.entry.str());
+        assert_eq!(vec![PathBuf::from("./.")], m.src_dirs());
+        assert!(m.deps.is_empty());
+    }
+
+    #[test]
+    fn test_parse_manifest_error_line()
+    {
+        let input = "[package]\nname = \"tacos\"\nentry = main\n";
+        let err = Manifest::parse(Path::new("leema.toml"), input).unwrap_err();
+        assert_eq!(ErrorKind::InvalidFile, err.kind);
+        assert_eq!(3, err.loc.lineno);
+    }
+
+    #[test]
+    fn test_dep_dirs_from_dep_manifest()
+    {
+        let root = env::temp_dir().join("leema_test_manifest_deps");
+        let app = root.join("app");
+        let salsa = root.join("salsa");
+        fs::create_dir_all(&app).unwrap();
+        fs::create_dir_all(salsa.join("src")).unwrap();
+        fs::write(
+            salsa.join("leema.toml"),
+            "[package]\nname = \"salsa\"\nsrc = \"src\"\n",
+        ).unwrap();
+        let app_file = app.join("leema.toml");
+        fs::write(
+            &app_file,
+            "[package]\nname = \"app\"\n[dependencies]\n\
+             salsa = { path = \"../salsa\" }\n",
+        ).unwrap();
+
+        let m = Manifest::read(&app_file).unwrap();
+        let dirs = m.dep_dirs().unwrap();
+        assert_eq!(vec![app.join("../salsa").join("src")], dirs);
+    }
+
+    #[test]
+    fn test_dep_dirs_dependency_cycle()
+    {
+        let root = env::temp_dir().join("leema_test_manifest_cycle");
+        let a = root.join("a");
+        let b = root.join("b");
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        let a_file = a.join("leema.toml");
+        fs::write(
+            &a_file,
+            "[package]\nname = \"a\"\n[dependencies]\n\
+             b = { path = \"../b\" }\n",
+        ).unwrap();
+        fs::write(
+            b.join("leema.toml"),
+            "[package]\nname = \"b\"\n[dependencies]\n\
+             a = { path = \"../a\" }\n",
+        ).unwrap();
+
+        let m = Manifest::read(&a_file).unwrap();
+        let dirs = m.dep_dirs().unwrap();
+        assert_eq!(vec![a.join("../b").join(".")], dirs);
+    }
+}
@@ -2,7 +2,10 @@
 pub mod log;
 pub mod application;
 pub mod ast;
+pub mod cache;
 pub mod code;
+pub mod disasm;
+pub mod error;
 pub mod fiber;
 pub mod frame;
 pub mod http;
@@ -15,7 +18,9 @@ pub mod list;
 pub mod lmap;
 pub mod loader;
 pub mod lri;
+pub mod mailbox;
 pub mod lstr;
+pub mod manifest;
 pub mod module;
 pub mod msg;
 pub mod parse;
@@ -26,6 +31,7 @@ pub mod reg;
 pub mod rsrc;
 pub mod sendclone;
 pub mod struple;
+pub mod supervisor;
 pub mod tcp;
 pub mod typecheck;
 pub mod types;
@@ -35,6 +41,9 @@ pub mod worker;
 
 // libraries
 pub mod file;
+pub mod lib_buffer;
 pub mod lib_hyper;
 pub mod lib_list;
+pub mod lib_map;
 pub mod lib_str;
+pub mod lib_supervisor;
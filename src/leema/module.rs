@@ -1,8 +1,9 @@
 use leema::ast::{self, Ast};
-use leema::lex::lex;
+use leema::error::{CompileError, CompileResult, ErrorKind};
+use leema::lex::{lex, lex_located};
 use leema::lstr::Lstr;
 use leema::parse::Token;
-use leema::val::{Type, Val};
+use leema::val::{SrcLoc, Type, Val};
 
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
@@ -47,27 +48,27 @@ pub struct ModuleSource
 
 impl ModuleSource
 {
-    pub fn new(mk: ModKey, txt: String) -> ModuleSource
+    pub fn new(mk: ModKey, txt: String) -> CompileResult<ModuleSource>
     {
-        let ast = ModuleSource::read_ast(&txt);
-        ModuleSource { key: mk, txt, ast }
+        let ast = ModuleSource::read_ast(&mk.name, &txt)?;
+        Ok(ModuleSource { key: mk, txt, ast })
     }
 
-    pub fn init() -> ModuleSource
+    pub fn init() -> CompileResult<ModuleSource>
     {
         let init_key = ModKey::name_only(Lstr::Sref("__init__"));
         ModuleSource::new(init_key, String::from(""))
     }
 
-    pub fn read_tokens(txt: &str) -> Vec<Token>
+    pub fn read_tokens(module: &Lstr, txt: &str) -> CompileResult<Vec<Token>>
     {
-        lex(txt)
+        lex(module, txt)
     }
 
-    pub fn read_ast(txt: &str) -> ast::Ast
+    pub fn read_ast(module: &Lstr, txt: &str) -> CompileResult<ast::Ast>
     {
-        let toks = ModuleSource::read_tokens(txt);
-        ast::parse(toks)
+        let toks = lex_located(module, txt)?;
+        ast::parse(module, toks)
     }
 }
 
@@ -76,16 +77,22 @@ pub struct ModulePreface
 {
     pub key: ModKey,
     pub imports: HashSet<Lstr>,
+    // full path for nested imports, keyed by the module's last name
+    pub import_paths: HashMap<Lstr, Vec<Lstr>>,
+    // nested imports w/ a last name already imported from another path
+    pub conflicting_paths: Vec<Vec<Lstr>>,
     pub macros: MacroMap,
 }
 
 impl ModulePreface
 {
-    pub fn new(ms: &ModuleSource) -> ModulePreface
+    pub fn new(ms: &ModuleSource) -> CompileResult<ModulePreface>
     {
         let mut mp = ModulePreface {
             key: ms.key.clone(),
             imports: HashSet::new(),
+            import_paths: HashMap::new(),
+            conflicting_paths: vec![],
             macros: HashMap::new(),
         };
         // everything imports prefab by default
@@ -93,21 +100,25 @@ impl ModulePreface
         if &*ms.key.name != "prefab" {
             mp.imports.insert(Lstr::Sref("prefab"));
         }
-        mp.split_ast(&ms.ast);
-        mp
+        mp.split_ast(&ms.ast)?;
+        Ok(mp)
     }
 
-    pub fn split_ast(&mut self, ast: &Ast)
+    pub fn split_ast(&mut self, ast: &Ast) -> CompileResult<()>
     {
         match ast {
             &Ast::Block(ref lines) => {
                 for l in lines.iter() {
                     ModulePreface::split_ast_block_item(self, l);
                 }
+                Ok(())
             }
-            _ => {
-                panic!("what's that doing in the ast? {:?}", ast);
-            }
+            _ => Err(CompileError::new(
+                &self.key.name,
+                SrcLoc::default(),
+                ErrorKind::Syntax,
+                format!("module is not a block: {:?}", ast),
+            )),
         }
     }
 
@@ -115,8 +126,23 @@ impl ModulePreface
     {
         match item {
             &Ast::Import(ref i, _) => {
-                let imp_string = (**i).localid_str();
-                mp.imports.insert(imp_string.clone());
+                if let Ast::Lri(ref path, _, _) = **i {
+                    let mod_name = path.last().unwrap().clone();
+                    mp.imports.insert(mod_name.clone());
+                    let prev = mp.import_paths.get(&mod_name).cloned();
+                    match prev {
+                        Some(ref prev_path) if prev_path != path => {
+                            mp.conflicting_paths.push(path.clone());
+                        }
+                        Some(_) => {}
+                        None => {
+                            mp.import_paths.insert(mod_name, path.clone());
+                        }
+                    }
+                } else {
+                    let imp_string = (**i).localid_str();
+                    mp.imports.insert(imp_string.clone());
+                }
             }
             &Ast::DefFunc(
                 ast::FuncClass::Macro,
@@ -1,4 +1,5 @@
 use leema::code::Code;
+use leema::fiber::MsgFiber;
 use leema::lri::Lri;
 use leema::lstr::Lstr;
 use leema::rsrc::IopAction;
@@ -49,24 +50,40 @@ pub enum AppMsg
 {
     // Spawn(module, function)
     Spawn(MsgLstr, MsgLstr),
-    Spawn2(FutureSender<Val>, Lri),
-    ResultSpawn(FutureSender<Val>, MsgLstr, MsgLstr),
+    // Spawn2(result, function, args)
+    Spawn2(FutureSender<Val>, Lri, MsgVal),
+    // ResultSpawn(result, module, function, args)
+    ResultSpawn(FutureSender<Val>, MsgLstr, MsgLstr, MsgVal),
     // RequestCode(worker_id, fiber_id, module, function)
     RequestCode(i64, i64, MsgLstr, MsgLstr),
     MainResult(MsgVal),
+    ReplResult(MsgVal),
+    // Steal(thief_id)
+    Steal(i64),
+    // Stolen(thief_id, fiber)
+    Stolen(i64, Option<MsgFiber>),
+    // WakeReceiver(worker_id, fiber_id)
+    WakeReceiver(i64, i64),
 }
 
 #[derive(Debug)]
 pub enum WorkerMsg
 {
-    // Spawn(module, function)
-    Spawn(MsgLstr, MsgLstr),
-    Spawn2(FutureSender<Val>, Lri),
-    ResultSpawn(FutureSender<Val>, MsgLstr, MsgLstr),
-    // FoundCode(fiber_id, module, function, code)
-    FoundCode(i64, MsgLstr, MsgLstr, Code),
+    // Spawn(module, function, args)
+    Spawn(MsgLstr, MsgLstr, MsgVal),
+    // ResultSpawn(result, module, function, args)
+    ResultSpawn(FutureSender<Val>, MsgLstr, MsgLstr, MsgVal),
+    // ReplSpawn(module, function)
+    ReplSpawn(MsgLstr, MsgLstr),
+    // FoundCode(fiber_id, module, function, file, code)
+    FoundCode(i64, MsgLstr, MsgLstr, MsgLstr, Code),
     // IopResult(fiber_id, MsgVal)
     IopResult(i64, MsgVal),
+    // Steal(thief_id)
+    Steal(i64),
+    Stolen(Option<MsgFiber>),
+    // WakeReceiver(fiber_id)
+    WakeReceiver(i64),
     Done,
 }
 
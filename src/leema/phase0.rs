@@ -1,4 +1,5 @@
 use leema::ast::{self, Ast, Kxpr};
+use leema::error::{CompileError, CompileResult, ErrorKind, ModuleResult};
 use leema::list;
 use leema::log;
 use leema::lri::Lri;
@@ -57,12 +58,13 @@ impl Protomod
         prog: &Lib,
         mp: &ModulePreface,
         x: &Ast,
-    )
+    ) -> CompileResult<()>
     {
         match x {
             &Ast::DefFunc(ast::FuncClass::Macro, _, _, _, _, _) => {
                 // do nothing. the macro definition will have been handled
                 // in the file read
+                Ok(())
             }
             &Ast::DefFunc(
                 fclass,
@@ -81,16 +83,18 @@ impl Protomod
                     result_type,
                     body,
                     loc,
-                );
+                )
             }
             &Ast::DefData(data_type, ref name, ref fields, ref loc) => {
-                self.preproc_data(prog, mp, data_type, name, fields, loc);
+                self.preproc_data(prog, mp, data_type, name, fields, loc)
             }
             &Ast::Import(ref _imports, _) => {
                 // do nothing. imports handled in file read
+                Ok(())
             }
             _ => {
                 println!("Cannot phase0: {:?}", x);
+                Ok(())
             }
         }
     }
@@ -100,23 +104,23 @@ impl Protomod
         mp: &ModulePreface,
         x: &Ast,
         loc: &SrcLoc,
-    ) -> Ast
+    ) -> CompileResult<Ast>
     {
-        match x {
+        let ppx = match x {
             &Ast::Block(ref items) => {
                 let pp_items = items
                     .iter()
                     .map(|i| Protomod::preproc_expr(prog, mp, i, loc))
-                    .collect();
+                    .collect::<CompileResult<_>>()?;
                 Ast::Block(pp_items)
             }
             &Ast::Cons(ref head, ref tail) => {
-                let pp_head = Protomod::preproc_expr(prog, mp, head, loc);
-                let pp_tail = Protomod::preproc_expr(prog, mp, tail, loc);
+                let pp_head = Protomod::preproc_expr(prog, mp, head, loc)?;
+                let pp_tail = Protomod::preproc_expr(prog, mp, tail, loc)?;
                 Ast::Cons(Box::new(pp_head), Box::new(pp_tail))
             }
             &Ast::ConstructData(datat, ref name) => {
-                let ppname = Protomod::preproc_expr(prog, mp, name, loc);
+                let ppname = Protomod::preproc_expr(prog, mp, name, loc)?;
                 Ast::ConstructData(datat, Box::new(ppname))
             }
             &Ast::ConstBool(b) => Ast::ConstBool(b),
@@ -127,10 +131,10 @@ impl Protomod
             &Ast::Deref(ref inner) => {
                 Ast::Deref(Box::new(Protomod::preproc_expr(
                     prog, mp, inner, loc,
-                )))
+                )?))
             }
             &Ast::DotAccess(ref base, ref fld) => {
-                let ppbase = Protomod::preproc_expr(prog, mp, base, loc);
+                let ppbase = Protomod::preproc_expr(prog, mp, base, loc)?;
                 Ast::DotAccess(Box::new(ppbase), fld.clone())
             }
             &Ast::IfExpr(
@@ -142,7 +146,13 @@ impl Protomod
                 if let Ast::Localid(_, _) = **input {
                     // this localid shouldn't need further processing
                 } else {
-                    panic!("match failed input must be a variable name");
+                    return Err(error(
+                        mp,
+                        iloc,
+                        ErrorKind::Syntax,
+                        "match failed input must be a variable name"
+                            .to_string(),
+                    ));
                 }
                 let pp_case = Protomod::preproc_ifcase(
                     prog,
@@ -150,7 +160,7 @@ impl Protomod
                     ast::IfType::MatchFailure,
                     case,
                     iloc,
-                );
+                )?;
                 Ast::IfExpr(
                     ast::IfType::MatchFailure,
                     input.clone(),
@@ -159,9 +169,9 @@ impl Protomod
                 )
             }
             &Ast::IfExpr(iftype, ref input, ref case, ref iloc) => {
-                let pp_input = Protomod::preproc_expr(prog, mp, input, iloc);
+                let pp_input = Protomod::preproc_expr(prog, mp, input, iloc)?;
                 let pp_case =
-                    Protomod::preproc_ifcase(prog, mp, iftype, case, iloc);
+                    Protomod::preproc_ifcase(prog, mp, iftype, case, iloc)?;
                 Ast::IfExpr(
                     iftype,
                     Box::new(pp_input),
@@ -171,59 +181,63 @@ impl Protomod
             }
             &Ast::Let(let_type, ref left, ref right, ref iloc) => {
                 let pp_left = Protomod::preproc_pattern(prog, mp, left, iloc);
-                let pp_right = Protomod::preproc_expr(prog, mp, right, iloc);
+                let pp_right = Protomod::preproc_expr(prog, mp, right, iloc)?;
                 Ast::Let(let_type, Box::new(pp_left), Box::new(pp_right), *iloc)
             }
             &Ast::Call(ref callx, ref args, ref iloc) => {
-                Protomod::preproc_call(prog, mp, callx, args, iloc)
+                Protomod::preproc_call(prog, mp, callx, args, iloc)?
             }
             &Ast::List(ref items) => {
                 Ast::List(
                     items
                         .iter()
                         .map(|i| Protomod::preproc_expr(prog, mp, i, loc))
-                        .collect(),
+                        .collect::<CompileResult<_>>()?,
                 )
             }
             &Ast::Localid(ref id, ref iloc) => {
                 Protomod::preproc_localid(prog, mp, id, iloc)
             }
             &Ast::Lri(ref mods, None, ref iloc) => {
-                Protomod::preproc_lri(prog, mp, mods, iloc)
+                Protomod::preproc_lri(prog, mp, mods, iloc)?
             }
             &Ast::Lri(ref mods, Some(ref typs), ref iloc) => {
-                Protomod::preproc_lri_with_types(prog, mp, mods, typs, iloc)
+                Protomod::preproc_lri_with_types(prog, mp, mods, typs, iloc)?
             }
             &Ast::Map(ref items) => {
                 let pp_items = items
                     .iter()
                     .map(|i| {
-                        i.map_x(|x| Protomod::preproc_expr(prog, mp, x, loc))
-                    }).collect();
+                        i.try_map_x(|x| {
+                            Protomod::preproc_expr(prog, mp, x, loc)
+                        })
+                    }).collect::<CompileResult<_>>()?;
                 Ast::Map(pp_items)
             }
             &Ast::Return(ref x, ref loc) => {
-                let px = Protomod::preproc_expr(prog, mp, x, loc);
+                let px = Protomod::preproc_expr(prog, mp, x, loc)?;
                 Ast::Return(Box::new(px), *loc)
             }
             &Ast::StrExpr(ref xs, ref loc) => {
                 let pxs = xs
                     .iter()
                     .map(|x| Protomod::preproc_expr(prog, mp, x, loc))
-                    .collect();
+                    .collect::<CompileResult<_>>()?;
                 Ast::StrExpr(pxs, *loc)
             }
             &Ast::Tuple(ref items) if items.len() == 1 => {
                 // one-tuples are compiled to just the value
                 let first_x = items.front().unwrap().x_ref().unwrap();
-                Protomod::preproc_expr(prog, mp, first_x, loc)
+                Protomod::preproc_expr(prog, mp, first_x, loc)?
             }
             &Ast::Tuple(ref items) => {
                 let pp_items = items
                     .iter()
                     .map(|i| {
-                        i.map_x(|x| Protomod::preproc_expr(prog, mp, x, loc))
-                    }).collect();
+                        i.try_map_x(|x| {
+                            Protomod::preproc_expr(prog, mp, x, loc)
+                        })
+                    }).collect::<CompileResult<_>>()?;
                 Ast::Tuple(pp_items)
             }
             &Ast::TypeFunc(ref parts, ref loc) => {
@@ -237,7 +251,7 @@ impl Protomod
                             p,
                             loc,
                         )
-                    }).collect();
+                    }).collect::<CompileResult<_>>()?;
                 Ast::TypeFunc(ppp, *loc)
             }
             &Ast::RustBlock => Ast::RustBlock,
@@ -250,16 +264,32 @@ impl Protomod
             &Ast::TypeVar(ref v, ref loc) => Ast::TypeVar(v.clone(), *loc),
             &Ast::TypeVoid => Ast::TypeVoid,
             &Ast::Wildcard => Ast::Wildcard,
-            &Ast::DefData(_, _, _, _) => {
-                panic!("cannot preproc: {:?}", x);
+            &Ast::DefData(_, _, _, ref iloc) => {
+                return Err(error(
+                    mp,
+                    iloc,
+                    ErrorKind::Unsupported,
+                    "data definitions must be at module level".to_string(),
+                ));
             }
-            &Ast::DefFunc(_, _, _, _, _, _) => {
-                panic!("cannot preproc: {:?}", x);
+            &Ast::DefFunc(_, _, _, _, _, ref iloc) => {
+                return Err(error(
+                    mp,
+                    iloc,
+                    ErrorKind::Unsupported,
+                    "function definitions must be at module level".to_string(),
+                ));
             }
-            &Ast::Import(_, _) => {
-                panic!("cannot preproc: {:?}", x);
+            &Ast::Import(_, ref iloc) => {
+                return Err(error(
+                    mp,
+                    iloc,
+                    ErrorKind::Unsupported,
+                    "imports must be at module level".to_string(),
+                ));
             }
-        }
+        };
+        Ok(ppx)
     }
 
     pub fn preproc_func_arg(
@@ -268,17 +298,27 @@ impl Protomod
         func_name: &Lstr,
         arg: &Kxpr,
         loc: &SrcLoc,
-    ) -> Kxpr
+    ) -> CompileResult<Kxpr>
     {
         match (arg.k_ref(), arg.x_ref()) {
             (None, None) => {
-                panic!("cannot preproc arg with no id or type: {:?}", loc);
+                Err(error(
+                    mp,
+                    loc,
+                    ErrorKind::Syntax,
+                    format!("argument for {} has no id or type", func_name),
+                ))
             }
             (None, Some(&Ast::TypeAnon)) => {
-                panic!(
-                    "cannot preproc arg with no id and anonymous type: {:?}",
-                    loc
-                );
+                Err(error(
+                    mp,
+                    loc,
+                    ErrorKind::Syntax,
+                    format!(
+                        "argument for {} has no id and an anonymous type",
+                        func_name
+                    ),
+                ))
             }
             (Some(id), None) => {
                 let type_name = Lstr::from(format!(
@@ -288,7 +328,7 @@ impl Protomod
                     id
                 ));
                 let typ = Ast::TypeVar(type_name, *loc);
-                Kxpr::new(id.clone(), typ)
+                Ok(Kxpr::new(id.clone(), typ))
             }
             (Some(id), Some(&Ast::TypeAnon)) => {
                 let type_name = Lstr::from(format!(
@@ -298,10 +338,12 @@ impl Protomod
                     id
                 ));
                 let new_typ = Ast::TypeVar(type_name, *loc);
-                Kxpr::new(id.clone(), new_typ)
+                Ok(Kxpr::new(id.clone(), new_typ))
             }
             (_, Some(_)) => {
-                arg.map_x(|typ| Protomod::preproc_expr(prog, mp, typ, &loc))
+                arg.try_map_x(|typ| {
+                    Protomod::preproc_expr(prog, mp, typ, &loc)
+                })
             }
         }
     }
@@ -311,10 +353,10 @@ impl Protomod
         mp: &ModulePreface,
         result_type: &Ast,
         loc: &SrcLoc,
-    ) -> Ast
+    ) -> CompileResult<Ast>
     {
         match result_type {
-            Ast::TypeAnon => Ast::TypeVar(Lstr::Sref("T_result"), *loc),
+            Ast::TypeAnon => Ok(Ast::TypeVar(Lstr::Sref("T_result"), *loc)),
             _ => Protomod::preproc_expr(prog, mp, result_type, &loc),
         }
     }
@@ -329,7 +371,7 @@ impl Protomod
         rtype: &Ast,
         body: &Ast,
         loc: &SrcLoc,
-    )
+    ) -> CompileResult<()>
     {
         let name_lri = Lri::from(name);
         let full_lri = name_lri.add_modules(mp.key.name.clone());
@@ -337,9 +379,10 @@ impl Protomod
         let pp_args: LinkedList<Kxpr> = args
             .iter()
             .map(|a| Protomod::preproc_func_arg(prog, mp, &lstr_name, a, loc))
-            .collect();
-        let pp_rtype_ast = Protomod::preproc_func_result(prog, mp, rtype, loc);
-        let pp_body = Protomod::preproc_expr(prog, mp, body, loc);
+            .collect::<CompileResult<_>>()?;
+        let pp_rtype_ast =
+            Protomod::preproc_func_result(prog, mp, rtype, loc)?;
+        let pp_body = Protomod::preproc_expr(prog, mp, body, loc)?;
         let pp_func = Ast::DefFunc(
             fclass,
             Box::new(name.clone()),
@@ -364,7 +407,7 @@ impl Protomod
                         argt.x_ref().unwrap(),
                         loc,
                     )
-                }).collect()
+                }).collect::<CompileResult<_>>()?
         };
         let rtype = Type::from(&pp_rtype_ast);
         ftype_parts.push(Kxpr::new_x(pp_rtype_ast));
@@ -376,6 +419,7 @@ impl Protomod
         self.funcsrc.insert(lstr_name.clone(), pp_func);
         self.valtypes.insert(lstr_name.clone(), ftype);
         self.constants.insert(lstr_name, funcref);
+        Ok(())
     }
 
     pub fn preproc_call(
@@ -384,46 +428,60 @@ impl Protomod
         callx: &Ast,
         args: &LinkedList<Kxpr>,
         loc: &SrcLoc,
-    ) -> Ast
+    ) -> CompileResult<Ast>
     {
         let pp_args: LinkedList<Kxpr> = args
             .iter()
-            .map(|arg| arg.map_x(|x| Protomod::preproc_expr(prog, mp, x, loc)))
-            .collect();
-        let pp_callx = Protomod::preproc_expr(prog, mp, callx, loc);
+            .map(|arg| {
+                arg.try_map_x(|x| Protomod::preproc_expr(prog, mp, x, loc))
+            }).collect::<CompileResult<_>>()?;
+        let pp_callx = Protomod::preproc_expr(prog, mp, callx, loc)?;
         match pp_callx {
             Ast::DefFunc(ast::FuncClass::Macro, mname, margs, _, body, _) => {
                 vout!("apply_macro({:?}, {:?})\n", mname, args);
-                let macrod =
-                    Protomod::apply_macro(&mname, &body, &margs, &pp_args, loc);
+                let macrod = Protomod::apply_macro(
+                    mp, &mname, &body, &margs, &pp_args, loc,
+                )?;
                 // do it again to make sure there's not a wrapped macro
                 Protomod::preproc_expr(prog, mp, &macrod, loc)
             }
-            _ => Ast::Call(Box::new(pp_callx), pp_args, *loc),
+            _ => Ok(Ast::Call(Box::new(pp_callx), pp_args, *loc)),
         }
     }
 
     pub fn apply_macro(
+        mp: &ModulePreface,
         macro_name: &Ast,
         body: &Ast,
         arg_names: &LinkedList<Kxpr>,
         args: &LinkedList<Kxpr>,
         loc: &SrcLoc,
-    ) -> Ast
+    ) -> CompileResult<Ast>
     {
         let mut arg_map = HashMap::new();
+        let macro_lstr = Lstr::from(macro_name);
         match (arg_names.len(), args.len()) {
             (a, b) if a < b => {
-                panic!(
-                    "Too many arguments passed to macro {:?}, expected {}",
-                    macro_name, a
-                );
+                return Err(error(
+                    mp,
+                    loc,
+                    ErrorKind::Macro,
+                    format!(
+                        "too many arguments passed to macro {}, expected {}",
+                        macro_lstr, a
+                    ),
+                ));
             }
             (a, b) if a > b => {
-                panic!(
-                    "Too few arguments passed to macro {:?}, expected {}",
-                    macro_name, a
-                );
+                return Err(error(
+                    mp,
+                    loc,
+                    ErrorKind::Macro,
+                    format!(
+                        "too few arguments passed to macro {}, expected {}",
+                        macro_lstr, a
+                    ),
+                ));
             }
             _ => {
                 // a == b. cool, proceed
@@ -432,16 +490,18 @@ impl Protomod
         for (n, arg_val) in arg_names.iter().zip(args.iter()) {
             let n_opt = n.k_ref();
             if n_opt.is_none() {
-                panic!(
-                    "macro has unnamed args: {:?} -> {:?}",
-                    macro_name, arg_names
-                );
+                return Err(error(
+                    mp,
+                    loc,
+                    ErrorKind::Macro,
+                    format!("macro has unnamed args: {}", macro_lstr),
+                ));
             }
             let n_lstr = n_opt.unwrap().clone();
             arg_map.insert(n_lstr, arg_val.x_ref().unwrap());
         }
         vout!("replace_ids({:?})\n", arg_map);
-        Protomod::replace_ids(body, &arg_map, loc)
+        Ok(Protomod::replace_ids(body, &arg_map, loc))
     }
 
     pub fn replace_ids(
@@ -541,17 +601,23 @@ impl Protomod
         mp: &ModulePreface,
         mods: &Vec<Lstr>,
         loc: &SrcLoc,
-    ) -> Ast
+    ) -> CompileResult<Ast>
     {
         let mod_name = mods.first().unwrap();
         if *mod_name != *mp.key.name && !mp.imports.contains(mod_name) {
-            panic!("module not found: {:?}", mods);
+            return Err(error(
+                mp,
+                loc,
+                ErrorKind::ModuleNotFound,
+                format!("module not imported: {}", mod_name),
+            ));
         }
         let val_name = mods.last().unwrap();
-        match prog.get_macro(mod_name, val_name) {
+        let lri = match prog.get_macro(mod_name, val_name) {
             Some(mac) => mac.clone(),
             None => Ast::Lri(mods.clone(), None, *loc),
-        }
+        };
+        Ok(lri)
     }
 
     pub fn preproc_lri_with_types(
@@ -560,13 +626,13 @@ impl Protomod
         mods: &Vec<Lstr>,
         typs: &LinkedList<Ast>,
         loc: &SrcLoc,
-    ) -> Ast
+    ) -> CompileResult<Ast>
     {
         let pp_types = typs
             .iter()
             .map(|t| Protomod::preproc_expr(prog, mp, t, loc))
-            .collect();
-        Ast::Lri(mods.clone(), Some(pp_types), *loc)
+            .collect::<CompileResult<_>>()?;
+        Ok(Ast::Lri(mods.clone(), Some(pp_types), *loc))
     }
 
     pub fn preproc_ifcase(
@@ -575,11 +641,11 @@ impl Protomod
         iftype: ast::IfType,
         case: &ast::IfCase,
         loc: &SrcLoc,
-    ) -> ast::IfCase
+    ) -> CompileResult<ast::IfCase>
     {
         let pp_cond = match iftype {
             ast::IfType::If => {
-                Protomod::preproc_expr(prog, mp, &case.cond, &case.loc)
+                Protomod::preproc_expr(prog, mp, &case.cond, &case.loc)?
             }
             ast::IfType::Match => {
                 Protomod::preproc_pattern(prog, mp, &case.cond, &case.loc)
@@ -589,19 +655,37 @@ impl Protomod
                     &Ast::ConstHashtag(ref ht) => Ast::ConstHashtag(ht.clone()),
                     &Ast::Wildcard => Ast::Wildcard,
                     _ => {
-                        panic!("match failure case pattern must be a hashtag or an underscore: {:?}", case.cond);
+                        return Err(error(
+                            mp,
+                            &case.loc,
+                            ErrorKind::Pattern,
+                            "match failure case pattern must be a hashtag \
+                             or an underscore"
+                                .to_string(),
+                        ));
                     }
                 }
             }
             ast::IfType::TypeCast => {
-                panic!("typecast not ready yet");
+                return Err(error(
+                    mp,
+                    &case.loc,
+                    ErrorKind::Unsupported,
+                    "typecast not ready yet".to_string(),
+                ));
             }
         };
-        let pp_body = Protomod::preproc_expr(prog, mp, &case.body, &case.loc);
-        let pp_else = case.else_case.as_ref().map(|else_case| {
-            Protomod::preproc_ifcase(prog, mp, iftype, &*else_case, &case.loc)
-        });
-        ast::IfCase::new(pp_cond, pp_body, pp_else, *loc)
+        let pp_body =
+            Protomod::preproc_expr(prog, mp, &case.body, &case.loc)?;
+        let pp_else = match case.else_case {
+            Some(ref else_case) => {
+                Some(Protomod::preproc_ifcase(
+                    prog, mp, iftype, &*else_case, &case.loc,
+                )?)
+            }
+            None => None,
+        };
+        Ok(ast::IfCase::new(pp_cond, pp_body, pp_else, *loc))
     }
 
     pub fn preproc_pattern(
@@ -672,11 +756,11 @@ impl Protomod
         opt_type_params: Option<&Vec<Type>>,
         typ: &Ast,
         loc: &SrcLoc,
-    ) -> Type
+    ) -> CompileResult<Type>
     {
-        let pp_x = Protomod::preproc_expr(prog, mp, typ, loc);
+        let pp_x = Protomod::preproc_expr(prog, mp, typ, loc)?;
         let local_type = Type::from(&pp_x);
-        self.replace_typeids(opt_type_params, local_type)
+        Ok(self.replace_typeids(opt_type_params, local_type))
     }
 
     pub fn replace_typeids(
@@ -698,6 +782,11 @@ impl Protomod
                     && self.deftypes.contains_key(&id.localid)
                 {
                     Type::UserDef(id.add_modules(self.key.name.clone()))
+                } else if id.local_only() && id.localid.str() == "Map" {
+                    // no keyword for the map type, so it's resolved here
+                    Type::Map
+                } else if id.local_only() && id.localid.str() == "Buffer" {
+                    Type::Buffer
                 } else {
                     Type::UserDef(id)
                 }
@@ -725,6 +814,7 @@ impl Protomod
                 Type::Tuple(Struple(items2))
             }
             Type::Map => Type::Map,
+            Type::Buffer => Type::Buffer,
             // primitive types
             Type::Bool => Type::Bool,
             Type::Hashtag => Type::Hashtag,
@@ -759,11 +849,16 @@ impl Protomod
         name_ast: &Ast,
         fields: &LinkedList<Kxpr>,
         loc: &SrcLoc,
-    )
+    ) -> CompileResult<()>
     {
         let base_name = Lri::from(name_ast);
         if base_name.mod_ref().is_some() {
-            panic!("no modules in data definitions: {}", base_name);
+            return Err(error(
+                mp,
+                loc,
+                ErrorKind::Syntax,
+                format!("no modules in data definitions: {}", base_name),
+            ));
         }
         let name = Protomod::replace_type_names_with_vars(
             base_name.add_modules(mp.key.name.clone()),
@@ -772,15 +867,15 @@ impl Protomod
         match datatype {
             ast::DataType::Struple => {
                 if fields.is_empty() {
-                    self.preproc_struple_token(name, loc);
+                    self.preproc_struple_token(mp, name, loc)
                 } else {
                     self.preproc_struple_with_fields(
                         prog, mp, name, fields, loc,
-                    );
+                    )
                 }
             }
             ast::DataType::Enum => {
-                self.preproc_enum(prog, mp, name_ast, fields);
+                self.preproc_enum(prog, mp, name_ast, fields, loc)
             }
         }
     }
@@ -812,10 +907,20 @@ impl Protomod
         i
     }
 
-    pub fn preproc_struple_token(&mut self, full_lri: Lri, _loc: &SrcLoc)
+    pub fn preproc_struple_token(
+        &mut self,
+        mp: &ModulePreface,
+        full_lri: Lri,
+        loc: &SrcLoc,
+    ) -> CompileResult<()>
     {
         if full_lri.param_ref().is_some() {
-            panic!("no type params for tokens: {}", full_lri);
+            return Err(error(
+                mp,
+                loc,
+                ErrorKind::Syntax,
+                format!("no type params for tokens: {}", full_lri),
+            ));
         }
         let name_lstr = full_lri.localid.clone();
         let type_name = Type::UserDef(full_lri.clone());
@@ -824,6 +929,7 @@ impl Protomod
         let constval = Val::Token(full_lri);
         self.constants.insert(name_lstr.clone(), constval);
         self.valtypes.insert(name_lstr, type_name);
+        Ok(())
     }
 
     pub fn preproc_struple_with_fields(
@@ -833,7 +939,7 @@ impl Protomod
         type_lri: Lri,
         src_fields: &LinkedList<Kxpr>,
         loc: &SrcLoc,
-    )
+    ) -> CompileResult<()>
     {
         let name_lstr = type_lri.local_ref().clone();
 
@@ -844,9 +950,10 @@ impl Protomod
             name_lstr.clone(),
             src_fields,
             loc,
-        );
+        )?;
         self.deftypes
             .insert(name_lstr.clone(), Type::UserDef(type_lri));
+        Ok(())
     }
 
     pub fn preproc_struple_fields(
@@ -857,7 +964,7 @@ impl Protomod
         local_name: Lstr,
         src_fields: &LinkedList<Kxpr>,
         loc: &SrcLoc,
-    )
+    ) -> CompileResult<()>
     {
         let struple_fields: Vec<(Option<Lstr>, Type)> = src_fields
             .iter()
@@ -870,9 +977,9 @@ impl Protomod
                     struple_lri.param_ref(),
                     f.x_ref().unwrap(),
                     loc,
-                );
-                (f.k_clone(), pp_type)
-            }).collect();
+                )?;
+                Ok((f.k_clone(), pp_type))
+            }).collect::<CompileResult<_>>()?;
         let field_type_vec = struple_fields
             .iter()
             .map(|&(_, ref ftype)| ftype.clone())
@@ -915,6 +1022,7 @@ impl Protomod
         self.funcseq.push_back(local_name.clone());
         self.funcsrc.insert(local_name.clone(), srcxpr);
         self.valtypes.insert(local_name, func_type);
+        Ok(())
     }
 
     pub fn struple_field_idx(
@@ -965,7 +1073,8 @@ impl Protomod
         mp: &ModulePreface,
         name_ast: &Ast,
         src_variants: &LinkedList<Kxpr>,
-    )
+        loc: &SrcLoc,
+    ) -> CompileResult<()>
     {
         let local_name = Lri::from(name_ast);
         let enum_lri = local_name.add_modules(self.key.name.clone());
@@ -978,18 +1087,24 @@ impl Protomod
             {
                 self.preproc_enum_variant(
                     prog, mp, &name_ast, vdatatype, vname, fields, iloc,
-                );
+                )?;
                 let variant_lstr = Lstr::from(&**vname);
                 let vf = (variant_lstr, mod_type.clone());
                 variant_fields.push(vf);
             } else {
-                panic!("variant data is not DefData: {:?}", v);
+                return Err(error(
+                    mp,
+                    loc,
+                    ErrorKind::Syntax,
+                    format!("invalid variant for enum {}", local_name),
+                ));
             }
         }
 
         // self.constants.insert(name_lstr.clone(), Val::Type(mod_type.clone()));
         self.deftypes
             .insert(enum_lri.local_ref().clone(), mod_type.clone());
+        Ok(())
     }
 
     pub fn preproc_enum_variant(
@@ -1001,7 +1116,7 @@ impl Protomod
         name: &Ast,
         fields: &LinkedList<Kxpr>,
         loc: &SrcLoc,
-    )
+    ) -> CompileResult<()>
     {
         let typ_lri = Lri::from(typename);
         let full_lri = typ_lri.add_modules(self.key.name.clone());
@@ -1019,6 +1134,7 @@ impl Protomod
                 let const_val = Val::EnumToken(full_lri, variant_name.clone());
                 self.constants.insert(variant_name.clone(), const_val);
                 self.valtypes.insert(variant_name, typ);
+                Ok(())
             } else {
                 self.preproc_struple_fields(
                     prog,
@@ -1027,30 +1143,62 @@ impl Protomod
                     variant_name,
                     fields,
                     loc,
-                );
+                )
             }
         } else {
-            panic!("unknown enum variant type: {:?}", dataclass);
+            Err(error(
+                mp,
+                loc,
+                ErrorKind::Unsupported,
+                format!("unknown enum variant type: {:?}", dataclass),
+            ))
         }
     }
 }
 
-pub fn preproc(prog: &mut Lib, mp: &ModulePreface, ast: &Ast) -> Protomod
+fn error(
+    mp: &ModulePreface,
+    loc: &SrcLoc,
+    kind: ErrorKind,
+    msg: String,
+) -> CompileError
+{
+    CompileError::new(&mp.key.name, *loc, kind, msg)
+}
+
+/**
+ * Preprocess each definition in the module, collecting errors
+ * so one bad definition doesn't hide the rest
+ */
+pub fn preproc(
+    prog: &mut Lib,
+    mp: &ModulePreface,
+    ast: &Ast,
+) -> ModuleResult<Protomod>
 {
     let mk = mp.key.clone();
     let mut p = Protomod::new(mk);
+    let mut errs = vec![];
     match ast {
         &Ast::Block(ref lines) => {
             for x in lines.iter() {
-                p.preproc_module_expr(prog, mp, x);
+                if let Err(e) = p.preproc_module_expr(prog, mp, x) {
+                    errs.push(e);
+                }
             }
         }
         _ => {
             println!("preproc(something_else, {:?})", ast);
-            p.preproc_module_expr(prog, mp, ast);
+            if let Err(e) = p.preproc_module_expr(prog, mp, ast) {
+                errs.push(e);
+            }
         }
     }
-    p
+    if errs.is_empty() {
+        Ok(p)
+    } else {
+        Err(errs)
+    }
 }
 
 
@@ -1080,10 +1228,10 @@ mod tests
             ",
         );
 
-        let mut loader = Interloader::new(Lstr::Sref("tacos.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("tacos"), input);
         let mut prog = program::Lib::new(loader);
-        let pmod = prog.read_proto(&Lstr::Sref("tacos"));
+        let pmod = prog.read_proto(&Lstr::Sref("tacos")).unwrap();
 
         assert_eq!(2, pmod.funcsrc.len());
 
@@ -1120,10 +1268,10 @@ mod tests
         );
 
         let colors_str = Lstr::Sref("colors");
-        let mut loader = Interloader::new(Lstr::Sref("colors.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("colors.lma")).unwrap();
         loader.set_mod_txt(colors_str.clone(), input);
         let mut prog = program::Lib::new(loader);
-        let pmod = prog.read_proto(&colors_str);
+        let pmod = prog.read_proto(&colors_str).unwrap();
 
         assert_eq!(0, pmod.funcsrc.len());
 
@@ -1154,10 +1302,10 @@ mod tests
         --
         ".to_string();
         let animals_str = Lstr::Sref("animals");
-        let mut loader = Interloader::new(Lstr::Sref("animals.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("animals.lma")).unwrap();
         loader.set_mod_txt(animals_str.clone(), input);
         let mut prog = program::Lib::new(loader);
-        let pmod = prog.read_proto(&animals_str);
+        let pmod = prog.read_proto(&animals_str).unwrap();
 
         let type_lri = Lri::full(
             Some(animals_str.clone()),
@@ -1241,10 +1389,10 @@ mod tests
     struct Greeting(Str, Str)
     ".to_string();
         let greet = Lstr::Sref("greet");
-        let mut loader = Interloader::new(Lstr::Sref("greet.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("greet.lma")).unwrap();
         loader.set_mod_txt(greet.clone(), input);
         let mut prog = program::Lib::new(loader);
-        let pmod = prog.read_proto(&greet);
+        let pmod = prog.read_proto(&greet).unwrap();
 
         let greeting_lstr = Lstr::Sref("Greeting");
         let greeting_fullri = Lri::with_modules(greet.clone(), greeting_lstr);
@@ -1286,10 +1434,10 @@ mod tests
             struct Burrito(Bool, buns: Int)
             ".to_string();
 
-        let mut loader = Interloader::new(Lstr::Sref("tacos.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("tacos"), input);
         let mut prog = program::Lib::new(loader);
-        let pmod = prog.read_proto(&Lstr::Sref("tacos"));
+        let pmod = prog.read_proto(&Lstr::Sref("tacos")).unwrap();
 
         // assert valtypes
         assert!(pmod.valtypes.contains_key("Burrito"));
@@ -1344,10 +1492,10 @@ mod tests
             .number: Int
             --
             ".to_string();
-        let mut loader = Interloader::new(Lstr::Sref("tacos.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("tacos"), input);
         let mut prog = program::Lib::new(loader);
-        let pmod = prog.read_proto(&Lstr::Sref("tacos"));
+        let pmod = prog.read_proto(&Lstr::Sref("tacos")).unwrap();
 
         // assert valtypes
         assert!(pmod.valtypes.contains_key("Burrito"));
@@ -1428,10 +1576,10 @@ mod tests
             struct Burrito --
             ".to_string();
 
-        let mut loader = Interloader::new(Lstr::Sref("tok.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("tok.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("tok"), input);
         let mut prog = program::Lib::new(loader);
-        let pmod = prog.read_proto(&Lstr::Sref("tok"));
+        let pmod = prog.read_proto(&Lstr::Sref("tok")).unwrap();
 
         let exptype_lri =
             Lri::with_modules(Lstr::from("tok"), Lstr::from("Burrito"));
@@ -1,16 +1,15 @@
 use leema::code::Code;
 use leema::fiber::Fiber;
-use leema::frame::Event;
+use leema::frame::{Event, Frame, FrameTrace, Parent};
 use leema::list;
 use leema::log;
 use leema::lstr::Lstr;
+use leema::mailbox::Pid;
 use leema::rsrc;
-use leema::val::{self, LibVal, Type, Val};
+use leema::struple::Struple;
+use leema::val::{self, Env, Type, Val};
 
-use std::fmt::{self, Debug, Display};
-use std::fs::File;
-use std::io::{stderr, stdin, Read, Write};
-use std::sync::Mutex;
+use std::io::{stderr, stdin, Write};
 use std::time::{Duration, Instant};
 
 use futures::future::empty;
@@ -312,88 +311,127 @@ pub fn create_failure(f: &mut Fiber) -> Event
     Event::failure()
 }
 
-
-struct LeemaFile
+/**
+ * failure_trace(f: Failure): [(Str, Str, Str, Int, Int)]
+ *
+ * list the frames of a failure's trace, most recent first, as
+ * (module, function, file, line, column)
+ */
+pub fn failure_trace(f: &mut Fiber) -> Event
 {
-    f: Mutex<File>,
+    let frames = match f.head.get_param(0) {
+        &Val::Failure(_, _, ref trace, _) => trace.to_val(),
+        _ => list::empty(),
+    };
+    f.head.parent.set_result(frames);
+    Event::success()
 }
 
-impl LeemaFile
+/**
+ * spawn(func, args): Pid
+ *
+ * start func in a new fiber w/ its own mailbox
+ */
+pub fn spawn(f: &mut Fiber) -> Event
 {
-    pub fn new(f: File) -> LeemaFile
-    {
-        LeemaFile { f: Mutex::new(f) }
-    }
+    let func = match f.head.get_param(0) {
+        &Val::FuncRef(ref lri, _) => lri.clone(),
+        other => {
+            panic!("cannot spawn a not function: {:?}", other);
+        }
+    };
+    let args = match f.head.get_param(1) {
+        &Val::Tuple(_) => f.head.get_param(1).deep_clone(),
+        &Val::Void => Val::Tuple(Struple(vec![])),
+        other => Val::Tuple(Struple::new_indexed(vec![other.deep_clone()])),
+    };
+    let pid = Pid::new();
+    let frame = Frame {
+        parent: Parent::Process(pid.clone()),
+        module: func.mod_ref().unwrap().clone(),
+        function: func.localid.clone(),
+        file: Lstr::empty(),
+        trace: FrameTrace::new_root(),
+        e: Env::with_args(args),
+        pc: 0,
+    };
+    f.head.parent.set_result(Val::libval(pid));
+    Event::Fork(frame)
 }
 
-impl LibVal for LeemaFile
+pub fn self_pid(f: &mut Fiber) -> Event
 {
-    fn get_type(&self) -> Type
-    {
-        Type::Lib("File".to_string())
-    }
+    let pid = f.pid();
+    f.head.parent.set_result(Val::libval(pid));
+    Event::success()
 }
 
-impl Display for LeemaFile
+/**
+ * send(pid, msg): Void
+ *
+ * copy msg into the pid's mailbox and wake it if it's waiting
+ */
+pub fn send(f: &mut Fiber) -> Event
 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
-    {
-        write!(f, "LeemaFile")
+    let waiter = {
+        let pidval = f.head.get_param(0);
+        let pid = match pidval.libval_as::<Pid>() {
+            Some(pid) => pid,
+            None => {
+                panic!("cannot send to a not pid: {:?}", pidval);
+            }
+        };
+        pid.mailbox.send(f.head.get_param(1))
+    };
+    f.head.parent.set_result(Val::Void);
+    match waiter {
+        Some((worker_id, fiber_id)) => {
+            Event::WakeReceiver(worker_id, fiber_id)
+        }
+        None => Event::success(),
     }
 }
 
-impl Debug for LeemaFile
+/**
+ * receive(pattern): $A
+ *
+ * take the oldest message in this fiber's mailbox that matches
+ * the pattern, waiting for one if necessary. _ matches anything.
+ */
+pub fn receive(f: &mut Fiber) -> Event
 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
-    {
-        write!(f, "LeemaFile")
-    }
+    let patt = f.head.get_param(0).clone();
+    receive_until(f, patt, None)
 }
 
-
-pub fn file_read(f: &mut Fiber) -> Event
+/**
+ * receive_timeout(pattern, ms): $A
+ *
+ * same as receive, but fails w/ #timeout if no matching message
+ * arrives in time
+ */
+pub fn receive_timeout(f: &mut Fiber) -> Event
 {
-    let open_result = {
-        let fnval = f.head.get_param(0);
-        match fnval {
-            &Val::Str(ref fnstr) => File::open(&**fnstr),
-            _ => {
-                panic!("Can't open file with not string {:?}", fnval);
-            }
-        }
-    };
-    let openf = match open_result {
-        Ok(file) => Val::libval(LeemaFile::new(file)),
-        Err(_) => {
-            Val::failure(
-                Val::Hashtag(Lstr::Sref("file_open_fail")),
-                Val::Str(Lstr::Sref("Failed to open file")),
-                f.head.trace.fail_here(),
-                val::FAILURE_INTERNAL,
-            )
-        }
-    };
-    f.head.parent.set_result(openf);
-    Event::success()
+    let patt = f.head.get_param(0).clone();
+    let ms = f.head.get_param(1).to_int().max(0);
+    let deadline = Instant::now() + Duration::from_millis(ms as u64);
+    receive_until(f, patt, Some(deadline))
 }
 
-pub fn file_stream_read(f: &mut Fiber) -> Event
+fn receive_until(f: &mut Fiber, patt: Val, deadline: Option<Instant>)
+    -> Event
 {
-    let mut input = "".to_string();
-    {
-        let streamval = f.head.e.get_param_mut(0);
-        let optf = streamval.libval_as();
-        let myfref: &LeemaFile = optf.unwrap();
-        let lockf = myfref.f.lock();
-        let mut rawf = lockf.unwrap();
-        rawf.read_to_string(&mut input)
-            .expect("failed to read from file to string");
-        //let result = myf.f.lock().unwrap().read_to_string(&mut input);
+    let mailbox = f.pid().mailbox;
+    match mailbox.take_match(&patt) {
+        Some(msg) => {
+            f.head.parent.set_result(msg);
+            Event::success()
+        }
+        None => Event::Receive(mailbox, patt, deadline),
     }
-    f.head.parent.set_result(Val::Str(Lstr::from(input)));
-    Event::success()
 }
 
+
 /*
 macro_rules! load_rust_funcs {
     ( $fname:ident, $( $f:ident ),* ) => {
@@ -430,8 +468,12 @@ pub fn load_rust_func(func_name: &str) -> Option<Code>
         "cin" => Some(Code::Rust(cin)),
         "print" => Some(Code::Rust(print)),
         "create_failure" => Some(Code::Rust(create_failure)),
-        "file_read" => Some(Code::Rust(file_read)),
-        "file_stream_read" => Some(Code::Rust(file_stream_read)),
+        "failure_trace" => Some(Code::Rust(failure_trace)),
+        "spawn" => Some(Code::Rust(spawn)),
+        "self_pid" => Some(Code::Rust(self_pid)),
+        "send" => Some(Code::Rust(send)),
+        "receive" => Some(Code::Rust(receive)),
+        "receive_timeout" => Some(Code::Rust(receive_timeout)),
         _ => None,
     }
 }
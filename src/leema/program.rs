@@ -1,5 +1,7 @@
 use leema::ast::Ast;
+use leema::cache::{self, CachedImport, CachedModule};
 use leema::code::{self, Code};
+use leema::error::{CompileError, ErrorKind, ModuleResult};
 use leema::infer::TypeSet;
 use leema::inter::Intermod;
 use leema::ixpr::Source;
@@ -11,11 +13,16 @@ use leema::lstr::Lstr;
 use leema::module::{ModulePreface, ModuleSource};
 use leema::phase0::{self, Protomod};
 use leema::typecheck::{self, CallFrame, CallOp, Typemod, Typescope};
-use leema::val::Type;
-use leema::{file, lib_hyper, lib_list, prefab, tcp, udp};
+use leema::val::{SrcLoc, Type};
+use leema::{
+    file, lib_buffer, lib_hyper, lib_list, lib_map, lib_supervisor, prefab,
+    tcp, udp,
+};
 
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::io::Write;
+use std::path::PathBuf;
 use std::rc::Rc;
 
 
@@ -29,6 +36,7 @@ pub struct Lib
     typed: HashMap<Lstr, Typemod>,
     rust_load: HashMap<Lstr, fn(&str) -> Option<code::Code>>,
     code: HashMap<Lstr, HashMap<Lstr, Code>>,
+    cached: HashMap<Lstr, Option<CachedModule>>,
 }
 
 impl Lib
@@ -44,20 +52,31 @@ impl Lib
             typed: HashMap::new(),
             rust_load: HashMap::new(),
             code: HashMap::new(),
+            cached: HashMap::new(),
         };
+        // prefab is compiled when first needed so a cached program
+        // doesn't have to compile it at all
         proglib
             .rust_load
             .insert(Lstr::Sref("prefab"), prefab::load_rust_func);
-        proglib.load_inter(&Lstr::Sref("prefab"));
         proglib
             .rust_load
             .insert(Lstr::Sref("file"), file::load_rust_func);
+        proglib
+            .rust_load
+            .insert(Lstr::Sref("buffer"), lib_buffer::load_rust_func);
         proglib
             .rust_load
             .insert(Lstr::Sref("list"), lib_list::load_rust_func);
+        proglib
+            .rust_load
+            .insert(Lstr::Sref("map"), lib_map::load_rust_func);
         proglib
             .rust_load
             .insert(Lstr::Sref("str"), lib_str::load_rust_func);
+        proglib
+            .rust_load
+            .insert(Lstr::Sref("supervisor"), lib_supervisor::load_rust_func);
         proglib
             .rust_load
             .insert(Lstr::Sref("tcp"), tcp::load_rust_func);
@@ -76,7 +95,64 @@ impl Lib
         &self.loader.main_mod
     }
 
-    pub fn load_code(&mut self, modname: &Lstr, funcname: &Lstr) -> &Code
+    /**
+     * Replace the text of a module and drop anything that was
+     * already compiled from the previous text
+     */
+    pub fn set_mod_txt(&mut self, modname: &Lstr, content: String)
+    {
+        self.loader.set_mod_txt(modname.clone(), content);
+        self.modsrc.remove(modname);
+        self.preface.remove(modname);
+        self.proto.remove(modname);
+        self.inter.remove(modname);
+        self.typed.remove(modname);
+        self.code.remove(modname);
+        self.cached.remove(modname);
+    }
+
+    /**
+     * Get the source text of a module if it's been read
+     */
+    pub fn mod_txt(&self, modname: &Lstr) -> Option<&str>
+    {
+        self.modsrc.get(modname).map(|ms| ms.txt.as_str())
+    }
+
+    /**
+     * The path of the file a module's source was read from
+     */
+    pub fn mod_file_name(&self, modname: &Lstr) -> String
+    {
+        self.loader.mod_file_name(modname)
+    }
+
+    /**
+     * Print compile errors w/ the file and source line
+     * each one came from
+     */
+    pub fn print_errors(&self, errs: &[CompileError])
+    {
+        for e in errs.iter() {
+            let file = self.loader.mod_file_name(&e.module);
+            // a module w/ a syntax error never made it into modsrc
+            let src = match self.modsrc.get(&e.module) {
+                Some(ms) => Some(ms.txt.clone()),
+                None => {
+                    let key = self.loader.mod_name_to_key(e.module.clone());
+                    self.loader.read_module(&key).ok()
+                }
+            };
+            let src_str = src.as_ref().map(|s| s.as_str());
+            eprintln!("{}\n", e.render(&file, src_str));
+        }
+    }
+
+    pub fn load_code(
+        &mut self,
+        modname: &Lstr,
+        funcname: &Lstr,
+    ) -> ModuleResult<&Code>
     {
         let (has_mod, has_func) = if self.code.contains_key(modname) {
             let old_mod = self.code.get(modname).unwrap();
@@ -86,7 +162,10 @@ impl Lib
         };
 
         if !has_func {
-            let new_code = self.read_code(modname, funcname);
+            let new_code = match self.cached_code(modname, funcname) {
+                Some(code) => code,
+                None => self.read_code(modname, funcname)?,
+            };
 
             if has_mod {
                 let old_mod = self.code.get_mut(modname).unwrap();
@@ -98,7 +177,204 @@ impl Lib
             }
         }
 
-        self.code.get(modname).unwrap().get(funcname).unwrap()
+        Ok(self.code.get(modname).unwrap().get(funcname).unwrap())
+    }
+
+    /**
+     * Get a function's code from its module's cache file if neither
+     * the module nor anything it imports has changed since the
+     * cache was written
+     */
+    fn cached_code(&mut self, modname: &Lstr, funcname: &Lstr) -> Option<Code>
+    {
+        if self.loader.ignore_cache() {
+            return None;
+        }
+        if !self.cached.contains_key(modname) {
+            let cm = self.read_cache(modname);
+            self.cached.insert(modname.clone(), cm);
+        }
+        let cm = self.cached.get(modname).unwrap().as_ref()?;
+        match cm.get_func(funcname)? {
+            &Some(ref ops) => Some(Code::Leema(ops.clone())),
+            &None => {
+                self.rust_load
+                    .get(modname)
+                    .and_then(|rust_loader| rust_loader(funcname))
+            }
+        }
+    }
+
+    fn read_cache(&mut self, modname: &Lstr) -> Option<CachedModule>
+    {
+        let modkey = self.loader.mod_name_to_key(modname.clone());
+        let cache_file = self.loader.cache_file(&modkey)?;
+        let bytes = fs::read(&cache_file).ok()?;
+        let cm = match CachedModule::decode(&bytes) {
+            Ok(cm) => cm,
+            Err(e) => {
+                vout!("ignoring cache {}: {}\n", cache_file.display(), e);
+                return None;
+            }
+        };
+        if !self.is_fresh(modname, cm.src_hash) {
+            vout!("stale cache for {}\n", modname);
+            return None;
+        }
+        for imp in cm.imports.iter() {
+            if !imp.path.is_empty() {
+                let set_path = self
+                    .loader
+                    .set_mod_path(imp.name.clone(), imp.path.clone());
+                if set_path.is_err() {
+                    // recompile so the ambiguity gets reported
+                    return None;
+                }
+            }
+            if !self.is_fresh(&imp.name, imp.src_hash) {
+                vout!("stale cache for {}, import {}\n", modname, imp.name);
+                return None;
+            }
+        }
+        if !self.typed.contains_key(modname) {
+            let mut typmod = Typemod::new(modname.clone());
+            for &(ref fname, ref ftype) in cm.types.iter() {
+                typmod.set_function_type(fname.clone(), ftype.clone());
+            }
+            self.typed.insert(modname.clone(), typmod);
+        }
+        vout!("loaded cache for {}\n", modname);
+        Some(cm)
+    }
+
+    fn is_fresh(&self, modname: &Lstr, src_hash: u64) -> bool
+    {
+        let modkey = self.loader.mod_name_to_key(modname.clone());
+        self.loader
+            .read_module(&modkey)
+            .map(|txt| cache::src_hash(&txt) == src_hash)
+            .unwrap_or(false)
+    }
+
+    /**
+     * Compile every function in a module and in all the modules
+     * it imports, then write the cache file for each of them.
+     * Returns the cache files that were written.
+     */
+    pub fn write_cache(&mut self, modname: &Lstr) -> ModuleResult<Vec<PathBuf>>
+    {
+        let mut written = vec![];
+        let mut pending = vec![modname.clone()];
+        let mut visited = HashSet::new();
+        while let Some(m) = pending.pop() {
+            if !visited.insert(m.clone()) {
+                continue;
+            }
+            self.load_inter(&m)?;
+            let cm = self.compile_cached_module(&m)?;
+            pending.extend(cm.imports.iter().map(|imp| imp.name.clone()));
+
+            let modkey = self.loader.mod_name_to_key(m.clone());
+            if let Some(cache_file) = self.loader.cache_file(&modkey) {
+                fs::write(&cache_file, cm.encode()).map_err(|e| {
+                    vec![CompileError::new(
+                        &m,
+                        SrcLoc::default(),
+                        ErrorKind::InvalidFile,
+                        format!(
+                            "cannot write cache {}: {}",
+                            cache_file.display(),
+                            e
+                        ),
+                    )]
+                })?;
+                written.push(cache_file);
+            }
+            self.cached.insert(m, Some(cm));
+        }
+        Ok(written)
+    }
+
+    fn compile_cached_module(
+        &mut self,
+        modname: &Lstr,
+    ) -> ModuleResult<CachedModule>
+    {
+        let mut funcnames: Vec<Lstr> = self
+            .inter
+            .get(modname)
+            .unwrap()
+            .interfunc
+            .keys()
+            .cloned()
+            .collect();
+        funcnames.sort();
+        let mut funcs = Vec::with_capacity(funcnames.len());
+        for fname in funcnames {
+            let ops = match self.read_code(modname, &fname)? {
+                Code::Leema(ops) => Some(ops),
+                _ => None,
+            };
+            funcs.push((fname, ops));
+        }
+
+        let imports = self.cached_imports(modname)?;
+
+        let mut types: Vec<(Lstr, Type)> = self
+            .proto
+            .get(modname)
+            .unwrap()
+            .valtypes
+            .iter()
+            .map(|(fname, ftype)| (fname.clone(), ftype.clone()))
+            .collect();
+        types.sort();
+
+        Ok(CachedModule {
+            src_hash: cache::src_hash(&self.modsrc.get(modname).unwrap().txt),
+            imports,
+            types,
+            funcs,
+        })
+    }
+
+    /**
+     * Every module imported by this one, directly or through one of
+     * its imports, so a change anywhere below it makes the cache stale
+     */
+    fn cached_imports(
+        &mut self,
+        modname: &Lstr,
+    ) -> ModuleResult<Vec<CachedImport>>
+    {
+        let mut imports = vec![];
+        let mut visited = HashSet::new();
+        visited.insert(modname.clone());
+        let mut pending = vec![modname.clone()];
+        while let Some(m) = pending.pop() {
+            let pref = self.preface.get(&m).unwrap().clone();
+            self.load_imports(&pref)?;
+            let mut import_names: Vec<Lstr> =
+                pref.imports.iter().cloned().collect();
+            if m != "prefab" {
+                import_names.push(Lstr::Sref("prefab"));
+            }
+            for i in import_names {
+                if !visited.insert(i.clone()) {
+                    continue;
+                }
+                let path = pref.import_paths.get(&i).cloned();
+                let txt = &self.modsrc.get(&i).unwrap().txt;
+                imports.push(CachedImport {
+                    path: path.unwrap_or_default(),
+                    src_hash: cache::src_hash(txt),
+                    name: i.clone(),
+                });
+                pending.push(i);
+            }
+        }
+        imports.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(imports)
     }
 
     pub fn find_preface(&self, modname: &Lstr) -> Option<&Rc<ModulePreface>>
@@ -106,12 +382,13 @@ impl Lib
         self.preface.get(modname)
     }
 
-    pub fn load_inter(&mut self, modname: &Lstr)
+    pub fn load_inter(&mut self, modname: &Lstr) -> ModuleResult<()>
     {
         if !self.inter.contains_key(modname) {
-            let inter = self.read_inter(modname);
+            let inter = self.read_inter(modname)?;
             self.inter.insert(modname.clone(), inter);
         }
+        Ok(())
     }
 
     pub fn init_typemod(&mut self, modname: &Lstr)
@@ -128,66 +405,78 @@ impl Lib
         }
     }
 
-    pub fn load_proto(&mut self, modname: &Lstr)
+    pub fn load_proto(&mut self, modname: &Lstr) -> ModuleResult<()>
     {
         if !self.proto.contains_key(modname) {
-            let proto = self.read_proto(modname);
+            let proto = self.read_proto(modname)?;
             self.proto.insert(modname.clone(), Rc::new(proto));
             self.init_typemod(modname);
         }
+        Ok(())
     }
 
-    pub fn load_preface(&mut self, modname: &Lstr)
+    pub fn load_preface(&mut self, modname: &Lstr) -> ModuleResult<()>
     {
         if !self.preface.contains_key(modname) {
-            let (msrc, mpref) = self.read_preface(modname);
+            let (msrc, mpref) = self.read_preface(modname)?;
             self.modsrc.insert(modname.clone(), msrc);
             self.preface.insert(modname.clone(), Rc::new(mpref));
         }
+        Ok(())
     }
 
-    pub fn read_modsrc(&self, modname: &Lstr) -> ModuleSource
+    pub fn read_modsrc(&self, modname: &Lstr) -> ModuleResult<ModuleSource>
     {
         let modkey = self.loader.mod_name_to_key(modname.clone());
-        let modtxt = self.loader.read_module(&modkey);
-        ModuleSource::new(modkey, modtxt)
+        let modtxt = self.loader.read_module(&modkey).map_err(|e| vec![e])?;
+        ModuleSource::new(modkey, modtxt).map_err(|e| vec![e])
     }
 
-    pub fn read_preface(&self, modname: &Lstr)
-        -> (ModuleSource, ModulePreface)
+    pub fn read_preface(
+        &self,
+        modname: &Lstr,
+    ) -> ModuleResult<(ModuleSource, ModulePreface)>
     {
-        let ms = self.read_modsrc(modname);
-        let pref = ModulePreface::new(&ms);
-        (ms, pref)
+        let ms = self.read_modsrc(modname)?;
+        let pref = ModulePreface::new(&ms).map_err(|e| vec![e])?;
+        Ok((ms, pref))
     }
 
-    pub fn read_proto(&mut self, modname: &Lstr) -> Protomod
+    pub fn read_proto(&mut self, modname: &Lstr) -> ModuleResult<Protomod>
     {
-        let (ms, pref) = self.read_preface(modname);
-        self.load_imports(modname, &pref.imports);
+        if modname != "prefab" {
+            // prefab macros are available in every module
+            self.load_preface(&Lstr::Sref("prefab"))?;
+        }
+        let (ms, pref) = self.read_preface(modname)?;
+        self.load_imports(&pref)?;
         let proto = phase0::preproc(self, &pref, &ms.ast);
         self.modsrc.insert(modname.clone(), ms);
         self.preface.insert(modname.clone(), Rc::new(pref));
         proto
     }
 
-    pub fn read_inter(&mut self, modname: &Lstr) -> Intermod
+    pub fn read_inter(&mut self, modname: &Lstr) -> ModuleResult<Intermod>
     {
         vout!("read_inter({})\n", modname);
-        self.load_proto(modname);
+        self.load_proto(modname)?;
         let preface = self.preface.get(modname).unwrap().clone();
-        let imports = self.import_protos(&preface.imports);
+        let imports = self.import_protos(&preface.imports)?;
         let proto = self.proto.get(modname).unwrap();
         Intermod::compile(&proto, &imports)
     }
 
-    pub fn read_code(&mut self, modname: &Lstr, funcname: &Lstr) -> Code
+    pub fn read_code(
+        &mut self,
+        modname: &Lstr,
+        funcname: &Lstr,
+    ) -> ModuleResult<Code>
     {
         vout!("read_code({}::{})\n", modname, funcname);
-        self.load_inter(modname);
+        self.load_inter(modname)?;
 
         let funcri = Lri::with_modules(modname.clone(), funcname.clone());
-        self.typecheck(&funcri, typecheck::Depth::One);
+        self.typecheck(&funcri, typecheck::Depth::One)?;
 
         let inter = self
             .inter
@@ -209,41 +498,67 @@ impl Lib
         }
 
         if let Source::RustBlock(_, _) = fix.src {
-            let rust_loader = self.rust_load.get(modname);
-            if rust_loader.is_none() {
-                panic!("no rust loader for: {}", modname);
-            }
-            let rustfunc = rust_loader.unwrap()(funcname);
+            let rustfunc = self
+                .rust_load
+                .get(modname)
+                .and_then(|rust_loader| rust_loader(funcname));
             if rustfunc.is_none() {
-                panic!("no rust function for: {}::{}", modname, funcname);
+                return Err(vec![CompileError::new(
+                    modname,
+                    SrcLoc::new(fix.line, 0),
+                    ErrorKind::Undefined,
+                    format!("no rust function for: {}::{}", modname, funcname),
+                )]);
             }
-            rustfunc.unwrap()
+            Ok(rustfunc.unwrap())
         } else {
             let ops = code::make_ops(fix);
             if modname == "prefab" {
                 vout!("prefab::{} ops: {:?}\n", funcname, ops);
             }
-            Code::Leema(ops)
+            Ok(Code::Leema(ops))
         }
     }
 
-    pub fn typecheck(&mut self, funcri: &Lri, depth: typecheck::Depth) -> Type
+    pub fn typecheck(
+        &mut self,
+        funcri: &Lri,
+        depth: typecheck::Depth,
+    ) -> ModuleResult<Type>
     {
         vout!("typecheck({}, {:?})\n", funcri, depth);
-        self.load_inter(funcri.mod_ref().expect("no typecheck module name"));
+        let modname = funcri.mod_ref().expect("no typecheck module name");
+        self.load_inter(modname)?;
+        let has_func = self
+            .inter
+            .get(modname)
+            .unwrap()
+            .interfunc
+            .contains_key(&funcri.localid);
+        if !has_func {
+            return Err(vec![CompileError::new(
+                modname,
+                SrcLoc::default(),
+                ErrorKind::Undefined,
+                format!("undefined function: {}", funcri),
+            )]);
+        }
         if depth.one_deeper() {
-            self.deeper_typecheck(funcri, depth);
+            self.deeper_typecheck(funcri, depth)?;
         }
 
-        let ftype = self.local_typecheck(funcri);
-        let modstr = funcri.mod_ref().unwrap().str();
-        let mutyped = self.typed.get_mut(modstr).unwrap();
+        let ftype = self.local_typecheck(funcri)?;
+        let mutyped = self.typed.get_mut(modname).unwrap();
         mutyped.set_function_type(funcri.localid.clone(), ftype.clone());
         vout!("\tfinish typecheck({})\n", funcri);
-        ftype
+        Ok(ftype)
     }
 
-    pub fn deeper_typecheck(&mut self, funcri: &Lri, depth: typecheck::Depth)
+    pub fn deeper_typecheck(
+        &mut self,
+        funcri: &Lri,
+        depth: typecheck::Depth,
+    ) -> ModuleResult<()>
     {
         let cf = {
             let mod_str = funcri.mod_ref().expect("typecheck module name");
@@ -275,7 +590,7 @@ impl Lib
                                     None,
                                 ),
                                 depth.next(),
-                            );
+                            )?;
                         }
                     } else {
                         self.typecheck(
@@ -284,21 +599,22 @@ impl Lib
                                 call_name.clone(),
                             ),
                             depth.next(),
-                        );
+                        )?;
                     }
                 }
                 &CallOp::ExternalCall(ref ext) => {
                     if funcri == ext {
                         // do nothing, it's recursive, we're already doing it
                     } else {
-                        self.typecheck(&ext, depth.next());
+                        self.typecheck(&ext, depth.next())?;
                     }
                 }
             }
         }
+        Ok(())
     }
 
-    pub fn local_typecheck(&mut self, funcri: &Lri) -> Type
+    pub fn local_typecheck(&mut self, funcri: &Lri) -> ModuleResult<Type>
     {
         vout!("local_typecheck({})\n", funcri);
         let modlstr = funcri.mod_ref().unwrap();
@@ -309,6 +625,7 @@ impl Lib
         }
         let inter = opt_inter.unwrap();
         let mut fix = inter.interfunc.get_mut(funclstr).unwrap();
+        let fix_loc = SrcLoc::new(fix.line, 0);
         if !self.typed.contains_key(modlstr) {
             self.typed
                 .insert(modlstr.clone(), Typemod::new(modlstr.clone()));
@@ -360,41 +677,63 @@ impl Lib
             &imports,
             &typeset,
         );
-        typecheck::typecheck_function(&mut scope, &mut fix).unwrap()
+        typecheck::typecheck_function(&mut scope, &mut fix)
+            .map_err(|e| vec![CompileError::type_err(modlstr, fix_loc, &e)])
     }
 
-    fn load_imports(&mut self, modname: &Lstr, imports: &HashSet<Lstr>)
+    fn load_imports(&mut self, pref: &ModulePreface) -> ModuleResult<()>
     {
-        for i in imports {
+        let modname = &pref.key.name;
+        for path in pref.conflicting_paths.iter() {
+            let name = path.last().unwrap().clone();
+            let prev = pref.import_paths.get(&name).unwrap().clone();
+            self.loader
+                .set_mod_path(name.clone(), prev)
+                .and_then(|_| self.loader.set_mod_path(name, path.clone()))
+                .map_err(|e| vec![e])?;
+        }
+        for i in pref.imports.iter() {
             if i == modname {
-                panic!("A module cannot import itself: {}", i);
+                return Err(vec![CompileError::new(
+                    modname,
+                    SrcLoc::default(),
+                    ErrorKind::Unsupported,
+                    format!("a module cannot import itself: {}", i),
+                )]);
+            }
+            if let Some(path) = pref.import_paths.get(i) {
+                self.loader
+                    .set_mod_path(i.clone(), path.clone())
+                    .map_err(|e| vec![e])?;
             }
             if self.preface.contains_key(i) {
                 continue;
             }
-            let im = self.read_modsrc(i);
-            let pref = ModulePreface::new(&im);
+            let im = self.read_modsrc(i)?;
+            let pref = ModulePreface::new(&im).map_err(|e| vec![e])?;
             self.modsrc.insert(i.clone(), im);
             self.preface.insert(i.clone(), Rc::new(pref));
         }
+        Ok(())
     }
 
     fn import_protos(
         &mut self,
         imports: &HashSet<Lstr>,
-    ) -> HashMap<Lstr, Rc<Protomod>>
+    ) -> ModuleResult<HashMap<Lstr, Rc<Protomod>>>
     {
+        self.load_proto(&Lstr::Sref("prefab"))?;
         let mut imported_protos: HashMap<Lstr, Rc<Protomod>> = HashMap::new();
         imported_protos.insert(
             Lstr::Sref("prefab"),
             self.proto.get("prefab").unwrap().clone(),
         );
         for i in imports {
-            self.load_proto(i);
+            self.load_proto(i)?;
             let p = self.proto.get(i).unwrap().clone();
             imported_protos.insert(i.clone(), p);
         }
-        imported_protos
+        Ok(imported_protos)
     }
 
     pub fn get_macro<'a>(
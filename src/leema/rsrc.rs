@@ -1,5 +1,7 @@
+use leema::frame::FrameTrace;
 use leema::io::Io;
 pub use leema::io::RunQueue;
+use leema::lstr::Lstr;
 use leema::val::{Type, Val};
 
 use std::cell::RefCell;
@@ -24,6 +26,8 @@ pub enum Event
     Stream(Box<stream::Stream<Item = Event, Error = Event>>),
     NewRsrc(Box<Rsrc>, Option<Box<Rsrc>>),
     Result(Val, Option<Box<Rsrc>>),
+    // the resource was closed, drop it and return the result
+    DropRsrc(Val),
 }
 
 impl fmt::Debug for Event
@@ -39,10 +43,27 @@ impl fmt::Debug for Event
             Event::Result(ref rv, ref r) => {
                 write!(f, "Event::Result({:?}, {:?})", rv, r)
             }
+            Event::DropRsrc(ref rv) => write!(f, "Event::DropRsrc({:?})", rv),
         }
     }
 }
 
+/**
+ * Make a failure result for an iop
+ *
+ * iops run outside of the fiber, so the trace is filled in
+ * by the worker when the result gets back to the fiber.
+ */
+pub fn iop_failure(tag: &'static str, msg: String, status: i8) -> Val
+{
+    Val::failure(
+        Val::Hashtag(Lstr::Sref(tag)),
+        Val::Str(Lstr::from(msg)),
+        FrameTrace::new_root(),
+        status,
+    )
+}
+
 pub struct IopCtx
 {
     rcio: Rc<RefCell<Io>>,
@@ -0,0 +1,527 @@
+use leema::frame::{Frame, FrameTrace, Parent};
+use leema::list;
+use leema::log;
+use leema::lri::Lri;
+use leema::lstr::Lstr;
+use leema::struple::Struple;
+use leema::val::{Env, FutureVal, LibVal, MsgVal, Type, Val};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+/**
+ * Which children to restart when one of them fails
+ *
+ * OneForOne restarts only the failed child, OneForAll restarts
+ * all of them and RestForOne restarts the failed child and all
+ * the children started after it.
+ */
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Strategy
+{
+    OneForOne,
+    OneForAll,
+    RestForOne,
+}
+
+impl Strategy
+{
+    pub fn from_tag(tag: &str) -> Option<Strategy>
+    {
+        match tag {
+            "one_for_one" => Some(Strategy::OneForOne),
+            "one_for_all" => Some(Strategy::OneForAll),
+            "rest_for_one" => Some(Strategy::RestForOne),
+            _ => None,
+        }
+    }
+}
+
+struct Child
+{
+    id: i64,
+    func: Lri,
+    args: MsgVal,
+    incarnation: i64,
+    // cleared to kill the current run of this child
+    alive: Arc<AtomicBool>,
+    running: bool,
+}
+
+struct State
+{
+    strategy: Strategy,
+    max_restarts: usize,
+    period: Duration,
+    restarts: VecDeque<Instant>,
+    children: Vec<Child>,
+    next_child_id: i64,
+    notices: VecDeque<MsgVal>,
+    waiters: VecDeque<FutureVal>,
+    stopped: bool,
+}
+
+/**
+ * Supervisor for a set of child fibers
+ *
+ * A child that fails is restarted according to the strategy,
+ * unless there have already been max_restarts restarts within
+ * the period. Then the supervisor gives up and stops all its
+ * children. Each failure is kept as a notice for the leema code
+ * that's watching the supervisor.
+ *
+ * Killed children aren't interrupted, they're dropped by their
+ * worker the next time they would run or while they're waiting
+ * on IO, a message or a future.
+ */
+#[derive(Clone)]
+pub struct Supervisor
+{
+    state: Arc<Mutex<State>>,
+}
+
+/**
+ * What a supervised fiber keeps in its root frame to report back
+ * to its supervisor when it exits
+ */
+#[derive(Clone)]
+pub struct ChildRef
+{
+    sup: Supervisor,
+    child_id: i64,
+    incarnation: i64,
+    alive: Arc<AtomicBool>,
+}
+
+impl Supervisor
+{
+    pub fn new(
+        strategy: Strategy,
+        max_restarts: usize,
+        period: Duration,
+    ) -> Supervisor
+    {
+        Supervisor {
+            state: Arc::new(Mutex::new(State {
+                strategy,
+                max_restarts,
+                period,
+                restarts: VecDeque::new(),
+                children: vec![],
+                next_child_id: 1,
+                notices: VecDeque::new(),
+                waiters: VecDeque::new(),
+                stopped: false,
+            })),
+        }
+    }
+
+    /**
+     * Add a child and get the frame for its first run
+     */
+    pub fn start_child(&self, func: Lri, args: &Val) -> (i64, Frame)
+    {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_child_id;
+        state.next_child_id += 1;
+        let alive = Arc::new(AtomicBool::new(!state.stopped));
+        state.children.push(Child {
+            id,
+            func,
+            args: MsgVal::new(args),
+            incarnation: 0,
+            alive,
+            running: !state.stopped,
+        });
+        let frame = self.child_frame(state.children.last().unwrap());
+        (id, frame)
+    }
+
+    /**
+     * Handle a child's exit and get the frames for any
+     * children that need to be restarted
+     */
+    pub fn child_exit(&self, child: &ChildRef, result: &Val) -> Vec<Frame>
+    {
+        let mut state = self.state.lock().unwrap();
+        let idx = match state.children.iter().position(|c| {
+            c.id == child.child_id && c.incarnation == child.incarnation
+        }) {
+            Some(idx) => idx,
+            None => {
+                // this run was already replaced, ignore it
+                return vec![];
+            }
+        };
+        state.children[idx].running = false;
+        if !result.is_failure() || state.stopped {
+            return vec![];
+        }
+
+        State::notify(&mut state, child_notice(child.child_id, result));
+
+        let now = Instant::now();
+        let period = state.period;
+        while state
+            .restarts
+            .front()
+            .map(|t| now.duration_since(*t) > period)
+            .unwrap_or(false)
+        {
+            state.restarts.pop_front();
+        }
+        if state.restarts.len() >= state.max_restarts {
+            vout!("supervisor reached max restarts\n");
+            State::stop(&mut state);
+            let msg = format!(
+                "more than {} restarts in {:?}",
+                state.max_restarts, state.period
+            );
+            let notice = Val::Tuple(Struple::new_indexed(vec![
+                Val::Int(child.child_id),
+                Val::Hashtag(Lstr::Sref("max_restarts")),
+                Val::Str(Lstr::from(msg)),
+                list::empty(),
+            ]));
+            State::notify(&mut state, notice);
+            return vec![];
+        }
+        state.restarts.push_back(now);
+
+        let restart: Vec<usize> = match state.strategy {
+            Strategy::OneForOne => vec![idx],
+            Strategy::OneForAll => {
+                (0..state.children.len())
+                    .filter(|i| *i == idx || state.children[*i].running)
+                    .collect()
+            }
+            Strategy::RestForOne => {
+                (idx..state.children.len())
+                    .filter(|i| *i == idx || state.children[*i].running)
+                    .collect()
+            }
+        };
+        restart
+            .into_iter()
+            .map(|i| {
+                let c = &mut state.children[i];
+                c.alive.store(false, atomic::Ordering::Release);
+                c.alive = Arc::new(AtomicBool::new(true));
+                c.incarnation += 1;
+                c.running = true;
+                vout!("restart child {}: {}\n", c.id, c.func);
+                self.child_frame(c)
+            }).collect()
+    }
+
+    /**
+     * Get the next failure notice, or a future for it if
+     * there haven't been any failures yet
+     */
+    pub fn next_notice(&self) -> Val
+    {
+        let mut state = self.state.lock().unwrap();
+        match state.notices.pop_front() {
+            Some(notice) => notice.take(),
+            None => {
+                let fv = FutureVal::new();
+                state.waiters.push_back(fv.clone());
+                Val::future(fv)
+            }
+        }
+    }
+
+    /**
+     * Stop all the children and don't restart any more
+     */
+    pub fn stop(&self)
+    {
+        let mut state = self.state.lock().unwrap();
+        State::stop(&mut state);
+    }
+
+    pub fn running_children(&self) -> usize
+    {
+        let state = self.state.lock().unwrap();
+        state.children.iter().filter(|c| c.running).count()
+    }
+
+    fn child_frame(&self, child: &Child) -> Frame
+    {
+        let child_ref = ChildRef {
+            sup: self.clone(),
+            child_id: child.id,
+            incarnation: child.incarnation,
+            alive: child.alive.clone(),
+        };
+        Frame {
+            parent: Parent::Supervised(child_ref, Val::Void),
+            module: child.func.mod_ref().unwrap().clone(),
+            function: child.func.localid.clone(),
+            file: Lstr::empty(),
+            trace: FrameTrace::new_root(),
+            e: Env::with_args(child.args.deep_clone()),
+            pc: 0,
+        }
+    }
+}
+
+impl State
+{
+    fn notify(state: &mut State, notice: Val)
+    {
+        match state.waiters.pop_front() {
+            Some(fv) => fv.set(&notice),
+            None => state.notices.push_back(MsgVal::new(&notice)),
+        }
+    }
+
+    fn stop(state: &mut State)
+    {
+        state.stopped = true;
+        for c in state.children.iter_mut() {
+            c.alive.store(false, atomic::Ordering::Release);
+            c.running = false;
+        }
+    }
+}
+
+impl LibVal for Supervisor
+{
+    fn get_type(&self) -> Type
+    {
+        Type::UserDef(Lri::with_modules(
+            Lstr::Sref("supervisor"),
+            Lstr::Sref("Supervisor"),
+        ))
+    }
+}
+
+impl fmt::Debug for Supervisor
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let state = self.state.lock().unwrap();
+        write!(
+            f,
+            "Supervisor({:?}, {} children)",
+            state.strategy,
+            state.children.len()
+        )
+    }
+}
+
+impl ChildRef
+{
+    pub fn is_alive(&self) -> bool
+    {
+        self.alive.load(atomic::Ordering::Acquire)
+    }
+
+    pub fn alive_flag(&self) -> Arc<AtomicBool>
+    {
+        self.alive.clone()
+    }
+
+    pub fn exit(&self, result: &Val) -> Vec<Frame>
+    {
+        self.sup.child_exit(self, result)
+    }
+}
+
+impl fmt::Debug for ChildRef
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "ChildRef({}.{})", self.child_id, self.incarnation)
+    }
+}
+
+/**
+ * A failure notice is a tuple of (child_id, tag, msg, trace)
+ * where trace is the list of frames from FrameTrace::to_val
+ */
+fn child_notice(child_id: i64, failure: &Val) -> Val
+{
+    let (tag, msg, trace) = match failure {
+        &Val::Failure(ref tag, ref msg, ref trace, _) => {
+            ((**tag).clone(), (**msg).clone(), trace.to_val())
+        }
+        _ => {
+            panic!("not a failure: {:?}", failure);
+        }
+    };
+    Val::Tuple(Struple::new_indexed(vec![
+        Val::Int(child_id),
+        tag,
+        msg,
+        trace,
+    ]))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::frame::{FrameTrace, Parent};
+    use leema::lri::Lri;
+    use leema::lstr::Lstr;
+    use leema::struple::Struple;
+    use leema::supervisor::{ChildRef, Strategy, Supervisor};
+    use leema::val::{self, Val};
+
+    use std::time::Duration;
+
+
+    fn start(sup: &Supervisor, func: &'static str) -> ChildRef
+    {
+        let f = Lri::with_modules(Lstr::Sref("tacos"), Lstr::Sref(func));
+        let args = Val::Tuple(Struple(vec![]));
+        let (_, frame) = sup.start_child(f, &args);
+        child_ref(frame.parent)
+    }
+
+    fn child_ref(p: Parent) -> ChildRef
+    {
+        match p {
+            Parent::Supervised(child, _) => child,
+            _ => panic!("not a supervised parent"),
+        }
+    }
+
+    fn failure() -> Val
+    {
+        Val::failure(
+            Val::Hashtag(Lstr::Sref("burnt")),
+            Val::Str(Lstr::Sref("too crispy")),
+            FrameTrace::new_root(),
+            val::FAILURE_INTERNAL,
+        )
+    }
+
+    fn notice_tag(notice: &Val) -> Val
+    {
+        match notice {
+            &Val::Tuple(ref items) => items.0[1].1.clone(),
+            _ => panic!("notice is not a tuple: {:?}", notice),
+        }
+    }
+
+    fn restarted(sup: &Supervisor, child: &ChildRef) -> Vec<String>
+    {
+        sup.child_exit(child, &failure())
+            .iter()
+            .map(|f| f.function.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_one_for_one_restarts_failed_child()
+    {
+        let sup =
+            Supervisor::new(Strategy::OneForOne, 3, Duration::from_secs(5));
+        start(&sup, "a");
+        let b = start(&sup, "b");
+        start(&sup, "c");
+
+        assert_eq!(vec!["b"], restarted(&sup, &b));
+        assert!(!b.is_alive());
+        assert_eq!(3, sup.running_children());
+    }
+
+    #[test]
+    fn test_one_for_all_restarts_all_children()
+    {
+        let sup =
+            Supervisor::new(Strategy::OneForAll, 3, Duration::from_secs(5));
+        let a = start(&sup, "a");
+        let b = start(&sup, "b");
+        let c = start(&sup, "c");
+
+        assert_eq!(vec!["a", "b", "c"], restarted(&sup, &b));
+        assert!(!a.is_alive());
+        assert!(!c.is_alive());
+    }
+
+    #[test]
+    fn test_rest_for_one_restarts_later_children()
+    {
+        let sup =
+            Supervisor::new(Strategy::RestForOne, 3, Duration::from_secs(5));
+        let a = start(&sup, "a");
+        let b = start(&sup, "b");
+        start(&sup, "c");
+
+        assert_eq!(vec!["b", "c"], restarted(&sup, &b));
+        assert!(a.is_alive());
+    }
+
+    #[test]
+    fn test_stale_child_exit_is_ignored()
+    {
+        let sup =
+            Supervisor::new(Strategy::OneForOne, 3, Duration::from_secs(5));
+        let a = start(&sup, "a");
+        assert_eq!(vec!["a"], restarted(&sup, &a));
+        // the old run of a exits again after it was replaced
+        assert!(restarted(&sup, &a).is_empty());
+    }
+
+    #[test]
+    fn test_max_restarts_stops_supervisor()
+    {
+        let sup =
+            Supervisor::new(Strategy::OneForOne, 1, Duration::from_secs(5));
+        let a = start(&sup, "a");
+        let b = start(&sup, "b");
+
+        let frames = sup.child_exit(&a, &failure());
+        let a2 = child_ref(frames.into_iter().next().unwrap().parent);
+        assert!(restarted(&sup, &a2).is_empty());
+        assert!(!b.is_alive());
+        assert_eq!(0, sup.running_children());
+
+        // one notice for each failure and one for stopping
+        let first = sup.next_notice();
+        assert_eq!(Val::Hashtag(Lstr::Sref("burnt")), notice_tag(&first));
+        sup.next_notice();
+        let stopped = sup.next_notice();
+        assert_eq!(
+            Val::Hashtag(Lstr::Sref("max_restarts")),
+            notice_tag(&stopped)
+        );
+    }
+
+    #[test]
+    fn test_notice_waits_for_failure()
+    {
+        let sup =
+            Supervisor::new(Strategy::OneForOne, 3, Duration::from_secs(5));
+        let a = start(&sup, "a");
+        let notice = sup.next_notice();
+        assert!(notice.is_future());
+        assert!(!notice.is_future_ready());
+
+        sup.child_exit(&a, &failure());
+        assert!(notice.is_future_ready());
+    }
+
+    #[test]
+    fn test_normal_exit_is_not_restarted()
+    {
+        let sup =
+            Supervisor::new(Strategy::OneForAll, 3, Duration::from_secs(5));
+        let a = start(&sup, "a");
+        start(&sup, "b");
+        assert!(sup.child_exit(&a, &Val::Int(3)).is_empty());
+        assert_eq!(1, sup.running_children());
+    }
+}
@@ -2,23 +2,176 @@ use leema::code::Code;
 use leema::log;
 use leema::lstr::Lstr;
 use leema::rsrc::{self, Rsrc};
-use leema::val::{Type, Val};
+use leema::struple::Struple;
+use leema::val::{self, Type, Val};
 
-// use bytes::buf::BufMut;
+use bytes::buf::BufMut;
 use bytes::BytesMut;
 use std;
 use std::io::{self, Write};
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Shutdown, SocketAddr};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use futures::future::Future;
 use futures::task;
 use futures::{Async, Poll};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::timer::{Delay, Timeout};
 
 
-impl Rsrc for TcpStream
+/**
+ * Convert a socket error into a failure
+ *
+ * action is what was being attempted, it's prepended to the OS message
+ */
+pub fn net_failure(action: &str, e: &io::Error) -> Val
+{
+    let (tag, status) = match e.kind() {
+        io::ErrorKind::ConnectionRefused => {
+            ("connection_refused", val::FAILURE_NOENTRY)
+        }
+        io::ErrorKind::ConnectionReset => {
+            ("connection_reset", val::FAILURE_INTERNAL)
+        }
+        io::ErrorKind::ConnectionAborted => {
+            ("connection_aborted", val::FAILURE_INTERNAL)
+        }
+        io::ErrorKind::NotConnected => ("not_connected", val::FAILURE_BADINPUT),
+        io::ErrorKind::BrokenPipe => ("broken_pipe", val::FAILURE_INTERNAL),
+        io::ErrorKind::AddrInUse => ("addr_in_use", val::FAILURE_BADINPUT),
+        io::ErrorKind::AddrNotAvailable => {
+            ("invalid_address", val::FAILURE_BADINPUT)
+        }
+        io::ErrorKind::InvalidInput => {
+            ("invalid_address", val::FAILURE_BADINPUT)
+        }
+        io::ErrorKind::PermissionDenied => {
+            ("permission_denied", val::FAILURE_UNAUTHORIZED)
+        }
+        io::ErrorKind::TimedOut => ("timeout", val::FAILURE_TIMEOUT),
+        io::ErrorKind::UnexpectedEof => {
+            ("connection_closed", val::FAILURE_MISSINGDATA)
+        }
+        _ => ("net_error", val::FAILURE_INTERNAL),
+    };
+    rsrc::iop_failure(tag, format!("{}: {}", action, e), status)
+}
+
+/**
+ * Parse an ip and port into a socket address
+ *
+ * Returns an #invalid_address failure if the ip doesn't parse
+ */
+pub fn socket_addr(ip: &Val, port: &Val) -> Result<SocketAddr, Val>
+{
+    let port = match port {
+        &Val::Int(p) if p >= 0 && p <= 0xffff => p as u16,
+        _ => {
+            return Err(rsrc::iop_failure(
+                "invalid_address",
+                format!("invalid port: {}", port),
+                val::FAILURE_BADINPUT,
+            ));
+        }
+    };
+    IpAddr::from_str(ip.str())
+        .map(|ipaddr| SocketAddr::new(ipaddr, port))
+        .map_err(|e| {
+            rsrc::iop_failure(
+                "invalid_address",
+                format!("invalid ip address {}: {}", ip, e),
+                val::FAILURE_BADINPUT,
+            )
+        })
+}
+
+/**
+ * Convert received bytes into a Str or Buffer
+ */
+pub fn recv_val(bytes: Vec<u8>, binary: bool) -> Val
+{
+    if binary {
+        return Val::Buffer(bytes);
+    }
+    match String::from_utf8(bytes) {
+        Ok(s) => Val::Str(Lstr::from(s)),
+        Err(e) => {
+            rsrc::iop_failure(
+                "invalid_utf8",
+                format!("received data is not utf8: {}", e),
+                val::FAILURE_BADINPUT,
+            )
+        }
+    }
+}
+
+/**
+ * Read a timeout param in milliseconds
+ */
+fn timeout_param(v: &Val) -> Duration
+{
+    Duration::from_millis(v.to_int().max(0) as u64)
+}
+
+/**
+ * Check if an optional deadline has passed
+ *
+ * Polling the delay also schedules a wakeup for when it will pass
+ */
+fn deadline_passed(deadline: &mut Option<Delay>) -> bool
+{
+    match deadline.as_mut().map(|d| d.poll()) {
+        Some(Ok(Async::Ready(()))) => true,
+        _ => false,
+    }
+}
+
+fn deadline(timeout: Option<Duration>) -> Option<Delay>
+{
+    timeout.map(|t| Delay::new(Instant::now() + t))
+}
+
+fn timed_out(action: &str) -> io::Error
+{
+    io::Error::new(io::ErrorKind::TimedOut, format!("{} timed out", action))
+}
+
+/**
+ * Convert a socket address into an (ip, port) tuple
+ */
+fn addr_val(addr: &SocketAddr) -> Val
+{
+    Val::Tuple(Struple::new_tuple2(
+        Val::Str(Lstr::from(addr.ip().to_string())),
+        Val::Int(addr.port() as i64),
+    ))
+}
+
+/**
+ * A connected socket w/ the bytes that have been read but not
+ * returned yet, so framed reads can leave the rest for the next call
+ */
+#[derive(Debug)]
+pub struct TcpSocket
+{
+    stream: TcpStream,
+    buf: BytesMut,
+}
+
+impl TcpSocket
+{
+    pub fn new(stream: TcpStream) -> TcpSocket
+    {
+        TcpSocket {
+            stream,
+            buf: BytesMut::new(),
+        }
+    }
+}
+
+impl Rsrc for TcpSocket
 {
     fn get_type(&self) -> Type
     {
@@ -37,6 +190,7 @@ impl Rsrc for TcpListener
 struct Acceptor
 {
     listener: Option<TcpListener>,
+    deadline: Option<Delay>,
 }
 
 impl Future for Acceptor
@@ -55,50 +209,173 @@ impl Future for Acceptor
                 Ok(Async::Ready((listener, sock, addr)))
             }
             Ok(Async::NotReady) => {
+                if deadline_passed(&mut self.deadline) {
+                    let listener = self.listener.take().unwrap();
+                    return Err((listener, timed_out("accept")));
+                }
                 task::current().notify();
                 Ok(Async::NotReady)
             }
             Err(e) => {
-                panic!("failure accepting new tcp connection: {:?}", e);
+                let listener = self.listener.take().unwrap();
+                Err((listener, e))
             }
         }
     }
 }
 
+/**
+ * How received bytes are split into the values returned by recv
+ */
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+enum Framing
+{
+    // whatever has been received so far
+    Any,
+    // up to the next newline, w/o the newline
+    Line,
+    // exactly this many bytes
+    Exact(usize),
+    // a 4 byte big endian length, then that many bytes
+    LengthPrefixed,
+}
+
+impl Framing
+{
+    /**
+     * Take the next frame out of the buffer if it's all there
+     *
+     * Fails w/ #frame_too_large if a length prefix or a line is over
+     * MAX_FRAME_LEN. The buffer is cleared then since the rest of the
+     * stream can't be framed anymore.
+     */
+    fn take_frame(&self, buf: &mut BytesMut) -> Result<Option<Vec<u8>>, Val>
+    {
+        let frame = match *self {
+            Framing::Any if buf.is_empty() => None,
+            Framing::Any => Some(buf.take().to_vec()),
+            Framing::Line => {
+                let newline = buf.iter().position(|b| *b == b'\n');
+                let len = newline.unwrap_or(buf.len());
+                if len > MAX_FRAME_LEN {
+                    buf.clear();
+                    return Err(frame_too_large(len));
+                }
+                newline.map(|end| {
+                    let mut line = buf.split_to(end + 1).to_vec();
+                    line.pop();
+                    if line.last() == Some(&b'\r') {
+                        line.pop();
+                    }
+                    line
+                })
+            }
+            Framing::Exact(n) if buf.len() >= n => {
+                Some(buf.split_to(n).to_vec())
+            }
+            Framing::Exact(_) => None,
+            Framing::LengthPrefixed => {
+                if buf.len() < FRAME_PREFIX_LEN {
+                    return Ok(None);
+                }
+                let n = buf[..FRAME_PREFIX_LEN]
+                    .iter()
+                    .fold(0usize, |n, b| (n << 8) | *b as usize);
+                if n > MAX_FRAME_LEN {
+                    buf.clear();
+                    return Err(frame_too_large(n));
+                }
+                if buf.len() < FRAME_PREFIX_LEN + n {
+                    return Ok(None);
+                }
+                buf.advance(FRAME_PREFIX_LEN);
+                Some(buf.split_to(n).to_vec())
+            }
+        };
+        Ok(frame)
+    }
+}
+
+const FRAME_PREFIX_LEN: usize = 4;
+// the largest line or length prefixed frame that can be sent or received
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+const READ_CHUNK: usize = 4096;
+
+fn frame_too_large(len: usize) -> Val
+{
+    rsrc::iop_failure(
+        "frame_too_large",
+        format!("frame of {} bytes is over the {} max", len, MAX_FRAME_LEN),
+        val::FAILURE_BADINPUT,
+    )
+}
+
 struct Receiver
 {
-    sock: Option<TcpStream>,
+    sock: Option<TcpSocket>,
+    framing: Framing,
+    // return a Buffer instead of a Str
+    binary: bool,
+    deadline: Option<Delay>,
 }
 
 impl Future for Receiver
 {
-    type Item = (TcpStream, Val);
-    type Error = (TcpStream, std::io::Error);
+    type Item = (TcpSocket, Val);
+    type Error = (TcpSocket, std::io::Error);
 
-    fn poll(&mut self) -> Poll<(TcpStream, Val), (TcpStream, std::io::Error)>
+    fn poll(&mut self) -> Poll<(TcpSocket, Val), (TcpSocket, std::io::Error)>
     {
-        let mut buf = BytesMut::new();
-        let read_result = self.sock.as_ref().unwrap().read_buf(&mut buf);
-        match read_result {
-            Ok(Async::Ready(_sz)) => {
-                let isock = self.sock.take().unwrap();
-                let rstr = String::from_utf8(buf.to_vec()).unwrap();
-                let rval = Val::Str(Lstr::from(rstr));
-                Ok(Async::Ready((isock, rval)))
-            }
-            Ok(Async::NotReady) => {
-                vout!("Receiver NotReady\n");
-                Ok(Async::NotReady)
-            }
-            Err(e) => {
-                match e.kind() {
-                    io::ErrorKind::WouldBlock => {
-                        vout!("Receiver WouldBlock\n");
-                        Ok(Async::NotReady)
+        loop {
+            let read_result = {
+                let sock = self.sock.as_mut().unwrap();
+                let next_frame = self.framing.take_frame(&mut sock.buf);
+                let rval = match next_frame {
+                    Ok(Some(frame)) => Some(recv_val(frame, self.binary)),
+                    Ok(None) => None,
+                    Err(failure) => Some(failure),
+                };
+                if let Some(rval) = rval {
+                    let isock = self.sock.take().unwrap();
+                    return Ok(Async::Ready((isock, rval)));
+                }
+                if sock.buf.remaining_mut() < READ_CHUNK {
+                    sock.buf.reserve(READ_CHUNK);
+                }
+                sock.stream.read_buf(&mut sock.buf)
+            };
+            match read_result {
+                Ok(Async::Ready(0)) => {
+                    let isock = self.sock.take().unwrap();
+                    if self.framing == Framing::Any {
+                        // the peer closed the connection
+                        let rval = recv_val(vec![], self.binary);
+                        return Ok(Async::Ready((isock, rval)));
                     }
-                    _ => {
-                        let sock = self.sock.take().unwrap();
-                        Err((sock, e))
+                    let e = io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "connection closed before a full message arrived",
+                    );
+                    return Err((isock, e));
+                }
+                Ok(Async::Ready(_sz)) => {}
+                Ok(Async::NotReady) => {
+                    vout!("Receiver NotReady\n");
+                    return self.not_ready();
+                }
+                Err(e) => {
+                    match e.kind() {
+                        io::ErrorKind::WouldBlock => {
+                            vout!("Receiver WouldBlock\n");
+                            return self.not_ready();
+                        }
+                        _ => {
+                            let sock = self.sock.take().unwrap();
+                            return Err((sock, e));
+                        }
                     }
                 }
             }
@@ -106,9 +383,26 @@ impl Future for Receiver
     }
 }
 
+impl Receiver
+{
+    fn not_ready(&mut self) -> Poll<(TcpSocket, Val), (TcpSocket, io::Error)>
+    {
+        if deadline_passed(&mut self.deadline) {
+            let sock = self.sock.take().unwrap();
+            return Err((sock, timed_out("recv")));
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+/**
+ * Write all of data to the socket, returning the number of bytes written
+ */
 struct Sender
 {
     ctx: rsrc::IopCtx,
+    data: Vec<u8>,
+    written: usize,
 }
 
 impl Future for Sender
@@ -118,73 +412,131 @@ impl Future for Sender
 
     fn poll(&mut self) -> Poll<rsrc::Event, rsrc::Event>
     {
-        let mut sock: TcpStream = self.ctx.take_rsrc();
-        let msg = self.ctx.take_param(1).unwrap();
-        vout!("tcp::Sender::poll({})\n", msg);
+        let mut sock: TcpSocket = self.ctx.take_rsrc();
+        vout!("tcp::Sender::poll({} bytes)\n", self.data.len());
 
-        let write_result = sock.poll_write(msg.str().as_bytes());
-        let nbytes = match write_result {
-            Ok(Async::Ready(nb)) => nb as i64,
-            Ok(Async::NotReady) => {
-                self.ctx.init_rsrc(Box::new(sock));
-                return Ok(Async::NotReady);
-            }
-            Err(e) => {
-                panic!("failed writing to socket: {:?}", e);
-            }
-        };
-        let result =
-            rsrc::Event::Result(Val::Int(nbytes), Some(Box::new(sock)));
-        Ok(Async::Ready(result))
+        while self.written < self.data.len() {
+            let unwritten = &self.data[self.written..];
+            let e: io::Error = match sock.stream.poll_write(unwritten) {
+                Ok(Async::Ready(0)) => io::ErrorKind::WriteZero.into(),
+                Ok(Async::Ready(nb)) => {
+                    self.written += nb;
+                    continue;
+                }
+                Ok(Async::NotReady) => {
+                    self.ctx.init_rsrc(Box::new(sock));
+                    return Ok(Async::NotReady);
+                }
+                Err(e) => e,
+            };
+            let failure = net_failure("tcp send failed", &e);
+            return Err(rsrc::Event::Result(failure, Some(Box::new(sock))));
+        }
+        let result = Val::Int(self.written as i64);
+        Ok(Async::Ready(rsrc::Event::Result(result, Some(Box::new(sock)))))
     }
 }
 
 
-pub fn tcp_connect(mut ctx: rsrc::IopCtx) -> rsrc::Event
+pub fn tcp_connect(ctx: rsrc::IopCtx) -> rsrc::Event
 {
     vout!("tcp_connect()\n");
-    let sock_addr = {
-        let sock_addr_str = ctx.take_param(0).unwrap();
-        let port = ctx.take_param(1).unwrap().to_int() as u16;
-        SocketAddr::new(IpAddr::from_str(sock_addr_str.str()).unwrap(), port)
+    connect(ctx, None)
+}
+
+/**
+ * tcp_connect_timeout(addr, port, timeout_ms)
+ */
+pub fn tcp_connect_timeout(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_connect_timeout()\n");
+    let timeout = timeout_param(&ctx.take_param(2).unwrap());
+    connect(ctx, Some(timeout))
+}
+
+fn connect(mut ctx: rsrc::IopCtx, timeout: Option<Duration>) -> rsrc::Event
+{
+    let ip = ctx.take_param(0).unwrap();
+    let port = ctx.take_param(1).unwrap();
+    let sock_addr = match socket_addr(&ip, &port) {
+        Ok(addr) => addr,
+        Err(failure) => return rsrc::Event::Result(failure, None),
     };
 
-    let fut = TcpStream::connect(&sock_addr)
+    let connecting = TcpStream::connect(&sock_addr);
+    let fut: Box<Future<Item = TcpStream, Error = io::Error>> =
+        match timeout {
+            Some(t) => {
+                Box::new(Timeout::new(connecting, t).map_err(|e| {
+                    if e.is_elapsed() {
+                        timed_out("connect")
+                    } else if e.is_inner() {
+                        e.into_inner().unwrap()
+                    } else {
+                        io::Error::new(io::ErrorKind::Other, "timer failure")
+                    }
+                }))
+            }
+            None => Box::new(connecting),
+        };
+    let result = fut
         .map(move |sock| {
             vout!("tcp connected");
-            rsrc::Event::NewRsrc(Box::new(sock), None)
-        }).map_err(move |_| {
-            rsrc::Event::Result(
-                Val::Str(Lstr::Sref("Failure to connect")),
-                None,
-            )
+            rsrc::Event::NewRsrc(Box::new(TcpSocket::new(sock)), None)
+        }).map_err(move |e| {
+            let action = format!("tcp connect to {} failed", sock_addr);
+            rsrc::Event::Result(net_failure(&action, &e), None)
         });
-    rsrc::Event::Future(Box::new(fut))
+    rsrc::Event::Future(Box::new(result))
 }
 
 pub fn tcp_listen(mut ctx: rsrc::IopCtx) -> rsrc::Event
 {
     vout!("tcp_listen()\n");
-    let ip_str = ctx.take_param(0).unwrap();
-    let port = ctx.take_param(1).unwrap().to_int() as u16;
-    let sock_addr =
-        SocketAddr::new(IpAddr::from_str(ip_str.str()).unwrap(), port);
-    let listen_result = TcpListener::bind(&sock_addr);
-    let listener: TcpListener = listen_result.unwrap();
-    rsrc::Event::NewRsrc(Box::new(listener), None)
+    let ip = ctx.take_param(0).unwrap();
+    let port = ctx.take_param(1).unwrap();
+    let sock_addr = match socket_addr(&ip, &port) {
+        Ok(addr) => addr,
+        Err(failure) => return rsrc::Event::Result(failure, None),
+    };
+    match TcpListener::bind(&sock_addr) {
+        Ok(listener) => rsrc::Event::NewRsrc(Box::new(listener), None),
+        Err(e) => {
+            let action = format!("tcp listen on {} failed", sock_addr);
+            rsrc::Event::Result(net_failure(&action, &e), None)
+        }
+    }
 }
 
-pub fn tcp_accept(mut ctx: rsrc::IopCtx) -> rsrc::Event
+pub fn tcp_accept(ctx: rsrc::IopCtx) -> rsrc::Event
 {
     vout!("tcp_accept()\n");
+    accept(ctx, None)
+}
+
+/**
+ * tcp_accept_timeout(listener, timeout_ms)
+ */
+pub fn tcp_accept_timeout(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_accept_timeout()\n");
+    let timeout = timeout_param(&ctx.take_param(1).unwrap());
+    accept(ctx, Some(timeout))
+}
+
+fn accept(mut ctx: rsrc::IopCtx, timeout: Option<Duration>) -> rsrc::Event
+{
     let listener: TcpListener = ctx.take_rsrc();
     let acc =
         Acceptor {
             listener: Some(listener),
-        }.map(|(_ilistener, sock, _addr)| {
-            rsrc::Event::NewRsrc(Box::new(sock), None)
-        }).map_err(|_| {
-            rsrc::Event::Result(Val::Str(Lstr::Sref("accept error")), None)
+            deadline: deadline(timeout),
+        }.map(|(ilistener, sock, _addr)| {
+            let new_sock = Box::new(TcpSocket::new(sock));
+            rsrc::Event::NewRsrc(new_sock, Some(Box::new(ilistener)))
+        }).map_err(|(ilistener, e)| {
+            let failure = net_failure("tcp accept failed", &e);
+            rsrc::Event::Result(failure, Some(Box::new(ilistener)))
         });
     rsrc::Event::Future(Box::new(acc))
 }
@@ -193,35 +545,372 @@ pub fn tcp_accept(mut ctx: rsrc::IopCtx) -> rsrc::Event
 /**
  * tcp_recv(sock)
  */
-pub fn tcp_recv(mut ctx: rsrc::IopCtx) -> rsrc::Event
+pub fn tcp_recv(ctx: rsrc::IopCtx) -> rsrc::Event
 {
     vout!("tcp_recv()\n");
+    recv(ctx, Framing::Any, false, None)
+}
+
+/**
+ * tcp_recv_buffer(sock)
+ */
+pub fn tcp_recv_buffer(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_recv_buffer()\n");
+    recv(ctx, Framing::Any, true, None)
+}
+
+/**
+ * tcp_recv_timeout(sock, timeout_ms)
+ */
+pub fn tcp_recv_timeout(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_recv_timeout()\n");
+    let timeout = timeout_param(&ctx.take_param(1).unwrap());
+    recv(ctx, Framing::Any, false, Some(timeout))
+}
+
+/**
+ * tcp_recv_buffer_timeout(sock, timeout_ms)
+ */
+pub fn tcp_recv_buffer_timeout(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_recv_buffer_timeout()\n");
+    let timeout = timeout_param(&ctx.take_param(1).unwrap());
+    recv(ctx, Framing::Any, true, Some(timeout))
+}
+
+/**
+ * tcp_recv_line(sock): Str
+ *
+ * receive up to the next newline, leaving the rest for later calls
+ */
+pub fn tcp_recv_line(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_recv_line()\n");
+    recv(ctx, Framing::Line, false, None)
+}
+
+/**
+ * tcp_recv_exact(sock, n): Buffer
+ */
+pub fn tcp_recv_exact(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_recv_exact()\n");
+    let n = ctx.take_param(1).unwrap().to_int().max(0) as usize;
+    recv(ctx, Framing::Exact(n), true, None)
+}
+
+/**
+ * tcp_recv_frame(sock): Buffer
+ *
+ * receive a frame sent w/ send_frame
+ */
+pub fn tcp_recv_frame(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_recv_frame()\n");
+    recv(ctx, Framing::LengthPrefixed, true, None)
+}
 
-    let sock: TcpStream = ctx.take_rsrc();
-    let fut = Receiver { sock: Some(sock) }
+fn recv(
+    mut ctx: rsrc::IopCtx,
+    framing: Framing,
+    binary: bool,
+    timeout: Option<Duration>,
+) -> rsrc::Event
+{
+    let sock: TcpSocket = ctx.take_rsrc();
+    let fut = Receiver {
+        sock: Some(sock),
+        framing,
+        binary,
+        deadline: deadline(timeout),
+    }
         .map(|(isock, data)| rsrc::Event::Result(data, Some(Box::new(isock))))
-        .map_err(|(isock, _err)| {
-            let errval = Val::Str(Lstr::Sref("recv failure"));
-            rsrc::Event::Result(errval, Some(Box::new(isock)))
+        .map_err(|(isock, e)| {
+            let failure = net_failure("tcp recv failed", &e);
+            rsrc::Event::Result(failure, Some(Box::new(isock)))
         });
     rsrc::Event::Future(Box::new(fut))
 }
 
-pub fn tcp_send(ctx: rsrc::IopCtx) -> rsrc::Event
+pub fn tcp_send(mut ctx: rsrc::IopCtx) -> rsrc::Event
 {
     vout!("tcp_send()\n");
-    let fut = Sender { ctx };
+    let data = ctx.take_param(1).unwrap().as_bytes().to_vec();
+    let fut = Sender {
+        ctx,
+        data,
+        written: 0,
+    };
+    rsrc::Event::Future(Box::new(fut))
+}
+
+/**
+ * tcp_send_frame(sock, data)
+ *
+ * send data w/ a 4 byte big endian length in front of it
+ */
+pub fn tcp_send_frame(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_send_frame()\n");
+    let msg = ctx.take_param(1).unwrap();
+    let msg_bytes = msg.as_bytes();
+    if msg_bytes.len() > MAX_FRAME_LEN {
+        let sock: TcpSocket = ctx.take_rsrc();
+        let failure = frame_too_large(msg_bytes.len());
+        return rsrc::Event::Result(failure, Some(Box::new(sock)));
+    }
+    let len = msg_bytes.len() as u32;
+    let mut data = Vec::with_capacity(FRAME_PREFIX_LEN + msg_bytes.len());
+    data.extend_from_slice(&[
+        (len >> 24) as u8,
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+    ]);
+    data.extend_from_slice(msg_bytes);
+    let fut = Sender {
+        ctx,
+        data,
+        written: 0,
+    };
     rsrc::Event::Future(Box::new(fut))
 }
 
+/**
+ * tcp_close(sock)
+ *
+ * drop the socket, closing the connection
+ */
+pub fn tcp_close(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_close()\n");
+    let _sock: TcpSocket = ctx.take_rsrc();
+    rsrc::Event::DropRsrc(Val::Void)
+}
+
+/**
+ * tcp_close_listener(listener)
+ */
+pub fn tcp_close_listener(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_close_listener()\n");
+    let _listener: TcpListener = ctx.take_rsrc();
+    rsrc::Event::DropRsrc(Val::Void)
+}
+
+/**
+ * tcp_shutdown(sock, how)
+ *
+ * how is one of #read, #write or #both
+ */
+pub fn tcp_shutdown(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_shutdown()\n");
+    let sock: TcpSocket = ctx.take_rsrc();
+    let how_val = ctx.take_param(1).unwrap();
+    let how = match how_val {
+        Val::Hashtag(ref tag) if tag.str() == "read" => Shutdown::Read,
+        Val::Hashtag(ref tag) if tag.str() == "write" => Shutdown::Write,
+        Val::Hashtag(ref tag) if tag.str() == "both" => Shutdown::Both,
+        _ => {
+            let failure = rsrc::iop_failure(
+                "invalid_shutdown",
+                format!("shutdown must be #read, #write or #both: {}", how_val),
+                val::FAILURE_BADINPUT,
+            );
+            return rsrc::Event::Result(failure, Some(Box::new(sock)));
+        }
+    };
+    let result = match sock.stream.shutdown(how) {
+        Ok(()) => Val::Void,
+        Err(e) => net_failure("tcp shutdown failed", &e),
+    };
+    rsrc::Event::Result(result, Some(Box::new(sock)))
+}
+
+/**
+ * tcp_peer_addr(sock): (Str, Int)
+ */
+pub fn tcp_peer_addr(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_peer_addr()\n");
+    let sock: TcpSocket = ctx.take_rsrc();
+    let result = match sock.stream.peer_addr() {
+        Ok(addr) => addr_val(&addr),
+        Err(e) => net_failure("tcp peer_addr failed", &e),
+    };
+    rsrc::Event::Result(result, Some(Box::new(sock)))
+}
+
+/**
+ * tcp_local_addr(sock): (Str, Int)
+ */
+pub fn tcp_local_addr(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_local_addr()\n");
+    let sock: TcpSocket = ctx.take_rsrc();
+    let result = match sock.stream.local_addr() {
+        Ok(addr) => addr_val(&addr),
+        Err(e) => net_failure("tcp local_addr failed", &e),
+    };
+    rsrc::Event::Result(result, Some(Box::new(sock)))
+}
+
+/**
+ * tcp_listener_addr(listener): (Str, Int)
+ */
+pub fn tcp_listener_addr(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_listener_addr()\n");
+    let listener: TcpListener = ctx.take_rsrc();
+    let result = match listener.local_addr() {
+        Ok(addr) => addr_val(&addr),
+        Err(e) => net_failure("tcp listener_addr failed", &e),
+    };
+    rsrc::Event::Result(result, Some(Box::new(listener)))
+}
+
+/**
+ * tcp_set_nodelay(sock, nodelay: Bool)
+ */
+pub fn tcp_set_nodelay(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("tcp_set_nodelay()\n");
+    let sock: TcpSocket = ctx.take_rsrc();
+    let nodelay = match ctx.take_param(1) {
+        Some(Val::Bool(b)) => b,
+        _ => false,
+    };
+    let result = match sock.stream.set_nodelay(nodelay) {
+        Ok(()) => Val::Void,
+        Err(e) => net_failure("tcp set_nodelay failed", &e),
+    };
+    rsrc::Event::Result(result, Some(Box::new(sock)))
+}
+
 pub fn load_rust_func(func_name: &str) -> Option<Code>
 {
     match func_name {
         "connect" => Some(Code::Iop(tcp_connect, None)),
+        "connect_timeout" => Some(Code::Iop(tcp_connect_timeout, None)),
         "listen" => Some(Code::Iop(tcp_listen, None)),
         "accept" => Some(Code::Iop(tcp_accept, Some(0))),
+        "accept_timeout" => Some(Code::Iop(tcp_accept_timeout, Some(0))),
         "recv" => Some(Code::Iop(tcp_recv, Some(0))),
+        "recv_buffer" => Some(Code::Iop(tcp_recv_buffer, Some(0))),
+        "recv_timeout" => Some(Code::Iop(tcp_recv_timeout, Some(0))),
+        "recv_buffer_timeout" => {
+            Some(Code::Iop(tcp_recv_buffer_timeout, Some(0)))
+        }
         "send" => Some(Code::Iop(tcp_send, Some(0))),
+        "send_buffer" => Some(Code::Iop(tcp_send, Some(0))),
+        "recv_line" => Some(Code::Iop(tcp_recv_line, Some(0))),
+        "recv_exact" => Some(Code::Iop(tcp_recv_exact, Some(0))),
+        "recv_frame" => Some(Code::Iop(tcp_recv_frame, Some(0))),
+        "send_frame" => Some(Code::Iop(tcp_send_frame, Some(0))),
+        "close" => Some(Code::Iop(tcp_close, Some(0))),
+        "close_listener" => Some(Code::Iop(tcp_close_listener, Some(0))),
+        "shutdown" => Some(Code::Iop(tcp_shutdown, Some(0))),
+        "peer_addr" => Some(Code::Iop(tcp_peer_addr, Some(0))),
+        "local_addr" => Some(Code::Iop(tcp_local_addr, Some(0))),
+        "listener_addr" => Some(Code::Iop(tcp_listener_addr, Some(0))),
+        "set_nodelay" => Some(Code::Iop(tcp_set_nodelay, Some(0))),
         _ => None,
     }
 }
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::lstr::Lstr;
+    use leema::struple::Struple;
+    use leema::tcp::{self, Framing};
+    use leema::val::{self, Val};
+
+    use bytes::BytesMut;
+
+
+    #[test]
+    fn test_timed_out_fails_with_timeout()
+    {
+        let e = tcp::timed_out("recv");
+        match tcp::net_failure("tcp recv failed", &e) {
+            Val::Failure(tag, msg, _, status) => {
+                assert_eq!(Val::Hashtag(Lstr::Sref("timeout")), *tag);
+                assert_eq!("tcp recv failed: recv timed out", msg.str());
+                assert_eq!(val::FAILURE_TIMEOUT, status);
+            }
+            other => panic!("expected failure, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_addr_val()
+    {
+        let addr = "127.0.0.1:8080".parse().unwrap();
+        let expected = Val::Tuple(Struple::new_tuple2(
+            Val::Str(Lstr::Sref("127.0.0.1")),
+            Val::Int(8080),
+        ));
+        assert_eq!(expected, tcp::addr_val(&addr));
+    }
+
+    #[test]
+    fn test_line_framing_keeps_leftover_bytes()
+    {
+        let mut buf = BytesMut::from(&b"first\r\nsecond\nthi"[..]);
+        let first = Framing::Line.take_frame(&mut buf);
+        assert_eq!(Ok(Some(b"first".to_vec())), first);
+        let second = Framing::Line.take_frame(&mut buf);
+        assert_eq!(Ok(Some(b"second".to_vec())), second);
+        assert_eq!(Ok(None), Framing::Line.take_frame(&mut buf));
+        assert_eq!(&b"thi"[..], &buf[..]);
+    }
+
+    #[test]
+    fn test_length_prefixed_framing()
+    {
+        let mut buf = BytesMut::from(&[0u8, 0, 0, 3, 7, 8][..]);
+        assert_eq!(Ok(None), Framing::LengthPrefixed.take_frame(&mut buf));
+        buf.extend_from_slice(&[9, 0, 0]);
+        let frame = Framing::LengthPrefixed.take_frame(&mut buf);
+        assert_eq!(Ok(Some(vec![7, 8, 9])), frame);
+        assert_eq!(Ok(None), Framing::LengthPrefixed.take_frame(&mut buf));
+        assert_eq!(2, buf.len());
+        let exact = Framing::Exact(2).take_frame(&mut buf);
+        assert_eq!(Ok(Some(vec![0, 0])), exact);
+    }
+
+    #[test]
+    fn test_length_prefix_over_max_fails()
+    {
+        let mut buf = BytesMut::from(&[0xffu8, 0xff, 0xff, 0xff, 1][..]);
+        match Framing::LengthPrefixed.take_frame(&mut buf) {
+            Err(Val::Failure(tag, _, _, status)) => {
+                let expected = Val::Hashtag(Lstr::Sref("frame_too_large"));
+                assert_eq!(expected, *tag);
+                assert_eq!(val::FAILURE_BADINPUT, status);
+            }
+            other => panic!("expected failure, found {:?}", other),
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_line_over_max_fails()
+    {
+        let mut buf = BytesMut::from(vec![b'a'; tcp::MAX_FRAME_LEN + 1]);
+        match Framing::Line.take_frame(&mut buf) {
+            Err(Val::Failure(tag, _, _, status)) => {
+                let expected = Val::Hashtag(Lstr::Sref("frame_too_large"));
+                assert_eq!(expected, *tag);
+                assert_eq!(val::FAILURE_BADINPUT, status);
+            }
+            other => panic!("expected failure, found {:?}", other),
+        }
+        assert!(buf.is_empty());
+    }
+}
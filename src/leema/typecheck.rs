@@ -5,7 +5,7 @@ use leema::lri::Lri;
 use leema::lstr::Lstr;
 use leema::phase0::Protomod;
 use leema::struple::Struple;
-use leema::val::{Type, TypeErr, TypeResult, Val};
+use leema::val::{SrcLoc, Type, TypeErr, TypeResult, Val};
 
 use std::collections::{HashMap, LinkedList};
 use std::io::Write;
@@ -51,7 +51,7 @@ impl<'a> CallFrame<'a>
     pub fn collect_calls<'b>(&mut self, ix: &'b Ixpr)
     {
         match ix.src {
-            Source::Call(ref callx, ref args) => {
+            Source::Call(ref callx, ref args, _) => {
                 self.collect_callexpr(callx);
                 self.collect_calls(args);
             }
@@ -70,6 +70,9 @@ impl<'a> CallFrame<'a>
                     self.collect_calls(f.case.as_ref().unwrap())
                 }
             }
+            Source::Fork(_, ref call) => {
+                self.collect_calls(call);
+            }
             Source::StrMash(ref items) => {
                 self.collect_calls_vec(items);
             }
@@ -290,16 +293,12 @@ impl<'a, 'b> Typescope<'a, 'b>
                 ref mut lies,
             ) = &mut case.src
             {
-                self.infer.match_pattern(
-                    &self.typeset,
-                    patt,
-                    valtype,
-                    case.line,
-                )?;
-                let ttype = typecheck_expr(self, truth).unwrap();
+                self.infer
+                    .match_pattern(&self.typeset, patt, valtype, case.line)
+                    .map_err(|e| e.at(SrcLoc::new(case.line, 0)))?;
+                let ttype = typecheck_expr(self, truth)?;
                 if lies.src != Source::ConstVal(Val::Void) {
-                    let ftype =
-                        self.typecheck_matchcase(valtype, lies).unwrap();
+                    let ftype = self.typecheck_matchcase(valtype, lies)?;
                     self.infer.merge_types(&ttype, &ftype)
                 } else {
                     Ok(ttype)
@@ -364,10 +363,20 @@ impl<'a, 'b> Typescope<'a, 'b>
     }
 }
 
+/**
+ * Typecheck an expression, marking any error w/ the location
+ * of the innermost expression where it happened
+ */
 pub fn typecheck_expr(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
+{
+    let loc = ix.loc();
+    typecheck_expr_src(scope, ix).map_err(|e| e.at(loc))
+}
+
+fn typecheck_expr_src(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
 {
     match &mut ix.src {
-        &mut Source::Call(ref mut func, ref mut args) => {
+        &mut Source::Call(ref mut func, ref mut args, _) => {
             let tfunc = scope.typecheck_call_func(&func.src).map_err(|e| {
                 e.add_context(Lstr::from(format!("function: {:?}", func.src)))
             })?;
@@ -391,13 +400,13 @@ pub fn typecheck_expr(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
                 targs_ref.push(ta);
             }
             let full_call_type =
-                scope.infer.make_call_type(&tfunc, &targs_ref).unwrap();
+                scope.infer.make_call_type(&tfunc, &targs_ref)?;
             let (_, call_result) = Type::split_func(full_call_type);
             Ok(call_result.clone())
         }
         &mut Source::Cons(ref mut head, ref mut tail) => {
-            let head_t = typecheck_expr(scope, head).unwrap();
-            let tail_t = typecheck_expr(scope, tail).unwrap();
+            let head_t = typecheck_expr(scope, head)?;
+            let tail_t = typecheck_expr(scope, tail)?;
             let head_list_t = Type::StrictList(Box::new(head_t));
             scope.infer.merge_types(&head_list_t, &tail_t)
         }
@@ -427,6 +436,17 @@ pub fn typecheck_expr(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
             }
             Ok(Type::Void)
         }
+        &mut Source::Fork(ref var, ref mut call) => {
+            // the future is typed as the result it will have
+            let call_type = typecheck_expr(scope, call)?;
+            scope.infer.match_pattern(
+                &scope.typeset,
+                &Val::Id(var.clone()),
+                &call_type,
+                ix.line,
+            )?;
+            Ok(Type::Void)
+        }
         &mut Source::Block(ref mut elems) => {
             let mut last_type = Ok(Type::Void);
             for e in elems {
@@ -458,7 +478,7 @@ pub fn typecheck_expr(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
             Ok(Type::Tuple(Struple(item_types)))
         }
         &mut Source::IfExpr(ref mut cond, ref mut truth, ref mut lies) => {
-            let cond_t = typecheck_expr(scope, cond).unwrap();
+            let cond_t = typecheck_expr(scope, cond)?;
             scope.infer.merge_types(&cond_t, &Type::Bool)?;
 
             let truth_result = typecheck_expr(scope, truth);
@@ -466,8 +486,8 @@ pub fn typecheck_expr(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
                 &mut None => truth_result,
                 &mut Some(ref mut some_lies) => {
                     let lies_result = typecheck_expr(scope, some_lies);
-                    let truth_t = truth_result.unwrap();
-                    let lies_t = lies_result.unwrap();
+                    let truth_t = truth_result?;
+                    let lies_t = lies_result?;
                     scope.infer.merge_types(&truth_t, &lies_t)
                 }
             }
@@ -503,6 +523,7 @@ pub fn typecheck_expr(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
 
 pub fn typecheck_function(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
 {
+    let loc = ix.loc();
     match &mut ix.src {
         &mut Source::Func(
             ref arg_names,
@@ -512,7 +533,10 @@ pub fn typecheck_function(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
         ) => {
             let zips = arg_names.iter().zip(arg_types.iter());
             for (i, (an, at)) in zips.enumerate() {
-                scope.infer.init_param(i as i16, an, at, ix.line)?;
+                scope
+                    .infer
+                    .init_param(i as i16, an, at, ix.line)
+                    .map_err(|e| e.at(loc))?;
             }
             vout!("f({:?}) =>\n{:?}\n", arg_names, body);
             let result_type = typecheck_expr(scope, &mut *body)
@@ -522,7 +546,7 @@ pub fn typecheck_function(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
                         scope.fname
                     );
                     e.add_context(Lstr::from(err_msg))
-                }).unwrap();
+                })?;
 
             vout!("type is: {}\n", result_type);
             vout!("vars:");
@@ -538,6 +562,7 @@ pub fn typecheck_function(scope: &mut Typescope, ix: &mut Ixpr) -> TypeResult
                 .infer
                 .merge_types(&result_type, declared_result_type)
                 .map(|final_type| Type::f(final_args, final_type))
+                .map_err(|e| e.at(loc))
         }
         &mut Source::RustBlock(ref arg_types, ref result_type) => {
             if *result_type == Type::Unknown {
@@ -590,13 +615,14 @@ pub fn typecheck_field_access(
                             TypeErr::Error(Lstr::Sref("invalid field index"))
                         })
                 }
-                Result::Err(ref e) => {
-                    panic!("cannot find defined type for: {}", e);
-                }
+                Result::Err(e) => Err(e),
             }
         }
         _ => {
-            panic!("cannot access field for type: {:?}.{}", xtyp, fld);
+            Err(TypeErr::Error(Lstr::from(format!(
+                "cannot access field {} of a non-struct: {}",
+                fld, xtyp
+            ))))
         }
     }
 }
@@ -605,6 +631,7 @@ pub fn typecheck_field_access(
 #[cfg(test)]
 mod tests
 {
+    use leema::error::{CompileError, ErrorKind};
     use leema::loader::Interloader;
     use leema::lri::Lri;
     use leema::lstr::Lstr;
@@ -629,11 +656,72 @@ mod tests
             --
             ".to_string();
 
-        let mut loader = Interloader::new(Lstr::Sref("tacos.lma"));
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
         loader.set_mod_txt(Lstr::Sref("tacos"), input);
         let mut prog = program::Lib::new(loader);
         let fri = Lri::with_modules(Lstr::from("tacos"), Lstr::from("main"));
-        prog.typecheck(&fri, Depth::Full);
+        prog.typecheck(&fri, Depth::Full).unwrap();
+    }
+
+    #[test]
+    fn test_call_arg_mismatch_has_location()
+    {
+        let input = "
+            func inc(i: Int): Int -> i + 1 --
+
+            func main() ->
+                inc(\"tacos\")
+            --
+            ".to_string();
+
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
+        loader.set_mod_txt(Lstr::Sref("tacos"), input);
+        let mut prog = program::Lib::new(loader);
+        let fri = Lri::with_modules(Lstr::from("tacos"), Lstr::from("main"));
+        let errs = prog.typecheck(&fri, Depth::Full).unwrap_err();
+        assert_eq!(1, errs.len());
+        assert_eq!(ErrorKind::Type, errs[0].kind);
+        assert_eq!(5, errs[0].loc.lineno);
+        assert!(errs[0].notes.contains(&Lstr::Sref("expected: Int")));
+    }
+
+    fn typecheck_errors(input: &str) -> Vec<CompileError>
+    {
+        let mut loader = Interloader::new(Lstr::Sref("tacos.lma")).unwrap();
+        loader.set_mod_txt(Lstr::Sref("tacos"), input.to_string());
+        let mut prog = program::Lib::new(loader);
+        let fri = Lri::with_modules(Lstr::from("tacos"), Lstr::from("main"));
+        prog.typecheck(&fri, Depth::Full).unwrap_err()
+    }
+
+    #[test]
+    fn test_too_few_args_is_type_error()
+    {
+        let errs = typecheck_errors(
+            "
+            func add(a: Int, b: Int): Int -> a + b --
+
+            func main() ->
+                add(3)
+            --
+            ",
+        );
+        assert_eq!(ErrorKind::Type, errs[0].kind);
+        assert_eq!(5, errs[0].loc.lineno);
+    }
+
+    #[test]
+    fn test_field_access_on_int_is_type_error()
+    {
+        let errs = typecheck_errors(
+            "
+            func main() ->
+                let x := 5
+                x.tacos
+            --
+            ",
+        );
+        assert_eq!(ErrorKind::Type, errs[0].kind);
     }
 }
 
@@ -2,17 +2,45 @@ use leema::code::Code;
 use leema::log;
 use leema::lstr::Lstr;
 use leema::rsrc::{self, Rsrc};
-use leema::val::{Type, Val};
+use leema::struple::Struple;
+use leema::tcp::{net_failure, recv_val, socket_addr};
+use leema::val::{self, Type, Val};
 
 use std::io::Write;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::str::FromStr;
 
 use futures::{Async, Future, Poll};
 use tokio::net::UdpSocket;
 
 
-impl Rsrc for UdpSocket
+const DEFAULT_MAX_DATAGRAM: usize = 2048;
+const LARGEST_DATAGRAM: i64 = 65535;
+
+/**
+ * A udp socket w/ the largest datagram it will receive
+ *
+ * Bytes past the max size of a datagram are dropped
+ */
+#[derive(Debug)]
+pub struct LeemaUdpSocket
+{
+    sock: UdpSocket,
+    max_datagram: usize,
+}
+
+impl LeemaUdpSocket
+{
+    pub fn new(sock: UdpSocket) -> LeemaUdpSocket
+    {
+        LeemaUdpSocket {
+            sock,
+            max_datagram: DEFAULT_MAX_DATAGRAM,
+        }
+    }
+}
+
+impl Rsrc for LeemaUdpSocket
 {
     fn get_type(&self) -> Type
     {
@@ -20,38 +48,93 @@ impl Rsrc for UdpSocket
     }
 }
 
+fn udp_result(sock: LeemaUdpSocket, result: Val) -> rsrc::Event
+{
+    rsrc::Event::Result(result, Some(Box::new(sock)))
+}
+
 
 pub fn udp_socket(_ctx: rsrc::IopCtx) -> rsrc::Event
 {
     let sock_addr = SocketAddr::new(IpAddr::from_str("0.0.0.0").unwrap(), 0);
-    let rsock = UdpSocket::bind(&sock_addr).unwrap();
-    rsrc::Event::NewRsrc(Box::new(rsock), None)
+    bind_result(&sock_addr)
+}
+
+fn bind_result(sock_addr: &SocketAddr) -> rsrc::Event
+{
+    match UdpSocket::bind(sock_addr) {
+        Ok(rsock) => {
+            rsrc::Event::NewRsrc(Box::new(LeemaUdpSocket::new(rsock)), None)
+        }
+        Err(e) => {
+            let action = format!("udp bind to {} failed", sock_addr);
+            rsrc::Event::Result(net_failure(&action, &e), None)
+        }
+    }
 }
 
 pub fn udp_bind(mut ctx: rsrc::IopCtx) -> rsrc::Event
 {
     vout!("udp_bind()\n");
-    let sock_addr_str = ctx.take_param(0).unwrap();
-    let port = ctx.take_param(1).unwrap().to_int() as u16;
-    let sock_addr =
-        SocketAddr::new(IpAddr::from_str(sock_addr_str.str()).unwrap(), port);
-    let rsock = UdpSocket::bind(&sock_addr).unwrap();
-    rsrc::Event::NewRsrc(Box::new(rsock), None)
+    let ip = ctx.take_param(0).unwrap();
+    let port = ctx.take_param(1).unwrap();
+    match socket_addr(&ip, &port) {
+        Ok(sock_addr) => bind_result(&sock_addr),
+        Err(failure) => rsrc::Event::Result(failure, None),
+    }
 }
 
 pub fn udp_recv(ctx: rsrc::IopCtx) -> rsrc::Event
 {
     vout!("udp_recv");
+    recv(ctx, false, false)
+}
+
+pub fn udp_recv_buffer(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("udp_recv_buffer");
+    recv(ctx, true, false)
+}
+
+/**
+ * udp_recv_from(sock): (Str, Str, Int)
+ *
+ * receive a datagram w/ the host and port that sent it
+ */
+pub fn udp_recv_from(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("udp_recv_from");
+    recv(ctx, false, true)
+}
+
+/**
+ * udp_recv_from_buffer(sock): (Buffer, Str, Int)
+ */
+pub fn udp_recv_from_buffer(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("udp_recv_from_buffer");
+    recv(ctx, true, true)
+}
+
+fn recv(ctx: rsrc::IopCtx, binary: bool, with_sender: bool) -> rsrc::Event
+{
     rsrc::Event::Future(Box::new(UdpRecv {
         ctx,
-        buffer: Some(vec![0; 2048]),
+        buffer: None,
+        binary,
+        with_sender,
     }))
 }
 
 struct UdpRecv
 {
     ctx: rsrc::IopCtx,
+    // allocated on the first poll from the socket's max datagram size
     buffer: Option<Vec<u8>>,
+    // return a Buffer instead of a Str
+    binary: bool,
+    // return (data, host, port) instead of only the data
+    with_sender: bool,
 }
 
 impl Future for UdpRecv
@@ -62,8 +145,13 @@ impl Future for UdpRecv
     fn poll(&mut self) -> Poll<rsrc::Event, rsrc::Event>
     {
         vout!("UdpRecv::poll()\n");
-        let mut sock: UdpSocket = self.ctx.take_rsrc();
-        let result = match sock.poll_recv_from(self.buffer.as_mut().unwrap()) {
+        let mut sock: LeemaUdpSocket = self.ctx.take_rsrc();
+        if self.buffer.is_none() {
+            self.buffer = Some(vec![0; sock.max_datagram]);
+        }
+        let recv_result =
+            sock.sock.poll_recv_from(self.buffer.as_mut().unwrap());
+        let result = match recv_result {
             Ok(Async::Ready(ready_result)) => {
                 vout!("poll_recv_from ready: {:?}", ready_result);
                 ready_result
@@ -74,48 +162,198 @@ impl Future for UdpRecv
                 return Ok(Async::NotReady);
             }
             Err(e) => {
-                panic!("UdpRecv error: {:?}", e);
+                let failure = net_failure("udp recv failed", &e);
+                return Err(udp_result(sock, failure));
             }
         };
-        let (nbytes, _addr) = result;
+        let (nbytes, addr) = result;
         let mut buf = self.buffer.take().unwrap();
-        unsafe {
-            buf.set_len(nbytes);
+        buf.truncate(nbytes);
+        let data = recv_val(buf, self.binary);
+        let result = if self.with_sender && !data.is_failure() {
+            Val::Tuple(Struple::new_indexed(vec![
+                data,
+                Val::Str(Lstr::from(addr.ip().to_string())),
+                Val::Int(addr.port() as i64),
+            ]))
+        } else {
+            data
+        };
+        Ok(Async::Ready(udp_result(sock, result)))
+    }
+}
+
+struct UdpSend
+{
+    ctx: rsrc::IopCtx,
+    dst: SocketAddr,
+    msg: Vec<u8>,
+    // return the number of bytes sent instead of 0
+    count_bytes: bool,
+}
+
+impl Future for UdpSend
+{
+    type Item = rsrc::Event;
+    type Error = rsrc::Event;
+
+    fn poll(&mut self) -> Poll<rsrc::Event, rsrc::Event>
+    {
+        vout!("UdpSend::poll()\n");
+        let mut sock: LeemaUdpSocket = self.ctx.take_rsrc();
+        match sock.sock.poll_send_to(&self.msg, &self.dst) {
+            Ok(Async::Ready(nbytes)) => {
+                let sent = if self.count_bytes { nbytes as i64 } else { 0 };
+                Ok(Async::Ready(udp_result(sock, Val::Int(sent))))
+            }
+            Ok(Async::NotReady) => {
+                self.ctx.init_rsrc(Box::new(sock));
+                Ok(Async::NotReady)
+            }
+            Err(e) => {
+                let action = format!("udp send to {} failed", self.dst);
+                let failure = net_failure(&action, &e);
+                Err(udp_result(sock, failure))
+            }
         }
-        let utf8 = String::from_utf8(buf).unwrap();
-        let str_result = Val::Str(Lstr::from(utf8));
-        Ok(Async::Ready(rsrc::Event::Result(
-            str_result,
-            Some(Box::new(sock)),
-        )))
     }
 }
 
-pub fn udp_send(mut ctx: rsrc::IopCtx) -> rsrc::Event
+pub fn udp_send(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    send(ctx, false)
+}
+
+/**
+ * udp_send_to(sock, host, port, data): Int
+ *
+ * send a datagram to host and port, returning the number of bytes sent
+ */
+pub fn udp_send_to(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    send(ctx, true)
+}
+
+fn send(mut ctx: rsrc::IopCtx, count_bytes: bool) -> rsrc::Event
 {
-    let sock: UdpSocket = ctx.take_rsrc();
     let dst_ip = ctx.take_param(1).unwrap();
-    let dst_port = ctx.take_param(2).unwrap().to_int() as u16;
+    let dst_port = ctx.take_param(2).unwrap();
     vout!("udp_send({}, {})\n", dst_ip, dst_port);
-    let msg = ctx.take_param(3).unwrap().to_string();
-
-    let dst_addr =
-        SocketAddr::new(IpAddr::from_str(dst_ip.str()).unwrap(), dst_port);
-    let fut = Box::new(
-        sock.send_dgram(msg, &dst_addr)
-            .map(move |(sock2, _buff)| {
-                let sockr: Box<Rsrc> = Box::new(sock2) as Box<Rsrc>;
-                rsrc::Event::Result(Val::Int(0), Some(sockr))
-            }).map_err(|_| {
-                rsrc::Event::Result(
-                    Val::Str(Lstr::Sref(
-                        "send dgram didn't work. socket is gone",
-                    )),
-                    None,
-                )
-            }),
-    );
-    rsrc::Event::Future(Box::new(fut))
+    let msg = ctx.take_param(3).unwrap().as_bytes().to_vec();
+
+    let dst = match socket_addr(&dst_ip, &dst_port) {
+        Ok(addr) => addr,
+        Err(failure) => {
+            let sock: LeemaUdpSocket = ctx.take_rsrc();
+            return udp_result(sock, failure);
+        }
+    };
+    rsrc::Event::Future(Box::new(UdpSend {
+        ctx,
+        dst,
+        msg,
+        count_bytes,
+    }))
+}
+
+/**
+ * udp_set_max_datagram(sock, size)
+ *
+ * set the largest datagram recv will return, up to 65535 bytes
+ */
+pub fn udp_set_max_datagram(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("udp_set_max_datagram()\n");
+    let mut sock: LeemaUdpSocket = ctx.take_rsrc();
+    let result = match ctx.take_param(1).unwrap() {
+        Val::Int(size) if size > 0 && size <= LARGEST_DATAGRAM => {
+            sock.max_datagram = size as usize;
+            Val::Void
+        }
+        size => {
+            rsrc::iop_failure(
+                "invalid_size",
+                format!(
+                    "max datagram size must be from 1 to {}: {}",
+                    LARGEST_DATAGRAM, size
+                ),
+                val::FAILURE_BADINPUT,
+            )
+        }
+    };
+    udp_result(sock, result)
+}
+
+/**
+ * udp_set_broadcast(sock, on: Bool)
+ */
+pub fn udp_set_broadcast(mut ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("udp_set_broadcast()\n");
+    let sock: LeemaUdpSocket = ctx.take_rsrc();
+    let on = match ctx.take_param(1) {
+        Some(Val::Bool(b)) => b,
+        _ => false,
+    };
+    let result = match sock.sock.set_broadcast(on) {
+        Ok(()) => Val::Void,
+        Err(e) => net_failure("udp set_broadcast failed", &e),
+    };
+    udp_result(sock, result)
+}
+
+/**
+ * udp_join_multicast(sock, group)
+ *
+ * join a multicast group on the default interface
+ */
+pub fn udp_join_multicast(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("udp_join_multicast()\n");
+    multicast(ctx, true)
+}
+
+/**
+ * udp_leave_multicast(sock, group)
+ */
+pub fn udp_leave_multicast(ctx: rsrc::IopCtx) -> rsrc::Event
+{
+    vout!("udp_leave_multicast()\n");
+    multicast(ctx, false)
+}
+
+fn multicast(mut ctx: rsrc::IopCtx, join: bool) -> rsrc::Event
+{
+    let sock: LeemaUdpSocket = ctx.take_rsrc();
+    let group_val = ctx.take_param(1).unwrap();
+    let group = match IpAddr::from_str(group_val.str()) {
+        Ok(ref ip) if ip.is_multicast() => ip.clone(),
+        _ => {
+            let failure = rsrc::iop_failure(
+                "invalid_address",
+                format!("not a multicast address: {}", group_val),
+                val::FAILURE_BADINPUT,
+            );
+            return udp_result(sock, failure);
+        }
+    };
+    let any_iface = Ipv4Addr::new(0, 0, 0, 0);
+    let multicast_result = match (group, join) {
+        (IpAddr::V4(ref g), true) => sock.sock.join_multicast_v4(g, &any_iface),
+        (IpAddr::V4(ref g), false) => {
+            sock.sock.leave_multicast_v4(g, &any_iface)
+        }
+        (IpAddr::V6(ref g), true) => sock.sock.join_multicast_v6(g, 0),
+        (IpAddr::V6(ref g), false) => sock.sock.leave_multicast_v6(g, 0),
+    };
+    let result = match multicast_result {
+        Ok(()) => Val::Void,
+        Err(e) => {
+            let action = format!("udp multicast {} failed", group);
+            net_failure(&action, &e)
+        }
+    };
+    udp_result(sock, result)
 }
 
 pub fn load_rust_func(func_name: &str) -> Option<Code>
@@ -123,7 +361,17 @@ pub fn load_rust_func(func_name: &str) -> Option<Code>
     match func_name {
         "bind" => Some(Code::Iop(udp_bind, None)),
         "recv" => Some(Code::Iop(udp_recv, Some(0))),
+        "recv_buffer" => Some(Code::Iop(udp_recv_buffer, Some(0))),
+        "recv_from" => Some(Code::Iop(udp_recv_from, Some(0))),
+        "recv_from_buffer" => Some(Code::Iop(udp_recv_from_buffer, Some(0))),
         "send" => Some(Code::Iop(udp_send, Some(0))),
+        "send_buffer" => Some(Code::Iop(udp_send, Some(0))),
+        "send_to" => Some(Code::Iop(udp_send_to, Some(0))),
+        "send_to_buffer" => Some(Code::Iop(udp_send_to, Some(0))),
+        "set_max_datagram" => Some(Code::Iop(udp_set_max_datagram, Some(0))),
+        "set_broadcast" => Some(Code::Iop(udp_set_broadcast, Some(0))),
+        "join_multicast" => Some(Code::Iop(udp_join_multicast, Some(0))),
+        "leave_multicast" => Some(Code::Iop(udp_leave_multicast, Some(0))),
         "socket" => Some(Code::Iop(udp_socket, None)),
         _ => None,
     }
@@ -133,10 +381,40 @@ pub fn load_rust_func(func_name: &str) -> Option<Code>
 #[cfg(test)]
 mod tests
 {
-    use leema::io::tests::exercise_iop_action;
+    use leema::io::tests::{exercise_iop_action, iop_result, spawn_io_thread};
+    use leema::lstr::Lstr;
+    use leema::msg::{IoMsg, WorkerMsg};
+    use leema::struple::Struple;
     use leema::udp;
     use leema::val::Val;
 
+    use std::net;
+    use std::sync::mpsc::{Receiver, Sender};
+    use std::time::Duration;
+
+
+    fn assert_failure_tag(expected: &'static str, result: Val)
+    {
+        match result {
+            Val::Failure(tag, _, _, _) => {
+                assert_eq!(Val::Hashtag(Lstr::Sref(expected)), *tag);
+            }
+            _ => panic!("expected failure, found {:?}", result),
+        }
+    }
+
+    fn bind_loopback(tx: &Sender<IoMsg>, rx: &Receiver<WorkerMsg>) -> i64
+    {
+        let params = vec![
+            (None, Val::Str(Lstr::Sref("127.0.0.1"))),
+            (None, Val::Int(0)),
+        ];
+        match iop_result(tx, rx, udp::udp_bind, None, params) {
+            Val::ResourceRef(id) => id,
+            other => panic!("expected a udp socket, found {:?}", other),
+        }
+    }
+
     #[test]
     fn test_udp_socket_creation()
     {
@@ -146,4 +424,124 @@ mod tests
         assert_eq!(Val::ResourceRef(1), rsrc_ref);
     }
 
+    #[test]
+    fn test_udp_bind_invalid_address_fails()
+    {
+        let params = vec![
+            (None, Val::Str(Lstr::Sref("not.an.ip"))),
+            (None, Val::Int(0)),
+        ];
+        let response = exercise_iop_action(udp::udp_bind, params);
+        let (_fiber_id, result) = response.ok().unwrap();
+        assert_failure_tag("invalid_address", result);
+    }
+
+    #[test]
+    fn test_udp_set_max_datagram_bounds()
+    {
+        let (tx, rx, io_thread) = spawn_io_thread();
+        let id = bind_loopback(&tx, &rx);
+        for size in vec![0, 65536] {
+            let params =
+                vec![(None, Val::ResourceRef(id)), (None, Val::Int(size))];
+            let action = udp::udp_set_max_datagram;
+            let result = iop_result(&tx, &rx, action, Some(id), params);
+            assert_failure_tag("invalid_size", result);
+        }
+        let params = vec![(None, Val::ResourceRef(id)), (None, Val::Int(512))];
+        let action = udp::udp_set_max_datagram;
+        let result = iop_result(&tx, &rx, action, Some(id), params);
+        assert_eq!(Val::Void, result);
+        tx.send(IoMsg::Done).unwrap();
+        io_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_udp_join_non_multicast_group_fails()
+    {
+        let (tx, rx, io_thread) = spawn_io_thread();
+        let id = bind_loopback(&tx, &rx);
+        let params = vec![
+            (None, Val::ResourceRef(id)),
+            (None, Val::Str(Lstr::Sref("10.0.0.1"))),
+        ];
+        let action = udp::udp_join_multicast;
+        let result = iop_result(&tx, &rx, action, Some(id), params);
+        assert_failure_tag("invalid_address", result);
+        tx.send(IoMsg::Done).unwrap();
+        io_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_udp_send_to_recv_from_round_trip()
+    {
+        let peer = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let peer_port = peer.local_addr().unwrap().port() as i64;
+
+        let (tx, rx, io_thread) = spawn_io_thread();
+        let id = bind_loopback(&tx, &rx);
+        let params = vec![
+            (None, Val::ResourceRef(id)),
+            (None, Val::Str(Lstr::Sref("127.0.0.1"))),
+            (None, Val::Int(peer_port)),
+            (None, Val::Str(Lstr::Sref("ping"))),
+        ];
+        let sent = iop_result(&tx, &rx, udp::udp_send_to, Some(id), params);
+        assert_eq!(Val::Int(4), sent);
+
+        let mut buf = [0; 16];
+        let (nbytes, leema_addr) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(b"ping", &buf[..nbytes]);
+        peer.send_to(b"pong", leema_addr).unwrap();
+
+        let params = vec![(None, Val::ResourceRef(id))];
+        let received =
+            iop_result(&tx, &rx, udp::udp_recv_from, Some(id), params);
+        let expected = Val::Tuple(Struple::new_indexed(vec![
+            Val::Str(Lstr::Sref("pong")),
+            Val::Str(Lstr::Sref("127.0.0.1")),
+            Val::Int(peer_port),
+        ]));
+        assert_eq!(expected, received);
+        tx.send(IoMsg::Done).unwrap();
+        io_thread.join().unwrap();
+    }
+    #[test]
+    fn test_udp_buffer_round_trip()
+    {
+        let peer = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let peer_port = peer.local_addr().unwrap().port() as i64;
+
+        let (tx, rx, io_thread) = spawn_io_thread();
+        let id = bind_loopback(&tx, &rx);
+        // buffers are cloned into and out of the io thread
+        let params = vec![
+            (None, Val::ResourceRef(id)),
+            (None, Val::Str(Lstr::Sref("127.0.0.1"))),
+            (None, Val::Int(peer_port)),
+            (None, Val::Buffer(vec![0, 1, 2, 255])),
+        ];
+        let sent = iop_result(&tx, &rx, udp::udp_send_to, Some(id), params);
+        assert_eq!(Val::Int(4), sent);
+
+        let mut buf = [0; 16];
+        let (nbytes, leema_addr) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(&[0, 1, 2, 255], &buf[..nbytes]);
+        peer.send_to(&[9, 0, 8], leema_addr).unwrap();
+
+        let params = vec![(None, Val::ResourceRef(id))];
+        let received =
+            iop_result(&tx, &rx, udp::udp_recv_from_buffer, Some(id), params);
+        let expected = Val::Tuple(Struple::new_indexed(vec![
+            Val::Buffer(vec![9, 0, 8]),
+            Val::Str(Lstr::Sref("127.0.0.1")),
+            Val::Int(peer_port),
+        ]));
+        assert_eq!(expected, received);
+        tx.send(IoMsg::Done).unwrap();
+        io_thread.join().unwrap();
+    }
+
 }
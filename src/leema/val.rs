@@ -1,6 +1,6 @@
 use leema::frame::FrameTrace;
 use leema::list;
-use leema::lmap::LmapNode;
+use leema::lmap::{Lmap, LmapNode};
 use leema::log;
 use leema::lri::Lri;
 use leema::lstr::Lstr;
@@ -16,8 +16,6 @@ use std::io::{Error, Write};
 use std::sync::atomic::{self, AtomicBool};
 use std::sync::{Arc, Mutex};
 
-use futures::sync::mpsc::Receiver;
-
 use mopa;
 
 
@@ -56,6 +54,7 @@ pub enum Type
     // and then it should be a protocol, not type
     StrictList(Box<Type>),
     Map,
+    Buffer,
     UserDef(Lri),
     Lib(String),
     Resource(Lstr),
@@ -163,6 +162,8 @@ impl Type
             &Type::Unknown => Type::Unknown,
             &Type::Var(ref id) => Type::Var(id.clone_for_send()),
             &Type::Void => Type::Void,
+            &Type::Map => Type::Map,
+            &Type::Buffer => Type::Buffer,
             _ => {
                 panic!("cannot deep_clone Type: {:?}", self);
             }
@@ -220,6 +221,7 @@ impl fmt::Display for Type
             // and then it should be a protocol, not type
             &Type::StrictList(ref typ) => write!(f, "List<{}>", typ),
             &Type::Map => write!(f, "Map"),
+            &Type::Buffer => write!(f, "Buffer"),
             &Type::Lib(ref name) => write!(f, "LibType({})", &name),
             &Type::Resource(ref name) => write!(f, "{}", &name),
             &Type::RustBlock => write!(f, "RustBlock"),
@@ -258,6 +260,7 @@ impl fmt::Debug for Type
             // and then it should be a protocol, not type
             &Type::StrictList(ref typ) => write!(f, "List<{}>", typ),
             &Type::Map => write!(f, "Map"),
+            &Type::Buffer => write!(f, "Buffer"),
             &Type::Lib(ref name) => write!(f, "LibType({})", &name),
             &Type::Resource(ref name) => write!(f, "Resource({})", &name),
             &Type::RustBlock => write!(f, "RustBlock"),
@@ -282,6 +285,7 @@ pub enum TypeErr
     Mismatch(Type, Type),
     Unknowable,
     Context(Box<TypeErr>, Lstr),
+    At(Box<TypeErr>, SrcLoc),
 }
 
 impl TypeErr
@@ -290,6 +294,56 @@ impl TypeErr
     {
         TypeErr::Context(Box::new(self), ctx)
     }
+
+    /**
+     * Set the location of the error, unless a more specific
+     * location was already set further in
+     */
+    pub fn at(self, loc: SrcLoc) -> TypeErr
+    {
+        if self.loc().is_some() {
+            self
+        } else {
+            TypeErr::At(Box::new(self), loc)
+        }
+    }
+
+    pub fn loc(&self) -> Option<SrcLoc>
+    {
+        match self {
+            &TypeErr::At(_, loc) => Some(loc),
+            &TypeErr::Context(ref inner, _) => inner.loc(),
+            _ => None,
+        }
+    }
+
+    /**
+     * Get the underlying error w/o any context or location
+     */
+    pub fn root(&self) -> &TypeErr
+    {
+        match self {
+            &TypeErr::At(ref inner, _) => inner.root(),
+            &TypeErr::Context(ref inner, _) => inner.root(),
+            _ => self,
+        }
+    }
+
+    /**
+     * Get the context messages, innermost first
+     */
+    pub fn context(&self) -> Vec<Lstr>
+    {
+        let mut ctx = match self {
+            &TypeErr::At(ref inner, _) => inner.context(),
+            &TypeErr::Context(ref inner, _) => inner.context(),
+            _ => vec![],
+        };
+        if let &TypeErr::Context(_, ref c) = self {
+            ctx.push(c.clone());
+        }
+        ctx
+    }
 }
 
 impl fmt::Display for TypeErr
@@ -305,6 +359,9 @@ impl fmt::Display for TypeErr
             &TypeErr::Context(ref inner_e, ref ctx) => {
                 write!(f, "({}, '{}')", inner_e, ctx)
             }
+            &TypeErr::At(ref inner_e, ref loc) => {
+                write!(f, "{} @ {}", inner_e, loc)
+            }
         }
     }
 }
@@ -327,8 +384,44 @@ pub trait LibVal: mopa::Any + fmt::Debug + Send + Sync
 
 mopafy!(LibVal);
 
+/**
+ * Result of a forked call
+ *
+ * The forked fiber sets the value once when it completes and then
+ * flips the ready flag. Readers copy the value out w/o taking it,
+ * so the same future can be read any number of times.
+ */
 #[derive(Clone)]
-pub struct FutureVal(pub Arc<AtomicBool>, pub Arc<Mutex<Receiver<MsgVal>>>);
+pub struct FutureVal(pub Arc<AtomicBool>, pub Arc<Mutex<Option<MsgVal>>>);
+
+impl FutureVal
+{
+    pub fn new() -> FutureVal
+    {
+        let ready = Arc::new(AtomicBool::new(false));
+        FutureVal(ready, Arc::new(Mutex::new(None)))
+    }
+
+    pub fn is_ready(&self) -> bool
+    {
+        self.0.load(atomic::Ordering::Acquire)
+    }
+
+    pub fn set(&self, v: &Val)
+    {
+        *self.1.lock().unwrap() = Some(MsgVal::new(v));
+        self.0.store(true, atomic::Ordering::Release);
+    }
+
+    pub fn get(&self) -> Option<Val>
+    {
+        if !self.is_ready() {
+            return None;
+        }
+        let result = self.1.lock().unwrap();
+        result.as_ref().map(|v| v.deep_clone())
+    }
+}
 
 impl fmt::Debug for FutureVal
 {
@@ -523,6 +616,20 @@ impl Val
         }
     }
 
+    /**
+     * The raw bytes of a str or buffer
+     */
+    pub fn as_bytes(&self) -> &[u8]
+    {
+        match self {
+            &Val::Str(ref s) => s.str().as_bytes(),
+            &Val::Buffer(ref b) => b,
+            _ => {
+                panic!("Cannot convert to bytes: {:?}", self);
+            }
+        }
+    }
+
     pub fn to_int(&self) -> i64
     {
         match self {
@@ -541,9 +648,9 @@ impl Val
         }
     }
 
-    pub fn future(ready: Arc<AtomicBool>, r: Receiver<MsgVal>) -> Val
+    pub fn future(fv: FutureVal) -> Val
     {
-        Val::Future(FutureVal(ready, Arc::new(Mutex::new(r))))
+        Val::Future(fv)
     }
 
     pub fn is_future(&self) -> bool
@@ -557,7 +664,7 @@ impl Val
     pub fn is_future_ready(&self) -> bool
     {
         match self {
-            &Val::Future(ref fv) => fv.0.load(atomic::Ordering::Relaxed),
+            &Val::Future(ref fv) => fv.is_ready(),
             _ => false,
         }
     }
@@ -644,7 +751,7 @@ impl Val
             &Val::EnumStruct(ref typ, _, _) => Type::UserDef(typ.clone()),
             &Val::EnumToken(ref typ, _) => Type::UserDef(typ.clone()),
             &Val::Token(ref typ) => Type::UserDef(typ.clone()),
-            &Val::Buffer(_) => Type::Str,
+            &Val::Buffer(_) => Type::Buffer,
             &Val::Kind(_) => {
                 panic!("is kind even a thing here?");
             }
@@ -788,7 +895,7 @@ impl Val
                 Val::Failure(
                     Box::new(tag.deep_clone()),
                     Box::new(msg.deep_clone()),
-                    ft.clone(),
+                    ft.clone_for_send(),
                     status,
                 )
             }
@@ -796,9 +903,13 @@ impl Val
             &Val::Type(ref t) => Val::Type(t.deep_clone()),
             &Val::ResourceRef(r) => Val::ResourceRef(r),
             &Val::Kind(k) => Val::Kind(k),
-            // &Val::Lib(LibVal),
+            &Val::Map(ref m) => Val::Map(Lmap::deep_clone(m)),
+            &Val::Buffer(ref b) => Val::Buffer(b.clone()),
+            // lib vals are Send + Sync, so they can be shared
+            &Val::Lib(ref lv) => Val::Lib(lv.clone()),
             // &Val::RustBlock,
-            // &Val::Future(FutureVal),
+            // futures are shared, not copied
+            &Val::Future(ref fv) => Val::Future(fv.clone()),
             &Val::Void => Val::Void,
             &Val::Wildcard => Val::Wildcard,
             &Val::PatternVar(ref r) => Val::PatternVar(r.clone()),
@@ -896,7 +1007,13 @@ impl fmt::Display for Val
             }
             Val::EnumToken(_, ref var_name) => write!(f, "{}", var_name),
             Val::Token(ref typename) => write!(f, "{}", typename),
-            Val::Map(ref map) => write!(f, "Map({:?})", map),
+            Val::Map(ref map) => {
+                write!(f, "{{")?;
+                for (k, v) in Lmap::iter(map) {
+                    write!(f, "{}: {},", k, v)?;
+                }
+                write!(f, "}}")
+            }
             Val::Buffer(ref _buf) => write!(f, "Buffer"),
             Val::Lib(ref lv) => write!(f, "LibVal({:?})", lv),
             Val::ResourceRef(rid) => write!(f, "ResourceRef({})", rid),
@@ -1144,6 +1261,8 @@ impl PartialOrd for Val
             (&Val::Buffer(ref b1), &Val::Buffer(ref b2)) => {
                 PartialOrd::partial_cmp(b1, b2)
             }
+            // maps compare entries in key order
+            (&Val::Map(ref m1), &Val::Map(ref m2)) => Some(Lmap::cmp(m1, m2)),
 
             // start comparing mixed types
             (&Val::Bool(false), _) => Some(Ordering::Less),
@@ -1176,6 +1295,8 @@ impl PartialOrd for Val
             (_, &Val::EnumToken(_, _)) => Some(Ordering::Greater),
             (&Val::Token(_), _) => Some(Ordering::Less),
             (_, &Val::Token(_)) => Some(Ordering::Greater),
+            (&Val::Map(_), _) => Some(Ordering::Less),
+            (_, &Val::Map(_)) => Some(Ordering::Greater),
             (&Val::Void, _) => Some(Ordering::Less),
             (_, &Val::Void) => Some(Ordering::Greater),
             (&Val::RustBlock, _) => Some(Ordering::Less),
@@ -1361,6 +1482,26 @@ impl Env
     }
 }
 
+impl SendClone for Env
+{
+    type Item = Env;
+
+    fn clone_for_send(&self) -> Env
+    {
+        Env {
+            params: self.params.deep_clone(),
+            result: self.result.as_ref().map(|r| r.deep_clone()),
+            reg: self.reg.iter().map(|(k, v)| (*k, v.deep_clone())).collect(),
+            kv: self
+                .kv
+                .iter()
+                .map(|(k, v)| (k.clone(), v.deep_clone()))
+                .collect(),
+            error: self.error.deep_clone(),
+        }
+    }
+}
+
 impl reg::Iregistry for Env
 {
     fn ireg_get(&self, i: &Ireg) -> &Val
@@ -1696,4 +1837,13 @@ mod tests
         assert!(pmatch.is_some());
     }
 
+    #[test]
+    fn test_deep_clone_buffer()
+    {
+        let buf = Val::Buffer(vec![1, 2, 3]);
+        assert_eq!(buf, buf.deep_clone());
+        assert_eq!(Type::Buffer, buf.get_type().deep_clone());
+        assert_eq!(Type::Map, Type::Map.deep_clone());
+    }
+
 }
@@ -1,22 +1,28 @@
 use leema::code::Code;
-use leema::fiber::Fiber;
+use leema::fiber::{Fiber, MsgFiber};
 use leema::frame::{Event, Frame, Parent};
 use leema::log;
 use leema::lstr::Lstr;
+use leema::mailbox::Mailbox;
 use leema::msg::{AppMsg, IoMsg, MsgItem, WorkerMsg};
-use leema::val::{MsgVal, Val};
+use leema::reg::Reg;
+use leema::val::{self, MsgVal, Val};
 
 use std::cmp::min;
 use std::collections::{HashMap, LinkedList};
 use std::io::Write;
 use std::rc::Rc;
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::{Async, Poll};
 
 
+pub const DEFAULT_REDUCTION_BUDGET: u32 = 2000;
+
+
 #[derive(Debug)]
 enum ReadyFiber
 {
@@ -29,7 +35,22 @@ enum FiberWait
 {
     Code(Fiber),
     Io(Fiber),
-    Future(Fiber, Rc<Code>),
+    Future(Fiber, Rc<Code>, Reg),
+    // Mail(fiber, mailbox, pattern, deadline)
+    Mail(Fiber, Arc<Mailbox>, Val, Option<Instant>),
+}
+
+impl FiberWait
+{
+    fn fiber(&self) -> &Fiber
+    {
+        match self {
+            &FiberWait::Code(ref f) => f,
+            &FiberWait::Io(ref f) => f,
+            &FiberWait::Future(ref f, _, _) => f,
+            &FiberWait::Mail(ref f, _, _, _) => f,
+        }
+    }
 }
 
 
@@ -38,12 +59,16 @@ pub struct Worker
     fresh: LinkedList<ReadyFiber>,
     waiting: HashMap<i64, FiberWait>,
     code: HashMap<Lstr, HashMap<Lstr, Rc<Code>>>,
+    // source file each module was loaded from
+    files: HashMap<Lstr, Lstr>,
     app_tx: Sender<AppMsg>,
     io_tx: Sender<IoMsg>,
     msg_rx: Receiver<WorkerMsg>,
     id: i64,
     next_fiber_id: i64,
     did_nothing: i32,
+    reduction_budget: u32,
+    stealing: bool,
     done: bool,
 }
 
@@ -67,16 +92,27 @@ impl Worker
             fresh: LinkedList::new(),
             waiting: HashMap::new(),
             code: HashMap::new(),
+            files: HashMap::new(),
             app_tx: send,
             io_tx: io,
             msg_rx: recv,
             id: wid,
             next_fiber_id: 0,
             did_nothing: 0,
+            reduction_budget: DEFAULT_REDUCTION_BUDGET,
+            stealing: false,
             done: false,
         }
     }
 
+    /**
+     * Number of ops a fiber can run before yielding to other fibers
+     */
+    pub fn set_reduction_budget(&mut self, budget: u32)
+    {
+        self.reduction_budget = budget.max(1);
+    }
+
     pub fn run(mut self)
     {
         let mut did_nothing = 0;
@@ -97,26 +133,100 @@ impl Worker
     {
         let mut did_something = false;
         while let Result::Ok(msg) = self.msg_rx.try_recv() {
+            if self.process_msg(msg) {
+                did_something = true;
+            }
+        }
+
+        if self.wake_futures() {
+            did_something = true;
+        }
+        if self.expire_receives() {
+            did_something = true;
+        }
+        if self.drop_killed() {
             did_something = true;
-            self.process_msg(msg);
         }
 
         match self.pop_fresh() {
+            Some(ReadyFiber::New(ref f)) | Some(ReadyFiber::Ready(ref f, _))
+                if f.is_killed() =>
+            {
+                vout!("drop killed fiber {}\n", f.fiber_id);
+                did_something = true;
+            }
             Some(ReadyFiber::New(f)) => {
                 did_something = true;
                 self.load_code(f);
             }
             Some(ReadyFiber::Ready(mut f, code)) => {
                 did_something = true;
-                let ev = Worker::execute_frame(&mut f, &*code);
+                let budget = self.reduction_budget;
+                let ev = Worker::execute_frame(&mut f, &*code, budget);
                 self.handle_event(f, ev, code)
                     .expect("failure handling event");
             }
-            None => {}
+            None => {
+                self.steal();
+            }
         }
         did_something
     }
 
+    /**
+     * out of ready fibers, ask the app to find some on another worker
+     */
+    fn steal(&mut self)
+    {
+        if self.stealing || self.done {
+            return;
+        }
+        self.stealing = true;
+        self.app_tx
+            .send(AppMsg::Steal(self.id))
+            .expect("failure sending steal message to app");
+    }
+
+    /**
+     * give a ready fiber to an idle worker
+     *
+     * leave the last one so this worker doesn't go idle too, and take
+     * from the back so the oldest fibers keep running here
+     */
+    fn give_fiber(&mut self) -> Option<MsgFiber>
+    {
+        if self.fresh.len() < 2 {
+            return None;
+        }
+        match self.fresh.pop_back() {
+            Some(ReadyFiber::New(f)) => Some(f.pack(None)),
+            Some(ReadyFiber::Ready(f, code)) => Some(f.pack(Some(&*code))),
+            None => None,
+        }
+    }
+
+    /**
+     * use this worker's copy of some code if it has one already,
+     * otherwise keep the copy that came w/ a stolen fiber
+     */
+    fn share_code(
+        &mut self,
+        modname: &Lstr,
+        funcname: &Lstr,
+        code: Code,
+    ) -> Rc<Code>
+    {
+        if let Some(existing) = self.find_code(modname, funcname) {
+            return existing;
+        }
+        let rc_code = Rc::new(code);
+        self.code
+            .entry(modname.clone())
+            .or_insert_with(HashMap::new)
+            .insert(funcname.clone(), rc_code.clone());
+        rc_code
+    }
+
     fn find_code<'a>(
         &'a self,
         modname: &str,
@@ -151,10 +261,10 @@ impl Worker
         }
     }
 
-    pub fn execute_frame(f: &mut Fiber, code: &Code) -> Event
+    pub fn execute_frame(f: &mut Fiber, code: &Code, budget: u32) -> Event
     {
         match code {
-            &Code::Leema(ref ops) => f.execute_leema_frame(ops),
+            &Code::Leema(ref ops) => f.execute_leema_frame(ops, budget),
             &Code::Rust(ref rf) => {
                 vout!("execute rust code\n");
                 rf(f)
@@ -190,14 +300,17 @@ impl Worker
                 vout!("function call failure\n");
                 Result::Ok(Async::NotReady)
             }
-            Event::Call(dst, line, module, func, args) => {
-                vout!("push_call({} {}.{}, {:?})\n", line, module, func, args);
-                fbr.push_call(code.clone(), dst, line, module, func, args);
+            Event::Call(dst, loc, module, func, args) => {
+                vout!("push_call({} {}.{}, {:?})\n", loc, module, func, args);
+                fbr.push_call(code.clone(), dst, loc, module, func, args);
                 self.load_code(fbr);
                 Result::Ok(Async::NotReady)
             }
             Event::FutureWait(reg) => {
-                println!("wait for future {:?}", reg);
+                vout!("wait for future {:?}\n", reg);
+                let fiber_id = fbr.fiber_id;
+                self.waiting
+                    .insert(fiber_id, FiberWait::Future(fbr, code, reg));
                 Result::Ok(Async::NotReady)
             }
             Event::Iop((rsrc_worker_id, rsrc_id), _iopf, _iopargs) => {
@@ -237,13 +350,39 @@ impl Worker
                 }
                 Result::Ok(Async::NotReady)
             }
+            Event::Receive(mailbox, patt, deadline) => {
+                self.wait_for_mail(fbr, mailbox, patt, deadline);
+                Result::Ok(Async::NotReady)
+            }
+            Event::WakeReceiver(worker_id, fiber_id) => {
+                if worker_id == self.id {
+                    self.wake_receiver(fiber_id);
+                } else {
+                    self.app_tx
+                        .send(AppMsg::WakeReceiver(worker_id, fiber_id))
+                        .expect("failure sending wake receiver to app");
+                }
+                self.return_from_call(fbr);
+                Result::Ok(Async::NotReady)
+            }
             Event::IOWait => {
                 println!("do I/O");
                 Result::Ok(Async::NotReady)
             }
-            Event::Fork => {
-                // self.fresh.push_back((code, curf));
-                // end this iteration,
+            Event::Yield => {
+                vout!("fiber {} yields\n", fbr.fiber_id);
+                self.push_fresh(ReadyFiber::Ready(fbr, code));
+                Result::Ok(Async::NotReady)
+            }
+            Event::Fork(forked) => {
+                self.spawn_fiber(forked);
+                if code.is_leema() {
+                    // let the forked fiber start before this one continues
+                    self.push_fresh(ReadyFiber::Ready(fbr, code));
+                } else {
+                    // rust functions are done once they fork
+                    self.return_from_call(fbr);
+                }
                 Result::Ok(Async::NotReady)
             }
             Event::Uneventful => {
@@ -268,7 +407,11 @@ impl Worker
                 vout!(" result: {}\n", dst);
                 self.push_fresh(ReadyFiber::Ready(fbr, old_code));
             }
-            Parent::Repl(_) => {}
+            Parent::Repl(res) => {
+                vout!("finished repl func\n");
+                let msg = AppMsg::ReplResult(MsgVal::new(&res));
+                self.app_tx.send(msg).expect("app message send failure");
+            }
             Parent::Main(res) => {
                 vout!("finished main func\n");
                 let msg = AppMsg::MainResult(MsgVal::new(&res));
@@ -278,63 +421,243 @@ impl Worker
             Parent::Future(result_dst, result) => {
                 result_dst.send(result).expect("fail sending future result");
             }
+            Parent::Fork(fv, result) => {
+                vout!("finished forked func\n");
+                fv.set(&result);
+            }
+            Parent::Supervised(child, result) => {
+                vout!("supervised child exited: {:?}\n", result);
+                for restart in child.exit(&result) {
+                    self.spawn_fiber(restart);
+                }
+            }
+            Parent::Process(pid) => {
+                vout!("process {} exited\n", pid.id);
+            }
             Parent::Null => {
                 // this shouldn't have happened
             }
         }
     }
 
-    pub fn process_msg(&mut self, msg: WorkerMsg)
+    /**
+     * take a matching message or leave the fiber waiting for one
+     */
+    fn wait_for_mail(
+        &mut self,
+        mut fbr: Fiber,
+        mailbox: Arc<Mailbox>,
+        patt: Val,
+        deadline: Option<Instant>,
+    )
+    {
+        match mailbox.wait(self.id, fbr.fiber_id, &patt) {
+            Some(msg) => {
+                fbr.head.parent.set_result(msg);
+                self.return_from_call(fbr);
+            }
+            None => {
+                let fiber_id = fbr.fiber_id;
+                let fw = FiberWait::Mail(fbr, mailbox, patt, deadline);
+                self.waiting.insert(fiber_id, fw);
+            }
+        }
+    }
+
+    /**
+     * a message arrived for a waiting fiber, check if it matches
+     */
+    fn wake_receiver(&mut self, fiber_id: i64)
+    {
+        match self.waiting.remove(&fiber_id) {
+            Some(FiberWait::Mail(fbr, mailbox, patt, deadline)) => {
+                self.wait_for_mail(fbr, mailbox, patt, deadline);
+            }
+            Some(other) => {
+                // woken by a stale message, keep waiting on the other thing
+                self.waiting.insert(fiber_id, other);
+            }
+            None => {}
+        }
+    }
+
+    /**
+     * fail receives that waited past their deadline
+     */
+    fn expire_receives(&mut self) -> bool
+    {
+        let now = Instant::now();
+        let expired: Vec<i64> = self
+            .waiting
+            .iter()
+            .filter_map(|(id, w)| {
+                match w {
+                    &FiberWait::Mail(_, _, _, Some(deadline))
+                        if deadline <= now =>
+                    {
+                        Some(*id)
+                    }
+                    _ => None,
+                }
+            }).collect();
+        for id in expired.iter() {
+            if let Some(FiberWait::Mail(mut f, mailbox, _, _)) =
+                self.waiting.remove(id)
+            {
+                mailbox.stop_waiting();
+                let failure = Val::failure(
+                    Val::Hashtag(Lstr::Sref("timeout")),
+                    Val::Str(Lstr::Sref("no matching message received")),
+                    f.head.trace.fail_here(),
+                    val::FAILURE_TIMEOUT,
+                );
+                f.head.parent.set_result(failure);
+                self.return_from_call(f);
+            }
+        }
+        !expired.is_empty()
+    }
+
+    /**
+     * drop waiting fibers that their supervisor killed, whatever
+     * they were waiting for is dropped too when it shows up
+     */
+    fn drop_killed(&mut self) -> bool
+    {
+        let killed: Vec<i64> = self
+            .waiting
+            .iter()
+            .filter(|&(_, w)| w.fiber().is_killed())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in killed.iter() {
+            vout!("drop killed fiber {}\n", id);
+            if let Some(FiberWait::Mail(_, mailbox, _, _)) =
+                self.waiting.remove(id)
+            {
+                mailbox.stop_waiting();
+            }
+        }
+        !killed.is_empty()
+    }
+
+    /**
+     * returns true if the message gave this worker something to do
+     */
+    pub fn process_msg(&mut self, msg: WorkerMsg) -> bool
     {
         match msg {
-            WorkerMsg::Spawn(module, call) => {
+            WorkerMsg::Spawn(module, call, args) => {
                 vout!("worker call {}.{}()\n", *module, *call);
                 let parent = Parent::new_main();
-                let root = Frame::new_root(parent, module.take(), call.take());
+                let root = Frame::new_root_with_args(
+                    parent,
+                    module.take(),
+                    call.take(),
+                    args.take(),
+                );
                 self.spawn_fiber(root);
             }
-            WorkerMsg::Spawn2(result_dst, func) => {
-                vout!("worker spawn2 {}\n", func);
+            WorkerMsg::ResultSpawn(result_dst, module, call, args) => {
+                vout!("worker call w/return {}.{}()\n", *module, *call);
                 let parent = Parent::new_future(result_dst);
-                let module = func.modules.unwrap();
-                let root = Frame::new_root(parent, module, func.localid);
+                let root = Frame::new_root_with_args(
+                    parent,
+                    module.take(),
+                    call.take(),
+                    args.take(),
+                );
                 self.spawn_fiber(root);
             }
-            WorkerMsg::ResultSpawn(result_dst, module, call) => {
-                vout!("worker call w/return {}.{}()\n", *module, *call);
-                let parent = Parent::new_future(result_dst);
+            WorkerMsg::ReplSpawn(module, call) => {
+                vout!("worker repl call {}.{}()\n", *module, *call);
+                let parent = Parent::Repl(Val::Void);
                 let root = Frame::new_root(parent, module.take(), call.take());
                 self.spawn_fiber(root);
             }
-            WorkerMsg::FoundCode(fiber_id, module, func, code) => {
-                let rc_code = Rc::new(code);
-                let mut new_mod = HashMap::new();
-                new_mod.insert(func.take(), rc_code.clone());
-                self.code.insert(module.take(), new_mod);
+            WorkerMsg::FoundCode(fiber_id, module, func, file, code) => {
+                let rc_code = self.share_code(&module, &func, code);
+                self.files.insert(module.take(), file.take());
                 let opt_fiber = self.waiting.remove(&fiber_id);
                 if let Some(FiberWait::Code(fib)) = opt_fiber {
                     self.push_coded_fiber(fib, rc_code);
                 } else {
-                    panic!("Cannot find waiting fiber: {}", fiber_id);
+                    // the fiber was killed while waiting for its code
+                    vout!("no fiber waiting for code: {}\n", fiber_id);
                 }
             }
             WorkerMsg::IopResult(fiber_id, result_msg) => {
                 vout!("iop_result({}, {:?})\n", fiber_id, result_msg);
                 let result_val = result_msg.take();
-                let wait = self.waiting.remove(&fiber_id).unwrap();
-                if let FiberWait::Io(mut fib) = wait {
+                // None if the fiber was killed while waiting for the iop
+                let wait = self.waiting.remove(&fiber_id);
+                if let Some(FiberWait::Io(mut fib)) = wait {
+                    // iop failures are made w/o a trace, so give them
+                    // the trace of the fiber that called the iop
+                    let result_val = match result_val {
+                        Val::Failure(tag, msg, _, status) => {
+                            let trace = fib.head.trace.fail_here();
+                            Val::Failure(tag, msg, trace, status)
+                        }
+                        _ => result_val,
+                    };
                     fib.head.parent.set_result(result_val);
                     self.return_from_call(fib);
                 }
             }
+            WorkerMsg::Steal(thief_id) => {
+                let opt_fiber = self.give_fiber();
+                let gave = opt_fiber.is_some();
+                self.app_tx
+                    .send(AppMsg::Stolen(thief_id, opt_fiber))
+                    .expect("failure sending stolen fiber to app");
+                return gave;
+            }
+            WorkerMsg::Stolen(opt_fiber) => {
+                self.stealing = false;
+                match opt_fiber {
+                    Some(mf) => {
+                        vout!("worker {} stole {:?}\n", self.id, mf);
+                        self.receive_fiber(mf);
+                    }
+                    None => {
+                        return false;
+                    }
+                }
+            }
+            WorkerMsg::WakeReceiver(fiber_id) => {
+                self.wake_receiver(fiber_id);
+            }
             WorkerMsg::Done => {
                 self.done = true;
             }
         }
+        true
     }
 
-    fn push_coded_fiber(&mut self, fib: Fiber, code: Rc<Code>)
+    fn receive_fiber(&mut self, mf: MsgFiber)
     {
+        let id = self.next_fiber_id;
+        self.next_fiber_id += 1;
+        let (fib, head_code) =
+            mf.unpack(id, |m, f, code| self.share_code(m, f, code));
+        if !String::from(&fib.head.file).is_empty() {
+            self.files
+                .entry(fib.head.module.clone())
+                .or_insert_with(|| fib.head.file.clone());
+        }
+        match head_code {
+            Some(code) => self.push_fresh(ReadyFiber::Ready(fib, code)),
+            // not started yet, load the code like a new fiber
+            None => self.push_fresh(ReadyFiber::New(fib)),
+        }
+    }
+
+    fn push_coded_fiber(&mut self, mut fib: Fiber, code: Rc<Code>)
+    {
+        if let Some(file) = self.files.get(fib.module_name()) {
+            fib.head.file = file.clone();
+        }
         if code.is_leema() {
             self.push_fresh(ReadyFiber::Ready(fib, code));
         } else if code.is_rust() {
@@ -372,6 +695,33 @@ impl Worker
         self.fresh.push_back(ReadyFiber::New(fib));
     }
 
+    /**
+     * move fibers whose futures are ready back to the fresh queue
+     */
+    fn wake_futures(&mut self) -> bool
+    {
+        let ready: Vec<i64> = self
+            .waiting
+            .iter()
+            .filter_map(|(id, w)| {
+                match w {
+                    &FiberWait::Future(ref f, _, ref reg)
+                        if f.head.e.get_reg(reg).is_future_ready() =>
+                    {
+                        Some(*id)
+                    }
+                    _ => None,
+                }
+            }).collect();
+        for id in ready.iter() {
+            if let Some(FiberWait::Future(f, code, _)) = self.waiting.remove(id)
+            {
+                self.push_fresh(ReadyFiber::Ready(f, code));
+            }
+        }
+        !ready.is_empty()
+    }
+
     fn pop_fresh(&mut self) -> Option<ReadyFiber>
     {
         self.fresh.pop_front()
@@ -382,3 +732,235 @@ impl Worker
         self.fresh.push_back(f)
     }
 }
+
+
+#[cfg(test)]
+mod tests
+{
+    use leema::code::{Code, Op};
+    use leema::fiber::Fiber;
+    use leema::frame::{Frame, Parent};
+    use leema::lmap::Lmap;
+    use leema::lri::Lri;
+    use leema::lstr::Lstr;
+    use leema::mailbox::Mailbox;
+    use leema::msg::{AppMsg, MsgItem, WorkerMsg};
+    use leema::reg::Reg;
+    use leema::struple::Struple;
+    use leema::supervisor::{Strategy, Supervisor};
+    use leema::val::{FutureVal, MsgVal, Val};
+    use leema::worker::{ReadyFiber, Worker};
+
+    use std::rc::Rc;
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use futures::sync::oneshot;
+
+
+    #[test]
+    fn test_idle_worker_steals_ready_fiber()
+    {
+        let (app_tx, app_rx) = channel();
+        let (io_tx, _io_rx) = channel();
+        let (_busy_tx, busy_rx) = channel();
+        let (_idle_tx, idle_rx) = channel();
+        let mut busy = Worker::init(1, app_tx.clone(), io_tx.clone(), busy_rx);
+        let mut idle = Worker::init(2, app_tx, io_tx, idle_rx);
+        for f in ["a", "b", "c"].iter() {
+            let func = Lstr::Sref(*f);
+            let root = Frame::new_root(Parent::Null, Lstr::Sref("foo"), func);
+            busy.spawn_fiber(root);
+        }
+
+        assert!(busy.process_msg(WorkerMsg::Steal(2)));
+        let stolen = match app_rx.try_recv() {
+            Ok(AppMsg::Stolen(2, Some(mf))) => mf,
+            other => panic!("expected a stolen fiber: {:?}", other),
+        };
+        assert_eq!("c", stolen.function_name().str());
+        assert_eq!(2, busy.fresh.len());
+
+        assert!(idle.process_msg(WorkerMsg::Stolen(Some(stolen))));
+        assert_eq!(1, idle.fresh.len());
+        assert!(!idle.stealing);
+    }
+
+    #[test]
+    fn test_spawn_with_struct_args()
+    {
+        let (app_tx, _app_rx) = channel();
+        let (io_tx, _io_rx) = channel();
+        let (_tx, rx) = channel();
+        let mut w = Worker::init(1, app_tx, io_tx, rx);
+        let headers = Lmap::insert(
+            &Lmap::new(),
+            Val::Str(Lstr::Sref("host")),
+            Val::Str(Lstr::Sref("tacos.com")),
+        );
+        let req = Val::Struct(
+            Lri::with_modules(Lstr::Sref("http"), Lstr::Sref("Request")),
+            Struple(vec![
+                (Some(Lstr::Sref("headers")), Val::Map(headers)),
+                (Some(Lstr::Sref("body")), Val::Buffer(vec![0, 1, 255])),
+            ]),
+        );
+        let args = Val::Tuple(Struple::new_indexed(vec![req]));
+
+        // args are cloned into a message and out again, like
+        // they are when sent from another thread
+        let (result_tx, _result_rx) = oneshot::channel();
+        let spawn = WorkerMsg::ResultSpawn(
+            result_tx,
+            MsgItem::new(&Lstr::Sref("app")),
+            MsgItem::new(&Lstr::Sref("main")),
+            MsgVal::new(&args),
+        );
+        assert!(w.process_msg(spawn));
+        match w.fresh.pop_front() {
+            Some(ReadyFiber::New(fib)) => {
+                assert_eq!(&args, fib.head.e.get_params());
+            }
+            _ => panic!("expected a new fiber"),
+        }
+    }
+
+    #[test]
+    fn test_worker_keeps_its_last_fiber()
+    {
+        let (app_tx, app_rx) = channel();
+        let (io_tx, _io_rx) = channel();
+        let (_tx, rx) = channel();
+        let mut w = Worker::init(1, app_tx, io_tx, rx);
+        let root =
+            Frame::new_root(Parent::Null, Lstr::Sref("foo"), Lstr::Sref("a"));
+        w.spawn_fiber(root);
+
+        assert!(!w.process_msg(WorkerMsg::Steal(2)));
+        match app_rx.try_recv() {
+            Ok(AppMsg::Stolen(2, None)) => {}
+            other => panic!("expected nothing stolen: {:?}", other),
+        }
+        assert_eq!(1, w.fresh.len());
+    }
+
+    #[test]
+    fn test_spinning_fiber_yields_to_sleeping_fiber()
+    {
+        let (app_tx, _app_rx) = channel();
+        let (io_tx, _io_rx) = channel();
+        let (_tx, rx) = channel();
+        let mut w = Worker::init(1, app_tx, io_tx, rx);
+        w.set_reduction_budget(10);
+
+        // a fiber that never stops jumping to itself
+        let spin_root =
+            Frame::new_root(Parent::Null, Lstr::Sref("t"), Lstr::Sref("spin"));
+        let spin_code = Rc::new(Code::Leema(vec![(Op::Jump(0), 1)]));
+        let spinner = Fiber::spawn(1, spin_root);
+        w.push_fresh(ReadyFiber::Ready(spinner, spin_code));
+
+        // a fiber that sleeps until the alarm goes off, then returns it
+        let alarm = FutureVal::new();
+        let done = FutureVal::new();
+        let mut sleep_root = Frame::new_root(
+            Parent::Fork(done.clone(), Val::Void),
+            Lstr::Sref("t"),
+            Lstr::Sref("sleep"),
+        );
+        sleep_root.e.set_reg(&Reg::local(0), Val::future(alarm.clone()));
+        let sleep_code = Rc::new(Code::Leema(vec![
+            (Op::SetResult(Reg::local(0)), 2),
+            (Op::Return, 2),
+        ]));
+        let sleeper = Fiber::spawn(2, sleep_root);
+        w.push_fresh(ReadyFiber::Ready(sleeper, sleep_code));
+
+        for _ in 0..10 {
+            w.run_once();
+        }
+        assert!(!done.is_ready());
+
+        alarm.set(&Val::Int(5));
+        for _ in 0..10 {
+            w.run_once();
+        }
+        assert_eq!(Some(Val::Int(5)), done.get());
+    }
+
+    fn receiver(done: &FutureVal) -> Fiber
+    {
+        let root = Frame::new_root(
+            Parent::Fork(done.clone(), Val::Void),
+            Lstr::Sref("t"),
+            Lstr::Sref("recv"),
+        );
+        Fiber::spawn(5, root)
+    }
+
+    #[test]
+    fn test_send_wakes_waiting_receiver()
+    {
+        let (app_tx, _app_rx) = channel();
+        let (io_tx, _io_rx) = channel();
+        let (_tx, rx) = channel();
+        let mut w = Worker::init(1, app_tx, io_tx, rx);
+        let mb = Arc::new(Mailbox::new());
+        let done = FutureVal::new();
+
+        w.wait_for_mail(receiver(&done), mb.clone(), Val::Wildcard, None);
+        assert!(!done.is_ready());
+
+        assert_eq!(Some((1, 5)), mb.send(&Val::Int(3)));
+        assert!(w.process_msg(WorkerMsg::WakeReceiver(5)));
+        assert_eq!(Some(Val::Int(3)), done.get());
+    }
+
+    #[test]
+    fn test_receive_times_out()
+    {
+        let (app_tx, _app_rx) = channel();
+        let (io_tx, _io_rx) = channel();
+        let (_tx, rx) = channel();
+        let mut w = Worker::init(1, app_tx, io_tx, rx);
+        let mb = Arc::new(Mailbox::new());
+        let done = FutureVal::new();
+        let deadline = Some(Instant::now());
+
+        w.wait_for_mail(receiver(&done), mb.clone(), Val::Wildcard, deadline);
+        w.run_once();
+        match done.get() {
+            Some(Val::Failure(ref tag, _, _, _)) => {
+                assert_eq!(Val::Hashtag(Lstr::Sref("timeout")), **tag);
+            }
+            other => panic!("expected a timeout: {:?}", other),
+        }
+        // a late message doesn't wake anyone
+        assert_eq!(None, mb.send(&Val::Int(3)));
+    }
+
+    #[test]
+    fn test_killed_receiver_stops_waiting()
+    {
+        let (app_tx, _app_rx) = channel();
+        let (io_tx, _io_rx) = channel();
+        let (_tx, rx) = channel();
+        let mut w = Worker::init(1, app_tx, io_tx, rx);
+        let sup =
+            Supervisor::new(Strategy::OneForOne, 3, Duration::from_secs(5));
+        let func = Lri::with_modules(Lstr::Sref("t"), Lstr::Sref("recv"));
+        let (_, frame) = sup.start_child(func, &Val::Tuple(Struple(vec![])));
+        let mb = Arc::new(Mailbox::new());
+
+        let child = Fiber::spawn(5, frame);
+        w.wait_for_mail(child, mb.clone(), Val::Wildcard, None);
+        assert_eq!(1, w.waiting.len());
+
+        sup.stop();
+        assert!(w.run_once());
+        assert!(w.waiting.is_empty());
+        // the mailbox isn't holding a spot for the killed child either
+        assert_eq!(None, mb.send(&Val::Int(3)));
+    }
+}
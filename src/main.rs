@@ -8,6 +8,7 @@ extern crate mopa;
 extern crate bytes;
 extern crate futures;
 extern crate hyper;
+extern crate num_cpus;
 extern crate rand;
 extern crate rustc_serialize;
 extern crate tokio;
@@ -15,22 +16,29 @@ extern crate tokio_current_thread;
 
 #[macro_use]
 mod leema;
+mod repl;
 
 use leema::log;
 
 use leema::application::Application;
+use leema::code::Code;
+use leema::disasm;
+use leema::error::CompileError;
 use leema::http;
 use leema::list;
 use leema::loader::Interloader;
 use leema::lri::Lri;
 use leema::lstr::Lstr;
+use leema::manifest::{Manifest, MANIFEST_FILE};
 use leema::module::ModuleSource;
 use leema::program;
+use leema::struple::Struple;
 use leema::typecheck;
 use leema::val::Val;
 
 use docopt::Docopt;
 use std::env;
+use std::ffi::OsStr;
 use std::io::Write;
 
 
@@ -42,18 +50,31 @@ struct Args
     arg_script: Vec<String>,
     flag_verbose: bool,
     flag_func: Option<String>,
+    flag_lib_path: Option<String>,
+    flag_no_cache: bool,
+    flag_workers: Option<usize>,
+    flag_reductions: Option<u32>,
 }
 
 static USAGE: &'static str = "
 leema interpreter
 
+Without a script, the package declared by the leema.toml
+in the current directory or one of its parents is used.
+`leema build` compiles that package and caches its modules.
+
 Usage:
-  leema [options] <cmd> <script>...
+  leema [options] <cmd> [<script>...]
   leema (-v | --verbose)
   leema (-h | --help)
 
 Options:
      --func=<func>
+     --lib-path=<path>  Directories to search for modules,
+                        before those in LEEMA_PATH
+     --no-cache         Compile from source, ignoring cached modules
+     --workers=<n>      Number of worker threads, one per cpu by default
+     --reductions=<n>   Ops a fiber runs before yielding to other fibers
   -v --verbose     Output debug messages
   -h --help        Show this message
   --repl           Launch the REPL
@@ -80,86 +101,225 @@ fn real_main() -> i32
     }
     vout!("verbose mode\nargs:{:?}\n", args);
 
-    let file = Lstr::from(args.arg_script.first().unwrap());
-    let leema_args: Val =
-        args.arg_script.iter().skip(1).fold(Val::Nil, |acc, a| {
-            let strval = Val::Str(Lstr::from(a.to_string()));
-            list::cons(strval, acc)
-        });
-    let inter = Interloader::new(file);
+    let loaded = match args.arg_script.first() {
+        Some(script) => Interloader::new(Lstr::from(script)),
+        None if args.arg_cmd == "repl" => {
+            Interloader::new(Lstr::Sref("repl.lma"))
+        }
+        None => {
+            // no script, so run the package in the current directory
+            let cwd = env::current_dir().unwrap();
+            match Manifest::find(&cwd) {
+                Some(manifest_file) => {
+                    let pkg = Manifest::read(&manifest_file)
+                        .and_then(|m| Interloader::from_manifest(&m));
+                    match pkg {
+                        Ok(inter) => Ok(inter),
+                        Err(e) => {
+                            // manifest errors are located in the manifest
+                            eprintln!("{}", e);
+                            return 1;
+                        }
+                    }
+                }
+                None => {
+                    println!(
+                        "missing script for command and no {} found: {}",
+                        MANIFEST_FILE, args.arg_cmd
+                    );
+                    return 1;
+                }
+            }
+        }
+    };
+    let script_args: Vec<Val> = args
+        .arg_script
+        .iter()
+        .skip(1)
+        .map(|a| Val::Str(Lstr::from(a.to_string())))
+        .collect();
+    let leema_args = list::from_vec(&script_args);
+    let mut inter = match loaded {
+        Ok(inter) => inter,
+        Err(e) => {
+            eprintln!("{}", e.msg);
+            return 1;
+        }
+    };
+    if let Some(ref lib_path) = args.flag_lib_path {
+        inter.add_lib_paths(OsStr::new(lib_path));
+    }
+    if let Some(leema_path) = env::var_os("LEEMA_PATH") {
+        inter.add_lib_paths(&leema_path);
+    }
+    inter.set_ignore_cache(args.flag_no_cache);
     let modkey = inter.mod_name_to_key(inter.main_mod.clone());
     vout!("{} {}\n", args.arg_cmd, inter.main_mod);
 
     let main_result = if args.arg_cmd == "tokens" {
-        let modtxt = inter.read_module(&modkey);
-        let toks = ModuleSource::read_tokens(&modtxt);
-        println!("{:?}\n", toks);
-        Val::Int(0)
+        match inter.read_module(&modkey) {
+            Ok(modtxt) => {
+                match ModuleSource::read_tokens(&modkey.name, &modtxt) {
+                    Ok(toks) => {
+                        println!("{:?}\n", toks);
+                        Val::Int(0)
+                    }
+                    Err(e) => {
+                        let file = inter.mod_file_name(&e.module);
+                        eprintln!("{}", e.render(&file, Some(&modtxt)));
+                        Val::Int(1)
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e.in_file(&inter.mod_file_name(&e.module)));
+                Val::Int(1)
+            }
+        }
     } else if args.arg_cmd == "ast" {
-        let modtxt = inter.read_module(&modkey);
-        let ast = ModuleSource::read_ast(&modtxt);
-        println!("{:?}\n", ast);
-        Val::Int(0)
+        match inter.read_module(&modkey) {
+            Ok(modtxt) => {
+                match ModuleSource::read_ast(&modkey.name, &modtxt) {
+                    Ok(ast) => {
+                        println!("{:?}\n", ast);
+                        Val::Int(0)
+                    }
+                    Err(e) => {
+                        let file = inter.mod_file_name(&e.module);
+                        eprintln!("{}", e.render(&file, Some(&modtxt)));
+                        Val::Int(1)
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e.in_file(&inter.mod_file_name(&e.module)));
+                Val::Int(1)
+            }
+        }
     } else if args.arg_cmd == "modsrc" {
         let prog = program::Lib::new(inter);
-        let src = prog.read_modsrc(&modkey.name);
-        println!("{:?}\n", src);
-        Val::Int(0)
+        match prog.read_modsrc(&modkey.name) {
+            Ok(src) => {
+                println!("{:?}\n", src);
+                Val::Int(0)
+            }
+            Err(errs) => compile_failed(&prog, &errs),
+        }
     } else if args.arg_cmd == "preface" {
         let prog = program::Lib::new(inter);
-        let (_, pref) = prog.read_preface(&modkey.name);
-        println!("{:?}\n", pref);
-        Val::Int(0)
+        match prog.read_preface(&modkey.name) {
+            Ok((_, pref)) => {
+                println!("{:?}\n", pref);
+                Val::Int(0)
+            }
+            Err(errs) => compile_failed(&prog, &errs),
+        }
     } else if args.arg_cmd == "proto" {
         let mut prog = program::Lib::new(inter);
-        let proto = prog.read_proto(&modkey.name);
-        println!("\n{:?}\n", proto);
-        Val::Int(0)
+        match prog.read_proto(&modkey.name) {
+            Ok(proto) => {
+                println!("\n{:?}\n", proto);
+                Val::Int(0)
+            }
+            Err(errs) => compile_failed(&prog, &errs),
+        }
     } else if args.arg_cmd == "inter" {
         let mut prog = program::Lib::new(inter);
-        let imod = prog.read_inter(&modkey.name);
-        let fix = match args.flag_func {
-            Some(func) => {
-                let lfunc = Lstr::from(func);
-                imod.interfunc.get(&lfunc)
+        match prog.read_inter(&modkey.name) {
+            Ok(imod) => {
+                let fix = match args.flag_func {
+                    Some(func) => {
+                        let lfunc = Lstr::from(func);
+                        imod.interfunc.get(&lfunc)
+                    }
+                    None => imod.interfunc.get("main"),
+                };
+                println!("\n{:?}\n", fix);
+                Val::Int(0)
             }
-            None => imod.interfunc.get("main"),
-        };
-        println!("\n{:?}\n", fix);
-        Val::Int(0)
+            Err(errs) => compile_failed(&prog, &errs),
+        }
     } else if args.arg_cmd == "typecheck" {
         let mut prog = program::Lib::new(inter);
         let mod_name = modkey.name.clone();
         let func_name = Lstr::Sref("main");
         let funcri = Lri::with_modules(mod_name, func_name);
-        let ftype = prog.typecheck(&funcri, typecheck::Depth::Full);
-        println!("type: {}", ftype);
-        Val::Int(0)
+        match prog.typecheck(&funcri, typecheck::Depth::Full) {
+            Ok(ftype) => {
+                println!("type: {}", ftype);
+                Val::Int(0)
+            }
+            Err(errs) => compile_failed(&prog, &errs),
+        }
     } else if args.arg_cmd == "code" {
         let mut prog = program::Lib::new(inter);
-        let code = match args.flag_func {
-            Some(func) => {
-                let func_name = Lstr::from(func);
-                prog.load_code(&modkey.name, &func_name)
-            }
-            None => prog.load_code(&modkey.name, &Lstr::Sref("main")),
+        let func_name = match args.flag_func {
+            Some(func) => Lstr::from(func),
+            None => Lstr::Sref("main"),
         };
-        println!("code: {:?}", code);
-        Val::Int(0)
+        let code = prog
+            .load_code(&modkey.name, &func_name)
+            .map(|code| code.clone());
+        match code {
+            Ok(Code::Leema(ops)) => {
+                println!("{}::{}", modkey.name, func_name);
+                let src = prog.mod_txt(&modkey.name);
+                print!("{}", disasm::disassemble(&ops, src));
+                Val::Int(0)
+            }
+            Ok(code) => {
+                println!("{}::{} {}", modkey.name, func_name, code);
+                Val::Int(0)
+            }
+            Err(errs) => compile_failed(&prog, &errs),
+        }
+    } else if args.arg_cmd == "compile" || args.arg_cmd == "build" {
+        let mut prog = program::Lib::new(inter);
+        match prog.write_cache(&modkey.name) {
+            Ok(cache_files) => {
+                for cf in cache_files.iter() {
+                    println!("wrote {}", cf.display());
+                }
+                Val::Int(0)
+            }
+            Err(errs) => compile_failed(&prog, &errs),
+        }
     } else if args.arg_cmd == "repl" {
-        println!("wouldn't it be cool if there was a repl?");
-        Val::Int(2)
+        let imports = if args.arg_script.is_empty() {
+            vec![]
+        } else {
+            vec![inter.main_mod.clone()]
+        };
+        repl::run(inter, imports)
     } else if args.arg_cmd == "run" {
-        let prog = program::Lib::new(inter);
+        let mut prog = program::Lib::new(inter);
+        // compile main before starting so errors are reported up front
+        let main_func = Lstr::Sref("main");
+        if let Err(errs) = prog.load_code(&modkey.name, &main_func).map(|_| ())
+        {
+            prog.print_errors(&errs);
+            return 1;
+        }
         let mut app = Application::new(prog);
-        app.set_args(leema_args);
-        app.push_call(modkey.name.clone(), Lstr::Sref("main"));
+        if let Some(n) = args.flag_workers {
+            app.set_worker_count(n);
+        }
+        if let Some(n) = args.flag_reductions {
+            app.set_reduction_budget(n);
+        }
+        let main_args = Val::Tuple(Struple::new_indexed(vec![leema_args]));
+        app.push_call_with_args(modkey.name.clone(), main_func, main_args);
         app.run();
         app.wait_for_result().unwrap()
     } else if args.arg_cmd == "http" {
         let prog = program::Lib::new(inter);
         let mut app = Application::new(prog);
-        app.set_args(leema_args);
+        if let Some(n) = args.flag_workers {
+            app.set_worker_count(n);
+        }
+        if let Some(n) = args.flag_reductions {
+            app.set_reduction_budget(n);
+        }
         app.run();
 
         http::spawn_thread(app.caller(), modkey.name.clone());
@@ -172,3 +332,9 @@ fn real_main() -> i32
 
     i32::from(Application::handle_result(main_result))
 }
+
+fn compile_failed(prog: &program::Lib, errs: &[CompileError]) -> Val
+{
+    prog.print_errors(errs);
+    Val::Int(1)
+}
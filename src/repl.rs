@@ -1,112 +1,300 @@
-use leema::val::{Env};
-use leema::frame::{Frame, Application, Parent};
-use leema::lex::{lex};
-use leema::ast::{Ast};
-use leema::val::{Val};
-use leema::compile::{StaticSpace};
-use leema::code::{Code, Op, make_ops};
-
-use std::sync::{Arc, Mutex};
-use std::thread;
+use leema::application::Application;
+use leema::error::ModuleResult;
+use leema::loader::Interloader;
+use leema::log;
+use leema::lri::Lri;
+use leema::lstr::Lstr;
+use leema::program;
+use leema::typecheck;
+use leema::val::{Type, Val};
+
+use std::collections::HashSet;
 use std::io::{stdin, stdout, Write};
 
 
-fn prompt() {
-    write!(stdout(), "> ").ok();
-    stdout().flush().ok();
-}
+const REPL_MOD: Lstr = Lstr::Sref("repl");
 
-fn read_cmd() -> String {
-    let mut buffer = String::new();
-    stdin().read_line(&mut buffer).ok();
-    buffer
+/**
+ * Definitions entered at the prompt accumulate in a generated repl module.
+ * Each expression gets compiled as a new function in that module
+ * so the workers never run stale code for it.
+ */
+pub struct Repl
+{
+    app: Application,
+    imports: Vec<Lstr>,
+    defs: String,
+    names: HashSet<Lstr>,
+    next_expr: i64,
 }
 
-/*
-fn apply_macro(mdef: Sxpr, input: List) -> Sxpr {
-    match mdef {
-        Sxpr::MacroDef(_, ref args, ref code) => {
-            if input_items.len() != args.len() {
-                panic!("Wrong number of args");
+impl Repl
+{
+    pub fn new(app: Application, imports: Vec<Lstr>) -> Repl
+    {
+        Repl {
+            app,
+            imports,
+            defs: String::new(),
+            names: HashSet::new(),
+            next_expr: 1,
+        }
+    }
+
+    pub fn run(&mut self)
+    {
+        while let Some(entry) = read_entry() {
+            if entry.trim().is_empty() {
+                continue;
+            }
+            match first_words(&entry) {
+                Some((ref kw, ref name)) if kw == "import" => {
+                    self.import(name.clone());
+                }
+                Some((ref kw, ref name))
+                    if kw == "func"
+                        || kw == "macro"
+                        || kw == "struct"
+                        || kw == "enum" =>
+                {
+                    self.define(kw, name.clone(), entry.clone());
+                }
+                _ => {
+                    self.eval(&entry);
+                }
+            }
+        }
+        println!();
+    }
+
+    fn import(&mut self, module: Lstr)
+    {
+        if self.imports.contains(&module) {
+            return;
+        }
+        self.imports.push(module);
+        let txt = self.mod_txt(&self.defs, None);
+        let result = self.compile(txt, |prog| {
+            prog.load_inter(&REPL_MOD)?;
+            Ok(Type::Void)
+        });
+        if result.is_none() {
+            self.imports.pop();
+            self.restore();
+        }
+    }
+
+    fn define(&mut self, kw: &str, name: Lstr, def: String)
+    {
+        if self.names.contains(&name) {
+            println!("{} is already defined", name);
+            return;
+        }
+        let new_defs = format!("{}{}\n", self.defs, def.trim());
+        let txt = self.mod_txt(&new_defs, None);
+        let is_func = kw == "func";
+        let funcri = Lri::with_modules(REPL_MOD, name.clone());
+        let result = self.compile(txt, |prog| {
+            prog.load_inter(&REPL_MOD)?;
+            if is_func {
+                prog.typecheck(&funcri, typecheck::Depth::Full)
+            } else {
+                Ok(Type::Void)
             }
-            let mut i = 0;
-            let mut argval: HashMap<&String, Rc<Ast>> = HashMap::new();
-            for arg in args {
-                argval.insert(arg, input_items[i].clone());
-                i = i + 1;
+        });
+        match result {
+            Some(ftype) => {
+                self.defs = new_defs;
+                if is_func {
+                    println!("{} : {}", name, ftype);
+                } else {
+                    println!("defined {}", name);
+                }
+                self.names.insert(name);
             }
-            println!("argval: {:?}", argval);
-            // do nothing
-            // Ast::Bool(true)
-            let mut new_code = Vec::with_capacity(code.len());
-            for c in code {
-                new_code.push(Ast::replace_id(c, &argval));
+            None => {
+                self.restore();
+            }
+        }
+    }
+
+    fn eval(&mut self, expr: &str)
+    {
+        let fname = Lstr::from(format!("__repl_{}", self.next_expr));
+        self.next_expr += 1;
+
+        let body = expr.trim();
+        let body = if body.ends_with("--") {
+            &body[..body.len() - 2]
+        } else {
+            body
+        };
+        let txt = self.mod_txt(&self.defs, Some((&fname, body)));
+        let funcri = Lri::with_modules(REPL_MOD, fname.clone());
+        let result = self.compile(txt, |prog| {
+            let ftype = prog.typecheck(&funcri, typecheck::Depth::Full)?;
+            prog.load_code(&REPL_MOD, &fname)?;
+            Ok(ftype)
+        });
+        let ftype = match result {
+            Some(Type::Func(_, result_type)) => *result_type,
+            Some(other) => other,
+            None => {
+                self.restore();
+                return;
             }
-            Sxpr::Block(List::Nil)
+        };
+
+        let val = self.app.repl_call(REPL_MOD, fname);
+        if let Val::Failure(_, _, _, _) = val {
+            Application::handle_result(val);
+        } else {
+            println!("{} : {}", val, ftype);
         }
-        _ => {
-            panic!("That was not a macro");
+    }
+
+    /**
+     * Compile new text for the repl module and print any errors
+     */
+    fn compile<F>(&mut self, txt: String, f: F) -> Option<Type>
+    where
+        F: FnOnce(&mut program::Lib) -> ModuleResult<Type>,
+    {
+        vout!("repl module:\n{}\n", txt);
+        let prog = self.app.prog_mut();
+        prog.set_mod_txt(&REPL_MOD, txt);
+        match f(prog) {
+            Ok(t) => Some(t),
+            Err(errs) => {
+                prog.print_errors(&errs);
+                None
+            }
         }
     }
+
+    fn restore(&mut self)
+    {
+        let txt = self.mod_txt(&self.defs, None);
+        self.app.prog_mut().set_mod_txt(&REPL_MOD, txt);
+    }
+
+    fn mod_txt(&self, defs: &str, expr: Option<(&Lstr, &str)>) -> String
+    {
+        let mut txt = String::new();
+        for i in self.imports.iter() {
+            txt.push_str(&format!("import {}\n", i));
+        }
+        txt.push_str(defs);
+        if let Some((fname, body)) = expr {
+            txt.push_str(&format!("func {}() ->\n{}\n--\n", fname, body));
+        }
+        txt
+    }
 }
-*/
 
+pub fn run(inter: Interloader, imports: Vec<Lstr>) -> Val
+{
+    let prog = program::Lib::new(inter);
+    let mut app = Application::new(prog);
+    app.run();
+    let mut repl = Repl::new(app, imports);
+    repl.run();
+    Val::Int(0)
+}
 
-pub fn push_eval(app: &Mutex<Application>, i: isize, function: Code, e: Env)
+fn prompt(continued: bool)
 {
-    println!("exec {:?}", function);
+    let p = if continued { ". " } else { "> " };
+    write!(stdout(), "{}", p).ok();
+    stdout().flush().ok();
+}
 
-    let frm = Frame::new_root(e);
-//println!("repl.push_eval: app.lock().unwrap()");
-    let mut _app = app.lock().unwrap();
-    _app.push_new_frame(frm);
-    _app.add_code(ckey, function);
+/**
+ * Read one entry from stdin
+ *
+ * definitions and block expressions continue until a line ending in --
+ */
+fn read_entry() -> Option<String>
+{
+    let mut entry = String::new();
+    loop {
+        prompt(!entry.is_empty());
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                return if entry.trim().is_empty() {
+                    None
+                } else {
+                    Some(entry)
+                };
+            }
+            Ok(_) => {}
+        }
+        entry.push_str(&line);
+        if !needs_more(&entry) {
+            return Some(entry);
+        }
+    }
 }
 
-pub fn wait_eval(app: Arc<Mutex<Application>>) -> Val
+fn needs_more(entry: &str) -> bool
 {
-//println!("repl.wait_eval: app.lock().unwrap()");
-    let mut result = Val::Void;
-    // this is crap. set this w/ a channel or something.
-    thread::yield_now();
-    let mut _app = app.lock().unwrap();
-//println!("repl.wait_eval: app.pop_old_frame().unwrap()");
-    _app.take_result()
+    if entry.trim().is_empty() || entry.trim().ends_with("--") {
+        return false;
+    }
+    match first_words(entry) {
+        Some((ref kw, _)) if kw == "import" => false,
+        Some((ref kw, _)) => {
+            kw == "func"
+                || kw == "macro"
+                || kw == "struct"
+                || kw == "enum"
+                || kw == "if"
+                || kw == "match"
+        }
+        None => false,
+    }
 }
 
-pub fn reploop(app: Arc<Mutex<Application>>, mut ss: StaticSpace)
+/**
+ * Get the leading keyword and the name that follows it
+ */
+fn first_words(entry: &str) -> Option<(String, Lstr)>
 {
-    let mut i = 1;
-    let mut e = Env::new();
-    loop {
-        prompt();
-        let input = read_cmd();
-
-        let tokens = lex(input);
-        println!("tokens = {:?}\n", tokens);
-
-        let ast_root = Ast::parse(tokens);
-        println!("ast = {:?}", ast_root);
-        let rootx = Ast::root(ast_root);
-
-        // static compilation
-        let inter = ss.compile(rootx);
-        println!("inter> {:?}", inter);
-
-        let ops = make_ops(&inter);
-        println!("ops>");
-        Op::print_list(&ops);
-        println!("---\n");
-
-        let code = Code::Leema(Arc::new(ops));
-        push_eval(&*app, i, code, e);
-        //let result = Worker::eval(cmd_code);
-        // println!("= {:?}", result);
-
-        let mut result = wait_eval(app.clone());
-        // TODO: but really, extract the env out of the execution
-        e = Env::new();
-        println!("= {:?}", result);
-        i += 1;
+    let mut words = entry.split_whitespace();
+    let kw = words.next()?;
+    let name: String = words
+        .next()
+        .unwrap_or("")
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    Some((kw.to_string(), Lstr::from(name)))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::{first_words, needs_more};
+    use leema::lstr::Lstr;
+
+
+    #[test]
+    fn test_first_words_func()
+    {
+        let (kw, name) = first_words("func foo(x: Int): Int ->").unwrap();
+        assert_eq!("func", kw);
+        assert_eq!(Lstr::Sref("foo"), name);
+    }
+
+    #[test]
+    fn test_needs_more()
+    {
+        assert!(!needs_more("3 + 4\n"));
+        assert!(needs_more("func foo(x) ->\n"));
+        assert!(needs_more("struct Point\n.x: Int\n"));
+        assert!(!needs_more("struct Point\n.x: Int\n.y: Int\n--\n"));
+        assert!(!needs_more("import list\n"));
     }
 }
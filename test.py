@@ -117,6 +117,19 @@ class TestScripts(unittest.TestCase):
             b"l2: [4,6,3,2,8,]\n",
             result['output'])
 
+    def test_list_lib(self):
+        result = run_leema('list_test')
+        self.assertEqual(0, result['code'])
+        expected = b"doubled: [6,2,8,2,10,18,4,12,]\n" \
+            + b"evens: [4,2,6,]\n" \
+            + b"total: 31\n" \
+            + b"both: [2,6,3,1,4,]\n" \
+            + b"backwards: [4,1,3,6,2,]\n" \
+            + b"4 true false 8 5\n" \
+            + b"pairs: [(3,a,),(1,b,),]\n" \
+            + b"sorted: [9,6,5,4,3,2,1,1,]\n"
+        self.assertEqual(expected, result['output'])
+
     def test_list_match_all(self):
         result = run_leema('list_match_all')
         self.assertEqual(0, result['code'])
@@ -162,14 +175,10 @@ class TestScripts(unittest.TestCase):
         self.assertEqual(
             b"Failure: #xis4\n" +
             b"Message: tacos are delicious\n" +
-            b"Stack Trace:\n" +
-            b"<  main:14\n" +
-            b"<  add5:9\n" +
-            b"<> add4:3\n" +
-            b" > add5:9\n" +
-            b" > main:14\n" +
-            b" > __init__\n" +
-            b"\n",
+            b"Stack Trace (most recent call first):\n" +
+            b"  at failure::add4 (failure.lma:3:5)\n" +
+            b"  at failure::add5 (failure.lma:9:11)\n" +
+            b"  at failure::main (failure.lma:14:11)\n",
             result['output'])
 
     def test_failed_handled(self):
@@ -187,13 +196,11 @@ class TestScripts(unittest.TestCase):
             b"c failed. log and propagate\n" +
             b"Failure: #xis4\n" +
             b"Message: tacos are delicious\n" +
-            b"Stack Trace:\n" +
-            b"<  main:33\n" +
-            b"<> foo:3\n" +
-            b" > handle_nonlinear:13\n" +
-            b" > main:33\n" +
-            b" > __init__\n" +
-            b"\n",
+            b"Stack Trace (most recent call first):\n" +
+            b"  at failed_propagated::foo (failed_propagated.lma:3:5)\n" +
+            b"  at failed_propagated::handle_nonlinear " +
+            b"(failed_propagated.lma:13:10)\n" +
+            b"  at failed_propagated::main (failed_propagated.lma:33:23)\n",
             result['output'])
 
     def test_destruct(self):
@@ -256,6 +263,73 @@ class TestScripts(unittest.TestCase):
             + b"option b? Some(4,)\n"
         self.assertEqual(exp, result['output'])
 
+    def test_fork(self):
+        result = run_leema('fork')
+        self.assertEqual(0, result['code'])
+        self.assertEqual(b"before sum\nsum is: 18\n", result['output'])
+
+    def test_mailbox(self):
+        result = run_leema('mailbox')
+        self.assertEqual(0, result['code'])
+        self.assertEqual(b"pong 8\n", result['output'])
+
+    def test_supervisor_restart(self):
+        result = run_leema('supervise')
+        self.assertEqual(0, result['code'])
+        expected = (
+            b"started child 1\n"
+            b"child 1 #flaky: flaky 3 failed\n"
+            b"child 1 #flaky: flaky 3 failed\n"
+            b"child 1 #flaky: flaky 3 failed\n"
+            b"child 1 #max_restarts\n"
+        )
+        self.assertEqual(expected, result['output'])
+
+    def test_syntax_error(self):
+        result = run_leema('syntax_error')
+        self.assertEqual(1, result['code'])
+        self.assertEqual(b"", result['output'])
+
+    def test_file_handle(self):
+        result = run_leema('file_handle')
+        self.assertEqual(0, result['code'])
+        expected = b"lines: [first,second,third,]\n" \
+            + b"at 6: second\n" \
+            + b"size: 19, dir: false\n" \
+            + b"names: [renamed.txt,]\n" \
+            + b"exists: false\n" \
+            + b"missing: not found\n"
+        self.assertEqual(expected, result['output'])
+
+    def test_buffer(self):
+        result = run_leema('buffer_test')
+        self.assertEqual(0, result['code'])
+        expected = b"msg: 01026869, 4 bytes\n" \
+            + b"size: 258, little: -2, first: 104\n" \
+            + b"text: hi, bad: <invalid>\n" \
+            + b"b64: aGk= [104,105,] [0,255,]\n"
+        self.assertEqual(expected, result['output'])
+
+    def test_map(self):
+        result = run_leema('map_test')
+        self.assertEqual(0, result['code'])
+        expected = b"m1: {#a: 1,#b: 2,#c: 3,}\n" \
+            + b"m3: {#a: 10,#c: 3,}\n" \
+            + b"size: 2, has b: false\n" \
+            + b"a: 10, d: 0\n" \
+            + b"total: 15\n"
+        self.assertEqual(expected, result['output'])
+
+    def test_str(self):
+        result = run_leema('str_test')
+        self.assertEqual(0, result['code'])
+        expected = "joined: a-b-c\n" \
+            + "héllo world has 11 chars, starts with héllo\n" \
+            + "world at 6, true true true\n" \
+            + "HÉLLO LEEMA\n" \
+            + "42 -1 43\n"
+        self.assertEqual(expected.encode('utf-8'), result['output'])
+
     def test_read_file(self):
         result = run_leema('read_file')
         self.assertEqual(0, result['code'])
@@ -62,7 +62,7 @@ class TestScripts(unittest.TestCase):
     def test_tcp(self):
         result = self.clientserver_success('pingtcp', 'listentcp')
         self.assertEqual(
-            b"received: hello tcp world\n\n",
+            b"received: hello tcp world\n",
             result['server_output'],
         )
         self.assertEqual(